@@ -0,0 +1,93 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+pub type Facts = HashMap<String, serde_json::Value>;
+
+/// One line of the cache file: the facts a command produced for a file at a
+/// given basis_rev. Entries for other commands can share the file.
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    path: String,
+    basis_rev: i64,
+    /// Command template, before `{}` substitution, led by any `--env` KEY=VALUE
+    command: Vec<String>,
+    /// Output mode ("fact:<key>", "kv" or "json")
+    mode: String,
+    facts: Facts,
+}
+
+/// `--cache FILE`: command results memoized per (path, basis_rev, command) in
+/// an append-only JSONL file. A changed file gets a new basis_rev from `canon
+/// scan`, so its old entries simply stop matching.
+pub struct ResultCache {
+    command: Vec<String>,
+    mode: String,
+    entries: HashMap<(String, i64), Facts>,
+    /// Appended to by the output thread while workers read `entries`
+    file: Mutex<File>,
+}
+
+impl ResultCache {
+    /// Load entries for this command and mode; create the file if needed
+    pub fn open(path: &Path, command: &[String], mode: &str) -> Result<Self> {
+        let mut entries = HashMap::new();
+        if path.exists() {
+            let reader = BufReader::new(
+                File::open(path).with_context(|| format!("Failed to open cache: {}", path.display()))?,
+            );
+            for line in reader.lines() {
+                let line = line.with_context(|| format!("Failed to read cache: {}", path.display()))?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let entry: CacheEntry = match serde_json::from_str(&line) {
+                    Ok(e) => e,
+                    Err(e) => {
+                        eprintln!("Warning: Skipping malformed cache entry: {}", e);
+                        continue;
+                    }
+                };
+                // Later entries win
+                if entry.command == command && entry.mode == mode {
+                    entries.insert((entry.path, entry.basis_rev), entry.facts);
+                }
+            }
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open cache: {}", path.display()))?;
+
+        Ok(Self {
+            command: command.to_vec(),
+            mode: mode.to_string(),
+            entries,
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn get(&self, path: &str, basis_rev: i64) -> Option<&Facts> {
+        self.entries.get(&(path.to_string(), basis_rev))
+    }
+
+    /// Record a fresh result (written through, so an interrupted run keeps it)
+    pub fn append(&self, path: &str, basis_rev: i64, facts: &Facts) -> Result<()> {
+        let entry = CacheEntry {
+            path: path.to_string(),
+            basis_rev,
+            command: self.command.clone(),
+            mode: self.mode.clone(),
+            facts: facts.clone(),
+        };
+        let mut file = self.file.lock().expect("cache file poisoned");
+        writeln!(file, "{}", serde_json::to_string(&entry)?).context("Failed to write cache")?;
+        Ok(())
+    }
+}
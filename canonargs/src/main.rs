@@ -1,10 +1,30 @@
 use anyhow::{bail, Context, Result};
-use clap::Parser;
+use canon_core::db;
+use canon_core::extract::{self, Extractor};
+use canon_core::import_facts::{FactImport, ImportStats, Importer};
+use canon_core::util::current_timestamp;
+use canon_core::{query, worklist};
+use clap::{Parser, ValueEnum};
+use rusqlite::OptionalExtension;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::io::{self, BufRead, Write};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Cursor, Write};
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod cache;
+mod process;
+mod template;
+mod value_type;
+
+use cache::{Facts, ResultCache};
+use process::Shell;
+use value_type::ValueType;
 
 #[derive(Parser)]
 #[command(name = "canonargs")]
@@ -14,31 +34,183 @@ struct Cli {
     #[arg(long, group = "mode")]
     fact: Option<String>,
 
-    /// Output mode: key=value pairs (one per line)
+    /// Output mode: key=value pairs (one per line). Values are text unless
+    /// typed with --kv-types or inline as key:type=value
     #[arg(long, group = "mode")]
     kv: bool,
 
+    /// Value types for --kv keys (e.g. "duration=num,taken_at=time");
+    /// types are text, num, time, bool and json
+    #[arg(long, requires = "kv", value_name = "KEY=TYPE,...")]
+    kv_types: Option<String>,
+
     /// Output mode: JSON object
     #[arg(long, group = "mode")]
     json: bool,
 
-    /// Command and arguments to run ({} is replaced with file path)
-    #[arg(last = true, required = true)]
+    /// Compute facts in-process with a built-in extractor instead of running a command
+    #[arg(long, group = "mode", value_enum)]
+    builtin: Option<Builtin>,
+
+    /// What stdin holds: worklist JSONL (from `canon worklist`) or file paths,
+    /// one per line or NUL-separated (from `find`, `fzf`), looked up in the database
+    #[arg(long, value_enum, default_value = "worklist")]
+    input: InputFormat,
+
+    /// Number of commands to run at once (0 = number of CPUs)
+    #[arg(short = 'j', long, default_value = "1")]
+    jobs: usize,
+
+    /// Write results as commands finish instead of in input order
+    #[arg(long, overrides_with = "ordered")]
+    unordered: bool,
+
+    /// Write results in input order. This is the default; the flag is
+    /// accepted so pipelines can ask for it explicitly.
+    #[arg(long, overrides_with = "unordered")]
+    ordered: bool,
+
+    /// Seconds between progress lines on stderr (0 = no progress lines)
+    #[arg(long, default_value = "10")]
+    progress: u64,
+
+    /// Print the final summary as JSON (on stderr)
+    #[arg(long)]
+    stats_json: bool,
+
+    /// Reuse results from this JSONL file for unchanged files (same path,
+    /// basis_rev and command), and record new ones in it
+    #[arg(long, value_name = "FILE")]
+    cache: Option<PathBuf>,
+
+    /// Write the input lines of entries that failed to this file, to re-run
+    /// them later (pipe it back in with a different --failed-out)
+    #[arg(long, value_name = "FILE")]
+    failed_out: Option<PathBuf>,
+
+    /// Skip entries this log lists as done (same source_id and basis_rev),
+    /// and record newly succeeded entries in it
+    #[arg(long, value_name = "FILE")]
+    resume: Option<PathBuf>,
+
+    /// Set an environment variable for the command, expanding the same
+    /// placeholders as the command (e.g. --env SRC={} --env REV={basis_rev});
+    /// repeatable
+    #[arg(long, value_name = "KEY=VALUE", conflicts_with = "builtin")]
+    env: Vec<String>,
+
+    /// Run the command line through a shell (sh on Unix, cmd on Windows, or
+    /// the one named). Placeholders are quoted for that shell
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = process::DEFAULT_SHELL, conflicts_with = "builtin")]
+    shell: Option<Shell>,
+
+    /// Kill the command if it runs longer than this many seconds (counts as a failure)
+    #[arg(long, value_name = "SECS", conflicts_with = "builtin")]
+    timeout: Option<u64>,
+
+    /// Record the command's stderr (trimmed, when not empty) as this fact
+    #[arg(long, value_name = "KEY", conflicts_with = "builtin")]
+    capture_stderr: Option<String>,
+
+    /// Record the command's exit code as this fact. A non-zero exit is then
+    /// recorded instead of failing the entry (its stdout is not parsed)
+    #[arg(long, value_name = "KEY", conflicts_with = "builtin")]
+    exit_code_fact: Option<String>,
+
+    /// Skip entries whose source already has this fact at its current
+    /// basis_rev (looked up in the database), for incremental runs
+    #[arg(long, value_name = "KEY")]
+    skip_if_fact: Option<String>,
+
+    /// Import facts straight into the canon database instead of printing
+    /// JSONL for `canon import-facts`
+    #[arg(long)]
+    import: bool,
+
+    /// Database for --import, --input paths and --skip-if-fact (default: ~/.canon/canon.db)
+    #[arg(long, value_name = "PATH")]
+    db: Option<PathBuf>,
+
+    /// With --import, also import facts for sources in archive roots
+    #[arg(long, requires = "import")]
+    allow_archived: bool,
+
+    /// Command and arguments to run. {} is replaced with the file path; {source_id},
+    /// {basis_rev}, {basename}, {stem}, {ext}, {dir} and {size} with entry fields,
+    /// and {fact:KEY} with a fact from `canon worklist --with-facts` (empty if absent).
+    /// With --shell, the words are joined into one command line
+    #[arg(last = true, required_unless_present = "builtin", conflicts_with = "builtin")]
     command: Vec<String>,
 }
 
+/// Extractors shared with `canon extract`
+#[derive(Clone, Copy, ValueEnum)]
+enum Builtin {
+    /// content.mime from file signatures
+    Mime,
+    /// content.exif.* (capture time, camera, lens, GPS, orientation)
+    Exif,
+    /// content.image.width/height/orientation from image headers
+    Dimensions,
+    /// content.kind (photo, video, raw, screenshot, audio, document, archive, other)
+    Kind,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum InputFormat {
+    Worklist,
+    Paths,
+}
+
 /// Input format - accepts both worklist entries and enriched entries (for chaining)
 #[derive(Deserialize)]
 struct InputEntry {
     source_id: i64,
     path: String,
     basis_rev: i64,
+    #[serde(default)]
+    size: Option<i64>,
     // Optional fields from enriched input (for chaining)
     #[serde(default)]
     facts: HashMap<String, serde_json::Value>,
     // observed_at is ignored on input - we always use current time
 }
 
+/// Final summary of a run
+#[derive(Default, Serialize)]
+struct RunStats {
+    succeeded: u64,
+    /// Entries served from --cache instead of running the command
+    cached: u64,
+    /// Entries a built-in extractor found nothing in (e.g. no EXIF block)
+    no_facts: u64,
+    failed: u64,
+    /// Input lines that are not worklist entries
+    skipped: u64,
+    /// Entries already done in a previous run (--resume)
+    resumed: u64,
+    /// Entries that already had the --skip-if-fact fact
+    fact_present: u64,
+    elapsed_secs: f64,
+    /// Database import counts (--import)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    import: Option<ImportStats>,
+}
+
+/// An input entry and the line it was read from (for --failed-out)
+struct Job {
+    entry: InputEntry,
+    line: String,
+}
+
+/// A successful entry, and the facts its command produced when it ran
+/// (None when served from the cache)
+struct Processed {
+    /// None when a built-in extractor found no facts
+    output: Option<FactOutput>,
+    fresh: Option<Facts>,
+}
+
 #[derive(Serialize)]
 struct FactOutput {
     source_id: i64,
@@ -55,79 +227,584 @@ fn main() -> Result<()> {
     let mode = if let Some(ref key) = cli.fact {
         OutputMode::SingleFact(key.clone())
     } else if cli.kv {
-        OutputMode::KeyValue
+        let types = match cli.kv_types {
+            Some(ref spec) => value_type::parse_kv_types(spec)?,
+            None => BTreeMap::new(),
+        };
+        OutputMode::KeyValue(types)
     } else if cli.json {
         OutputMode::Json
+    } else if let Some(builtin) = cli.builtin {
+        OutputMode::Builtin(builtin.extractor())
     } else {
-        bail!("Must specify one of --fact <key>, --kv, or --json");
+        bail!("Must specify one of --fact <key>, --kv, --json or --builtin <name>");
     };
 
-    let stdin = io::stdin();
-    let stdout = io::stdout();
-    let mut stdout_handle = stdout.lock();
+    let template = CommandTemplate {
+        args: cli.command.clone(),
+        env: cli.env.iter().map(|s| parse_env(s)).collect::<Result<_>>()?,
+        stderr_fact: cli.capture_stderr.clone(),
+        exit_code_fact: cli.exit_code_fact.clone(),
+        shell: cli.shell,
+        timeout: cli.timeout.filter(|&secs| secs > 0).map(Duration::from_secs),
+    };
 
-    for line in stdin.lock().lines() {
-        let line = line.context("Failed to read line from stdin")?;
-        if line.trim().is_empty() {
-            continue;
+    let cache = match cli.cache {
+        Some(ref path) => {
+            let mode_key = format!("{}{}", mode.cache_key(), template.capture_key());
+            Some(ResultCache::open(path, &template.cache_key(), &mode_key)?)
         }
+        None => None,
+    };
 
-        let entry: InputEntry = match serde_json::from_str(&line) {
-            Ok(e) => e,
-            Err(e) => {
-                eprintln!("Warning: Failed to parse input entry: {}", e);
-                continue;
+    let done: HashSet<(i64, i64)> = match cli.resume {
+        Some(ref path) => read_done_log(path)?,
+        None => HashSet::new(),
+    };
+
+    let db_path = cli.db.clone().unwrap_or_else(db::default_path);
+    let database = if cli.import || cli.input == InputFormat::Paths || cli.skip_if_fact.is_some() {
+        Some(db::open(&db_path, false, false)?)
+    } else {
+        None
+    };
+
+    let mut sink = Sink {
+        out: io::stdout().lock(),
+        importer: database
+            .as_ref()
+            .filter(|_| cli.import)
+            .map(|db| Importer::new(db, cli.allow_archived)),
+        cache: cache.as_ref(),
+        failed_out: match cli.failed_out {
+            Some(ref path) => Some(
+                File::create(path).with_context(|| format!("Failed to create {}", path.display()))?,
+            ),
+            None => None,
+        },
+        done_log: cli.resume.as_deref().map(open_append).transpose()?,
+        stats: RunStats::default(),
+    };
+
+    let jobs = if cli.jobs == 0 {
+        thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+    } else {
+        cli.jobs
+    };
+
+    let input: Box<dyn BufRead + Send> = match database {
+        Some(ref db) if cli.input == InputFormat::Paths => {
+            // Resolve up front: the lookup needs the whole list, and paths
+            // that are not indexed sources are reported before any work starts
+            let source_ids = query::read_source_ids(db.conn(), io::stdin().lock())?;
+            let mut worklist = Vec::new();
+            worklist::write_sources_to(db, &source_ids, &mut worklist)?;
+            Box::new(Cursor::new(worklist))
+        }
+        _ => Box::new(BufReader::new(io::stdin())),
+    };
+
+    // Entries flow reader -> workers -> this thread, which writes results.
+    // The bounded queue keeps the reader from running far ahead of the workers.
+    let (entry_tx, entry_rx) = mpsc::sync_channel::<(u64, Job)>(jobs * 4);
+    let entry_rx = Mutex::new(entry_rx);
+    let (result_tx, result_rx) = mpsc::channel::<(u64, Job, Result<Processed>)>();
+
+    // Shared with the reader; the total is only known once stdin is exhausted
+    let entries_read = AtomicU64::new(0);
+    let lines_skipped = AtomicU64::new(0);
+    let entries_resumed = AtomicU64::new(0);
+    let entries_with_fact = AtomicU64::new(0);
+    let input_done = AtomicBool::new(false);
+    // Set when writing a result fails (e.g. stdout piped into `head`): the
+    // reader stops and workers drain the queue without running commands
+    let stop = AtomicBool::new(false);
+
+    let started = Instant::now();
+
+    thread::scope(|scope| -> Result<()> {
+        let reader = scope.spawn(|| -> Result<()> {
+            let entry_tx = entry_tx;
+            // The reader checks --skip-if-fact on its own connection
+            let fact_check = match cli.skip_if_fact {
+                Some(ref key) => Some((db::open(&db_path, false, false)?, normalize_fact_key(key))),
+                None => None,
+            };
+            let mut seq = 0;
+            for line in input.lines() {
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                let line = line.context("Failed to read line from stdin")?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let entry: InputEntry = match serde_json::from_str(&line) {
+                    Ok(e) => e,
+                    Err(e) => {
+                        eprintln!("Warning: Failed to parse input entry: {}", e);
+                        lines_skipped.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    }
+                };
+
+                if done.contains(&(entry.source_id, entry.basis_rev)) {
+                    entries_resumed.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+
+                if let Some((ref db, ref key)) = fact_check {
+                    if has_current_fact(db.conn(), &entry, key)? {
+                        entries_with_fact.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    }
+                }
+
+                if entry_tx.send((seq, Job { entry, line })).is_err() {
+                    // Every worker has exited
+                    break;
+                }
+                seq += 1;
+                entries_read.store(seq, Ordering::Relaxed);
             }
+            input_done.store(true, Ordering::Relaxed);
+            Ok(())
+        });
+
+        for _ in 0..jobs {
+            let result_tx = result_tx.clone();
+            let entry_rx = &entry_rx;
+            let template = &template;
+            let mode = &mode;
+            let cache = cache.as_ref();
+            let stop = &stop;
+            scope.spawn(move || loop {
+                let next = entry_rx.lock().expect("entry queue poisoned").recv();
+                let Ok((seq, job)) = next else {
+                    break;
+                };
+                if stop.load(Ordering::Relaxed) {
+                    continue;
+                }
+                // A failing entry only produces a warning; the rest carry on
+                let result = process_entry(&job.entry, template, mode, cache);
+                if result_tx.send((seq, job, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(result_tx);
+
+        let progress_interval = Duration::from_secs(cli.progress);
+        let mut last_progress = Instant::now();
+
+        // Results that finished ahead of an earlier entry (ordered output)
+        let ordered = cli.ordered || !cli.unordered;
+        let mut pending: BTreeMap<u64, (Job, Result<Processed>)> = BTreeMap::new();
+        let mut next_seq = 0;
+        let mut write_results = || -> Result<()> {
+            loop {
+                // Wake up now and then to report progress while commands run
+                match result_rx.recv_timeout(Duration::from_millis(500)) {
+                    Ok((seq, job, result)) => {
+                        if !ordered {
+                            sink.write(job, result)?;
+                        } else {
+                            pending.insert(seq, (job, result));
+                            while let Some((job, result)) = pending.remove(&next_seq) {
+                                sink.write(job, result)?;
+                                next_seq += 1;
+                            }
+                        }
+                    }
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+
+                if cli.progress > 0 && last_progress.elapsed() >= progress_interval {
+                    let total = input_done
+                        .load(Ordering::Relaxed)
+                        .then(|| entries_read.load(Ordering::Relaxed));
+                    print_progress(&sink.stats, total, started.elapsed());
+                    last_progress = Instant::now();
+                }
+            }
+            Ok(())
+        };
+        let written = write_results();
+        if written.is_err() {
+            stop.store(true, Ordering::Relaxed);
+        }
+
+        let read = reader.join().expect("stdin reader panicked");
+        written.and(read)
+    })?;
+
+    let mut stats = sink.stats;
+    stats.import = sink.importer.map(Importer::finish);
+    stats.skipped = lines_skipped.load(Ordering::Relaxed);
+    stats.resumed = entries_resumed.load(Ordering::Relaxed);
+    stats.fact_present = entries_with_fact.load(Ordering::Relaxed);
+    stats.elapsed_secs = started.elapsed().as_secs_f64();
+    if cli.stats_json {
+        eprintln!("{}", serde_json::to_string(&stats)?);
+    } else {
+        let cached = if cache.is_some() {
+            format!(" ({} cached)", stats.cached)
+        } else {
+            String::new()
+        };
+        let no_facts = if cli.builtin.is_some() {
+            format!(", {} without facts", stats.no_facts)
+        } else {
+            String::new()
+        };
+        let resumed = if cli.resume.is_some() {
+            format!(", {} already done", stats.resumed)
+        } else {
+            String::new()
+        };
+        let fact_present = match cli.skip_if_fact {
+            Some(ref key) => format!(", {} already had {}", stats.fact_present, key),
+            None => String::new(),
         };
+        eprintln!(
+            "Done: {} succeeded{}{}, {} failed, {} skipped{}{} in {}",
+            stats.succeeded,
+            cached,
+            no_facts,
+            stats.failed,
+            stats.skipped,
+            resumed,
+            fact_present,
+            format_duration(started.elapsed())
+        );
+        if let Some(ref import) = stats.import {
+            eprintln!(
+                "Imported: {} facts, {} skipped (stale), {} skipped (reserved), {} skipped (archived), {} objects created, {} facts promoted",
+                import.facts_imported,
+                import.skipped_stale,
+                import.skipped_reserved,
+                import.skipped_archived,
+                import.objects_created,
+                import.facts_promoted
+            );
+        }
+    }
+
+    Ok(())
+}
 
-        match process_entry(&entry, &cli.command, &mode) {
-            Ok(output) => {
-                let json = serde_json::to_string(&output)?;
-                writeln!(stdout_handle, "{}", json)?;
+/// Where results go: facts to stdout (or the database with --import), plus
+/// the cache, failed-entry file and done log when enabled. Only the output
+/// thread writes, and each record goes out as one complete line in a single
+/// write, so parallel workers can't interleave JSONL.
+struct Sink<'a, W: Write> {
+    out: W,
+    importer: Option<Importer<'a>>,
+    cache: Option<&'a ResultCache>,
+    failed_out: Option<File>,
+    done_log: Option<File>,
+    stats: RunStats,
+}
+
+impl<W: Write> Sink<'_, W> {
+    fn write(&mut self, job: Job, result: Result<Processed>) -> Result<()> {
+        match result {
+            Ok(processed) => {
+                match processed.output {
+                    Some(output) => {
+                        match self.importer {
+                            Some(ref mut importer) => importer.import(&FactImport {
+                                source_id: output.source_id,
+                                basis_rev: output.basis_rev,
+                                observed_at: output.observed_at,
+                                facts: output.facts,
+                            }),
+                            None => {
+                                let mut line = serde_json::to_vec(&output)?;
+                                line.push(b'\n');
+                                self.out.write_all(&line)?;
+                            }
+                        }
+                        self.stats.succeeded += 1;
+                    }
+                    None => self.stats.no_facts += 1,
+                }
+                match (processed.fresh, self.cache) {
+                    (Some(facts), Some(cache)) => cache.append(&job.entry.path, job.entry.basis_rev, &facts)?,
+                    (None, Some(_)) => self.stats.cached += 1,
+                    _ => {}
+                }
+                if let Some(ref mut done_log) = self.done_log {
+                    writeln!(done_log, "{} {}", job.entry.source_id, job.entry.basis_rev)
+                        .context("Failed to write resume log")?;
+                }
             }
             Err(e) => {
-                eprintln!("Warning: {}: {}", entry.path, e);
+                eprintln!("Warning: {}: {:#}", job.entry.path, e);
+                self.stats.failed += 1;
+                if let Some(ref mut failed_out) = self.failed_out {
+                    writeln!(failed_out, "{}", job.line).context("Failed to write failed entries")?;
+                }
             }
         }
+        Ok(())
     }
+}
 
-    Ok(())
+/// Entries recorded as done by --resume: "source_id basis_rev" per line
+fn read_done_log(path: &Path) -> Result<HashSet<(i64, i64)>> {
+    let mut done = HashSet::new();
+    if !path.exists() {
+        return Ok(done);
+    }
+    let file = File::open(path).with_context(|| format!("Failed to open resume log: {}", path.display()))?;
+    for line in BufReader::new(file).lines() {
+        let line = line.with_context(|| format!("Failed to read resume log: {}", path.display()))?;
+        let mut fields = line.split_whitespace().map(str::parse::<i64>);
+        match (fields.next(), fields.next()) {
+            (Some(Ok(source_id)), Some(Ok(basis_rev))) => {
+                done.insert((source_id, basis_rev));
+            }
+            _ if line.trim().is_empty() => {}
+            _ => eprintln!("Warning: Skipping malformed resume log line: {}", line),
+        }
+    }
+    Ok(done)
+}
+
+/// Imported facts live under content.*; accept the key with or without it
+fn normalize_fact_key(key: &str) -> String {
+    if key.starts_with("content.") || key.starts_with("source.") {
+        key.to_string()
+    } else {
+        format!("content.{}", key)
+    }
+}
+
+/// Whether the entry's source, still at the entry's basis_rev, has the fact:
+/// on the source as observed at that basis_rev, or on its object
+fn has_current_fact(conn: &db::Connection, entry: &InputEntry, key: &str) -> Result<bool> {
+    let found = conn
+        .query_row(
+            "SELECT 1 FROM sources s
+             WHERE s.id = ?1 AND s.basis_rev = ?2
+               AND (EXISTS (SELECT 1 FROM facts f
+                            WHERE f.entity_type = 'source' AND f.entity_id = s.id
+                              AND f.key = ?3 AND f.observed_basis_rev = s.basis_rev)
+                    OR EXISTS (SELECT 1 FROM facts f
+                               WHERE f.entity_type = 'object' AND f.entity_id = s.object_id
+                                 AND f.key = ?3))",
+            rusqlite::params![entry.source_id, entry.basis_rev, key],
+            |_| Ok(()),
+        )
+        .optional()
+        .context("Failed to look up existing facts")?;
+    Ok(found.is_some())
+}
+
+fn open_append(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))
+}
+
+/// "Progress: 1200/5000 entries (24%), 35.2/s, 3 failed"
+fn print_progress(stats: &RunStats, total: Option<u64>, elapsed: Duration) {
+    let processed = stats.succeeded + stats.no_facts + stats.failed;
+    let count = match total {
+        Some(total) if total > 0 => format!(
+            "{}/{} entries ({}%)",
+            processed,
+            total,
+            processed * 100 / total
+        ),
+        _ => format!("{} entries", processed),
+    };
+    let rate = processed as f64 / elapsed.as_secs_f64().max(0.001);
+    eprintln!("Progress: {}, {:.1}/s, {} failed", count, rate, stats.failed);
+}
+
+/// "2h 05m", "3m 12s", "41.3s"
+fn format_duration(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    if secs >= 3600 {
+        format!("{}h {:02}m", secs / 3600, secs % 3600 / 60)
+    } else if secs >= 60 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{:.1}s", elapsed.as_secs_f64())
+    }
+}
+
+/// The command to run for each entry, before placeholder expansion
+struct CommandTemplate {
+    args: Vec<String>,
+    /// --env variables, in the order given
+    env: Vec<(String, String)>,
+    /// --capture-stderr
+    stderr_fact: Option<String>,
+    /// --exit-code-fact
+    exit_code_fact: Option<String>,
+    /// --shell: `args` are joined into one command line for it
+    shell: Option<Shell>,
+    timeout: Option<Duration>,
+}
+
+impl CommandTemplate {
+    /// Identifies the command in cache entries: env assignments come first,
+    /// as they would on a shell command line
+    fn cache_key(&self) -> Vec<String> {
+        self.env
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .chain(self.shell.map(|shell| format!("--shell={}", shell.name())))
+            .chain(self.args.iter().cloned())
+            .collect()
+    }
+
+    /// Suffix for the mode's cache key when stderr or the exit code become facts
+    fn capture_key(&self) -> String {
+        let mut key = String::new();
+        if let Some(ref fact) = self.stderr_fact {
+            key.push_str(&format!(",stderr:{}", fact));
+        }
+        if let Some(ref fact) = self.exit_code_fact {
+            key.push_str(&format!(",exit:{}", fact));
+        }
+        key
+    }
+}
+
+/// Parse `--env KEY=VALUE`
+fn parse_env(spec: &str) -> Result<(String, String)> {
+    match spec.split_once('=') {
+        Some((key, value)) if !key.is_empty() && !key.contains(char::is_whitespace) => {
+            Ok((key.to_string(), value.to_string()))
+        }
+        _ => bail!("Invalid --env '{}': expected KEY=VALUE", spec),
+    }
 }
 
 enum OutputMode {
     SingleFact(String),
-    KeyValue,
+    /// With the --kv-types hints
+    KeyValue(BTreeMap<String, ValueType>),
     Json,
+    Builtin(Box<dyn Extractor>),
+}
+
+impl Builtin {
+    fn extractor(self) -> Box<dyn Extractor> {
+        match self {
+            Builtin::Mime => Box::new(extract::mime::MimeExtractor),
+            Builtin::Exif => Box::new(extract::exif::ExifExtractor),
+            Builtin::Dimensions => Box::new(extract::image::ImageExtractor),
+            Builtin::Kind => Box::new(extract::kind::KindExtractor),
+        }
+    }
+}
+
+impl OutputMode {
+    /// Identifies the mode in cache entries: the same command parsed
+    /// differently produces different facts
+    fn cache_key(&self) -> String {
+        match self {
+            OutputMode::SingleFact(key) => format!("fact:{}", key),
+            OutputMode::KeyValue(types) if types.is_empty() => "kv".to_string(),
+            OutputMode::KeyValue(types) => format!("kv:{}", value_type::describe(types)),
+            OutputMode::Json => "json".to_string(),
+            OutputMode::Builtin(extractor) => format!("builtin:{}", extractor.name()),
+        }
+    }
 }
 
 fn process_entry(
     entry: &InputEntry,
-    command_template: &[String],
+    command_template: &CommandTemplate,
     mode: &OutputMode,
-) -> Result<FactOutput> {
-    // Build command by replacing {} with path
-    let command: Vec<String> = command_template
-        .iter()
-        .map(|arg| {
-            if arg == "{}" {
-                entry.path.clone()
-            } else {
-                arg.replace("{}", &entry.path)
-            }
-        })
-        .collect();
+    cache: Option<&ResultCache>,
+) -> Result<Processed> {
+    let (new_facts, fresh) = match (cache.and_then(|c| c.get(&entry.path, entry.basis_rev)), mode) {
+        (Some(facts), _) => (facts.clone(), false),
+        (None, OutputMode::Builtin(extractor)) => (run_builtin(entry, extractor.as_ref())?, true),
+        (None, _) => (run_command(entry, command_template, mode)?, true),
+    };
+
+    // Only built-in extractors (and their cached results) come back empty
+    if new_facts.is_empty() {
+        return Ok(Processed {
+            output: None,
+            fresh: fresh.then_some(new_facts),
+        });
+    }
 
-    if command.is_empty() {
+    // Merge with existing facts from input (new facts override existing)
+    let mut facts = entry.facts.clone();
+    facts.extend(new_facts.clone());
+
+    Ok(Processed {
+        output: Some(FactOutput {
+            source_id: entry.source_id,
+            path: entry.path.clone(),
+            basis_rev: entry.basis_rev,
+            observed_at: current_timestamp(),
+            facts,
+        }),
+        fresh: fresh.then_some(new_facts),
+    })
+}
+
+/// Extract facts in-process. Files of a type the extractor doesn't read
+/// produce no facts, as with `canon extract`.
+fn run_builtin(entry: &InputEntry, extractor: &dyn Extractor) -> Result<Facts> {
+    let path = Path::new(&entry.path);
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+    if !extractor.handles_ext(&ext) {
+        return Ok(Facts::new());
+    }
+    Ok(extractor.extract(path)?.into_iter().collect())
+}
+
+/// Run the command for one entry and parse the facts it prints
+fn run_command(entry: &InputEntry, command_template: &CommandTemplate, mode: &OutputMode) -> Result<Facts> {
+    if command_template.args.is_empty() {
         bail!("Empty command");
     }
 
+    // Build command by expanding {} (path) and the other placeholders
+    let mut command = match command_template.shell {
+        Some(shell) => {
+            let script = template::expand_for_shell(&command_template.args.join(" "), entry, shell)?;
+            shell.command(&script)
+        }
+        None => {
+            let args = command_template
+                .args
+                .iter()
+                .map(|arg| template::expand(arg, entry))
+                .collect::<Result<Vec<String>>>()?;
+            let mut command = Command::new(&args[0]);
+            command.args(&args[1..]);
+            command
+        }
+    };
+    for (key, value) in &command_template.env {
+        command.env(key, template::expand(value, entry)?);
+    }
+
     // Execute command
-    let output = Command::new(&command[0])
-        .args(&command[1..])
-        .output()
-        .with_context(|| format!("Failed to execute: {}", command[0]))?;
+    let output = process::run(command, command_template.timeout)?;
 
-    if !output.status.success() {
+    if !output.status.success() && command_template.exit_code_fact.is_none() {
         bail!(
             "Command failed with status {}: {}",
             output.status,
@@ -135,27 +812,40 @@ fn process_entry(
         );
     }
 
-    let stdout = String::from_utf8(output.stdout)
-        .context("Command output is not valid UTF-8")?;
+    // Facts about the run itself, when asked for
+    let mut run_facts = Facts::new();
+    if let Some(ref key) = command_template.exit_code_fact {
+        let Some(code) = output.status.code() else {
+            bail!("Command was terminated: {}", output.status);
+        };
+        run_facts.insert(key.clone(), serde_json::Value::from(code));
+    }
+    if let Some(ref key) = command_template.stderr_fact {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stderr = stderr.trim();
+        if !stderr.is_empty() {
+            run_facts.insert(key.clone(), serde_json::Value::String(stderr.to_string()));
+        }
+    }
 
-    // Parse output based on mode
-    let new_facts = parse_output(&stdout, mode)?;
+    // A failed command's stdout isn't trusted; a successful one may print
+    // nothing when its stderr or exit code is the point
+    let mut new_facts = if !output.status.success() || (output.stdout.is_empty() && !run_facts.is_empty()) {
+        Facts::new()
+    } else {
+        let stdout = String::from_utf8(output.stdout)
+            .context("Command output is not valid UTF-8")?;
+
+        // Parse output based on mode
+        parse_output(&stdout, mode)?
+    };
+    new_facts.extend(run_facts);
 
     if new_facts.is_empty() {
         bail!("No facts produced");
     }
 
-    // Merge with existing facts from input (new facts override existing)
-    let mut facts = entry.facts.clone();
-    facts.extend(new_facts);
-
-    Ok(FactOutput {
-        source_id: entry.source_id,
-        path: entry.path.clone(),
-        basis_rev: entry.basis_rev,
-        observed_at: current_timestamp(),
-        facts,
-    })
+    Ok(new_facts)
 }
 
 fn parse_output(stdout: &str, mode: &OutputMode) -> Result<HashMap<String, serde_json::Value>> {
@@ -169,20 +859,20 @@ fn parse_output(stdout: &str, mode: &OutputMode) -> Result<HashMap<String, serde
             }
             facts.insert(key.clone(), serde_json::Value::String(value.to_string()));
         }
-        OutputMode::KeyValue => {
+        OutputMode::KeyValue(types) => {
             for line in stdout.lines() {
                 let line = line.trim();
                 if line.is_empty() {
                     continue;
                 }
                 if let Some((key, value)) = line.split_once('=') {
-                    let key = key.trim();
+                    let (key, value_type) = typed_key(key.trim(), types);
                     let value = value.trim();
                     if !key.is_empty() {
-                        facts.insert(
-                            key.to_string(),
-                            serde_json::Value::String(value.to_string()),
-                        );
+                        let value = value_type
+                            .convert(value)
+                            .with_context(|| format!("Invalid value for {}", key))?;
+                        facts.insert(key.to_string(), value);
                     }
                 } else {
                     eprintln!("Warning: Skipping malformed key=value line: {}", line);
@@ -202,14 +892,19 @@ fn parse_output(stdout: &str, mode: &OutputMode) -> Result<HashMap<String, serde
                 _ => bail!("JSON output must be an object"),
             }
         }
+        OutputMode::Builtin(_) => unreachable!("built-in extractors run no command"),
     }
 
     Ok(facts)
 }
 
-fn current_timestamp() -> i64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_secs() as i64
+/// Split an inline type off a kv key ("taken_at:time"), else look the key up
+/// in the --kv-types hints. Untyped values are text.
+fn typed_key<'a>(key: &'a str, types: &BTreeMap<String, ValueType>) -> (&'a str, ValueType) {
+    if let Some((name, type_name)) = key.rsplit_once(':') {
+        if let Some(value_type) = ValueType::parse(type_name) {
+            return (name, value_type);
+        }
+    }
+    (key, types.get(key).copied().unwrap_or(ValueType::Text))
 }
@@ -0,0 +1,151 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use std::io::Read;
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// `--shell` when given without a value
+pub const DEFAULT_SHELL: &str = if cfg!(windows) { "cmd" } else { "sh" };
+
+/// Shell that runs the command line given with --shell
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    /// sh -c
+    Sh,
+    /// cmd.exe /d /s /c
+    Cmd,
+    /// powershell -NoProfile -NonInteractive -Command
+    Powershell,
+}
+
+impl Shell {
+    pub fn name(self) -> &'static str {
+        match self {
+            Shell::Sh => "sh",
+            Shell::Cmd => "cmd",
+            Shell::Powershell => "powershell",
+        }
+    }
+
+    /// Quote a substituted value so the shell sees it as one literal word
+    pub fn quote(self, value: &str) -> String {
+        match self {
+            Shell::Sh => format!("'{}'", value.replace('\'', r"'\''")),
+            // Windows paths can't contain '"'; %VAR% is still expanded inside
+            // quotes, which cmd offers no way around
+            Shell::Cmd => format!("\"{}\"", value.replace('"', "\"\"")),
+            Shell::Powershell => format!("'{}'", value.replace('\'', "''")),
+        }
+    }
+
+    /// A command that runs `script` in this shell
+    pub fn command(self, script: &str) -> Command {
+        match self {
+            Shell::Sh => {
+                let mut command = Command::new("sh");
+                command.arg("-c").arg(script);
+                command
+            }
+            Shell::Cmd => cmd_command(script),
+            Shell::Powershell => {
+                let mut command = Command::new("powershell");
+                command.args(["-NoProfile", "-NonInteractive", "-Command", script]);
+                command
+            }
+        }
+    }
+}
+
+/// cmd.exe does its own parsing of the command line, so the script is passed
+/// as is rather than quoted the way other programs expect (/s strips the
+/// outer quotes)
+#[cfg(windows)]
+fn cmd_command(script: &str) -> Command {
+    use std::os::windows::process::CommandExt;
+    let mut command = Command::new("cmd");
+    command.raw_arg(format!("/d /s /c \"{}\"", script));
+    command
+}
+
+#[cfg(not(windows))]
+fn cmd_command(script: &str) -> Command {
+    let mut command = Command::new("cmd");
+    command.args(["/d", "/s", "/c", script]);
+    command
+}
+
+/// Run a command to completion, capturing its output, and kill it if it
+/// runs longer than `timeout`
+pub fn run(mut command: Command, timeout: Option<Duration>) -> Result<Output> {
+    // Its own process group, so a timeout can kill whatever the shell started
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to execute: {}", command.get_program().to_string_lossy()))?;
+
+    // Drain pipes on their own threads so a chatty command can't block on a full pipe
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let stdout_reader = thread::spawn(move || {
+        let mut buf = Vec::new();
+        stdout.read_to_end(&mut buf).map(|_| buf)
+    });
+    let stderr_reader = thread::spawn(move || {
+        let mut buf = Vec::new();
+        stderr.read_to_end(&mut buf).map(|_| buf)
+    });
+
+    let status = match timeout {
+        None => child.wait()?,
+        Some(timeout) => {
+            let started = Instant::now();
+            loop {
+                if let Some(status) = child.try_wait()? {
+                    break status;
+                }
+                if started.elapsed() > timeout {
+                    kill(&mut child);
+                    bail!("Command timed out after {}s", timeout.as_secs());
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout_reader.join().expect("stdout reader panicked").context("Failed to read stdout")?,
+        stderr: stderr_reader.join().expect("stderr reader panicked").context("Failed to read stderr")?,
+    })
+}
+
+/// Kill a timed-out command along with everything it started: its process
+/// group on Unix, its process tree on Windows. Nothing started under the
+/// shell is left running.
+fn kill(child: &mut Child) {
+    #[cfg(unix)]
+    {
+        if let Ok(pgid) = libc::pid_t::try_from(child.id()) {
+            // The group was created with the child's pid as its id
+            unsafe { libc::kill(-pgid, libc::SIGKILL) };
+        }
+    }
+    #[cfg(windows)]
+    {
+        let _ = Command::new("taskkill")
+            .args(["/F", "/T", "/PID", &child.id().to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
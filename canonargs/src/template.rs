@@ -0,0 +1,80 @@
+use anyhow::{bail, Result};
+use std::path::Path;
+
+use crate::process::Shell;
+use crate::InputEntry;
+
+/// Expand `{}` and the named placeholders in `arg` from the worklist entry.
+/// Other braces (e.g. exiftool's `${Tag}`) are left alone, and substituted
+/// values are never expanded again.
+pub fn expand(arg: &str, entry: &InputEntry) -> Result<String> {
+    expand_with(arg, entry, None)
+}
+
+/// Like `expand`, but for a --shell command line: each substituted value is
+/// quoted for the shell
+pub fn expand_for_shell(script: &str, entry: &InputEntry, shell: Shell) -> Result<String> {
+    expand_with(script, entry, Some(shell))
+}
+
+fn expand_with(arg: &str, entry: &InputEntry, shell: Option<Shell>) -> Result<String> {
+    let mut out = String::with_capacity(arg.len());
+    let mut rest = arg;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('}') else {
+            out.push_str(&rest[start..]);
+            return Ok(out);
+        };
+        match value(&after[..end], entry)? {
+            Some(value) => match shell {
+                Some(shell) => out.push_str(&shell.quote(&value)),
+                None => out.push_str(&value),
+            },
+            None => out.push_str(&rest[start..start + end + 2]),
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn value(name: &str, entry: &InputEntry) -> Result<Option<String>> {
+    let path = Path::new(&entry.path);
+    let os_str = |s: Option<&std::ffi::OsStr>| s.map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    if let Some(key) = name.strip_prefix("fact:") {
+        // Facts carried by `canon worklist --with-facts`; empty when the file has none
+        let value = match entry.facts.get(key) {
+            Some(serde_json::Value::String(s)) => s.clone(),
+            Some(serde_json::Value::Null) | None => String::new(),
+            Some(other) => other.to_string(),
+        };
+        return Ok(Some(value));
+    }
+    let value = match name {
+        "" => native(&entry.path),
+        "source_id" => entry.source_id.to_string(),
+        "basis_rev" => entry.basis_rev.to_string(),
+        "basename" => os_str(path.file_name()),
+        "stem" => os_str(path.file_stem()),
+        "ext" => os_str(path.extension()),
+        "dir" => native(&os_str(path.parent().map(|p| p.as_os_str()))),
+        "size" => match entry.size {
+            Some(size) => size.to_string(),
+            None => bail!("{{size}} needs a size in the worklist entry"),
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(value))
+}
+
+/// Paths with the platform's separator, so Windows tools that don't accept
+/// '/' get `C:\Photos\a.jpg`
+fn native(path: &str) -> String {
+    if cfg!(windows) {
+        path.replace('/', "\\")
+    } else {
+        path.to_string()
+    }
+}
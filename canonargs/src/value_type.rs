@@ -0,0 +1,110 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Type of a `--kv` value, given with `--kv-types key=type` or inline as `key:type=value`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ValueType {
+    Text,
+    Num,
+    Time,
+    Bool,
+    Json,
+}
+
+impl ValueType {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "text" | "str" => Some(ValueType::Text),
+            "num" => Some(ValueType::Num),
+            "time" => Some(ValueType::Time),
+            "bool" => Some(ValueType::Bool),
+            "json" => Some(ValueType::Json),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ValueType::Text => "text",
+            ValueType::Num => "num",
+            ValueType::Time => "time",
+            ValueType::Bool => "bool",
+            ValueType::Json => "json",
+        }
+    }
+
+    /// Convert a raw kv value. Times are normalized to RFC 3339, which
+    /// `canon import-facts` stores as a time.
+    pub fn convert(self, raw: &str) -> Result<Value> {
+        match self {
+            ValueType::Text => Ok(Value::String(raw.to_string())),
+            ValueType::Num => {
+                if let Ok(i) = raw.parse::<i64>() {
+                    return Ok(Value::from(i));
+                }
+                let f: f64 = raw.parse().with_context(|| format!("'{}' is not a number", raw))?;
+                serde_json::Number::from_f64(f)
+                    .map(Value::Number)
+                    .with_context(|| format!("'{}' is not a finite number", raw))
+            }
+            ValueType::Time => parse_time(raw).map(Value::String),
+            ValueType::Bool => match raw.to_ascii_lowercase().as_str() {
+                "true" | "yes" | "1" => Ok(Value::Bool(true)),
+                "false" | "no" | "0" => Ok(Value::Bool(false)),
+                _ => bail!("'{}' is not a boolean", raw),
+            },
+            ValueType::Json => serde_json::from_str(raw).with_context(|| format!("'{}' is not valid JSON", raw)),
+        }
+    }
+}
+
+/// Parse `--kv-types duration=num,taken_at=time`
+pub fn parse_kv_types(spec: &str) -> Result<BTreeMap<String, ValueType>> {
+    let mut types = BTreeMap::new();
+    for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let Some((key, type_name)) = item.split_once('=') else {
+            bail!("Invalid --kv-types entry '{}': expected key=type", item);
+        };
+        let Some(value_type) = ValueType::parse(type_name.trim()) else {
+            bail!(
+                "Unknown type '{}' for {}: must be text, num, time, bool or json",
+                type_name.trim(),
+                key.trim()
+            );
+        };
+        types.insert(key.trim().to_string(), value_type);
+    }
+    Ok(types)
+}
+
+/// Stable description of the hints, for cache keys
+pub fn describe(types: &BTreeMap<String, ValueType>) -> String {
+    types
+        .iter()
+        .map(|(key, t)| format!("{}={}", key, t.name()))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Accepts RFC 3339, ISO-like and EXIF date-times, plain dates and Unix seconds
+fn parse_time(raw: &str) -> Result<String> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(raw) {
+        return Ok(dt.to_rfc3339());
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y:%m:%d %H:%M:%S"] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(raw, format) {
+            return Ok(dt.and_utc().to_rfc3339());
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(raw, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc().to_rfc3339());
+    }
+    if let Ok(secs) = raw.parse::<i64>() {
+        if let Some(dt) = DateTime::from_timestamp(secs, 0) {
+            return Ok(dt.to_rfc3339());
+        }
+    }
+    bail!("'{}' is not a recognized date/time", raw)
+}
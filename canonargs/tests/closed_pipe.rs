@@ -0,0 +1,39 @@
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Output piped into something like `head -1` fails with EPIPE: the run must
+/// stop there instead of running the command on every remaining entry
+#[test]
+fn stops_when_output_pipe_closes() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_canonargs"))
+        .args(["--jobs", "1", "--shell", "--fact", "x", "--", "sleep 0.1; echo v"])
+        .env("RUST_BACKTRACE", "0")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = thread::spawn(move || {
+        for id in 1..=100 {
+            let entry = format!("{{\"source_id\":{},\"path\":\"/nonexistent/{}\",\"basis_rev\":0}}\n", id, id);
+            if stdin.write_all(entry.as_bytes()).is_err() {
+                break;
+            }
+        }
+    });
+
+    let started = Instant::now();
+    let mut first = String::new();
+    BufReader::new(child.stdout.take().unwrap()).read_line(&mut first).unwrap();
+    assert!(first.contains("\"x\":\"v\""), "unexpected output: {}", first);
+
+    // The reader is dropped, closing the pipe; 100 entries would take 10s
+    let status = child.wait().unwrap();
+    writer.join().unwrap();
+    assert!(!status.success());
+    assert!(started.elapsed() < Duration::from_secs(5), "took {:?}", started.elapsed());
+}
@@ -1,31 +1,57 @@
 use anyhow::{bail, Context, Result};
 use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 use std::collections::{HashMap, HashSet};
 use std::fs::{self, Metadata};
-#[cfg(unix)]
-use std::os::unix::fs::MetadataExt;
+use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant};
 
-use crate::cluster::{Manifest, ManifestSource};
-use crate::db::{parse_root_spec, Connection, Db};
+use crate::cluster::{capture_time, Manifest, ManifestSource};
+use crate::db::{self, parse_root_spec, Connection, Db};
+use crate::error::{coded, ErrorCode};
 use crate::exclude;
+use crate::import_facts;
+use crate::output;
+use crate::platform;
+use crate::pin;
+use crate::remote::{self, Rclone, RemoteFile};
+use crate::roots;
+use crate::trash;
+use crate::util::current_timestamp;
+
+/// Read size when hashing files for --hash-missing
+const HASH_BUFFER_SIZE: usize = 1024 * 1024;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TransferMode {
     Copy,   // Default: copy only, source remains
-    Rename, // Unix only, error if cross-device
-    Move,   // Try rename, fallback to copy+delete on EXDEV (requires --yes)
+    Rename, // Error if cross-device
+    Move,   // Try rename, fallback to copy+delete across devices (requires --yes)
 }
 
-#[derive(Default)]
+#[derive(Default, Serialize)]
 struct ApplyStats {
+    dry_run: bool,
     copied: u64,
     renamed: u64,
     moved: u64,
     skipped_missing: u64,
     skipped_filtered: u64,
+    /// Sources hashed by --hash-missing that turned out to be archived already
+    skipped_archived: u64,
+    /// Sources whose size or mtime changed since the manifest was generated
+    skipped_stale: u64,
     errors: u64,
+    files: Vec<AppliedFile>,
+    failed: Vec<FailedFile>,
+}
+
+#[derive(Serialize)]
+struct FailedFile {
+    source: String,
+    error: String,
 }
 
 pub struct ApplyOptions {
@@ -33,23 +59,25 @@ pub struct ApplyOptions {
     pub allow_cross_archive_duplicates: bool,
     pub roots: Vec<String>,
     pub transfer_mode: TransferMode,
+    /// Let --rename/--move take pinned sources away from their location
+    pub include_pinned: bool,
+    /// Hash unhashed sources during the transfer, then run the archive checks
+    /// preflight had to skip for them
+    pub hash_missing: bool,
+    /// Remote roots: hash uploads by downloading them when the backend reports no SHA-256
+    pub download_verify: bool,
 }
 
+/// Copy/rename/move the manifest's sources to their destinations and register them in the archive root
 pub fn run(db: &Db, manifest_path: &Path, options: &ApplyOptions) -> Result<()> {
-    // Platform checks: --rename and --move are Unix-only
-    #[cfg(not(unix))]
-    if options.transfer_mode == TransferMode::Rename || options.transfer_mode == TransferMode::Move {
-        bail!("--rename and --move are only supported on Unix platforms");
-    }
-
-    // Metadata preservation warning for Copy mode on non-Unix
-    #[cfg(not(unix))]
-    if options.transfer_mode == TransferMode::Copy {
-        eprintln!("Note: mtime/permissions preservation not available on this platform");
-    }
-
-    let content = fs::read_to_string(manifest_path)
-        .with_context(|| format!("Failed to read manifest: {}", manifest_path.display()))?;
+    // "-" reads the manifest from stdin (e.g. piped from `collection export --dest`)
+    let from_stdin = manifest_path == Path::new("-");
+    let content = if from_stdin {
+        io::read_to_string(io::stdin()).context("Failed to read manifest from stdin")?
+    } else {
+        fs::read_to_string(manifest_path)
+            .with_context(|| format!("Failed to read manifest: {}", manifest_path.display()))?
+    };
 
     let manifest: Manifest = toml::from_str(&content)
         .with_context(|| format!("Failed to parse manifest: {}", manifest_path.display()))?;
@@ -64,6 +92,15 @@ pub fn run(db: &Db, manifest_path: &Path, options: &ApplyOptions) -> Result<()>
             |row| row.get(0),
         )
         .with_context(|| format!("Archive root id {} not found", manifest.output.archive_root_id))?;
+    let archive_available = db::refresh_one_root_availability(conn, manifest.output.archive_root_id)?;
+    if !archive_available {
+        let reason = if roots::setting(conn, manifest.output.archive_root_id, "offline")?.as_deref() == Some("on") {
+            "is marked offline (canon root online to clear)"
+        } else {
+            "is unavailable (not mounted?)"
+        };
+        return Err(coded(ErrorCode::RootUnavailable, format!("Archive root '{}' {}", archive_root_path, reason)));
+    }
 
     // Construct full base_dir from archive root + relative subdir
     let base_dir = if manifest.output.base_dir.is_empty() {
@@ -72,12 +109,40 @@ pub fn run(db: &Db, manifest_path: &Path, options: &ApplyOptions) -> Result<()>
         PathBuf::from(&archive_root_path).join(&manifest.output.base_dir)
     };
 
+    // Remote (rclone) archive roots: copy with rclone; rename is impossible
+    let rclone = if remote::is_remote(&archive_root_path) {
+        if options.transfer_mode == TransferMode::Rename {
+            bail!("--rename is not supported for remote archive root '{}'", archive_root_path);
+        }
+        if options.dry_run {
+            None
+        } else {
+            Some(Rclone::new()?)
+        }
+    } else {
+        None
+    };
+
     // Filter sources by root if specified
     let filtered_sources = filter_by_roots(&manifest, &options.roots, conn)?;
     let skipped_by_filter = manifest.sources.len() - filtered_sources.len();
 
     // Pre-flight checks (mandatory, always run)
-    // Check destination uniqueness first
+    // Sources whose content changed since generation would be filed under stale facts
+    let stale = check_stale_sources_filtered(conn, &filtered_sources)?;
+    if !stale.is_empty() {
+        eprintln!(
+            "Error: {} sources changed since the manifest was generated:",
+            stale.len()
+        );
+        for path in &stale {
+            eprintln!("  {}", path);
+        }
+        eprintln!("\nRegenerate the manifest with `canon cluster generate`.");
+        return Err(coded(ErrorCode::StaleManifest, "Aborting due to changed sources in manifest"));
+    }
+
+    // Check destination uniqueness
     let collisions = check_destination_collisions_filtered(&filtered_sources, &manifest.output.pattern, &base_dir)?;
     if !collisions.is_empty() {
         eprintln!(
@@ -90,7 +155,7 @@ pub fn run(db: &Db, manifest_path: &Path, options: &ApplyOptions) -> Result<()>
                 eprintln!("    {}", src);
             }
         }
-        bail!("Aborting due to destination collisions");
+        return Err(coded(ErrorCode::PreflightConflict, "Aborting due to destination collisions"));
     }
 
     // Check archive conflicts
@@ -104,7 +169,7 @@ pub fn run(db: &Db, manifest_path: &Path, options: &ApplyOptions) -> Result<()>
         for (src, dst) in &conflicts.in_dest_archive {
             eprintln!("  {} -> {}", src, dst);
         }
-        bail!("Aborting due to files already in destination archive");
+        return Err(coded(ErrorCode::PreflightConflict, "Aborting due to files already in destination archive"));
     }
 
     if !conflicts.in_other_archives.is_empty() && !options.allow_cross_archive_duplicates {
@@ -116,7 +181,7 @@ pub fn run(db: &Db, manifest_path: &Path, options: &ApplyOptions) -> Result<()>
             eprintln!("  {} -> {}", src, dst);
         }
         eprintln!("\nUse --allow-cross-archive-duplicates to copy anyway");
-        bail!("Aborting due to files already in other archives");
+        return Err(coded(ErrorCode::PreflightConflict, "Aborting due to files already in other archives"));
     }
 
     // Defense-in-depth: Check for excluded sources in manifest (hard gate, no override)
@@ -133,15 +198,47 @@ pub fn run(db: &Db, manifest_path: &Path, options: &ApplyOptions) -> Result<()>
                 eprintln!("  {} (id: {})", path, id);
             }
             eprintln!("\nExcluded sources cannot be applied. Regenerate the manifest after clearing exclusions.");
-            bail!("Aborting due to excluded sources in manifest");
+            return Err(coded(ErrorCode::PreflightConflict, "Aborting due to excluded sources in manifest"));
+        }
+    }
+
+    // Pinned sources stay where they are unless --include-pinned
+    if options.transfer_mode != TransferMode::Copy && !options.include_pinned {
+        let pinned = check_pinned_sources_filtered(conn, &filtered_sources)?;
+        if !pinned.is_empty() {
+            eprintln!("Error: {} sources in manifest are pinned:", pinned.len());
+            for (id, path) in &pinned {
+                eprintln!("  {} (id: {})", path, id);
+            }
+            eprintln!("\nPinned sources are only renamed or moved with --include-pinned (or copy them instead).");
+            return Err(coded(ErrorCode::PreflightConflict, "Aborting due to pinned sources in manifest"));
         }
     }
 
     let mut stats = ApplyStats {
+        dry_run: options.dry_run,
         skipped_filtered: skipped_by_filter as u64,
         ..Default::default()
     };
 
+    // Record the run so files it places can be told apart from hand-copied ones (canon lint)
+    let apply_run_id = if options.dry_run {
+        None
+    } else {
+        let manifest_abs = if from_stdin {
+            PathBuf::from("(stdin)")
+        } else {
+            fs::canonicalize(manifest_path).unwrap_or_else(|_| manifest_path.to_path_buf())
+        };
+        let now = current_timestamp();
+        let manifest_sha256 = format!("{:x}", Sha256::digest(content.as_bytes()));
+        conn.execute(
+            "INSERT INTO apply_runs (manifest, manifest_sha256, applied_at) VALUES (?, ?, ?)",
+            params![manifest_abs.to_string_lossy(), manifest_sha256, now],
+        )?;
+        Some(conn.last_insert_rowid())
+    };
+
     for source in &filtered_sources {
         match process_source(
             source,
@@ -151,26 +248,58 @@ pub fn run(db: &Db, manifest_path: &Path, options: &ApplyOptions) -> Result<()>
             options,
             conn,
             manifest.output.archive_root_id,
+            apply_run_id,
+            rclone.as_ref(),
         ) {
-            Ok(action) => match action {
-                ApplyAction::Copied => stats.copied += 1,
-                ApplyAction::Renamed => stats.renamed += 1,
-                ApplyAction::Moved => stats.moved += 1,
-                ApplyAction::SkippedMissing => stats.skipped_missing += 1,
-            },
+            Ok(applied) => {
+                match applied.action {
+                    ApplyAction::Copied => stats.copied += 1,
+                    ApplyAction::Renamed => stats.renamed += 1,
+                    ApplyAction::Moved => stats.moved += 1,
+                    ApplyAction::SkippedMissing => stats.skipped_missing += 1,
+                    ApplyAction::SkippedArchived => stats.skipped_archived += 1,
+                    ApplyAction::SkippedStale => stats.skipped_stale += 1,
+                }
+                if !output::is_json() {
+                    applied.print(options.dry_run);
+                }
+                stats.files.push(applied);
+            }
             Err(e) => {
                 eprintln!("Error processing {}: {}", source.path, e);
                 stats.errors += 1;
+                stats.failed.push(FailedFile {
+                    source: source.path.clone(),
+                    error: format!("{:#}", e),
+                });
             }
         }
     }
 
-    let mode = if options.dry_run { " (dry-run)" } else { "" };
-    println!(
-        "Applied{}: {} copied, {} renamed, {} moved, {} skipped (missing), {} skipped (filtered), {} errors",
-        mode, stats.copied, stats.renamed, stats.moved, stats.skipped_missing, stats.skipped_filtered, stats.errors
-    );
+    output::emit(&stats, |stats| {
+        let mode = if stats.dry_run { " (dry-run)" } else { "" };
+        let archived = if stats.skipped_archived > 0 {
+            format!(", {} skipped (archived)", stats.skipped_archived)
+        } else {
+            String::new()
+        };
+        let stale = if stats.skipped_stale > 0 {
+            format!(", {} skipped (changed)", stats.skipped_stale)
+        } else {
+            String::new()
+        };
+        println!(
+            "Applied{}: {} copied, {} renamed, {} moved, {} skipped (missing), {} skipped (filtered){}{}, {} errors",
+            mode, stats.copied, stats.renamed, stats.moved, stats.skipped_missing, stats.skipped_filtered, archived, stale, stats.errors
+        );
+        if stats.skipped_stale > 0 {
+            println!("Files changed since the manifest was generated; re-scan and regenerate it to archive them");
+        }
+    })?;
 
+    if stats.errors > 0 {
+        return Err(coded(ErrorCode::PartialFailure, format!("{} files failed to apply", stats.errors)));
+    }
     Ok(())
 }
 
@@ -249,28 +378,14 @@ fn check_archive_conflicts_filtered(
     };
 
     for source in sources {
+        // Cheap cache check first; only archived content needs its location looked up
+        if let Some(object_id) = source.object_id {
+            if !db::is_archived(conn, object_id)? {
+                continue;
+            }
+        }
         if let Some(ref hash) = source.hash_value {
-            // Check if this hash exists in any archive
-            let archive_match: Option<(i64, String, String)> = conn
-                .query_row(
-                    "SELECT r.id, r.path, s.rel_path
-                     FROM sources s
-                     JOIN roots r ON s.root_id = r.id
-                     JOIN objects o ON s.object_id = o.id
-                     WHERE r.role = 'archive' AND o.hash_value = ? AND s.present = 1
-                     LIMIT 1",
-                    [hash],
-                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
-                )
-                .optional()?;
-
-            if let Some((archive_id, root_path, rel_path)) = archive_match {
-                let archive_path = if rel_path.is_empty() {
-                    root_path
-                } else {
-                    format!("{}/{}", root_path, rel_path)
-                };
-
+            if let Some((archive_id, archive_path)) = archived_copy(conn, hash)? {
                 if archive_id == dest_archive_id {
                     conflicts.in_dest_archive.push((source.path.clone(), archive_path));
                 } else {
@@ -283,6 +398,63 @@ fn check_archive_conflicts_filtered(
     Ok(conflicts)
 }
 
+/// An archived copy of the content with this hash, as (archive root id, path),
+/// preferring one on an available root
+fn archived_copy(conn: &Connection, hash: &str) -> Result<Option<(i64, String)>> {
+    let archive_match: Option<(i64, String, String, bool)> = conn
+        .query_row(
+            "SELECT r.id, r.path, s.rel_path, r.available
+             FROM sources s
+             JOIN roots r ON s.root_id = r.id
+             JOIN objects o ON s.object_id = o.id
+             WHERE r.role = 'archive' AND o.hash_value = ? AND s.present = 1
+             ORDER BY r.available DESC
+             LIMIT 1",
+            [hash],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .optional()?;
+
+    Ok(archive_match.map(|(archive_id, root_path, rel_path, available)| {
+        let mut archive_path = if rel_path.is_empty() {
+            root_path
+        } else {
+            format!("{}/{}", root_path, rel_path)
+        };
+        if !available {
+            archive_path.push_str(" (offline)");
+        }
+        (archive_id, archive_path)
+    }))
+}
+
+/// Manifest sources whose indexed size or content no longer match the
+/// manifest (changed and rescanned, or rehashed). Sources gone from the
+/// index are left to the missing-file handling.
+fn check_stale_sources_filtered(
+    conn: &Connection,
+    sources: &[&ManifestSource],
+) -> Result<Vec<String>> {
+    let mut stale = Vec::new();
+
+    for source in sources {
+        let current: Option<(i64, Option<i64>)> = conn
+            .query_row(
+                "SELECT size, object_id FROM sources WHERE id = ? AND present = 1",
+                [source.id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        if let Some((size, object_id)) = current {
+            if size != source.size || object_id != source.object_id {
+                stale.push(source.path.clone());
+            }
+        }
+    }
+
+    Ok(stale)
+}
+
 fn check_excluded_sources_filtered(
     conn: &Connection,
     sources: &[&ManifestSource],
@@ -298,13 +470,77 @@ fn check_excluded_sources_filtered(
     Ok(excluded)
 }
 
+fn check_pinned_sources_filtered(
+    conn: &Connection,
+    sources: &[&ManifestSource],
+) -> Result<Vec<(i64, String)>> {
+    let mut pinned = Vec::new();
+
+    for source in sources {
+        if pin::is_pinned(conn, source.id)? {
+            pinned.push((source.id, source.path.clone()));
+        }
+    }
+
+    Ok(pinned)
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
 enum ApplyAction {
     Copied,
     Renamed,
     Moved,
     SkippedMissing,
+    /// Hashed by --hash-missing and found in an archive (`dest` is the archived copy)
+    SkippedArchived,
+    /// Size or mtime differs from the manifest (`reason` says how)
+    SkippedStale,
+}
+
+/// What happened to one manifest source (or would happen, in a dry run)
+#[derive(Serialize)]
+struct AppliedFile {
+    action: ApplyAction,
+    source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dest: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+impl AppliedFile {
+    fn new(action: ApplyAction, source: &ManifestSource, dest: impl std::fmt::Display) -> Self {
+        AppliedFile {
+            action,
+            source: source.path.clone(),
+            dest: Some(dest.to_string()),
+            reason: None,
+        }
+    }
+
+    fn print(&self, dry_run: bool) {
+        let dest = self.dest.as_deref().unwrap_or_default();
+        match (self.action, dry_run) {
+            (ApplyAction::SkippedMissing, true) => println!("SKIP (missing): {}", self.source),
+            (ApplyAction::SkippedMissing, false) => {}
+            (ApplyAction::SkippedArchived, _) => println!("Skipped (already archived): {} == {}", self.source, dest),
+            (ApplyAction::SkippedStale, _) => {
+                println!("Skipped (changed since manifest): {} ({})", self.source, self.reason.as_deref().unwrap_or_default())
+            }
+            (ApplyAction::Copied, true) => println!("COPY: {} -> {}", self.source, dest),
+            (ApplyAction::Renamed, true) => println!("RENAME: {} -> {}", self.source, dest),
+            (ApplyAction::Moved, true) => {
+                println!("MOVE: {} -> {} (may copy if cross-device, trashing the original)", self.source, dest)
+            }
+            (ApplyAction::Copied, false) => println!("Copied: {} -> {}", self.source, dest),
+            (ApplyAction::Renamed, false) => println!("Renamed: {} -> {}", self.source, dest),
+            (ApplyAction::Moved, false) => println!("Moved: {} -> {}", self.source, dest),
+        }
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn process_source(
     source: &ManifestSource,
     pattern: &str,
@@ -313,15 +549,29 @@ fn process_source(
     options: &ApplyOptions,
     conn: &Connection,
     archive_root_id: i64,
-) -> Result<ApplyAction> {
+    apply_run_id: Option<i64>,
+    rclone: Option<&Rclone>,
+) -> Result<AppliedFile> {
     let src_path = Path::new(&source.path);
 
     // Check if source exists
     if !src_path.exists() {
-        if options.dry_run {
-            println!("SKIP (missing): {}", source.path);
-        }
-        return Ok(ApplyAction::SkippedMissing);
+        return Ok(AppliedFile {
+            action: ApplyAction::SkippedMissing,
+            source: source.path.clone(),
+            dest: None,
+            reason: None,
+        });
+    }
+
+    // An edit after the manifest was generated would archive content nobody reviewed
+    if let Some(reason) = stale_reason(source, src_path)? {
+        return Ok(AppliedFile {
+            action: ApplyAction::SkippedStale,
+            source: source.path.clone(),
+            dest: None,
+            reason: Some(reason),
+        });
     }
 
     // Expand pattern to get destination path
@@ -336,20 +586,33 @@ fn process_source(
     };
 
     if options.dry_run {
-        match options.transfer_mode {
-            TransferMode::Copy => {
-                println!("COPY: {} -> {}", source.path, dest_path.display());
-                return Ok(ApplyAction::Copied);
-            }
-            TransferMode::Rename => {
-                println!("RENAME: {} -> {}", source.path, dest_path.display());
-                return Ok(ApplyAction::Renamed);
-            }
-            TransferMode::Move => {
-                println!("MOVE: {} -> {} (will delete source; may copy if cross-device)", source.path, dest_path.display());
-                return Ok(ApplyAction::Moved);
-            }
+        let action = match options.transfer_mode {
+            TransferMode::Copy => ApplyAction::Copied,
+            TransferMode::Rename => ApplyAction::Renamed,
+            TransferMode::Move => ApplyAction::Moved,
+        };
+        return Ok(AppliedFile::new(action, source, dest_path.display()));
+    }
+
+    // --hash-missing: a local copy hashes the bytes as it writes them; other
+    // transfers read the file first, so nothing archived already gets moved
+    let hashed;
+    let source = if options.hash_missing && source.hash_value.is_none() {
+        if rclone.is_none() && options.transfer_mode == TransferMode::Copy {
+            return copy_hashing(source, src_path, &dest_path, &archive_rel_path, options, conn, archive_root_id, apply_run_id);
+        }
+        hashed = link_hash(conn, source, &hash_file(src_path)?)?;
+        if let Some(skipped) = skip_if_archived(conn, &hashed, archive_root_id, options)? {
+            return Ok(skipped);
         }
+        &hashed
+    } else {
+        source
+    };
+
+    if let Some(rclone) = rclone {
+        let dest = dest_path.to_str().context("Path is not valid UTF-8")?;
+        return transfer_remote(rclone, source, src_path, dest, &archive_rel_path, options, conn, archive_root_id, apply_run_id);
     }
 
     // Create parent directories
@@ -369,9 +632,9 @@ fn process_source(
             fs::copy(src_path, &dest_path)
                 .with_context(|| format!("Failed to copy {} to {}", source.path, dest_path.display()))?;
             preserve_metadata(&dest_path, &src_meta)?;
-            register_destination(conn, archive_root_id, &dest_path, &archive_rel_path, source.object_id)?;
-            println!("Copied: {} -> {}", source.path, dest_path.display());
-            Ok(ApplyAction::Copied)
+            let dest_id = register_destination(conn, archive_root_id, &dest_path, &archive_rel_path, source.object_id, apply_run_id)?;
+            record_provenance(conn, apply_run_id, source, dest_id, "copy", false)?;
+            Ok(AppliedFile::new(ApplyAction::Copied, source, dest_path.display()))
         }
         TransferMode::Rename => {
             // Check exists right before rename (noclobber)
@@ -381,9 +644,9 @@ fn process_source(
             // No metadata read needed - rename preserves all attributes
             fs::rename(src_path, &dest_path)
                 .with_context(|| format!("Failed to rename {} to {}", source.path, dest_path.display()))?;
-            register_destination(conn, archive_root_id, &dest_path, &archive_rel_path, source.object_id)?;
-            println!("Renamed: {} -> {}", source.path, dest_path.display());
-            Ok(ApplyAction::Renamed)
+            let dest_id = register_destination(conn, archive_root_id, &dest_path, &archive_rel_path, source.object_id, apply_run_id)?;
+            record_provenance(conn, apply_run_id, source, dest_id, "rename", false)?;
+            Ok(AppliedFile::new(ApplyAction::Renamed, source, dest_path.display()))
         }
         TransferMode::Move => {
             // Check exists right before rename attempt (noclobber)
@@ -393,12 +656,11 @@ fn process_source(
             // Try rename first (mv semantics)
             match fs::rename(src_path, &dest_path) {
                 Ok(()) => {
-                    register_destination(conn, archive_root_id, &dest_path, &archive_rel_path, source.object_id)?;
-                    println!("Renamed: {} -> {}", source.path, dest_path.display());
-                    Ok(ApplyAction::Renamed)
+                    let dest_id = register_destination(conn, archive_root_id, &dest_path, &archive_rel_path, source.object_id, apply_run_id)?;
+                    record_provenance(conn, apply_run_id, source, dest_id, "rename", false)?;
+                    Ok(AppliedFile::new(ApplyAction::Renamed, source, dest_path.display()))
                 }
-                #[cfg(unix)]
-                Err(e) if e.raw_os_error() == Some(libc::EXDEV) => {
+                Err(e) if platform::is_cross_device(&e) => {
                     // Cross-device only: fallback to copy + delete
                     // Re-check dest doesn't exist (race condition guard)
                     if dest_path.exists() {
@@ -409,11 +671,11 @@ fn process_source(
                     fs::copy(src_path, &dest_path)
                         .with_context(|| format!("Failed to copy {} to {}", source.path, dest_path.display()))?;
                     preserve_metadata(&dest_path, &src_meta)?;
-                    fs::remove_file(src_path)
-                        .with_context(|| format!("Failed to delete source: {}", source.path))?;
-                    register_destination(conn, archive_root_id, &dest_path, &archive_rel_path, source.object_id)?;
-                    println!("Moved: {} -> {}", source.path, dest_path.display());
-                    Ok(ApplyAction::Moved)
+                    // The original goes to the trash only once the copy is indexed
+                    let dest_id = register_destination(conn, archive_root_id, &dest_path, &archive_rel_path, source.object_id, apply_run_id)?;
+                    record_provenance(conn, apply_run_id, source, dest_id, "move", false)?;
+                    trash::trash_source(conn, source.id, "apply --move")?;
+                    Ok(AppliedFile::new(ApplyAction::Moved, source, dest_path.display()))
                 }
                 Err(e) => Err(e).with_context(|| {
                     format!("Failed to rename {} to {}", source.path, dest_path.display())
@@ -423,7 +685,308 @@ fn process_source(
     }
 }
 
-#[cfg(unix)]
+/// `--hash-missing` copy of an unhashed source: hash while copying, then
+/// register the copy, or remove it again if the content turns out to be
+/// archived already
+#[allow(clippy::too_many_arguments)]
+fn copy_hashing(
+    source: &ManifestSource,
+    src_path: &Path,
+    dest_path: &Path,
+    archive_rel_path: &str,
+    options: &ApplyOptions,
+    conn: &Connection,
+    archive_root_id: i64,
+    apply_run_id: Option<i64>,
+) -> Result<AppliedFile> {
+    if let Some(parent) = dest_path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+    let src_meta = fs::metadata(src_path)
+        .with_context(|| format!("Failed to read metadata: {}", source.path))?;
+    let hashes = copy_and_hash(src_path, dest_path)
+        .with_context(|| format!("Failed to copy {} to {}", source.path, dest_path.display()))?;
+    preserve_metadata(dest_path, &src_meta)?;
+
+    let source = link_hash(conn, source, &hashes)?;
+    if let Some(skipped) = skip_if_archived(conn, &source, archive_root_id, options)? {
+        fs::remove_file(dest_path)
+            .with_context(|| format!("Failed to remove duplicate copy {}", dest_path.display()))?;
+        return Ok(skipped);
+    }
+    let dest_id = register_destination(conn, archive_root_id, dest_path, archive_rel_path, source.object_id, apply_run_id)?;
+    record_provenance(conn, apply_run_id, &source, dest_id, "copy", false)?;
+    Ok(AppliedFile::new(ApplyAction::Copied, &source, dest_path.display()))
+}
+
+/// Copy `src` to a new file at `dest` (never overwriting), hashing the bytes
+/// on the way
+fn copy_and_hash(src: &Path, dest: &Path) -> Result<ContentHashes> {
+    let mut reader = fs::File::open(src)?;
+    let mut writer = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dest)
+        .with_context(|| format!("Destination already exists or can't be created: {}", dest.display()))?;
+    let mut hasher = ContentHasher::new();
+    let mut buffer = vec![0u8; HASH_BUFFER_SIZE];
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+        writer.write_all(&buffer[..n])?;
+    }
+    writer.sync_all()?;
+    Ok(hasher.finish())
+}
+
+/// Fact key of the quick fingerprint: BLAKE3 of the size and the first and
+/// last `QUICKHASH_SPAN` bytes. Files that differ in it differ in content;
+/// files that share it are worth a full hash.
+pub(crate) const QUICKHASH_KEY: &str = "content.quickhash";
+
+/// Bytes read from each end of a file for its quickhash
+const QUICKHASH_SPAN: usize = 64 * 1024;
+
+/// Hashes of a file's content as hex: SHA-256, which identifies objects, and
+/// BLAKE3 and the quickhash, recorded alongside
+pub(crate) struct ContentHashes {
+    pub sha256: String,
+    pub blake3: String,
+    pub quickhash: String,
+}
+
+/// Computes every `ContentHashes` hash in a single pass over the bytes
+struct ContentHasher {
+    sha256: Sha256,
+    blake3: blake3::Hasher,
+    size: u64,
+    head: Vec<u8>,
+    /// The last `QUICKHASH_SPAN` bytes seen so far
+    tail: Vec<u8>,
+}
+
+impl ContentHasher {
+    fn new() -> Self {
+        ContentHasher {
+            sha256: Sha256::new(),
+            blake3: blake3::Hasher::new(),
+            size: 0,
+            head: Vec::with_capacity(QUICKHASH_SPAN),
+            tail: Vec::with_capacity(QUICKHASH_SPAN),
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        self.sha256.update(bytes);
+        self.blake3.update(bytes);
+        self.size += bytes.len() as u64;
+        let head_room = QUICKHASH_SPAN - self.head.len();
+        self.head.extend_from_slice(&bytes[..bytes.len().min(head_room)]);
+        if bytes.len() >= QUICKHASH_SPAN {
+            self.tail.clear();
+            self.tail.extend_from_slice(&bytes[bytes.len() - QUICKHASH_SPAN..]);
+        } else {
+            self.tail.extend_from_slice(bytes);
+            let excess = self.tail.len().saturating_sub(QUICKHASH_SPAN);
+            self.tail.drain(..excess);
+        }
+    }
+
+    fn finish(self) -> ContentHashes {
+        // The tail never overlaps the head, as in quick_hash_file
+        let tail_len = (self.size - self.head.len() as u64).min(QUICKHASH_SPAN as u64) as usize;
+        let tail = &self.tail[self.tail.len() - tail_len..];
+        ContentHashes {
+            sha256: format!("{:x}", self.sha256.finalize()),
+            blake3: self.blake3.finalize().to_hex().to_string(),
+            quickhash: quickhash(self.size, &self.head, tail),
+        }
+    }
+}
+
+fn quickhash(size: u64, head: &[u8], tail: &[u8]) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&size.to_le_bytes());
+    hasher.update(head);
+    hasher.update(tail);
+    hasher.finalize().to_hex().to_string()
+}
+
+/// SHA-256, BLAKE3 and quickhash of a file, read once
+pub(crate) fn hash_file(path: &Path) -> Result<ContentHashes> {
+    Ok(hash_file_timed(path)?.0)
+}
+
+/// `hash_file`, also returning how much of the time went to waiting for reads
+pub(crate) fn hash_file_timed(path: &Path) -> Result<(ContentHashes, Duration)> {
+    let mut reader = fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = ContentHasher::new();
+    let mut buffer = vec![0u8; HASH_BUFFER_SIZE];
+    let mut reading = Duration::ZERO;
+    loop {
+        let started = Instant::now();
+        let n = reader.read(&mut buffer).with_context(|| format!("Failed to read {}", path.display()))?;
+        reading += started.elapsed();
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+    Ok((hasher.finish(), reading))
+}
+
+/// Quickhash of a file, reading only its ends
+pub(crate) fn quick_hash_file(path: &Path) -> Result<String> {
+    let read = || -> io::Result<String> {
+        let mut file = fs::File::open(path)?;
+        let size = file.metadata()?.len();
+        let head_len = size.min(QUICKHASH_SPAN as u64);
+        let mut head = vec![0u8; head_len as usize];
+        file.read_exact(&mut head)?;
+        let tail_len = (size - head_len).min(QUICKHASH_SPAN as u64);
+        let mut tail = vec![0u8; tail_len as usize];
+        file.seek(SeekFrom::Start(size - tail_len))?;
+        file.read_exact(&mut tail)?;
+        Ok(quickhash(size, &head, &tail))
+    };
+    read().with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// How the file at `src_path` differs from what the manifest recorded, if it
+/// does: its size, or its mtime when the manifest has one
+fn stale_reason(source: &ManifestSource, src_path: &Path) -> Result<Option<String>> {
+    let metadata = fs::metadata(src_path).with_context(|| format!("Failed to read metadata: {}", source.path))?;
+    let size = metadata.len() as i64;
+    if size != source.size {
+        return Ok(Some(format!("size {} -> {}", source.size, size)));
+    }
+    match source.mtime {
+        Some(mtime) if mtime != platform::mtime(&metadata) => {
+            Ok(Some(format!("mtime {} -> {}", mtime, platform::mtime(&metadata))))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Link a freshly hashed source to its object; returns the source as the
+/// manifest would have listed it had it been hashed
+fn link_hash(conn: &Connection, source: &ManifestSource, hashes: &ContentHashes) -> Result<ManifestSource> {
+    let object_id = import_facts::link_hash(conn, source.id, hashes)?;
+    Ok(ManifestSource {
+        object_id: Some(object_id),
+        hash_type: Some("sha256".to_string()),
+        hash_value: Some(hashes.sha256.clone()),
+        ..source.clone()
+    })
+}
+
+/// The archive checks preflight runs for hashed sources, for one source
+/// hashed during apply: content already in the destination archive is always
+/// skipped, content in another archive unless --allow-cross-archive-duplicates
+fn skip_if_archived(
+    conn: &Connection,
+    source: &ManifestSource,
+    dest_archive_id: i64,
+    options: &ApplyOptions,
+) -> Result<Option<AppliedFile>> {
+    let hash = source.hash_value.as_deref().expect("source was just hashed");
+    match archived_copy(conn, hash)? {
+        Some((archive_id, archive_path))
+            if archive_id == dest_archive_id || !options.allow_cross_archive_duplicates =>
+        {
+            Ok(Some(AppliedFile::new(ApplyAction::SkippedArchived, source, archive_path)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Copy (or move) a local file to a remote archive root with `rclone copyto`.
+/// The upload must have the source's size, and is checked against the manifest
+/// hash when the backend reports a SHA-256 (or by downloading it with
+/// `--download-verify`). A move whose upload couldn't be hash-checked keeps
+/// the source, like a copy.
+#[allow(clippy::too_many_arguments)]
+fn transfer_remote(
+    rclone: &Rclone,
+    source: &ManifestSource,
+    src_path: &Path,
+    dest: &str,
+    rel_path: &str,
+    options: &ApplyOptions,
+    conn: &Connection,
+    archive_root_id: i64,
+    apply_run_id: Option<i64>,
+) -> Result<AppliedFile> {
+    let local_size = fs::metadata(src_path)
+        .with_context(|| format!("Failed to read metadata: {}", source.path))?
+        .len() as i64;
+
+    // Check exists right before copy (noclobber)
+    if rclone.stat(dest)?.is_some() {
+        bail!("Destination already exists: {}", dest);
+    }
+    rclone
+        .copyto(src_path, dest)
+        .with_context(|| format!("Failed to copy {} to {}", source.path, dest))?;
+
+    let uploaded = rclone
+        .stat(dest)?
+        .with_context(|| format!("Copied file not found on remote: {}", dest))?;
+    if uploaded.size != local_size {
+        bail!(
+            "Size mismatch after copying {} to {} (expected {} bytes, remote has {})",
+            source.path,
+            dest,
+            local_size,
+            uploaded.size
+        );
+    }
+    let remote_hash = match uploaded.sha256 {
+        Some(ref hash) => Some(hash.clone()),
+        None if options.download_verify => rclone.hashsum(dest, true)?.into_values().next().flatten(),
+        None => None,
+    };
+    let mut verified = false;
+    if let (Some(remote_hash), Some(expected)) = (&remote_hash, &source.hash_value) {
+        verified = true;
+        if remote_hash != expected {
+            bail!(
+                "Hash mismatch after copying {} to {} (expected {}, remote has {})",
+                source.path,
+                dest,
+                expected,
+                remote_hash
+            );
+        }
+    }
+
+    let mode = if options.transfer_mode == TransferMode::Move && !verified {
+        eprintln!(
+            "Warning: Kept {}: its upload to {} could not be checked against a hash (use --download-verify)",
+            source.path, dest
+        );
+        TransferMode::Copy
+    } else {
+        options.transfer_mode
+    };
+
+    let dest_id = register_remote_destination(conn, archive_root_id, rel_path, &uploaded, source.object_id, apply_run_id)?;
+    let transfer = if mode == TransferMode::Move { "move" } else { "copy" };
+    record_provenance(conn, apply_run_id, source, dest_id, transfer, verified)?;
+
+    if mode == TransferMode::Move {
+        trash::trash_source(conn, source.id, "apply --move")?;
+        Ok(AppliedFile::new(ApplyAction::Moved, source, dest))
+    } else {
+        Ok(AppliedFile::new(ApplyAction::Copied, source, dest))
+    }
+}
+
+/// Carry over the mtime and permissions (just the read-only flag on Windows)
 fn preserve_metadata(dest: &Path, src_meta: &Metadata) -> Result<()> {
     use filetime::FileTime;
 
@@ -435,72 +998,84 @@ fn preserve_metadata(dest: &Path, src_meta: &Metadata) -> Result<()> {
     Ok(())
 }
 
-#[cfg(not(unix))]
-fn preserve_metadata(_dest: &Path, _src_meta: &Metadata) -> Result<()> {
-    // No-op on non-Unix
-    Ok(())
-}
-
-#[cfg(unix)]
 fn register_destination(
     conn: &Connection,
     archive_root_id: i64,
     dest_path: &Path,
     rel_path: &str,
     object_id: Option<i64>,
-) -> Result<()> {
+    apply_run_id: Option<i64>,
+) -> Result<i64> {
     let meta = fs::metadata(dest_path)
         .with_context(|| format!("Failed to read metadata for registration: {}", dest_path.display()))?;
-    let device = meta.dev() as i64;
-    let inode = meta.ino() as i64;
-    let size = meta.size() as i64;
-    let mtime = meta.mtime();
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_secs() as i64;
+    let file_id = platform::file_id(dest_path, &meta);
+    let (device, inode) = file_id.map(|file_id| file_id.device_inode()).unzip();
+    let nlink = file_id.map(|file_id| file_id.links);
+    let size = meta.len() as i64;
+    let mtime = platform::mtime(&meta);
+    let btime = platform::btime(&meta);
+    let now = current_timestamp();
 
     conn.execute(
-        "INSERT INTO sources (root_id, rel_path, device, inode, size, mtime,
-         object_id, basis_rev, scanned_at, last_seen_at, present)
-         VALUES (?, ?, ?, ?, ?, ?, ?, 0, ?, ?, 1)",
-        params![archive_root_id, rel_path, device, inode, size, mtime, object_id, now, now],
+        "INSERT INTO sources (root_id, rel_path, device, inode, nlink, size, mtime, btime,
+         object_id, apply_run_id, basis_rev, scanned_at, last_seen_at, present)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 0, ?, ?, 1)",
+        params![archive_root_id, rel_path, device, inode, nlink, size, mtime, btime, object_id, apply_run_id, now, now],
     )?;
-    Ok(())
+    Ok(conn.last_insert_rowid())
 }
 
-#[cfg(not(unix))]
-fn register_destination(
+/// Register a file uploaded to a remote archive root (no device/inode on remotes)
+fn register_remote_destination(
     conn: &Connection,
     archive_root_id: i64,
-    dest_path: &Path,
     rel_path: &str,
+    file: &RemoteFile,
     object_id: Option<i64>,
-) -> Result<()> {
-    let meta = fs::metadata(dest_path)
-        .with_context(|| format!("Failed to read metadata for registration: {}", dest_path.display()))?;
-    let size = meta.len() as i64;
-    let mtime = meta.modified()
-        .ok()
-        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
-        .map(|d| d.as_secs() as i64)
-        .unwrap_or(0);
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_secs() as i64;
-
-    // No device/inode on non-Unix
+    apply_run_id: Option<i64>,
+) -> Result<i64> {
+    let now = current_timestamp();
+
     conn.execute(
         "INSERT INTO sources (root_id, rel_path, size, mtime,
-         object_id, basis_rev, scanned_at, last_seen_at, present)
-         VALUES (?, ?, ?, ?, ?, 0, ?, ?, 1)",
-        params![archive_root_id, rel_path, size, mtime, object_id, now, now],
+         object_id, apply_run_id, basis_rev, scanned_at, last_seen_at, present)
+         VALUES (?, ?, ?, ?, ?, ?, 0, ?, ?, 1)",
+        params![archive_root_id, rel_path, file.size, file.mtime, object_id, apply_run_id, now, now],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Record how a file got into the archive (shown by `canon object show`).
+/// `verified` is whether the copy's hash was checked against the manifest.
+fn record_provenance(
+    conn: &Connection,
+    apply_run_id: Option<i64>,
+    source: &ManifestSource,
+    dest_source_id: i64,
+    transfer: &str,
+    verified: bool,
+) -> Result<()> {
+    let Some(apply_run_id) = apply_run_id else {
+        return Ok(());
+    };
+    let now = current_timestamp();
+    conn.execute(
+        "INSERT INTO apply_provenance (apply_run_id, source_id, object_id, dest_source_id, transfer, verify, applied_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?)",
+        params![
+            apply_run_id,
+            source.id,
+            source.object_id,
+            dest_source_id,
+            transfer,
+            if verified { "hash-match" } else { "unverified" },
+            now
+        ],
     )?;
     Ok(())
 }
 
-fn expand_pattern(pattern: &str, source: &ManifestSource, src_path: &Path) -> Result<String> {
+pub(crate) fn expand_pattern(pattern: &str, source: &ManifestSource, src_path: &Path) -> Result<String> {
     let mut result = pattern.to_string();
 
     // Build substitution map
@@ -524,19 +1099,26 @@ fn expand_pattern(pattern: &str, source: &ManifestSource, src_path: &Path) -> Re
         vars.insert("hash_short", hash.chars().take(8).collect());
     }
 
-    // Date/time from facts (if available)
-    if let Some(dt) = source.facts.get("exif.datetime_original") {
-        if let Some(ts) = dt.as_i64() {
-            let dt = chrono::DateTime::from_timestamp(ts, 0);
-            if let Some(dt) = dt {
-                vars.insert("year", dt.format("%Y").to_string());
-                vars.insert("month", dt.format("%m").to_string());
-                vars.insert("day", dt.format("%d").to_string());
-                vars.insert("date", dt.format("%Y-%m-%d").to_string());
-            }
+    // Capture date from facts (if available): EXIF, then video creation time
+    if let Some(ts) = capture_time(source) {
+        let dt = chrono::DateTime::from_timestamp(ts, 0);
+        if let Some(dt) = dt {
+            vars.insert("year", dt.format("%Y").to_string());
+            vars.insert("month", dt.format("%m").to_string());
+            vars.insert("day", dt.format("%d").to_string());
+            vars.insert("date", dt.format("%Y-%m-%d").to_string());
         }
     }
 
+    // Burst placement (empty for sources that aren't part of a burst)
+    let (burst, burst_index, burst_size) = match source.burst {
+        Some(ref burst) => (burst.id.clone(), burst.index.to_string(), burst.size.to_string()),
+        None => Default::default(),
+    };
+    vars.insert("burst", burst);
+    vars.insert("burst_index", burst_index);
+    vars.insert("burst_size", burst_size);
+
     // Add all facts as variables
     for (key, value) in &source.facts {
         let str_value = match value {
@@ -571,10 +1153,15 @@ fn expand_pattern(pattern: &str, source: &ManifestSource, src_path: &Path) -> Re
         }
     }
 
-    // Sanitize path (remove potentially dangerous characters)
+    // Sanitize path (remove potentially dangerous characters), and drop the
+    // empty segments placeholders like {burst} leave behind
     let result = result
         .replace("..", "_")
-        .replace('\0', "_");
+        .replace('\0', "_")
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>()
+        .join("/");
 
     Ok(result)
 }
@@ -0,0 +1,355 @@
+use anyhow::{bail, Context, Result};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::db::{parse_root_spec, root_available, Connection, Db};
+use crate::error::{coded, ErrorCode};
+use crate::import_facts;
+use crate::output;
+use crate::remote;
+use crate::roots;
+use crate::util::current_timestamp;
+
+/// Value of the header's `format` field
+const FORMAT: &str = "canon-catalog";
+const VERSION: u32 = 1;
+
+/// First line of a catalog: the archive root it describes
+#[derive(Serialize, Deserialize)]
+struct Header {
+    format: String,
+    version: u32,
+    root: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    exported_at: i64,
+}
+
+/// One archived file (every line after the header)
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    path: String,
+    size: i64,
+    mtime: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+    /// Content facts of the file's object, plus its current source facts
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    facts: BTreeMap<String, Value>,
+}
+
+// ============================================================================
+// canon export --format catalog
+// ============================================================================
+
+/// Write a catalog of an archive root: every present file's path, hash, size
+/// and content facts, as JSONL (gzipped when `output` ends in `.gz`). Another
+/// index can load it with `canon import-catalog` and search or coverage-check
+/// the archive without the drive.
+pub fn export(db: &Db, archive_spec: &str, output: Option<&Path>) -> Result<()> {
+    let conn = db.conn();
+    let root_id = parse_root_spec(conn, archive_spec, Some("archive"))?;
+    let root_path: String = conn.query_row("SELECT path FROM roots WHERE id = ?", [root_id], |row| row.get(0))?;
+
+    let header = Header {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        root: root_path.clone(),
+        label: roots::setting(conn, root_id, "label")?,
+        exported_at: current_timestamp(),
+    };
+
+    let count = match output {
+        Some(path) => {
+            let file = File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+            let count = if path.extension().is_some_and(|ext| ext == "gz") {
+                let mut encoder = GzEncoder::new(BufWriter::new(file), Compression::default());
+                let count = write_catalog(conn, root_id, &header, &mut encoder)?;
+                encoder.finish()?.flush()?;
+                count
+            } else {
+                let mut writer = BufWriter::new(file);
+                let count = write_catalog(conn, root_id, &header, &mut writer)?;
+                writer.flush()?;
+                count
+            };
+            eprintln!("Exported {} files from {} to {}", count, root_path, path.display());
+            count
+        }
+        None => {
+            let mut writer = BufWriter::new(io::stdout().lock());
+            let count = write_catalog(conn, root_id, &header, &mut writer)?;
+            writer.flush()?;
+            count
+        }
+    };
+    if count == 0 {
+        eprintln!("Warning: {} has no indexed files", root_path);
+    }
+    Ok(())
+}
+
+/// Write the header and one entry per present source of the root; returns the
+/// number of entries
+fn write_catalog<W: Write>(conn: &Connection, root_id: i64, header: &Header, writer: &mut W) -> Result<u64> {
+    writeln!(writer, "{}", serde_json::to_string(header)?)?;
+
+    // Value columns first, as `import_facts::stored_value` expects
+    let mut object_facts = conn.prepare(
+        "SELECT value_text, value_num, value_time, value_json, key FROM facts
+         WHERE entity_type = 'object' AND entity_id = ? AND key LIKE 'content.%'",
+    )?;
+    let mut source_facts = conn.prepare(
+        "SELECT value_text, value_num, value_time, value_json, key FROM facts
+         WHERE entity_type = 'source' AND entity_id = ? AND observed_basis_rev = ? AND key LIKE 'content.%'",
+    )?;
+
+    let mut stmt = conn.prepare(
+        "SELECT s.id, s.rel_path, s.size, s.mtime, s.basis_rev, s.object_id, o.hash_value
+         FROM sources s LEFT JOIN objects o ON s.object_id = o.id
+         WHERE s.root_id = ? AND s.present = 1
+         ORDER BY s.rel_path",
+    )?;
+    let sources = stmt.query_map([root_id], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, i64>(2)?,
+            row.get::<_, i64>(3)?,
+            row.get::<_, i64>(4)?,
+            row.get::<_, Option<i64>>(5)?,
+            row.get::<_, Option<String>>(6)?,
+        ))
+    })?;
+
+    let mut count = 0u64;
+    for source in sources {
+        let (source_id, rel_path, size, mtime, basis_rev, object_id, hash) = source?;
+        let mut facts = BTreeMap::new();
+        // Object facts describe the content and win over source facts
+        let fact = |row: &rusqlite::Row| Ok((row.get::<_, String>(4)?, import_facts::stored_value(row)?));
+        for row in source_facts.query_map(params![source_id, basis_rev], fact)? {
+            let (key, value) = row?;
+            facts.insert(key, value);
+        }
+        if let Some(object_id) = object_id {
+            for row in object_facts.query_map([object_id], fact)? {
+                let (key, value) = row?;
+                facts.insert(key, value);
+            }
+        }
+
+        let entry = Entry { path: rel_path, size, mtime, hash, facts };
+        writeln!(writer, "{}", serde_json::to_string(&entry)?)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+// ============================================================================
+// canon import-catalog
+// ============================================================================
+
+/// Result of `canon import-catalog`
+#[derive(Serialize)]
+struct ImportReport {
+    root: String,
+    root_id: i64,
+    /// Whether the root was registered by this import
+    created_root: bool,
+    files: u64,
+    new: u64,
+    updated: u64,
+    /// Files indexed before that the catalog no longer lists (marked missing)
+    missing: u64,
+    facts: u64,
+}
+
+/// Load a catalog written by `canon export --format catalog` (plain or
+/// gzipped), registering its archive root if needed. The root stays
+/// unavailable here, so its files count for coverage and show up in queries,
+/// but nothing tries to read them.
+pub fn import(db: &Db, path: &Path) -> Result<()> {
+    let conn = db.conn();
+    let mut reader = open(path)?;
+
+    let mut line = String::new();
+    reader.read_line(&mut line).with_context(|| format!("Failed to read {}", path.display()))?;
+    let header: Header = serde_json::from_str(&line)
+        .ok()
+        .filter(|header: &Header| header.format == FORMAT)
+        .ok_or_else(|| coded(ErrorCode::Usage, format!("{} is not a canon catalog", path.display())))?;
+    if header.version > VERSION {
+        bail!("{} is a version {} catalog; this canon reads up to version {}", path.display(), header.version, VERSION);
+    }
+
+    let tx = conn.unchecked_transaction()?;
+    let (root_id, created_root) = catalog_root(&tx, &header)?;
+    let now = current_timestamp();
+    let mut report = ImportReport {
+        root: header.root.clone(),
+        root_id,
+        created_root,
+        files: 0,
+        new: 0,
+        updated: 0,
+        missing: 0,
+        facts: 0,
+    };
+
+    let mut seen = HashSet::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line.with_context(|| format!("Failed to read {}", path.display()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: Entry = serde_json::from_str(&line)
+            .with_context(|| format!("{}: invalid entry on line {}", path.display(), number + 2))?;
+        let source_id = upsert_source(&tx, root_id, &entry, now, &mut report)?;
+        seen.insert(source_id);
+
+        let object_id = match entry.hash {
+            Some(ref hash) => {
+                remote::link_object(&tx, source_id, hash)?;
+                tx.query_row("SELECT object_id FROM sources WHERE id = ?", [source_id], |row| row.get(0))?
+            }
+            None => {
+                tx.execute("UPDATE sources SET object_id = NULL WHERE id = ?", [source_id])?;
+                None
+            }
+        };
+        let basis_rev: i64 = tx.query_row("SELECT basis_rev FROM sources WHERE id = ?", [source_id], |row| row.get(0))?;
+        for (key, value) in &entry.facts {
+            if !key.starts_with("content.") {
+                continue;
+            }
+            match object_id {
+                Some(object_id) => import_facts::insert_fact(&tx, "object", object_id, key, value, now, None)?,
+                None => import_facts::insert_fact(&tx, "source", source_id, key, value, now, Some(basis_rev))?,
+            }
+            report.facts += 1;
+        }
+        report.files += 1;
+    }
+
+    let indexed: Vec<i64> = tx
+        .prepare("SELECT id FROM sources WHERE root_id = ? AND present = 1")?
+        .query_map([root_id], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    for id in indexed.into_iter().filter(|id| !seen.contains(id)) {
+        tx.execute("UPDATE sources SET present = 0, last_seen_at = ? WHERE id = ?", params![now, id])?;
+        report.missing += 1;
+    }
+    tx.commit()?;
+
+    output::emit(&report, |report| {
+        if report.created_root {
+            println!("Registered archive root {} ({}), offline", report.root_id, report.root);
+        }
+        println!(
+            "Imported {} files: {} new, {} updated, {} missing, {} facts",
+            report.files, report.new, report.updated, report.missing, report.facts
+        );
+    })
+}
+
+/// The catalog's archive root, registered (unavailable) if this index doesn't
+/// know it yet. A root that is mounted here should be scanned instead.
+fn catalog_root(conn: &Connection, header: &Header) -> Result<(i64, bool)> {
+    let existing: Option<(i64, String, Option<String>)> = conn
+        .query_row(
+            "SELECT id, role, mount_point FROM roots WHERE path = ?",
+            [&header.root],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?;
+
+    if let Some((root_id, role, mount_point)) = existing {
+        if role != "archive" {
+            return Err(coded(
+                ErrorCode::Usage,
+                format!("{} is a {} root here; catalogs describe archive roots", header.root, role),
+            ));
+        }
+        if !remote::is_remote(&header.root) && root_available(&header.root, mount_point.as_deref()) {
+            return Err(coded(
+                ErrorCode::Usage,
+                format!("{} is available here; scan it instead (canon scan {})", header.root, header.root),
+            ));
+        }
+        return Ok((root_id, false));
+    }
+
+    conn.execute(
+        "INSERT INTO roots (path, role, available) VALUES (?, 'archive', ?)",
+        params![header.root, remote::is_remote(&header.root)],
+    )?;
+    let root_id = conn.last_insert_rowid();
+    if let Some(ref label) = header.label {
+        conn.execute(
+            "INSERT INTO root_settings (root_id, key, value) VALUES (?, 'label', ?)",
+            params![root_id, label],
+        )?;
+    }
+    Ok((root_id, true))
+}
+
+/// Index a catalog entry as scan would a file: new, updated (size or mtime
+/// changed, which bumps basis_rev) or unchanged
+fn upsert_source(conn: &Connection, root_id: i64, entry: &Entry, now: i64, report: &mut ImportReport) -> Result<i64> {
+    let existing: Option<(i64, i64, i64, i64)> = conn
+        .query_row(
+            "SELECT id, size, mtime, basis_rev FROM sources WHERE root_id = ? AND rel_path = ?",
+            params![root_id, entry.path],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .optional()?;
+
+    match existing {
+        Some((id, size, mtime, basis_rev)) => {
+            let changed = size != entry.size || mtime != entry.mtime;
+            if changed {
+                report.updated += 1;
+            }
+            conn.execute(
+                "UPDATE sources SET size = ?, mtime = ?, basis_rev = ?, last_seen_at = ?, present = 1 WHERE id = ?",
+                params![entry.size, entry.mtime, if changed { basis_rev + 1 } else { basis_rev }, now, id],
+            )?;
+            Ok(id)
+        }
+        None => {
+            conn.execute(
+                "INSERT INTO sources (root_id, rel_path, size, mtime, basis_rev, scanned_at, last_seen_at, present)
+                 VALUES (?, ?, ?, ?, 0, ?, ?, 1)",
+                params![root_id, entry.path, entry.size, entry.mtime, now, now],
+            )?;
+            report.new += 1;
+            Ok(conn.last_insert_rowid())
+        }
+    }
+}
+
+// ============================================================================
+// Helper Functions
+// ============================================================================
+
+/// Open a catalog, decompressing it if it is gzipped (whatever its name)
+fn open(path: &Path) -> Result<Box<dyn BufRead>> {
+    let mut file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut magic = [0u8; 2];
+    let gzipped = file.read(&mut magic)? == 2 && magic == [0x1f, 0x8b];
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    Ok(if gzipped {
+        Box::new(BufReader::new(MultiGzDecoder::new(file)))
+    } else {
+        Box::new(BufReader::new(file))
+    })
+}
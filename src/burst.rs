@@ -0,0 +1,170 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+use crate::cluster::{capture_time, ManifestSource};
+use crate::sort::PathOrder;
+
+/// How `cluster generate --group-by` groups sources
+#[derive(Clone, Copy, ValueEnum)]
+pub enum GroupBy {
+    /// Photos from the same camera taken within --gap of each other
+    Burst,
+}
+
+/// A source's place in a burst, recorded in the manifest for `{burst}`,
+/// `{burst_index}` and `{burst_size}`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Burst {
+    /// "burst-20240612-143005": capture time of the burst's first frame
+    pub id: String,
+    /// 1-based position in capture order
+    pub index: usize,
+    /// Frames in the burst (before --burst-keep dropped any)
+    pub size: usize,
+}
+
+/// Which frames of a burst stay in the manifest
+#[derive(Clone)]
+pub enum BurstKeep {
+    All,
+    /// The earliest frame
+    First,
+    /// The largest file
+    Largest,
+    /// The frame with the highest value of this fact (numbers, or true/yes
+    /// for selection flags); frames without it rank last
+    Fact(String),
+}
+
+impl BurstKeep {
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(match s {
+            "all" => BurstKeep::All,
+            "first" => BurstKeep::First,
+            "largest" => BurstKeep::Largest,
+            key if key.contains('.') => BurstKeep::Fact(key.to_string()),
+            _ => bail!("Invalid --burst-keep '{}'. Use all, first, largest or a fact key (e.g. quality.score)", s),
+        })
+    }
+}
+
+pub struct BurstOptions {
+    /// Longest interval between consecutive frames of a burst, in seconds
+    pub gap: i64,
+    pub keep: BurstKeep,
+}
+
+/// Parse a burst gap like "2s", "1m" or plain seconds. Capture times have
+/// one-second resolution, so that is the smallest meaningful gap.
+pub fn parse_gap(s: &str) -> Result<i64> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: i64 = number.parse().with_context(|| format!("Invalid gap '{}'", s))?;
+    let multiplier = match unit.trim() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        _ => bail!("Invalid gap unit in '{}'. Use s, m or h", s),
+    };
+    Ok(number * multiplier)
+}
+
+/// Group sources into bursts (same camera make/model, each frame within `gap`
+/// of the previous one), mark their `burst`, and drop frames per `keep`.
+/// Sources without a capture time are never part of a burst, and frames taken
+/// in the same second are numbered in `order` of their paths.
+/// Returns (bursts found, frames dropped).
+pub fn group(sources: &mut Vec<ManifestSource>, options: &BurstOptions, order: PathOrder) -> (usize, usize) {
+    // Timed sources per camera, in capture order
+    let mut cameras: BTreeMap<String, Vec<(i64, usize)>> = BTreeMap::new();
+    for (i, source) in sources.iter().enumerate() {
+        if let Some(time) = capture_time(source) {
+            cameras.entry(camera(source)).or_default().push((time, i));
+        }
+    }
+
+    let mut bursts: Vec<Vec<usize>> = Vec::new();
+    for frames in cameras.values_mut() {
+        frames.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| order.compare(&sources[a.1].path, &sources[b.1].path)));
+        let mut current: Vec<usize> = Vec::new();
+        let mut last_time = i64::MIN;
+        for &(time, i) in frames.iter() {
+            if !current.is_empty() && time - last_time > options.gap {
+                bursts.push(std::mem::take(&mut current));
+            }
+            current.push(i);
+            last_time = time;
+        }
+        bursts.push(current);
+    }
+    bursts.retain(|frames| frames.len() > 1);
+
+    let mut used_ids = HashSet::new();
+    let mut dropped = HashSet::new();
+    for frames in &bursts {
+        let first_time = capture_time(&sources[frames[0]]).unwrap_or_default();
+        let base = chrono::DateTime::from_timestamp(first_time, 0)
+            .map(|dt| dt.format("burst-%Y%m%d-%H%M%S").to_string())
+            .unwrap_or_else(|| "burst".to_string());
+        // Bursts from two cameras can start in the same second
+        let mut id = base.clone();
+        let mut n = 1;
+        while !used_ids.insert(id.clone()) {
+            n += 1;
+            id = format!("{}-{}", base, n);
+        }
+
+        for (position, &i) in frames.iter().enumerate() {
+            sources[i].burst = Some(Burst { id: id.clone(), index: position + 1, size: frames.len() });
+        }
+
+        if let Some(kept) = kept_frame(sources, frames, &options.keep) {
+            dropped.extend(frames.iter().copied().filter(|&i| i != kept));
+        }
+    }
+
+    let mut i = 0;
+    sources.retain(|_| {
+        let keep = !dropped.contains(&i);
+        i += 1;
+        keep
+    });
+
+    (bursts.len(), dropped.len())
+}
+
+/// The one frame to keep, or None to keep them all
+fn kept_frame(sources: &[ManifestSource], frames: &[usize], keep: &BurstKeep) -> Option<usize> {
+    match keep {
+        BurstKeep::All => None,
+        BurstKeep::First => frames.first().copied(),
+        // max_by_key keeps the last of equal maxima; iterate in reverse so ties go to the earliest frame
+        BurstKeep::Largest => frames.iter().rev().copied().max_by_key(|&i| sources[i].size),
+        BurstKeep::Fact(key) => frames
+            .iter()
+            .rev()
+            .copied()
+            .max_by(|&a, &b| score(&sources[a], key).total_cmp(&score(&sources[b], key))),
+    }
+}
+
+fn score(source: &ManifestSource, key: &str) -> f64 {
+    match source.facts.get(key) {
+        Some(serde_json::Value::Number(n)) => n.as_f64().unwrap_or(f64::NEG_INFINITY),
+        Some(serde_json::Value::Bool(b)) => f64::from(u8::from(*b)),
+        Some(serde_json::Value::String(s)) => match s.to_ascii_lowercase().as_str() {
+            "true" | "yes" => 1.0,
+            "false" | "no" => 0.0,
+            other => other.parse().unwrap_or(f64::NEG_INFINITY),
+        },
+        _ => f64::NEG_INFINITY,
+    }
+}
+
+fn camera(source: &ManifestSource) -> String {
+    let field = |key: &str| source.facts.get(key).and_then(|v| v.as_str()).unwrap_or_default().to_string();
+    format!("{}\0{}", field("content.exif.make"), field("content.exif.model"))
+}
@@ -0,0 +1,384 @@
+use anyhow::{bail, Context, Result};
+use rusqlite::{Connection, OpenFlags};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+use crate::db::Db;
+use crate::import_facts::{FactImport, Importer};
+use crate::util::current_timestamp;
+
+/// Photo managers whose catalogs `canon import-facts --format` reads
+#[derive(Clone, Copy)]
+pub enum CatalogKind {
+    /// digiKam's `digikam4.db`
+    Digikam,
+    /// Lightroom Classic's `.lrcat`
+    Lightroom,
+}
+
+/// One image in a catalog, with the curation to import for it
+struct CatalogEntry {
+    path: String,
+    /// File size, when the catalog records it (used to match moved files)
+    size: Option<i64>,
+    keywords: BTreeSet<String>,
+    people: BTreeSet<String>,
+    rating: Option<i64>,
+    pick: Option<&'static str>,
+    label: Option<String>,
+}
+
+impl CatalogEntry {
+    fn new(path: String, size: Option<i64>) -> Self {
+        CatalogEntry {
+            path,
+            size,
+            keywords: BTreeSet::new(),
+            people: BTreeSet::new(),
+            rating: None,
+            pick: None,
+            label: None,
+        }
+    }
+
+    /// Facts under `catalog.*` (imported as `content.catalog.*`)
+    fn facts(&self) -> HashMap<String, Value> {
+        let mut facts = HashMap::new();
+        if !self.keywords.is_empty() {
+            facts.insert("catalog.keywords".to_string(), Value::from(self.keywords.iter().cloned().collect::<Vec<_>>()));
+        }
+        if !self.people.is_empty() {
+            facts.insert("catalog.people".to_string(), Value::from(self.people.iter().cloned().collect::<Vec<_>>()));
+        }
+        if let Some(rating) = self.rating {
+            facts.insert("catalog.rating".to_string(), Value::from(rating));
+        }
+        if let Some(pick) = self.pick {
+            facts.insert("catalog.pick".to_string(), Value::from(pick));
+        }
+        if let Some(ref label) = self.label {
+            facts.insert("catalog.label".to_string(), Value::from(label.clone()));
+        }
+        facts
+    }
+}
+
+/// Import keywords, people, ratings (and Lightroom picks and color labels) from
+/// a catalog, matching its images to sources by path after applying
+/// `path_maps` (`OLD=NEW` prefix rewrites). Images the catalog records a size
+/// for are also matched by file name and size when the name+size pair is
+/// unique, so files moved since they were catalogued are still found.
+pub fn import(db: &Db, kind: CatalogKind, catalog_path: &Path, path_maps: &[String], allow_archived: bool) -> Result<()> {
+    let path_maps = path_maps
+        .iter()
+        .map(|m| match m.split_once('=') {
+            Some((old, new)) if !old.is_empty() => Ok((old.to_string(), new.to_string())),
+            _ => bail!("Invalid --path-map '{}'. Use OLD=NEW", m),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let catalog = Connection::open_with_flags(catalog_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Failed to open catalog: {}", catalog_path.display()))?;
+    let entries = match kind {
+        CatalogKind::Digikam => read_digikam(&catalog),
+        CatalogKind::Lightroom => read_lightroom(&catalog),
+    }
+    .with_context(|| format!("Failed to read catalog: {}", catalog_path.display()))?;
+
+    let conn = db.conn();
+    let mut by_path: HashMap<String, (i64, i64)> = HashMap::new();
+    let mut by_name_size: HashMap<(String, i64), Vec<(i64, i64)>> = HashMap::new();
+    let mut stmt = conn.prepare(
+        "SELECT s.id, s.basis_rev, r.path, s.rel_path, s.size
+         FROM sources s JOIN roots r ON s.root_id = r.id
+         WHERE s.present = 1",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, String>(2)?, row.get::<_, String>(3)?, row.get::<_, i64>(4)?))
+    })?;
+    for row in rows {
+        let (id, basis_rev, root_path, rel_path, size) = row?;
+        let name = rel_path.rsplit('/').next().unwrap_or_default().to_string();
+        by_name_size.entry((name, size)).or_default().push((id, basis_rev));
+        by_path.insert(format!("{}/{}", root_path, rel_path), (id, basis_rev));
+    }
+
+    let observed_at = current_timestamp();
+    let mut importer = Importer::new(db, allow_archived);
+    let (mut by_path_count, mut by_name_count, mut unmatched, mut empty) = (0u64, 0u64, 0u64, 0u64);
+    for entry in &entries {
+        let facts = entry.facts();
+        if facts.is_empty() {
+            empty += 1;
+            continue;
+        }
+
+        let path = map_path(&entry.path, &path_maps);
+        let source = match by_path.get(&path) {
+            Some(&source) => {
+                by_path_count += 1;
+                Some(source)
+            }
+            None => {
+                let name = path.rsplit('/').next().unwrap_or_default().to_string();
+                match entry.size.and_then(|size| by_name_size.get(&(name, size))) {
+                    Some(candidates) if candidates.len() == 1 => {
+                        by_name_count += 1;
+                        Some(candidates[0])
+                    }
+                    _ => None,
+                }
+            }
+        };
+        let Some((source_id, basis_rev)) = source else {
+            unmatched += 1;
+            continue;
+        };
+
+        importer.import(&FactImport { source_id, basis_rev, observed_at, facts });
+    }
+
+    let stats = importer.finish();
+    println!(
+        "Read {} catalog images: {} matched by path, {} by name and size, {} unmatched, {} without keywords, people or ratings",
+        entries.len(),
+        by_path_count,
+        by_name_count,
+        unmatched,
+        empty
+    );
+    println!(
+        "Imported {} facts, {} skipped (archived)",
+        stats.facts_imported, stats.skipped_archived
+    );
+    if unmatched > 0 && path_maps.is_empty() {
+        eprintln!("Note: Use --path-map OLD=NEW if the catalog refers to the files by other paths");
+    }
+    Ok(())
+}
+
+/// Apply the longest matching `OLD=NEW` prefix rewrite
+fn map_path(path: &str, path_maps: &[(String, String)]) -> String {
+    path_maps
+        .iter()
+        .filter(|(old, _)| path.starts_with(old.as_str()))
+        .max_by_key(|(old, _)| old.len())
+        .map(|(old, new)| format!("{}{}", new, &path[old.len()..]))
+        .unwrap_or_else(|| path.to_string())
+}
+
+// ============================================================================
+// digiKam
+// ============================================================================
+
+/// Root of digiKam's own bookkeeping tags (pick/color labels, face states)
+const DIGIKAM_INTERNAL_TAGS: &str = "_Digikam_Internal_Tags_";
+
+fn read_digikam(catalog: &Connection) -> Result<Vec<CatalogEntry>> {
+    // Tag tree, and which tags name people
+    let tags: HashMap<i64, (i64, String)> = catalog
+        .prepare("SELECT id, pid, name FROM Tags")?
+        .query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?
+        .collect::<Result<_, _>>()?;
+    let person_tags: HashMap<i64, String> = catalog
+        .prepare("SELECT tagid, property FROM TagProperties WHERE property IN ('person', 'unknownPerson', 'unconfirmedPerson')")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let mut entries: HashMap<i64, CatalogEntry> = HashMap::new();
+    let mut stmt = catalog.prepare(
+        "SELECT i.id, ar.identifier, ar.specificPath, a.relativePath, i.name, i.fileSize, ii.rating
+         FROM Images i
+         JOIN Albums a ON i.album = a.id
+         JOIN AlbumRoots ar ON a.albumRoot = ar.id
+         LEFT JOIN ImageInformation ii ON ii.imageid = i.id
+         WHERE i.status = 1",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, Option<String>>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, String>(4)?,
+            row.get::<_, Option<i64>>(5)?,
+            row.get::<_, Option<i64>>(6)?,
+        ))
+    })?;
+    for row in rows {
+        let (id, identifier, specific_path, relative_path, name, size, rating) = row?;
+        let root = digikam_root_path(&identifier, specific_path.as_deref().unwrap_or("/"));
+        let path = join_path(&[&root, &relative_path, &name]);
+        let mut entry = CatalogEntry::new(path, size);
+        // digiKam stores -1 for "no rating" and 0 for zero stars
+        entry.rating = rating.filter(|&r| r > 0);
+        entries.insert(id, entry);
+    }
+
+    let mut stmt = catalog.prepare("SELECT imageid, tagid FROM ImageTags")?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)))?;
+    for row in rows {
+        let (image_id, tag_id) = row?;
+        let (Some(entry), Some((_, name))) = (entries.get_mut(&image_id), tags.get(&tag_id)) else {
+            continue;
+        };
+        match person_tags.get(&tag_id).map(String::as_str) {
+            Some("person") => {
+                entry.people.insert(name.clone());
+            }
+            // Faces digiKam found but nobody named
+            Some(_) => {}
+            None if !digikam_tag_under(&tags, tag_id, DIGIKAM_INTERNAL_TAGS) => {
+                entry.keywords.insert(name.clone());
+            }
+            None => {}
+        }
+    }
+
+    Ok(entries.into_values().collect())
+}
+
+/// The directory an album root stands for. Identifiers look like
+/// `volumeid:?uuid=...` (specificPath is relative to that volume's mount
+/// point, assumed to be `/`), `volumeid:?path=/photos` or
+/// `networkshareid:?mountpath=/mnt/share`.
+fn digikam_root_path(identifier: &str, specific_path: &str) -> String {
+    let query = identifier.split_once('?').map(|(_, q)| q).unwrap_or_default();
+    let base = query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("path=").or_else(|| pair.strip_prefix("mountpath=")))
+        .map(percent_decode);
+    match base {
+        Some(base) => join_path(&[&base, specific_path]),
+        None => join_path(&[specific_path]),
+    }
+}
+
+fn digikam_tag_under(tags: &HashMap<i64, (i64, String)>, mut tag_id: i64, root_name: &str) -> bool {
+    // Bounded walk, in case of a corrupt (cyclic) tag tree
+    for _ in 0..64 {
+        match tags.get(&tag_id) {
+            Some((parent, name)) => {
+                if name == root_name {
+                    return true;
+                }
+                tag_id = *parent;
+            }
+            None => return false,
+        }
+    }
+    false
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok()).and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// ============================================================================
+// Lightroom
+// ============================================================================
+
+fn read_lightroom(catalog: &Connection) -> Result<Vec<CatalogEntry>> {
+    let mut entries: HashMap<i64, CatalogEntry> = HashMap::new();
+    // Virtual copies (masterImage set) share the file of their master
+    let mut stmt = catalog.prepare(
+        "SELECT i.id_local, rf.absolutePath, fo.pathFromRoot, fi.baseName, fi.extension, i.rating, i.pick, i.colorLabels
+         FROM Adobe_images i
+         JOIN AgLibraryFile fi ON i.rootFile = fi.id_local
+         JOIN AgLibraryFolder fo ON fi.folder = fo.id_local
+         JOIN AgLibraryRootFolder rf ON fo.rootFolder = rf.id_local
+         WHERE i.masterImage IS NULL",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, Option<String>>(4)?,
+            row.get::<_, Option<f64>>(5)?,
+            row.get::<_, Option<f64>>(6)?,
+            row.get::<_, Option<String>>(7)?,
+        ))
+    })?;
+    for row in rows {
+        let (id, root, folder, base_name, extension, rating, pick, label) = row?;
+        let name = match extension.as_deref() {
+            Some(ext) if !ext.is_empty() => format!("{}.{}", base_name, ext),
+            _ => base_name,
+        };
+        let mut entry = CatalogEntry::new(join_path(&[&root, &folder, &name]), None);
+        entry.rating = rating.map(|r| r as i64).filter(|&r| r > 0);
+        entry.pick = match pick {
+            Some(p) if p > 0.0 => Some("picked"),
+            Some(p) if p < 0.0 => Some("rejected"),
+            _ => None,
+        };
+        entry.label = label.filter(|l| !l.is_empty());
+        entries.insert(id, entry);
+    }
+
+    // Lightroom 6 and later mark people keywords with keywordType
+    let has_type: bool = catalog.query_row(
+        "SELECT EXISTS(SELECT 1 FROM pragma_table_info('AgLibraryKeyword') WHERE name = 'keywordType')",
+        [],
+        |row| row.get(0),
+    )?;
+    let type_column = if has_type { "k.keywordType" } else { "NULL" };
+    let mut stmt = catalog.prepare(&format!(
+        "SELECT ki.image, k.name, {}
+         FROM AgLibraryKeywordImage ki
+         JOIN AgLibraryKeyword k ON ki.tag = k.id_local
+         WHERE k.name IS NOT NULL",
+        type_column
+    ))?;
+    let rows = stmt.query_map([], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?))
+    })?;
+    for row in rows {
+        let (image_id, name, keyword_type) = row?;
+        let Some(entry) = entries.get_mut(&image_id) else {
+            continue;
+        };
+        if keyword_type.as_deref() == Some("person") {
+            entry.people.insert(name);
+        } else {
+            entry.keywords.insert(name);
+        }
+    }
+
+    Ok(entries.into_values().collect())
+}
+
+/// Join path parts with single slashes (catalog parts come with and without
+/// leading/trailing ones). Windows drive paths (`C:/Photos`) keep no leading slash.
+fn join_path(parts: &[&str]) -> String {
+    let joined = parts
+        .iter()
+        .flat_map(|part| part.split(['/', '\\']))
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>()
+        .join("/");
+    if joined.as_bytes().get(1) == Some(&b':') {
+        joined
+    } else {
+        format!("/{}", joined)
+    }
+}
@@ -1,14 +1,16 @@
-use anyhow::{Context, Result};
-use rusqlite::OptionalExtension;
+use anyhow::{bail, Context, Result};
+use rusqlite::{params, OptionalExtension};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::path::{Path, PathBuf};
 
-use crate::db::{resolve_archive_path, Connection, Db};
+use crate::burst::{self, Burst, BurstOptions};
+use crate::db::{self, resolve_archive_path, Connection, Db};
 use crate::exclude;
-use crate::filter::{self, Filter};
+use crate::query::SourceQuery;
+use crate::sort::PathOrder;
+use crate::util::{current_timestamp, format_bytes};
 
 #[derive(Serialize, Deserialize)]
 pub struct Manifest {
@@ -21,6 +23,20 @@ pub struct Manifest {
 pub struct ManifestMeta {
     pub query: Vec<String>,
     pub generated_at: i64,
+    /// Set when the manifest is one volume of a multi-volume plan
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume: Option<ManifestVolume>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ManifestVolume {
+    /// 1-based volume number
+    pub index: usize,
+    pub count: usize,
+    /// Volume size in bytes
+    pub capacity: u64,
+    /// Bytes assigned to this volume
+    pub bytes: u64,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -30,23 +46,48 @@ pub struct ManifestOutput {
     pub base_dir: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ManifestSource {
     pub id: i64,
     pub root_id: i64,
     pub path: String,
     pub size: i64,
+    /// Modification time when the manifest was generated (absent in older manifests)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<i64>,
     pub object_id: Option<i64>,
     pub hash_type: Option<String>,
     pub hash_value: Option<String>,
     pub facts: HashMap<String, serde_json::Value>,
+    /// Set when the manifest was generated with `--group-by burst` and the
+    /// source is one of several frames of a burst
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub burst: Option<Burst>,
+}
+
+/// Capture time from facts: EXIF, then video creation time
+pub(crate) fn capture_time(source: &ManifestSource) -> Option<i64> {
+    ["content.exif.datetime_original", "exif.datetime_original", "content.video.creation_time"]
+        .iter()
+        .find_map(|key| source.facts.get(*key))
+        .and_then(|value| value.as_i64())
 }
 
 pub struct GenerateOptions {
     pub include_archived: bool,
     pub show_archived: bool,
+    /// Only consider these sources (`--stdin`)
+    pub source_ids: Option<Vec<i64>>,
+    /// Group sources into bursts (`--group-by burst`)
+    pub burst: Option<BurstOptions>,
+    /// List the manifest's sources by path rather than in index order (`--sort path`)
+    pub sort_by_path: bool,
+    /// How paths compare: for `sort_by_path`, volume contents and burst frames
+    /// taken in the same second (`--natural`)
+    pub path_order: PathOrder,
 }
 
+/// Write a manifest of matching sources destined for `dest` (a path inside an archive root)
 pub fn generate(
     db: &Db,
     filters: &[String],
@@ -59,12 +100,204 @@ pub fn generate(
     // Resolve destination to archive root + relative subdir
     let (archive_root_id, _archive_root_path, base_dir) = resolve_archive_path(conn, dest)?;
 
-    let parsed_filters: Vec<Filter> = filters
+    let mut sources = select_sources(conn, filters, options)?;
+    if sources.is_empty() {
+        println!("No sources matched the query");
+        return Ok(());
+    }
+    if options.sort_by_path {
+        sources.sort_by(|a, b| options.path_order.compare(&a.path, &b.path));
+    }
+
+    let manifest = Manifest {
+        meta: ManifestMeta {
+            query: filters.to_vec(),
+            generated_at: current_timestamp(),
+            volume: None,
+        },
+        output: ManifestOutput {
+            pattern: output_pattern(conn, archive_root_id, &base_dir)?,
+            archive_root_id,
+            base_dir,
+        },
+        sources,
+    };
+    write_manifest(&manifest, output_path)?;
+
+    println!(
+        "Generated manifest with {} sources: {}",
+        manifest.sources.len(),
+        output_path.display()
+    );
+
+    Ok(())
+}
+
+/// Bin-pack matching sources across archive volumes of `volume_size` bytes
+/// (first-fit decreasing), one destination per volume in `dests` order. Writes
+/// one manifest per used volume (`<output>-vol<N>.toml`) and records each
+/// source's volume in `volume_assignments`.
+pub fn generate_volumes(
+    db: &Db,
+    filters: &[String],
+    dests: &[PathBuf],
+    output_path: &Path,
+    volume_size: u64,
+    options: &GenerateOptions,
+) -> Result<()> {
+    let conn = db.conn();
+
+    let volumes_dest = dests
         .iter()
-        .map(|f| Filter::parse(f))
+        .map(|d| resolve_archive_path(conn, d))
         .collect::<Result<Vec<_>>>()?;
 
-    let (sources, archived, excluded_count) = query_sources(&conn, &parsed_filters, options.include_archived)?;
+    let mut sources = select_sources(conn, filters, options)?;
+    if sources.is_empty() {
+        println!("No sources matched the query");
+        return Ok(());
+    }
+
+    // First-fit decreasing: largest files first, each into the first volume with room
+    sources.sort_by(|a, b| b.size.cmp(&a.size).then(a.path.cmp(&b.path)));
+    if let Some(too_big) = sources.iter().find(|s| s.size as u64 > volume_size) {
+        bail!(
+            "{} ({}) is larger than the volume size ({})",
+            too_big.path,
+            format_bytes(too_big.size as u64),
+            format_bytes(volume_size)
+        );
+    }
+
+    let mut volumes: Vec<(u64, Vec<ManifestSource>)> = Vec::new();
+    for source in sources {
+        let size = source.size as u64;
+        match volumes.iter_mut().find(|(used, _)| used + size <= volume_size) {
+            Some((used, members)) => {
+                *used += size;
+                members.push(source);
+            }
+            None => volumes.push((size, vec![source])),
+        }
+    }
+
+    if volumes.len() > volumes_dest.len() {
+        let total: u64 = volumes.iter().map(|(used, _)| used).sum();
+        bail!(
+            "Selected sources ({}) need {} volumes of {}, but only {} --dest given",
+            format_bytes(total),
+            volumes.len(),
+            format_bytes(volume_size),
+            volumes_dest.len()
+        );
+    }
+
+    let generated_at = current_timestamp();
+    let count = volumes.len();
+    let tx = conn.unchecked_transaction()?;
+    for (i, ((used, mut members), (archive_root_id, archive_root_path, base_dir))) in
+        volumes.into_iter().zip(volumes_dest).enumerate()
+    {
+        let index = i + 1;
+        members.sort_by(|a, b| options.path_order.compare(&a.path, &b.path));
+        let manifest_path = volume_manifest_path(output_path, index);
+
+        for source in &members {
+            tx.execute(
+                "INSERT OR REPLACE INTO volume_assignments
+                 (source_id, archive_root_id, volume, manifest, assigned_at)
+                 VALUES (?, ?, ?, ?, ?)",
+                params![
+                    source.id,
+                    archive_root_id,
+                    index as i64,
+                    manifest_path.to_string_lossy(),
+                    generated_at
+                ],
+            )?;
+        }
+
+        let manifest = Manifest {
+            meta: ManifestMeta {
+                query: filters.to_vec(),
+                generated_at,
+                volume: Some(ManifestVolume {
+                    index,
+                    count,
+                    capacity: volume_size,
+                    bytes: used,
+                }),
+            },
+            output: ManifestOutput {
+                pattern: output_pattern(conn, archive_root_id, &base_dir)?,
+                archive_root_id,
+                base_dir,
+            },
+            sources: members,
+        };
+        write_manifest(&manifest, &manifest_path)?;
+
+        println!(
+            "Volume {}/{}: {} sources, {} of {} ({:.1}%) -> {} [{}]",
+            index,
+            count,
+            manifest.sources.len(),
+            format_bytes(used),
+            format_bytes(volume_size),
+            used as f64 / volume_size as f64 * 100.0,
+            archive_root_path,
+            manifest_path.display()
+        );
+    }
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// Parse a human size like "3.5TB", "500G", "2TiB" or plain bytes.
+/// Decimal units (KB, MB, GB, TB) use powers of 1000, as drive vendors do;
+/// binary units (KiB, MiB, GiB, TiB) use powers of 1024.
+pub fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number
+        .parse()
+        .with_context(|| format!("Invalid size '{}'", s))?;
+    let multiplier: f64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "k" | "kb" => 1e3,
+        "m" | "mb" => 1e6,
+        "g" | "gb" => 1e9,
+        "t" | "tb" => 1e12,
+        "kib" => 1024.0,
+        "mib" => 1024f64.powi(2),
+        "gib" => 1024f64.powi(3),
+        "tib" => 1024f64.powi(4),
+        _ => bail!("Invalid size unit in '{}'. Use B, KB, MB, GB, TB (or KiB..TiB)", s),
+    };
+    let bytes = (number * multiplier) as u64;
+    if bytes == 0 {
+        bail!("Size must be greater than zero: '{}'", s);
+    }
+    Ok(bytes)
+}
+
+/// Run the query and report what was left out (excluded, already archived)
+fn select_sources(
+    conn: &Connection,
+    filters: &[String],
+    options: &GenerateOptions,
+) -> Result<Vec<ManifestSource>> {
+    let query = SourceQuery::new()
+        .filters(conn, filters)?
+        .include_archived(options.include_archived)
+        .include_excluded(true)
+        .selection(options.source_ids.as_deref());
+
+    let (mut sources, archived, excluded_count) = query_sources(conn, &query, options.include_archived)?;
 
     // Report excluded files (hard gate - always skipped)
     if excluded_count > 0 {
@@ -87,67 +320,52 @@ pub fn generate(
         }
     }
 
-    if sources.is_empty() {
-        println!("No sources matched the query");
-        return Ok(());
+    if let Some(ref burst_options) = options.burst {
+        let (bursts, dropped) = burst::group(&mut sources, burst_options, options.path_order);
+        eprintln!("Found {} bursts", bursts);
+        if dropped > 0 {
+            eprintln!("Dropped {} burst frames (keeping one per burst)", dropped);
+        }
     }
 
-    let manifest = Manifest {
-        meta: ManifestMeta {
-            query: filters.to_vec(),
-            generated_at: current_timestamp(),
-        },
-        output: ManifestOutput {
-            pattern: "{filename}".to_string(),
-            archive_root_id,
-            base_dir,
-        },
-        sources,
-    };
+    Ok(sources)
+}
 
-    let toml_str = toml::to_string_pretty(&manifest)
+pub(crate) fn write_manifest(manifest: &Manifest, output_path: &Path) -> Result<()> {
+    let toml_str = toml::to_string_pretty(manifest)
         .context("Failed to serialize manifest")?;
 
     fs::write(output_path, &toml_str)
         .with_context(|| format!("Failed to write manifest to {}", output_path.display()))?;
-
-    println!(
-        "Generated manifest with {} sources: {}",
-        manifest.sources.len(),
-        output_path.display()
-    );
-
     Ok(())
 }
 
+/// "manifest.toml" -> "manifest-vol2.toml"
+fn volume_manifest_path(output_path: &Path, index: usize) -> PathBuf {
+    let stem = output_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "manifest".to_string());
+    let name = match output_path.extension() {
+        Some(ext) => format!("{}-vol{}.{}", stem, index, ext.to_string_lossy()),
+        None => format!("{}-vol{}", stem, index),
+    };
+    output_path.with_file_name(name)
+}
+
+/// (included_sources, archived_sources, excluded_count)
+type QueryResult = (Vec<ManifestSource>, Vec<(String, String)>, usize);
+
 /// Returns (included_sources, archived_sources, excluded_count)
 /// archived_sources is a list of (source_path, archive_path) for files already in an archive
 /// excluded_count is the number of sources skipped due to policy.exclude (hard gate)
 fn query_sources(
     conn: &Connection,
-    filters: &[Filter],
+    query: &SourceQuery,
     include_archived: bool,
-) -> Result<(Vec<ManifestSource>, Vec<(String, String)>, usize)> {
-    // Build query based on filters
-    // By default only source roots, with --include-archived also include archive roots
-    let role_clause = if include_archived {
-        "1=1" // Include all roles
-    } else {
-        "r.role = 'source'"
-    };
-
-    let mut source_ids: Vec<i64> = conn
-        .prepare(&format!(
-            "SELECT s.id FROM sources s
-             JOIN roots r ON s.root_id = r.id
-             WHERE s.present = 1 AND {}",
-            role_clause
-        ))?
-        .query_map([], |row| row.get(0))?
-        .collect::<Result<Vec<_>, _>>()?;
-
-    // Apply filters
-    source_ids = filter::apply_filters(conn, &source_ids, filters)?;
+) -> Result<QueryResult> {
+    // Excluded sources are selected too, so they can be counted below
+    let source_ids = query.ids(conn)?;
 
     // Check which sources are already archived (same object_id exists in an archive root)
     // Also apply hard gate for excluded sources
@@ -164,10 +382,11 @@ fn query_sources(
 
         if let Some(source) = fetch_source(conn, source_id)? {
             // Check if this content is already in an archive
-            let archive_path = if let Some(ref hash) = source.hash_value {
-                find_in_archive(conn, hash)?
-            } else {
-                None
+            let archive_path = match (source.object_id, &source.hash_value) {
+                (Some(object_id), Some(hash)) if db::is_archived(conn, object_id)? => {
+                    find_in_archive(conn, hash)?
+                }
+                _ => None,
             };
 
             if let Some(arch_path) = archive_path {
@@ -209,19 +428,33 @@ fn find_in_archive(conn: &Connection, hash_value: &str) -> Result<Option<String>
     }))
 }
 
-fn fetch_source(conn: &Connection, source_id: i64) -> Result<Option<ManifestSource>> {
-    let row: Option<(i64, i64, String, String, i64, Option<i64>)> = conn
+/// The archive root's declared layout when writing to the root itself,
+/// otherwise files keep their name
+pub(crate) fn output_pattern(conn: &Connection, archive_root_id: i64, base_dir: &str) -> Result<String> {
+    let layout = if base_dir.is_empty() {
+        db::root_layout(conn, archive_root_id)?
+    } else {
+        None
+    };
+    Ok(layout.unwrap_or_else(|| "{filename}".to_string()))
+}
+
+/// (id, root_id, root path, rel_path, size, mtime, object_id) of a source
+type SourceRow = (i64, i64, String, String, i64, i64, Option<i64>);
+
+pub(crate) fn fetch_source(conn: &Connection, source_id: i64) -> Result<Option<ManifestSource>> {
+    let row: Option<SourceRow> = conn
         .query_row(
-            "SELECT s.id, s.root_id, r.path, s.rel_path, s.size, s.object_id
+            "SELECT s.id, s.root_id, r.path, s.rel_path, s.size, s.mtime, s.object_id
              FROM sources s
              JOIN roots r ON s.root_id = r.id
              WHERE s.id = ?",
             [source_id],
-            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)),
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?)),
         )
         .ok();
 
-    let (id, root_id, root_path, rel_path, size, object_id) = match row {
+    let (id, root_id, root_path, rel_path, size, mtime, object_id) = match row {
         Some(r) => r,
         None => return Ok(None),
     };
@@ -292,10 +525,12 @@ fn fetch_source(conn: &Connection, source_id: i64) -> Result<Option<ManifestSour
         root_id,
         path: full_path,
         size,
+        mtime: Some(mtime),
         object_id,
         hash_type,
         hash_value,
         facts,
+        burst: None,
     }))
 }
 
@@ -317,10 +552,3 @@ fn fact_to_json(
         serde_json::Value::Null
     }
 }
-
-fn current_timestamp() -> i64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_secs() as i64
-}
@@ -0,0 +1,364 @@
+use anyhow::{bail, Context, Result};
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::cluster::{self, fetch_source, output_pattern, Manifest, ManifestMeta, ManifestOutput};
+use crate::db::{resolve_archive_path, Connection, Db};
+use crate::query::SourceQuery;
+use crate::util::{current_timestamp, format_bytes, format_number, format_timestamp};
+
+/// One exported collection member: the object and a present copy of it, if any
+#[derive(Serialize)]
+struct ExportEntry {
+    hash_type: String,
+    hash_value: String,
+    size: Option<i64>,
+    path: Option<String>,
+}
+
+// ============================================================================
+// Create Command
+// ============================================================================
+
+/// Create an empty collection named `name`
+pub fn create(db: &Db, name: &str) -> Result<()> {
+    let conn = db.conn();
+
+    if find_collection(conn, name)?.is_some() {
+        bail!("Collection '{}' already exists", name);
+    }
+
+    conn.execute(
+        "INSERT INTO collections (name, created_at) VALUES (?, ?)",
+        params![name, current_timestamp()],
+    )?;
+    println!("Created collection '{}'", name);
+    Ok(())
+}
+
+// ============================================================================
+// Add / Remove Commands
+// ============================================================================
+
+/// Add the objects of matching sources to a collection. Sources are matched in
+/// source and archive roots alike; unhashed sources cannot be added.
+pub fn add(db: &Db, name: &str, scope_path: Option<&Path>, filter_strs: &[String]) -> Result<()> {
+    let conn = db.conn();
+    let collection_id = require_collection(conn, name)?;
+
+    let (object_ids, unhashed) = matching_objects(conn, scope_path, filter_strs)?;
+    if unhashed > 0 {
+        eprintln!(
+            "Warning: {} matching sources are not hashed yet and were skipped",
+            unhashed
+        );
+    }
+
+    let now = current_timestamp();
+    let tx = conn.unchecked_transaction()?;
+    let mut added = 0;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT OR IGNORE INTO collection_objects (collection_id, object_id, added_at)
+             VALUES (?, ?, ?)",
+        )?;
+        for object_id in &object_ids {
+            added += stmt.execute(params![collection_id, object_id, now])?;
+        }
+    }
+    tx.commit()?;
+
+    println!(
+        "Added {} objects to '{}' ({} already present)",
+        added,
+        name,
+        object_ids.len() - added
+    );
+    Ok(())
+}
+
+/// Remove the objects of matching sources from a collection
+pub fn remove(db: &Db, name: &str, scope_path: Option<&Path>, filter_strs: &[String]) -> Result<()> {
+    let conn = db.conn();
+    let collection_id = require_collection(conn, name)?;
+
+    let (object_ids, _) = matching_objects(conn, scope_path, filter_strs)?;
+
+    let tx = conn.unchecked_transaction()?;
+    let mut removed = 0;
+    {
+        let mut stmt = tx.prepare(
+            "DELETE FROM collection_objects WHERE collection_id = ? AND object_id = ?",
+        )?;
+        for object_id in &object_ids {
+            removed += stmt.execute(params![collection_id, object_id])?;
+        }
+    }
+    tx.commit()?;
+
+    println!("Removed {} objects from '{}'", removed, name);
+    Ok(())
+}
+
+// ============================================================================
+// List Command
+// ============================================================================
+
+/// List collections, or the members of one collection with a current path for each
+pub fn list(db: &Db, name: Option<&str>) -> Result<()> {
+    let conn = db.conn();
+
+    let Some(name) = name else {
+        return list_collections(conn);
+    };
+    let collection_id = require_collection(conn, name)?;
+
+    let mut missing = 0;
+    let mut count = 0;
+    for entry in collection_entries(conn, collection_id)? {
+        count += 1;
+        match entry.path {
+            Some(path) => println!("{}", path),
+            None => {
+                println!("(no present copy) {}:{}", entry.hash_type, entry.hash_value);
+                missing += 1;
+            }
+        }
+    }
+
+    if count == 0 {
+        println!("Collection '{}' is empty. Add to it with: canon collection add {} <path>", name, name);
+    } else if missing > 0 {
+        eprintln!("Warning: {} of {} objects have no present copy", missing, count);
+    }
+    Ok(())
+}
+
+fn list_collections(conn: &Connection) -> Result<()> {
+    let rows: Vec<(String, i64, i64, i64)> = conn
+        .prepare(
+            "SELECT c.name, c.created_at, COUNT(co.object_id),
+                    COALESCE(SUM((SELECT MAX(s.size) FROM sources s WHERE s.object_id = co.object_id)), 0)
+             FROM collections c
+             LEFT JOIN collection_objects co ON co.collection_id = c.id
+             GROUP BY c.id
+             ORDER BY c.name",
+        )?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if rows.is_empty() {
+        println!("No collections. Create one with: canon collection create <name>");
+        return Ok(());
+    }
+
+    println!("{:<30} {:<17} {:>10} {:>12}", "Collection", "Created", "Objects", "Size");
+    println!("{}", "─".repeat(72));
+    for (name, created_at, objects, bytes) in &rows {
+        println!(
+            "{:<30} {:<17} {:>10} {:>12}",
+            name,
+            format_timestamp(*created_at),
+            format_number(*objects),
+            format_bytes(*bytes as u64)
+        );
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Export Command
+// ============================================================================
+
+/// Write a collection to `output`, or stdout when None: as JSONL (hash, size
+/// and a present path per object), or with `dest` as a manifest `canon apply`
+/// copies to that archive path
+pub fn export(db: &Db, name: &str, dest: Option<&Path>, output: Option<&Path>) -> Result<()> {
+    let conn = db.conn();
+    let collection_id = require_collection(conn, name)?;
+    if let Some(dest) = dest {
+        return export_manifest(conn, name, collection_id, dest, output);
+    }
+    let entries = collection_entries(conn, collection_id)?;
+
+    let writer: Box<dyn Write> = match output {
+        Some(path) => Box::new(
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?,
+        ),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut writer = BufWriter::new(writer);
+
+    let missing = entries.iter().filter(|e| e.path.is_none()).count();
+    for entry in &entries {
+        writeln!(writer, "{}", serde_json::to_string(entry)?)?;
+    }
+    writer.flush()?;
+
+    if missing > 0 {
+        eprintln!(
+            "Warning: {} of {} objects have no present copy (exported without a path)",
+            missing,
+            entries.len()
+        );
+    }
+    if let Some(path) = output {
+        eprintln!("Exported {} objects to {}", entries.len(), path.display());
+    }
+    Ok(())
+}
+
+/// Manifest of one present copy per member (source roots preferred), destined for `dest`
+fn export_manifest(conn: &Connection, name: &str, collection_id: i64, dest: &Path, output: Option<&Path>) -> Result<()> {
+    let (archive_root_id, _archive_root_path, base_dir) = resolve_archive_path(conn, dest)?;
+
+    let member_count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM collection_objects WHERE collection_id = ?",
+        [collection_id],
+        |row| row.get(0),
+    )?;
+    let source_ids: Vec<i64> = conn
+        .prepare(
+            "SELECT (SELECT s.id FROM sources s
+                     JOIN roots r ON s.root_id = r.id
+                     WHERE s.object_id = co.object_id AND s.present = 1
+                     ORDER BY r.role DESC, s.id LIMIT 1) AS source_id
+             FROM collection_objects co
+             WHERE co.collection_id = ? AND source_id IS NOT NULL",
+        )?
+        .query_map([collection_id], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut sources = Vec::with_capacity(source_ids.len());
+    for source_id in source_ids {
+        if let Some(source) = fetch_source(conn, source_id)? {
+            sources.push(source);
+        }
+    }
+    sources.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let manifest = Manifest {
+        meta: ManifestMeta {
+            query: vec![format!("collection {}", name)],
+            generated_at: current_timestamp(),
+            volume: None,
+        },
+        output: ManifestOutput {
+            pattern: output_pattern(conn, archive_root_id, &base_dir)?,
+            archive_root_id,
+            base_dir,
+        },
+        sources,
+    };
+    match output {
+        Some(path) => cluster::write_manifest(&manifest, path)?,
+        None => print!("{}", toml::to_string_pretty(&manifest).context("Failed to serialize manifest")?),
+    }
+
+    let missing = member_count as usize - manifest.sources.len();
+    if missing > 0 {
+        eprintln!(
+            "Warning: {} of {} objects have no present copy (left out of the manifest)",
+            missing, member_count
+        );
+    }
+    if let Some(path) = output {
+        eprintln!("Exported {} sources to {}", manifest.sources.len(), path.display());
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Delete Command
+// ============================================================================
+
+/// Delete a collection (the objects and files themselves are untouched)
+pub fn delete(db: &Db, name: &str) -> Result<()> {
+    let conn = db.conn();
+    let collection_id = require_collection(conn, name)?;
+
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("DELETE FROM collection_objects WHERE collection_id = ?", [collection_id])?;
+    tx.execute("DELETE FROM collections WHERE id = ?", [collection_id])?;
+    tx.commit()?;
+
+    println!("Deleted collection '{}'", name);
+    Ok(())
+}
+
+// ============================================================================
+// Helper Functions
+// ============================================================================
+
+fn find_collection(conn: &Connection, name: &str) -> Result<Option<i64>> {
+    Ok(conn
+        .query_row("SELECT id FROM collections WHERE name = ?", [name], |row| row.get(0))
+        .optional()?)
+}
+
+fn require_collection(conn: &Connection, name: &str) -> Result<i64> {
+    find_collection(conn, name)?
+        .with_context(|| format!("Collection '{}' not found. Create it with: canon collection create {}", name, name))
+}
+
+/// Distinct object ids of present, non-excluded sources matching scope and
+/// filters, plus the number of matching sources that have no object yet
+fn matching_objects(
+    conn: &Connection,
+    scope_path: Option<&Path>,
+    filter_strs: &[String],
+) -> Result<(Vec<i64>, usize)> {
+    let mut object_ids = Vec::new();
+    let mut unhashed = 0;
+    SourceQuery::new()
+        .scope(scope_path)?
+        .filters(conn, filter_strs)?
+        .include_archived(true)
+        .for_each_batch(
+            conn,
+            "s.id, s.object_id",
+            |row| row.get::<_, Option<i64>>(1),
+            |batch| {
+                for object_id in batch {
+                    match object_id {
+                        Some(object_id) => object_ids.push(object_id),
+                        None => unhashed += 1,
+                    }
+                }
+                Ok(())
+            },
+        )?;
+
+    object_ids.sort_unstable();
+    object_ids.dedup();
+    Ok((object_ids, unhashed))
+}
+
+/// Members of a collection, each with a present copy (source roots preferred)
+fn collection_entries(conn: &Connection, collection_id: i64) -> Result<Vec<ExportEntry>> {
+    let entries = conn
+        .prepare(
+            "SELECT o.hash_type, o.hash_value,
+                    (SELECT MAX(s.size) FROM sources s WHERE s.object_id = o.id),
+                    (SELECT r.path || '/' || s.rel_path FROM sources s
+                     JOIN roots r ON s.root_id = r.id
+                     WHERE s.object_id = o.id AND s.present = 1
+                     ORDER BY r.role DESC, s.id LIMIT 1) AS path
+             FROM collection_objects co
+             JOIN objects o ON co.object_id = o.id
+             WHERE co.collection_id = ?
+             ORDER BY path IS NULL, path",
+        )?
+        .query_map([collection_id], |row| {
+            Ok(ExportEntry {
+                hash_type: row.get(0)?,
+                hash_value: row.get(1)?,
+                size: row.get(2)?,
+                path: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(entries)
+}
@@ -0,0 +1,279 @@
+use anyhow::Result;
+use clap::{Arg, Command, ValueEnum};
+use rusqlite::params;
+use std::path::PathBuf;
+
+use crate::db::{self, Connection};
+
+/// Built-in keys the filter engine answers from the sources table
+const SOURCE_KEYS: &[&str] = &[
+    "source.ext",
+    "source.size",
+    "source.mtime",
+    "source.btime",
+    "source.path",
+    "source.root",
+    "source.rel_path",
+    "source.device",
+    "source.inode",
+    "source.nlink",
+    "source.link_group",
+];
+
+/// Comparison operators, longest first so `>=` wins over `>` at the same position
+const OPERATORS: &[&str] = &["!=", ">=", "<=", "=", ">", "<"];
+
+/// Most fact values offered for one key
+const VALUE_LIMIT: i64 = 100;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+// ============================================================================
+// Candidates (`canon __complete`)
+// ============================================================================
+
+/// Print completion candidates, one per line, for the last of `words` (the
+/// command line up to the cursor, starting with the program name). Prints
+/// nothing when the shell should fall back to file names.
+pub fn run(cli: &Command, words: &[String]) -> Result<()> {
+    for candidate in candidates(cli, words) {
+        println!("{}", candidate);
+    }
+    Ok(())
+}
+
+fn candidates(cli: &Command, words: &[String]) -> Vec<String> {
+    let Some((current, before)) = words.split_last() else {
+        return Vec::new();
+    };
+
+    // Walk the words so far to find the subcommand being typed and --db
+    let globals: Vec<&Arg> = cli.get_arguments().filter(|a| a.is_global_set()).collect();
+    let mut command = cli;
+    let mut db_path = None;
+    let mut pending_value: Option<&Arg> = None;
+    for word in before.iter().skip(1) {
+        if let Some(arg) = pending_value.take() {
+            if arg.get_id() == "db" {
+                db_path = Some(PathBuf::from(word));
+            }
+            continue;
+        }
+        if let Some(long) = word.strip_prefix("--") {
+            let (name, inline_value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (long, None),
+            };
+            let arg = find_long(command, &globals, name);
+            match (arg, inline_value) {
+                (Some(arg), Some(value)) if arg.get_id() == "db" => db_path = Some(PathBuf::from(value)),
+                (Some(arg), None) if takes_value(arg) => pending_value = Some(arg),
+                _ => {}
+            }
+        } else if let Some(sub) = command.find_subcommand(word) {
+            command = sub;
+        }
+    }
+
+    // The value of a flag: --where expr, --root spec, or --where=expr
+    let (flag, prefix, value) = match (pending_value, current.strip_prefix("--").and_then(|l| l.split_once('='))) {
+        (Some(arg), _) => (arg.get_long(), String::new(), current.as_str()),
+        (None, Some((name, value))) => (
+            find_long(command, &globals, name).and_then(Arg::get_long),
+            format!("--{}=", name),
+            value,
+        ),
+        (None, None) => (None, String::new(), current.as_str()),
+    };
+    if let Some(flag) = flag {
+        let conn = open_db(db_path);
+        let values = match (flag, conn) {
+            ("where", Some(db)) => where_candidates(db.conn(), value),
+            ("root", Some(db)) => root_candidates(db.conn(), value, None),
+            ("archive", Some(db)) => root_candidates(db.conn(), value, Some("archive")),
+            _ => Vec::new(),
+        };
+        return values.into_iter().map(|v| format!("{}{}", prefix, v)).collect();
+    }
+    if pending_value.is_some() {
+        return Vec::new();
+    }
+
+    let names: Vec<String> = if current.starts_with('-') {
+        command
+            .get_arguments()
+            .chain(globals.iter().copied())
+            .filter(|a| !a.is_hide_set())
+            .filter_map(|a| a.get_long().map(|l| format!("--{}", l)))
+            .collect()
+    } else {
+        command
+            .get_subcommands()
+            .filter(|c| !c.is_hide_set())
+            .map(|c| c.get_name().to_string())
+            .collect()
+    };
+    names.into_iter().filter(|n| n.starts_with(current.as_str())).collect()
+}
+
+fn find_long<'a>(command: &'a Command, globals: &[&'a Arg], name: &str) -> Option<&'a Arg> {
+    command
+        .get_arguments()
+        .chain(globals.iter().copied())
+        .find(|a| a.get_long() == Some(name))
+}
+
+fn takes_value(arg: &Arg) -> bool {
+    arg.get_action().takes_values()
+}
+
+/// The database, if it exists; completion never creates one
+fn open_db(path: Option<PathBuf>) -> Option<db::Db> {
+    let path = path.unwrap_or_else(db::default_path);
+    if !path.exists() {
+        return None;
+    }
+    db::open(&path, false, false).ok()
+}
+
+/// Complete the last term of a filter expression: a key (optionally negated),
+/// or a value once the key and operator are typed
+fn where_candidates(conn: &Connection, expr: &str) -> Vec<String> {
+    // Only the term at the end of the expression is completed
+    let term_start = expr.rfind(|c: char| c.is_whitespace() || c == '(').map_or(0, |i| i + 1);
+    let (head, term) = expr.split_at(term_start);
+
+    let operator = OPERATORS.iter().filter_map(|op| term.find(op).map(|i| (i, *op))).min_by_key(|&(i, _)| i);
+    match operator {
+        Some((i, op)) if i > 0 => {
+            let key = term[..i].trim_start_matches('!');
+            let value_prefix = &term[i + op.len()..];
+            fact_values(conn, key, value_prefix)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|value| format!("{}{}{}", head, &term[..i + op.len()], value))
+                .collect()
+        }
+        _ => {
+            let negation = if term.starts_with('!') { "!" } else { "" };
+            let key_prefix = &term[negation.len()..];
+            fact_keys(conn)
+                .unwrap_or_default()
+                .into_iter()
+                .chain(SOURCE_KEYS.iter().map(|k| k.to_string()))
+                .filter(|k| k.starts_with(key_prefix))
+                .map(|k| format!("{}{}{}", head, negation, k))
+                .collect()
+        }
+    }
+}
+
+fn fact_keys(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT DISTINCT key FROM facts ORDER BY key")?;
+    let keys = stmt.query_map([], |row| row.get(0))?.collect::<Result<Vec<String>, _>>()?;
+    Ok(keys)
+}
+
+/// Text values stored for a key (values with spaces would need quoting, so
+/// they are left out)
+fn fact_values(conn: &Connection, key: &str, prefix: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT value_text FROM facts
+         WHERE key = ?1 AND value_text IS NOT NULL AND substr(value_text, 1, length(?2)) = ?2
+         ORDER BY value_text
+         LIMIT ?3",
+    )?;
+    let values = stmt
+        .query_map(params![key, prefix, VALUE_LIMIT], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+    Ok(values.into_iter().filter(|v| !v.contains(char::is_whitespace)).collect())
+}
+
+/// `id:N`, `path:/root` and `name:ALIAS` specs for roots, optionally of one role
+fn root_candidates(conn: &Connection, prefix: &str, role: Option<&str>) -> Vec<String> {
+    let roots = || -> Result<Vec<(i64, String, Option<String>)>> {
+        let mut stmt = conn.prepare(
+            "SELECT id, path, (SELECT value FROM root_settings WHERE root_id = roots.id AND key = 'alias')
+             FROM roots WHERE ?1 IS NULL OR role = ?1 ORDER BY id",
+        )?;
+        let roots = stmt
+            .query_map([role], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(roots)
+    };
+    roots()
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|(id, path, alias)| {
+            [Some(format!("id:{}", id)), Some(format!("path:{}", path)), alias.map(|alias| format!("name:{}", alias))]
+        })
+        .flatten()
+        .filter(|spec| spec.starts_with(prefix))
+        .collect()
+}
+
+// ============================================================================
+// Scripts (`canon completions`)
+// ============================================================================
+
+/// Shell script that asks `canon __complete` for candidates
+pub fn script(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash => BASH_SCRIPT,
+        Shell::Zsh => ZSH_SCRIPT,
+        Shell::Fish => FISH_SCRIPT,
+    }
+}
+
+/// Bash splits words at '=' and ':', so the line is re-split on whitespace for
+/// canon, and candidates are trimmed back to the part bash is completing
+const BASH_SCRIPT: &str = r#"_canon() {
+    local line="${COMP_LINE:0:$COMP_POINT}" words
+    read -ra words <<< "$line"
+    [[ "$line" =~ [[:space:]]$ ]] && words+=("")
+    local full="${words[${#words[@]}-1]}" cur="${COMP_WORDS[COMP_CWORD]}"
+    local candidates
+    candidates="$(canon __complete -- "${words[@]}" 2>/dev/null)"
+    if [[ -z "$candidates" ]]; then
+        COMPREPLY=($(compgen -f -- "$cur"))
+        return
+    fi
+    local strip=$(( ${#full} - ${#cur} )) c
+    COMPREPLY=()
+    while IFS= read -r c; do
+        COMPREPLY+=("${c:$strip}")
+    done <<< "$candidates"
+}
+complete -o filenames -F _canon canon
+"#;
+
+const ZSH_SCRIPT: &str = r#"#compdef canon
+_canon() {
+    local -a candidates
+    candidates=("${(@f)$(canon __complete -- "${(@)words[1,CURRENT]}" 2>/dev/null)}")
+    if [[ -z "${candidates[1]}" ]]; then
+        _files
+    else
+        compadd -Q -- "${candidates[@]}"
+    fi
+}
+compdef _canon canon
+"#;
+
+/// The current token is quoted so an empty one is still passed
+const FISH_SCRIPT: &str = r#"function __canon_complete
+    set -l current (commandline -ct)
+    set -l candidates (canon __complete -- (commandline -opc) "$current" 2>/dev/null)
+    if test (count $candidates) -eq 0
+        __fish_complete_path "$current"
+    else
+        printf '%s\n' $candidates
+    end
+end
+complete -c canon -f -a '(__canon_complete)'
+"#;
@@ -1,31 +1,62 @@
 use anyhow::Result;
+use serde::Serialize;
 use std::path::Path;
 
-use crate::db::{parse_root_spec, populate_temp_sources, Db};
-use crate::exclude;
-use crate::filter::{self, Filter};
-
-const BATCH_SIZE: i64 = 1000;
+use crate::db::{parse_root_spec, refresh_root_availability, Connection, Db, TempSources};
+use crate::output;
+use crate::query::SourceQuery;
+use crate::roots;
+use crate::summary;
+use crate::util::format_number;
 
 /// Statistics for a single root or overall
+#[derive(Serialize)]
 struct CoverageStats {
+    #[serde(skip_serializing_if = "Option::is_none")]
     root_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    root_label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     root_role: Option<String>,
+    /// Root unavailable: marked offline or not mounted
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    root_offline: bool,
     total_sources: i64,
     excluded_sources: i64,
     hashed_sources: i64,
     archived_sources: i64,
+    /// Archived sources whose archive copies are all on unavailable roots
+    offline_only_sources: i64,
+    /// Unhashed sources whose quickhash matches archived content; the first
+    /// candidates for a full hash
+    quickhash_archived_sources: i64,
+}
+
+/// Everything `canon coverage` reports: per-root stats (unscoped runs only) and totals
+#[derive(Serialize)]
+struct CoverageReport<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    archive: Option<&'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    roots: Vec<CoverageStats>,
+    overall: CoverageStats,
 }
 
 impl CoverageStats {
     fn new() -> Self {
         CoverageStats {
             root_path: None,
+            root_label: None,
             root_role: None,
+            root_offline: false,
             total_sources: 0,
             excluded_sources: 0,
             hashed_sources: 0,
             archived_sources: 0,
+            offline_only_sources: 0,
+            quickhash_archived_sources: 0,
         }
     }
 
@@ -63,8 +94,9 @@ impl CoverageStats {
     }
 }
 
+/// Print archive coverage per root for sources matching the scope and filters
 pub fn run(
-    db: &mut Db,
+    db: &Db,
     scope_path: Option<&Path>,
     filter_strs: &[String],
     archive_spec: Option<&str>,
@@ -72,19 +104,15 @@ pub fn run(
     include_excluded: bool,
 ) -> Result<()> {
     let conn = db.conn();
+    refresh_root_availability(conn)?;
 
-    // Parse filters
-    let filters: Vec<Filter> = filter_strs
-        .iter()
-        .map(|f| Filter::parse(f))
-        .collect::<Result<Vec<_>>>()?;
-
-    // Resolve scope path
-    let scope_prefix = if let Some(p) = scope_path {
-        Some(std::fs::canonicalize(p)?.to_string_lossy().to_string())
-    } else {
-        None
-    };
+    // Coverage counts excluded sources separately, so they are always selected
+    let query = SourceQuery::new()
+        .scope(scope_path)?
+        .filters(conn, filter_strs)?
+        .include_archived(include_archived)
+        .include_excluded(true);
+    let scope_prefix = query.scope_prefix().map(String::from);
 
     // Parse and validate archive spec (must be archive role)
     let archive_root_id = if let Some(spec) = archive_spec {
@@ -93,104 +121,42 @@ pub fn run(
         None
     };
 
-    // Get mutable reference for temp table operations
-    let conn = db.conn_mut();
-
     // Compute and display stats
     if scope_prefix.is_some() {
         // Single scope mode
-        let stats = compute_scoped_stats(
-            conn,
-            scope_prefix.as_deref(),
-            &filters,
-            archive_root_id,
-            include_archived,
-        )?;
-        display_scoped_stats(&stats, scope_prefix.as_deref(), archive_spec, include_excluded);
+        let report = CoverageReport {
+            scope: scope_prefix.as_deref(),
+            archive: archive_spec,
+            roots: Vec::new(),
+            overall: compute_scoped_stats(conn, &query, archive_root_id)?,
+        };
+        output::emit(&report, |r| display_scoped_stats(&r.overall, r.scope, r.archive, include_excluded))
     } else {
         // Per-root breakdown mode
-        let (per_root_stats, overall) = compute_per_root_stats(
-            conn,
-            &filters,
-            archive_root_id,
-            include_archived,
-        )?;
-        display_per_root_stats(&per_root_stats, &overall, archive_spec, include_excluded);
+        let (roots, overall) = compute_per_root_stats(conn, &query, archive_root_id, include_archived)?;
+        let report = CoverageReport { scope: None, archive: archive_spec, roots, overall };
+        output::emit(&report, |r| display_per_root_stats(&r.roots, &r.overall, r.archive, include_excluded))
     }
-
-    Ok(())
 }
 
 /// Compute coverage stats for sources under a specific path scope using pure SQL aggregates
 fn compute_scoped_stats(
-    conn: &mut rusqlite::Connection,
-    scope_prefix: Option<&str>,
-    filters: &[Filter],
+    conn: &Connection,
+    query: &SourceQuery,
     archive_root_id: Option<i64>,
-    include_archived: bool,
 ) -> Result<CoverageStats> {
-    // Build role clause
-    let role_clause = if include_archived {
-        "1=1"
-    } else {
-        "r.role = 'source'"
-    };
-
-    // Build path clause
-    let path_clause = if scope_prefix.is_some() {
-        "(r.path || '/' || s.rel_path) LIKE ? || '/%'"
-    } else {
-        "1=1"
-    };
-
-    // Always query all sources (no exclude filtering at query level)
-    let exclude_clause = exclude::exclude_clause(true);
-
-    // Collect all filtered source IDs
-    let mut all_filtered_ids: Vec<i64> = Vec::new();
-    let mut last_id: i64 = 0;
-
-    loop {
-        let batch_query = format!(
-            "SELECT s.id FROM sources s
-             JOIN roots r ON s.root_id = r.id
-             WHERE s.present = 1 AND {} AND {} AND {} AND s.id > ?
-             ORDER BY s.id LIMIT ?",
-            role_clause, path_clause, exclude_clause
-        );
-
-        let source_ids: Vec<i64> = if let Some(prefix) = scope_prefix {
-            conn.prepare(&batch_query)?
-                .query_map(rusqlite::params![prefix, last_id, BATCH_SIZE], |row| row.get(0))?
-                .collect::<Result<Vec<_>, _>>()?
-        } else {
-            conn.prepare(&batch_query)?
-                .query_map(rusqlite::params![last_id, BATCH_SIZE], |row| row.get(0))?
-                .collect::<Result<Vec<_>, _>>()?
-        };
-
-        if source_ids.is_empty() {
-            break;
-        }
-
-        last_id = *source_ids.last().unwrap();
-
-        // Apply filters
-        let filtered_ids = filter::apply_filters(conn, &source_ids, filters)?;
-        all_filtered_ids.extend(filtered_ids);
-    }
-
     // Populate temp table with all filtered source IDs
-    populate_temp_sources(conn, &all_filtered_ids)?;
+    let source_ids = query.ids(conn)?;
+    let _selection = TempSources::load_keyed(conn, &source_ids)?;
 
     // Now compute all stats with aggregate queries
-    compute_stats_from_temp_table(conn, archive_root_id)
+    compute_stats_from_temp_table(conn, archive_root_id, None)
 }
 
 /// Compute coverage stats per root, plus overall totals using pure SQL aggregates
 fn compute_per_root_stats(
-    conn: &mut rusqlite::Connection,
-    filters: &[Filter],
+    conn: &Connection,
+    query: &SourceQuery,
     archive_root_id: Option<i64>,
     include_archived: bool,
 ) -> Result<(Vec<CoverageStats>, CoverageStats)> {
@@ -201,56 +167,38 @@ fn compute_per_root_stats(
         "role = 'source'"
     };
 
-    let roots: Vec<(i64, String, String)> = conn
+    let roots: Vec<(i64, String, String, bool)> = conn
         .prepare(&format!(
-            "SELECT id, path, role FROM roots WHERE {} ORDER BY path",
+            "SELECT id, path, role, available FROM roots WHERE {} ORDER BY path",
             role_clause
         ))?
-        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?
         .collect::<Result<Vec<_>, _>>()?;
 
+    let mut labels = roots::labels(conn)?;
     let mut per_root_stats = Vec::new();
     let mut overall = CoverageStats::new();
 
-    for (root_id, root_path, root_role) in roots {
-        // Collect all filtered source IDs for this root
-        let mut all_filtered_ids: Vec<i64> = Vec::new();
-        let mut last_id: i64 = 0;
-
-        loop {
-            let source_ids: Vec<i64> = conn
-                .prepare(
-                    "SELECT id FROM sources
-                     WHERE root_id = ? AND present = 1 AND id > ?
-                     ORDER BY id LIMIT ?"
-                )?
-                .query_map(rusqlite::params![root_id, last_id, BATCH_SIZE], |row| row.get(0))?
-                .collect::<Result<Vec<_>, _>>()?;
-
-            if source_ids.is_empty() {
-                break;
-            }
-
-            last_id = *source_ids.last().unwrap();
-
-            // Apply filters
-            let filtered_ids = filter::apply_filters(conn, &source_ids, filters)?;
-            all_filtered_ids.extend(filtered_ids);
-        }
-
-        // Populate temp table with all filtered source IDs for this root
-        populate_temp_sources(conn, &all_filtered_ids)?;
+    // One selection for all roots (the query covers the same roles as the
+    // list above); each root's sources are a contiguous range of the table
+    let source_ids = query.ids(conn)?;
+    let _selection = TempSources::load_keyed(conn, &source_ids)?;
 
+    for (root_id, root_path, root_role, available) in roots {
         // Compute stats from temp table
-        let mut stats = compute_stats_from_temp_table(conn, archive_root_id)?;
+        let mut stats = compute_stats_from_temp_table(conn, archive_root_id, Some(root_id))?;
         stats.root_path = Some(root_path);
+        stats.root_label = labels.remove(&root_id);
         stats.root_role = Some(root_role);
+        stats.root_offline = !available;
 
         // Add to overall totals
         overall.total_sources += stats.total_sources;
         overall.excluded_sources += stats.excluded_sources;
         overall.hashed_sources += stats.hashed_sources;
         overall.archived_sources += stats.archived_sources;
+        overall.offline_only_sources += stats.offline_only_sources;
+        overall.quickhash_archived_sources += stats.quickhash_archived_sources;
 
         per_root_stats.push(stats);
     }
@@ -258,71 +206,105 @@ fn compute_per_root_stats(
     Ok((per_root_stats, overall))
 }
 
-/// Compute all coverage stats from temp_sources using pure SQL aggregates
+/// Compute all coverage stats from the keyed temp_sources (all of it, or one
+/// root's sources) using pure SQL aggregates
 fn compute_stats_from_temp_table(
-    conn: &rusqlite::Connection,
+    conn: &Connection,
     archive_root_id: Option<i64>,
+    root_id: Option<i64>,
 ) -> Result<CoverageStats> {
     let mut stats = CoverageStats::new();
+    let root_clause = match root_id {
+        Some(id) => format!("ts.root_id = {}", id),
+        None => "1=1".to_string(),
+    };
 
     // Total sources
     stats.total_sources = conn.query_row(
-        "SELECT COUNT(*) FROM temp_sources",
+        &format!("SELECT COUNT(*) FROM temp_sources ts WHERE {}", root_clause),
         [],
         |row| row.get(0),
     )?;
 
     // Excluded sources (presence of policy.exclude key)
     stats.excluded_sources = conn.query_row(
-        "SELECT COUNT(*) FROM temp_sources ts
-         WHERE EXISTS (
-             SELECT 1 FROM facts f
-             WHERE f.entity_type = 'source' AND f.entity_id = ts.id
-               AND f.key = 'policy.exclude'
-         )",
+        &format!(
+            "SELECT COUNT(*) FROM temp_sources ts
+             WHERE {} AND EXISTS (
+                 SELECT 1 FROM facts f
+                 WHERE f.entity_type = 'source' AND f.entity_id = ts.id
+                   AND f.key = 'policy.exclude'
+             )",
+            root_clause
+        ),
         [],
         |row| row.get(0),
     )?;
 
     // Hashed sources (have an object_id)
     stats.hashed_sources = conn.query_row(
-        "SELECT COUNT(*) FROM temp_sources ts
-         JOIN sources s ON s.id = ts.id
-         WHERE s.object_id IS NOT NULL",
+        &format!("SELECT COUNT(*) FROM temp_sources ts WHERE {} AND ts.object_id IS NOT NULL", root_clause),
         [],
         |row| row.get(0),
     )?;
 
     // Archived sources
-    if let Some(root_id) = archive_root_id {
+    if let Some(archive_id) = archive_root_id {
         // Specific archive root
         stats.archived_sources = conn.query_row(
-            "SELECT COUNT(*) FROM temp_sources ts
-             JOIN sources s ON s.id = ts.id
-             WHERE s.object_id IS NOT NULL AND EXISTS (
-                 SELECT 1 FROM sources arch_s
-                 WHERE arch_s.root_id = ?1 AND arch_s.present = 1
-                   AND arch_s.object_id = s.object_id
-             )",
-            [root_id],
+            &format!(
+                "SELECT COUNT(*) FROM temp_sources ts
+                 WHERE {} AND ts.object_id IS NOT NULL AND EXISTS (
+                     SELECT 1 FROM sources arch_s
+                     WHERE arch_s.root_id = ?1 AND arch_s.present = 1
+                       AND arch_s.object_id = ts.object_id
+                 )",
+                root_clause
+            ),
+            [archive_id],
             |row| row.get(0),
         )?;
+        let available: bool =
+            conn.query_row("SELECT available FROM roots WHERE id = ?", [archive_id], |row| row.get(0))?;
+        if !available {
+            stats.offline_only_sources = stats.archived_sources;
+        }
     } else {
         // Any archive root
-        stats.archived_sources = conn.query_row(
-            "SELECT COUNT(*) FROM temp_sources ts
-             JOIN sources s ON s.id = ts.id
-             WHERE s.object_id IS NOT NULL AND EXISTS (
-                 SELECT 1 FROM sources arch_s
-                 JOIN roots r ON arch_s.root_id = r.id
-                 WHERE r.role = 'archive' AND arch_s.present = 1
-                   AND arch_s.object_id = s.object_id
-             )",
+        let (archived, offline_only): (i64, i64) = conn.query_row(
+            &format!(
+                "SELECT COUNT(*), COALESCE(SUM(ao.online_copies = 0), 0) FROM temp_sources ts
+                 JOIN archived_objects ao ON ao.object_id = ts.object_id
+                 WHERE {}",
+                root_clause
+            ),
             [],
-            |row| row.get(0),
+            |row| Ok((row.get(0)?, row.get(1)?)),
         )?;
+        stats.archived_sources = archived;
+        stats.offline_only_sources = offline_only;
     }
 
+    let matched = match archive_root_id {
+        Some(id) => format!(
+            "(m.entity_type = 'object' AND EXISTS (
+                SELECT 1 FROM sources arch_s WHERE arch_s.root_id = {} AND arch_s.present = 1
+                  AND arch_s.object_id = m.entity_id))",
+            id
+        ),
+        None => summary::QUICKHASH_MATCHES_ARCHIVED.to_string(),
+    };
+    stats.quickhash_archived_sources = conn.query_row(
+        &format!(
+            "SELECT COUNT(*) FROM temp_sources ts JOIN sources s ON s.id = ts.id
+             WHERE {} AND ts.object_id IS NULL AND {}",
+            root_clause,
+            summary::quickhash_match_condition(&matched)
+        ),
+        [],
+        |row| row.get(0),
+    )?;
+
     Ok(stats)
 }
 
@@ -383,6 +365,7 @@ fn display_scoped_stats(stats: &CoverageStats, scope: Option<&str>, archive: Opt
         );
         println!("  Unarchived:      {:>8}", format_number(stats.unarchived()));
     }
+    display_notes(stats);
 }
 
 fn display_per_root_stats(per_root: &[CoverageStats], overall: &CoverageStats, archive: Option<&str>, include_excluded: bool) {
@@ -402,9 +385,13 @@ fn display_per_root_stats(per_root: &[CoverageStats], overall: &CoverageStats, a
             continue;
         }
 
-        let root_path = stats.root_path.as_deref().unwrap_or("unknown");
+        let root_name = stats.root_label.as_deref().or(stats.root_path.as_deref()).unwrap_or("unknown");
         let root_role = stats.root_role.as_deref().unwrap_or("unknown");
-        println!("Root: {} ({})", root_path, root_role);
+        if stats.root_offline {
+            println!("Root: {} ({}, offline)", root_name, root_role);
+        } else {
+            println!("Root: {} ({})", root_name, root_role);
+        }
 
         if include_excluded && stats.excluded_sources > 0 {
             println!("  Total sources:   {:>8}", format_number(stats.total_sources));
@@ -443,6 +430,7 @@ fn display_per_root_stats(per_root: &[CoverageStats], overall: &CoverageStats, a
             );
             println!("  Unarchived:      {:>8}", format_number(stats.unarchived()));
         }
+        display_notes(stats);
         println!();
     }
 
@@ -487,16 +475,22 @@ fn display_per_root_stats(per_root: &[CoverageStats], overall: &CoverageStats, a
         );
         println!("  Unarchived:      {:>8}", format_number(overall.unarchived()));
     }
+    display_notes(overall);
 }
 
-fn format_number(n: i64) -> String {
-    let s = n.to_string();
-    let mut result = String::new();
-    for (i, c) in s.chars().rev().enumerate() {
-        if i > 0 && i % 3 == 0 {
-            result.push(',');
-        }
-        result.push(c);
+/// Note archived sources that are only reachable on unplugged/unmounted
+/// archives, and unhashed ones that look archived by their quickhash
+fn display_notes(stats: &CoverageStats) {
+    if stats.offline_only_sources > 0 {
+        println!(
+            "  Offline only:    {:>8} (archive copies on unavailable roots)",
+            format_number(stats.offline_only_sources)
+        );
+    }
+    if stats.quickhash_archived_sources > 0 {
+        println!(
+            "  Likely archived: {:>8} (unhashed, quickhash matches archived content; canon hash --quick-matches)",
+            format_number(stats.quickhash_archived_sources)
+        );
     }
-    result.chars().rev().collect()
 }
@@ -1,10 +1,17 @@
 use anyhow::{bail, Context, Result};
+use rusqlite::{ffi, params, params_from_iter};
 pub use rusqlite::Connection;
+use std::collections::HashMap;
+use std::ffi::CStr;
 use std::fs;
 use std::ops::Deref;
-use std::path::Path;
+use std::os::raw::{c_char, c_int, c_uint, c_void};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use std::time::Duration;
 
+use crate::remote;
+
 /// Database context that wraps a Connection with optional SQL debug logging
 pub struct Db {
     conn: Connection,
@@ -35,7 +42,11 @@ const SCHEMA: &str = r#"
 CREATE TABLE IF NOT EXISTS roots (
     id INTEGER PRIMARY KEY,
     path TEXT NOT NULL UNIQUE,
-    role TEXT NOT NULL DEFAULT 'source' CHECK (role IN ('source', 'archive'))
+    role TEXT NOT NULL DEFAULT 'source' CHECK (role IN ('source', 'archive')),
+    mount_point TEXT,
+    available INTEGER NOT NULL DEFAULT 1,
+    layout TEXT,
+    scanned_at INTEGER
 );
 
 -- Sources: files discovered on disk
@@ -52,6 +63,13 @@ CREATE TABLE IF NOT EXISTS sources (
     last_seen_at INTEGER NOT NULL,
     present INTEGER NOT NULL DEFAULT 1,
     object_id INTEGER REFERENCES objects(id),
+    apply_run_id INTEGER REFERENCES apply_runs(id),
+    -- Archive files indexed before apply runs were recorded: whether canon
+    -- apply placed them can't be told (canon lint doesn't call them strays)
+    provenance_unknown INTEGER NOT NULL DEFAULT 0,
+    -- Hard links to the file when last scanned (NULL when unknown, e.g. remotes)
+    nlink INTEGER,
+    btime INTEGER,
     UNIQUE(root_id, rel_path)
 );
 
@@ -83,14 +101,307 @@ CREATE TABLE IF NOT EXISTS facts (
     CHECK (entity_type != 'object' OR observed_basis_rev IS NULL)
 );
 
+-- Snapshots: named point-in-time summaries of the index
+CREATE TABLE IF NOT EXISTS snapshots (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    created_at INTEGER NOT NULL,
+    total_sources INTEGER NOT NULL,
+    present_sources INTEGER NOT NULL,
+    hashed_sources INTEGER NOT NULL,
+    archived_sources INTEGER NOT NULL,
+    objects INTEGER NOT NULL,
+    facts INTEGER NOT NULL
+);
+
+-- Per-source digest state captured with each snapshot
+CREATE TABLE IF NOT EXISTS snapshot_sources (
+    snapshot_id INTEGER NOT NULL REFERENCES snapshots(id),
+    source_id INTEGER NOT NULL,
+    basis_rev INTEGER NOT NULL,
+    object_id INTEGER,
+    present INTEGER NOT NULL,
+    archived INTEGER NOT NULL,
+    PRIMARY KEY (snapshot_id, source_id)
+) WITHOUT ROWID;
+
+-- Collections: named sets of objects (content), so selections survive file moves
+CREATE TABLE IF NOT EXISTS collections (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    created_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS collection_objects (
+    collection_id INTEGER NOT NULL REFERENCES collections(id),
+    object_id INTEGER NOT NULL REFERENCES objects(id),
+    added_at INTEGER NOT NULL,
+    PRIMARY KEY (collection_id, object_id)
+) WITHOUT ROWID;
+
+-- Apply runs: each non-dry-run `canon apply`; archive sources it placed point here
+CREATE TABLE IF NOT EXISTS apply_runs (
+    id INTEGER PRIMARY KEY,
+    manifest TEXT NOT NULL,
+    manifest_sha256 TEXT,
+    applied_at INTEGER NOT NULL
+);
+
+-- Apply provenance: one row per file `canon apply` placed in an archive, linking
+-- the manifest source to the archive source it became (canon object show)
+CREATE TABLE IF NOT EXISTS apply_provenance (
+    id INTEGER PRIMARY KEY,
+    apply_run_id INTEGER NOT NULL REFERENCES apply_runs(id),
+    source_id INTEGER NOT NULL REFERENCES sources(id),
+    object_id INTEGER REFERENCES objects(id),
+    dest_source_id INTEGER NOT NULL REFERENCES sources(id),
+    transfer TEXT NOT NULL CHECK (transfer IN ('copy', 'rename', 'move')),
+    verify TEXT NOT NULL CHECK (verify IN ('hash-match', 'unverified')),
+    applied_at INTEGER NOT NULL
+);
+
+-- Volume plans: which archive volume each source was assigned to by
+-- `cluster generate --split-by-volume` (latest plan wins)
+CREATE TABLE IF NOT EXISTS volume_assignments (
+    source_id INTEGER PRIMARY KEY REFERENCES sources(id),
+    archive_root_id INTEGER NOT NULL REFERENCES roots(id),
+    volume INTEGER NOT NULL,
+    manifest TEXT NOT NULL,
+    assigned_at INTEGER NOT NULL
+);
+
+-- Verification history: each hash check `canon verify` made of a source
+CREATE TABLE IF NOT EXISTS verifications (
+    id INTEGER PRIMARY KEY,
+    source_id INTEGER NOT NULL REFERENCES sources(id),
+    object_id INTEGER REFERENCES objects(id),
+    verified_at INTEGER NOT NULL,
+    result TEXT NOT NULL CHECK (result IN ('ok', 'mismatch', 'missing'))
+);
+
+-- Per-root settings (canon root set): label, ignore, hash, inode-tracking
+CREATE TABLE IF NOT EXISTS root_settings (
+    root_id INTEGER NOT NULL REFERENCES roots(id),
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (root_id, key)
+);
+
+-- Trash: files canon moved aside instead of deleting (canon trash); the file
+-- lives at <root>/trash_rel_path until restored or purged
+CREATE TABLE IF NOT EXISTS trash (
+    id INTEGER PRIMARY KEY,
+    source_id INTEGER REFERENCES sources(id),
+    root_id INTEGER NOT NULL REFERENCES roots(id),
+    rel_path TEXT NOT NULL,
+    trash_rel_path TEXT NOT NULL,
+    object_id INTEGER REFERENCES objects(id),
+    size INTEGER NOT NULL,
+    reason TEXT NOT NULL,
+    trashed_at INTEGER NOT NULL
+);
+
+-- Directory mtimes recorded by `scan --fast` (nanoseconds), with the walk
+-- policy they were listed under: a directory whose mtime still matches has the
+-- same entries, so it isn't listed again
+CREATE TABLE IF NOT EXISTS dirs (
+    root_id INTEGER NOT NULL REFERENCES roots(id),
+    rel_path TEXT NOT NULL,
+    mtime INTEGER NOT NULL,
+    policy TEXT NOT NULL,
+    PRIMARY KEY (root_id, rel_path)
+);
+
+-- Progress of a sequential scan (scan --resume): the last file committed, in
+-- walk order, with the walk policy and start time of the scan. Removed once
+-- the scan completes, so a row left behind marks an interrupted scan.
+CREATE TABLE IF NOT EXISTS scan_checkpoints (
+    root_id INTEGER NOT NULL REFERENCES roots(id),
+    prefix TEXT NOT NULL,
+    started_at INTEGER NOT NULL,
+    policy TEXT NOT NULL,
+    last_path TEXT,
+    PRIMARY KEY (root_id, prefix)
+);
+
+-- Files canon hash failed to read, for the version in the index: later runs
+-- skip them until the file changes or --retry-failed asks for them again
+CREATE TABLE IF NOT EXISTS hash_failures (
+    source_id INTEGER PRIMARY KEY REFERENCES sources(id),
+    basis_rev INTEGER NOT NULL,
+    error TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    failed_at INTEGER NOT NULL
+);
+
+-- Operation locks: running commands that change files or the index (canon lock)
+CREATE TABLE IF NOT EXISTS locks (
+    id INTEGER PRIMARY KEY,
+    operation TEXT NOT NULL,
+    pid INTEGER NOT NULL,
+    host TEXT NOT NULL,
+    started_at INTEGER NOT NULL
+);
+
 -- Indexes
-CREATE UNIQUE INDEX IF NOT EXISTS sources_device_inode_uq ON sources(device, inode)
+-- Not unique: hard links are one device/inode at several paths
+CREATE INDEX IF NOT EXISTS sources_device_inode ON sources(device, inode)
     WHERE device IS NOT NULL AND inode IS NOT NULL;
+-- Finds the old spelling of a path renamed only in case
+CREATE INDEX IF NOT EXISTS sources_rel_path_nocase ON sources(root_id, rel_path COLLATE NOCASE);
 CREATE INDEX IF NOT EXISTS sources_object_id ON sources(object_id);
+CREATE INDEX IF NOT EXISTS apply_provenance_object ON apply_provenance(object_id);
+CREATE INDEX IF NOT EXISTS apply_provenance_dest ON apply_provenance(dest_source_id);
 CREATE INDEX IF NOT EXISTS facts_entity ON facts(entity_type, entity_id);
 CREATE INDEX IF NOT EXISTS facts_key ON facts(key);
 CREATE INDEX IF NOT EXISTS facts_key_entity ON facts(key, entity_type, entity_id);
+CREATE INDEX IF NOT EXISTS facts_quickhash ON facts(value_text) WHERE key = 'content.quickhash';
 CREATE UNIQUE INDEX IF NOT EXISTS facts_entity_key_uq ON facts(entity_type, entity_id, key);
+CREATE INDEX IF NOT EXISTS verifications_object ON verifications(object_id);
+-- Last verification of each source (verify --oldest-first), and pruning by source
+CREATE INDEX IF NOT EXISTS verifications_source ON verifications(source_id, verified_at);
+"#;
+
+/// Archived-object cache: for every object with a present copy in an archive
+/// root, its number of copies and of copies on available roots. Kept current by
+/// triggers so archive-status checks are a primary-key lookup instead of a
+/// join over all archive sources. Applied after column migrations, since the
+/// triggers reference `roots.available`.
+const ARCHIVED_CACHE_SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS archived_objects (
+    object_id INTEGER PRIMARY KEY REFERENCES objects(id),
+    copies INTEGER NOT NULL,
+    online_copies INTEGER NOT NULL
+);
+
+CREATE TRIGGER IF NOT EXISTS archived_objects_source_insert AFTER INSERT ON sources
+WHEN NEW.object_id IS NOT NULL
+    AND (SELECT role FROM roots WHERE id = NEW.root_id) = 'archive'
+BEGIN
+    DELETE FROM archived_objects WHERE object_id = NEW.object_id;
+    INSERT INTO archived_objects (object_id, copies, online_copies)
+        SELECT s.object_id, COUNT(*), SUM(r.available) FROM sources s JOIN roots r ON s.root_id = r.id
+        WHERE s.object_id = NEW.object_id AND s.present = 1 AND r.role = 'archive'
+        GROUP BY s.object_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS archived_objects_source_update AFTER UPDATE OF object_id, present, root_id ON sources
+WHEN (OLD.object_id IS NOT NEW.object_id OR OLD.present != NEW.present OR OLD.root_id != NEW.root_id)
+    AND EXISTS (SELECT 1 FROM roots WHERE id IN (OLD.root_id, NEW.root_id) AND role = 'archive')
+BEGIN
+    DELETE FROM archived_objects WHERE object_id IN (OLD.object_id, NEW.object_id);
+    INSERT INTO archived_objects (object_id, copies, online_copies)
+        SELECT s.object_id, COUNT(*), SUM(r.available) FROM sources s JOIN roots r ON s.root_id = r.id
+        WHERE s.object_id IN (OLD.object_id, NEW.object_id) AND s.present = 1 AND r.role = 'archive'
+        GROUP BY s.object_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS archived_objects_source_delete AFTER DELETE ON sources
+WHEN OLD.object_id IS NOT NULL
+    AND (SELECT role FROM roots WHERE id = OLD.root_id) = 'archive'
+BEGIN
+    DELETE FROM archived_objects WHERE object_id = OLD.object_id;
+    INSERT INTO archived_objects (object_id, copies, online_copies)
+        SELECT s.object_id, COUNT(*), SUM(r.available) FROM sources s JOIN roots r ON s.root_id = r.id
+        WHERE s.object_id = OLD.object_id AND s.present = 1 AND r.role = 'archive'
+        GROUP BY s.object_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS archived_objects_root_update AFTER UPDATE OF role, available ON roots
+WHEN OLD.role != NEW.role OR OLD.available != NEW.available
+BEGIN
+    DELETE FROM archived_objects
+        WHERE object_id IN (SELECT object_id FROM sources WHERE root_id = NEW.id AND object_id IS NOT NULL);
+    INSERT INTO archived_objects (object_id, copies, online_copies)
+        SELECT s.object_id, COUNT(*), SUM(r.available) FROM sources s JOIN roots r ON s.root_id = r.id
+        WHERE s.object_id IN (SELECT object_id FROM sources WHERE root_id = NEW.id AND object_id IS NOT NULL)
+            AND s.present = 1 AND r.role = 'archive'
+        GROUP BY s.object_id;
+END;
+"#;
+
+/// Every hash known for each object: the one it is identified by
+/// (`objects.hash_type`) and others recorded alongside, such as BLAKE3, so
+/// content can be looked up by any of them. Only the identifying hash (SHA-256)
+/// creates objects and decides dedup; the others are recorded for lookup.
+/// Triggers keep the identifying hash in step with `objects`.
+const OBJECT_HASHES_SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS object_hashes (
+    object_id INTEGER NOT NULL REFERENCES objects(id),
+    algorithm TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (object_id, algorithm),
+    UNIQUE (algorithm, value)
+);
+
+CREATE TRIGGER IF NOT EXISTS object_hashes_object_insert AFTER INSERT ON objects
+BEGIN
+    INSERT OR IGNORE INTO object_hashes (object_id, algorithm, value) VALUES (NEW.id, NEW.hash_type, NEW.hash_value);
+END;
+
+CREATE TRIGGER IF NOT EXISTS object_hashes_object_delete AFTER DELETE ON objects
+BEGIN
+    DELETE FROM object_hashes WHERE object_id = OLD.id;
+END;
+"#;
+
+/// Query result cache (`--query-cache`, see `query_cache`): matched source ids
+/// per query, valid while the generation counter and max source id are
+/// unchanged. Triggers bump the counter on every change a filter can see.
+const QUERY_CACHE_SCHEMA: &str = r#"
+CREATE TABLE IF NOT EXISTS query_cache (
+    id INTEGER PRIMARY KEY,
+    key TEXT NOT NULL UNIQUE,
+    generation INTEGER NOT NULL,
+    max_source_id INTEGER NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS query_cache_matches (
+    cache_id INTEGER NOT NULL REFERENCES query_cache(id),
+    source_id INTEGER NOT NULL,
+    PRIMARY KEY (cache_id, source_id)
+) WITHOUT ROWID;
+
+CREATE TABLE IF NOT EXISTS query_generation (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    generation INTEGER NOT NULL
+);
+INSERT OR IGNORE INTO query_generation (id, generation) VALUES (1, 0);
+
+CREATE TRIGGER IF NOT EXISTS query_generation_fact_insert AFTER INSERT ON facts
+BEGIN
+    UPDATE query_generation SET generation = generation + 1;
+END;
+
+CREATE TRIGGER IF NOT EXISTS query_generation_fact_update AFTER UPDATE ON facts
+BEGIN
+    UPDATE query_generation SET generation = generation + 1;
+END;
+
+CREATE TRIGGER IF NOT EXISTS query_generation_fact_delete AFTER DELETE ON facts
+BEGIN
+    UPDATE query_generation SET generation = generation + 1;
+END;
+
+CREATE TRIGGER IF NOT EXISTS query_generation_source_update
+AFTER UPDATE OF root_id, rel_path, size, mtime, device, inode, object_id, present ON sources
+BEGIN
+    UPDATE query_generation SET generation = generation + 1;
+END;
+
+CREATE TRIGGER IF NOT EXISTS query_generation_source_delete AFTER DELETE ON sources
+BEGIN
+    UPDATE query_generation SET generation = generation + 1;
+END;
+
+-- Role and availability decide archived, archived.online and in_archive.
+-- Replaces query_generation_root_update, which only fired on path changes.
+DROP TRIGGER IF EXISTS query_generation_root_update;
+CREATE TRIGGER IF NOT EXISTS query_generation_root_change AFTER UPDATE OF path, role, available ON roots
+BEGIN
+    UPDATE query_generation SET generation = generation + 1;
+END;
 "#;
 
 /// Profile callback for SQL debug logging
@@ -98,7 +409,199 @@ fn sql_profile_callback(sql: &str, duration: Duration) {
     eprintln!("[SQL {:.1}ms] {}", duration.as_secs_f64() * 1000.0, sql);
 }
 
-pub fn open(path: &Path, debug_sql: bool) -> Result<Db> {
+// ============================================================================
+// Query Profiling (--profile)
+// ============================================================================
+
+/// Aggregated statistics for one query shape
+#[derive(Default)]
+struct ShapeStats {
+    count: u64,
+    total: Duration,
+    rows: u64,
+}
+
+struct Profiler {
+    /// Also print each statement as it completes (--debug-sql together with --profile)
+    echo: bool,
+    shapes: HashMap<String, ShapeStats>,
+    /// Rows returned so far by each running statement, keyed by statement pointer
+    pending_rows: HashMap<usize, u64>,
+}
+
+static PROFILER: Mutex<Option<Profiler>> = Mutex::new(None);
+
+/// Collapse whitespace and replace numeric literals with `?` so that queries
+/// built with format!() (e.g. LIMIT values) aggregate into a single shape.
+fn normalize_query_shape(sql: &str) -> String {
+    let mut shape = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    let mut prev = ' ';
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            shape.push(c);
+            if c == '\'' {
+                in_string = false;
+            }
+            prev = c;
+            continue;
+        }
+        if c == '\'' {
+            in_string = true;
+            shape.push(c);
+        } else if c.is_whitespace() {
+            if prev != ' ' {
+                shape.push(' ');
+            }
+            prev = ' ';
+            continue;
+        } else if c.is_ascii_digit() && !(prev.is_alphanumeric() || prev == '_') {
+            while matches!(chars.peek(), Some(n) if n.is_ascii_digit() || *n == '.') {
+                chars.next();
+            }
+            shape.push('?');
+            prev = '?';
+            continue;
+        } else {
+            shape.push(c);
+        }
+        prev = c;
+    }
+
+    shape.trim().to_string()
+}
+
+/// sqlite3_trace_v2 callback: counts rows per statement and records timing when it completes
+unsafe extern "C" fn profile_trace_callback(
+    event: c_uint,
+    _ctx: *mut c_void,
+    p: *mut c_void,
+    x: *mut c_void,
+) -> c_int {
+    let mut guard = match PROFILER.lock() {
+        Ok(g) => g,
+        Err(_) => return 0,
+    };
+    let profiler = match guard.as_mut() {
+        Some(p) => p,
+        None => return 0,
+    };
+
+    let stmt = p as *mut ffi::sqlite3_stmt;
+    match event as c_int {
+        // A top-level statement is starting (X is its own SQL text; trigger
+        // programs pass a comment instead). Statement pointers get reused,
+        // so start its row count fresh.
+        ffi::SQLITE_TRACE_STMT if x as *const c_char == ffi::sqlite3_sql(stmt) => {
+            profiler.pending_rows.remove(&(stmt as usize));
+        }
+        ffi::SQLITE_TRACE_ROW => {
+            *profiler.pending_rows.entry(stmt as usize).or_insert(0) += 1;
+        }
+        ffi::SQLITE_TRACE_PROFILE => {
+            // SAFETY: for SQLITE_TRACE_PROFILE, P is the statement and X points to an i64 of nanoseconds
+            let nanos = *(x as *const i64);
+            let duration = Duration::from_nanos(nanos.max(0) as u64);
+            let sql_ptr = ffi::sqlite3_sql(stmt);
+            if sql_ptr.is_null() {
+                return 0;
+            }
+            let sql = CStr::from_ptr(sql_ptr).to_string_lossy();
+            let rows = profiler.pending_rows.remove(&(stmt as usize)).unwrap_or(0);
+
+            if profiler.echo {
+                sql_profile_callback(&sql, duration);
+            }
+
+            let entry = profiler.shapes.entry(normalize_query_shape(&sql)).or_default();
+            entry.count += 1;
+            entry.total += duration;
+            entry.rows += rows;
+        }
+        _ => {}
+    }
+    0
+}
+
+fn enable_profiling(conn: &Connection, echo: bool) -> Result<()> {
+    *PROFILER.lock().map_err(|_| anyhow::anyhow!("Profiler lock poisoned"))? = Some(Profiler {
+        echo,
+        shapes: HashMap::new(),
+        pending_rows: HashMap::new(),
+    });
+
+    let mask = (ffi::SQLITE_TRACE_STMT | ffi::SQLITE_TRACE_PROFILE | ffi::SQLITE_TRACE_ROW) as c_uint;
+    // SAFETY: the handle is valid for the lifetime of the connection, and the callback
+    // only touches the global profiler state and the statement pointer it is given.
+    let rc = unsafe {
+        ffi::sqlite3_trace_v2(conn.handle(), mask, Some(profile_trace_callback), std::ptr::null_mut())
+    };
+    if rc != ffi::SQLITE_OK {
+        bail!("Failed to enable SQL profiling (sqlite error {})", rc);
+    }
+    Ok(())
+}
+
+/// Print the aggregated query profile to stderr, sorted by total time.
+/// Does nothing unless the database was opened with profiling enabled.
+pub fn print_profile_report() {
+    let profiler = match PROFILER.lock() {
+        Ok(mut g) => g.take(),
+        Err(_) => return,
+    };
+    let profiler = match profiler {
+        Some(p) => p,
+        None => return,
+    };
+
+    let mut shapes: Vec<(String, ShapeStats)> = profiler.shapes.into_iter().collect();
+    shapes.sort_by_key(|(_, s)| std::cmp::Reverse(s.total));
+
+    let total_count: u64 = shapes.iter().map(|(_, s)| s.count).sum();
+    let total_time: Duration = shapes.iter().map(|(_, s)| s.total).sum();
+
+    eprintln!();
+    eprintln!(
+        "SQL profile: {} statements, {} shapes, {:.1}ms total",
+        total_count,
+        shapes.len(),
+        total_time.as_secs_f64() * 1000.0
+    );
+    eprintln!(
+        "{:>10} {:>12} {:>10} {:>10}  Query",
+        "Count", "Total ms", "Mean ms", "Rows"
+    );
+    eprintln!("{}", "─".repeat(100));
+
+    for (shape, stats) in &shapes {
+        let total_ms = stats.total.as_secs_f64() * 1000.0;
+        let mean_ms = total_ms / stats.count.max(1) as f64;
+        let display = if shape.chars().count() > 120 {
+            format!("{}...", shape.chars().take(117).collect::<String>())
+        } else {
+            shape.clone()
+        };
+        eprintln!(
+            "{:>10} {:>12.1} {:>10.3} {:>10}  {}",
+            stats.count, total_ms, mean_ms, stats.rows, display
+        );
+    }
+}
+
+/// `~/.canon/canon.db`, used when no --db is given
+pub fn default_path() -> PathBuf {
+    let mut path = dirs::home_dir().expect("Could not determine home directory");
+    path.push(".canon");
+    path.push("canon.db");
+    path
+}
+
+/// Open (creating if needed) the database at `path` and apply the schema.
+/// `debug_sql` echoes each statement to stderr; `profile` aggregates query shapes
+/// for [`print_profile_report`].
+pub fn open(path: &Path, debug_sql: bool, profile: bool) -> Result<Db> {
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)
             .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
@@ -107,8 +610,11 @@ pub fn open(path: &Path, debug_sql: bool) -> Result<Db> {
     let mut conn = Connection::open(path)
         .with_context(|| format!("Failed to open database: {}", path.display()))?;
 
-    // Enable SQL profiling if debug flag is set
-    if debug_sql {
+    // Enable SQL profiling if requested. --profile takes over the trace hook,
+    // so it also handles --debug-sql echoing when both are set.
+    if profile {
+        enable_profiling(&conn, debug_sql)?;
+    } else if debug_sql {
         conn.profile(Some(sql_profile_callback));
     }
 
@@ -123,26 +629,299 @@ pub fn open(path: &Path, debug_sql: bool) -> Result<Db> {
     conn.execute_batch(SCHEMA)
         .context("Failed to initialize database schema")?;
 
+    // Columns added after the initial schema (CREATE TABLE IF NOT EXISTS won't add them)
+    add_column_if_missing(&conn, "roots", "mount_point", "TEXT")?;
+    add_column_if_missing(&conn, "roots", "available", "INTEGER NOT NULL DEFAULT 1")?;
+    add_column_if_missing(&conn, "roots", "layout", "TEXT")?;
+    add_column_if_missing(&conn, "roots", "scanned_at", "INTEGER")?;
+    if add_column_if_missing(&conn, "sources", "apply_run_id", "INTEGER REFERENCES apply_runs(id)")? {
+        // Which apply run placed existing archive files was never recorded
+        add_column_if_missing(&conn, "sources", "provenance_unknown", "INTEGER NOT NULL DEFAULT 0")?;
+        conn.execute(
+            "UPDATE sources SET provenance_unknown = 1 WHERE root_id IN (SELECT id FROM roots WHERE role = 'archive')",
+            [],
+        )?;
+    }
+    add_column_if_missing(&conn, "apply_runs", "manifest_sha256", "TEXT")?;
+    add_column_if_missing(&conn, "sources", "nlink", "INTEGER")?;
+    add_column_if_missing(&conn, "sources", "btime", "INTEGER")?;
+    add_column_if_missing(&conn, "sources", "provenance_unknown", "INTEGER NOT NULL DEFAULT 0")?;
+    // Replaced by the non-unique sources_device_inode, so hard links can all be indexed
+    conn.execute_batch("DROP INDEX IF EXISTS sources_device_inode_uq")?;
+
+    // Databases from before the cache existed get it populated once
+    let cache_exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'archived_objects')",
+        [],
+        |row| row.get(0),
+    )?;
+    conn.execute_batch(ARCHIVED_CACHE_SCHEMA)
+        .context("Failed to initialize archived-object cache")?;
+    if !cache_exists {
+        rebuild_archived_objects(&conn)?;
+    }
+    conn.execute_batch(QUERY_CACHE_SCHEMA)
+        .context("Failed to initialize query cache")?;
+
+    // Databases from before multiple hashes get each object's identifying hash once
+    let hashes_exist: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'object_hashes')",
+        [],
+        |row| row.get(0),
+    )?;
+    conn.execute_batch(OBJECT_HASHES_SCHEMA)
+        .context("Failed to initialize object hashes")?;
+    if !hashes_exist {
+        conn.execute(
+            "INSERT OR IGNORE INTO object_hashes (object_id, algorithm, value)
+             SELECT id, hash_type, hash_value FROM objects",
+            [],
+        )?;
+    }
+
     Ok(Db { conn })
 }
 
-/// Populate temp_sources table with source IDs using a transaction for efficiency
-pub fn populate_temp_sources(conn: &mut Connection, source_ids: &[i64]) -> Result<()> {
-    conn.execute("CREATE TEMP TABLE IF NOT EXISTS temp_sources (id INTEGER PRIMARY KEY)", [])?;
+/// Add a column unless the table already has it. Returns whether it was added.
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, decl: &str) -> Result<bool> {
+    let exists: bool = conn.query_row(
+        &format!("SELECT EXISTS(SELECT 1 FROM pragma_table_info('{}') WHERE name = ?)", table),
+        [column],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        conn.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, decl))
+            .with_context(|| format!("Failed to add column {}.{}", table, column))?;
+    }
+    Ok(!exists)
+}
+
+// ============================================================================
+// Root Availability
+// ============================================================================
+
+/// The mount point containing `path`: the nearest ancestor (or `path` itself)
+/// on a different device than its parent. Recorded when a root is added so an
+/// unmounted drive can be told apart from an empty mount directory.
+#[cfg(unix)]
+pub fn find_mount_point(path: &Path) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
 
-    let tx = conn.transaction()?;
-    tx.execute("DELETE FROM temp_sources", [])?;
-    {
-        let mut stmt = tx.prepare("INSERT INTO temp_sources (id) VALUES (?)")?;
-        for id in source_ids {
-            stmt.execute([id])?;
+    let mut current = path;
+    let mut dev = fs::metadata(current).ok()?.dev();
+    while let Some(parent) = current.parent() {
+        let parent_dev = fs::metadata(parent).ok()?.dev();
+        if parent_dev != dev {
+            break;
         }
+        current = parent;
+        dev = parent_dev;
+    }
+    current.to_str().map(String::from)
+}
+
+#[cfg(not(unix))]
+pub fn find_mount_point(_path: &Path) -> Option<String> {
+    None
+}
+
+#[cfg(unix)]
+fn is_mount_point(path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::metadata(path), path.parent().map(fs::metadata)) {
+        (Ok(meta), Some(Ok(parent))) => meta.dev() != parent.dev(),
+        (Ok(_), None) => true, // filesystem root
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_mount_point(path: &Path) -> bool {
+    path.exists()
+}
+
+/// Whether a root can be read right now: its directory exists and, if it lives
+/// on a separately mounted filesystem, that filesystem is still mounted.
+/// rclone remotes are assumed reachable.
+pub fn root_available(root_path: &str, mount_point: Option<&str>) -> bool {
+    if remote::is_remote(root_path) {
+        return true;
     }
+    if !Path::new(root_path).is_dir() {
+        return false;
+    }
+    match mount_point {
+        Some(mp) if mp != "/" => is_mount_point(Path::new(mp)),
+        _ => true,
+    }
+}
+
+/// Re-detect availability of every root and store it in `roots.available`.
+/// Roots marked `offline=on` are unavailable whatever is at their path.
+/// Not done on open: stat-ing a stale mount can hang, so only commands that
+/// act on availability (apply, verify, coverage, organize, `archived.online`
+/// filters) refresh it; scan refreshes each root it scans.
+pub fn refresh_root_availability(conn: &Connection) -> Result<()> {
+    let ids: Vec<i64> = conn
+        .prepare("SELECT id FROM roots")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    for id in ids {
+        refresh_one_root_availability(conn, id)?;
+    }
+    Ok(())
+}
+
+/// Re-detect availability of one root, store it, and return it
+pub fn refresh_one_root_availability(conn: &Connection, root_id: i64) -> Result<bool> {
+    let (path, mount_point, was_available, offline): (String, Option<String>, bool, bool) = conn.query_row(
+        "SELECT path, mount_point, available, EXISTS (
+             SELECT 1 FROM root_settings WHERE root_id = roots.id AND key = 'offline' AND value = 'on'
+         ) FROM roots WHERE id = ?",
+        [root_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )?;
+    let available = !offline && root_available(&path, mount_point.as_deref());
+    if available != was_available {
+        conn.execute("UPDATE roots SET available = ? WHERE id = ?", params![available, root_id])?;
+    }
+    Ok(available)
+}
+
+// ============================================================================
+// Archived-Object Cache
+// ============================================================================
+
+/// Recompute `archived_objects` from scratch. Returns (objects, objects with an
+/// online copy).
+pub fn rebuild_archived_objects(conn: &Connection) -> Result<(i64, i64)> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute("DELETE FROM archived_objects", [])?;
+    tx.execute(
+        "INSERT INTO archived_objects (object_id, copies, online_copies)
+         SELECT s.object_id, COUNT(*), SUM(r.available) FROM sources s JOIN roots r ON s.root_id = r.id
+         WHERE s.object_id IS NOT NULL AND s.present = 1 AND r.role = 'archive'
+         GROUP BY s.object_id",
+        [],
+    )?;
+    let counts = tx.query_row(
+        "SELECT COUNT(*), COALESCE(SUM(online_copies > 0), 0) FROM archived_objects",
+        [],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
     tx.commit()?;
+    Ok(counts)
+}
+
+/// Whether an object has a present copy in any archive root (cache lookup)
+pub fn is_archived(conn: &Connection, object_id: i64) -> Result<bool> {
+    Ok(conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM archived_objects WHERE object_id = ?)",
+        [object_id],
+        |row| row.get(0),
+    )?)
+}
+
+/// `canon db refresh-cache`: rebuild the archived-object cache and report whether
+/// it had drifted from the sources
+pub fn refresh_cache(db: &Db) -> Result<()> {
+    let conn = db.conn();
+    let before: i64 = conn.query_row("SELECT COUNT(*) FROM archived_objects", [], |row| row.get(0))?;
+    let (objects, online) = rebuild_archived_objects(conn)?;
+    println!(
+        "Rebuilt archived-object cache: {} objects archived ({} with an online copy)",
+        objects, online
+    );
+    if before != objects {
+        eprintln!("Note: the cache had {} entries before the rebuild", before);
+    }
     Ok(())
 }
 
-/// Parse root spec (id:N or path:/path) with optional role validation
+/// Rows per INSERT when loading `temp_sources`, well under SQLite's bound
+/// parameter limit
+const TEMP_SOURCES_CHUNK: usize = 500;
+
+/// A selection of sources loaded into the `temp_sources` table, so that
+/// set-based queries can join against it. Load it once and run every query
+/// over the same selection while the guard lives; the table is dropped with it.
+pub struct TempSources<'a> {
+    conn: &'a Connection,
+}
+
+impl<'a> TempSources<'a> {
+    /// `temp_sources (id INTEGER PRIMARY KEY)`, for `IN (SELECT id FROM temp_sources)`
+    /// and joins on the source id
+    pub fn load(conn: &'a Connection, source_ids: &[i64]) -> Result<Self> {
+        Self::create(conn, "CREATE TEMP TABLE temp_sources (id INTEGER PRIMARY KEY)", source_ids, |n| {
+            format!("INSERT INTO temp_sources (id) VALUES {}", vec!["(?)"; n].join(", "))
+        })
+    }
+
+    /// `temp_sources (root_id, id, object_id)`, stored in (root_id, id) order
+    /// and indexed by id and object: per-root ranges are read contiguously and
+    /// object joins don't go through the sources table
+    pub fn load_keyed(conn: &'a Connection, source_ids: &[i64]) -> Result<Self> {
+        let create = "CREATE TEMP TABLE temp_sources (
+                          root_id INTEGER NOT NULL,
+                          id INTEGER NOT NULL,
+                          object_id INTEGER,
+                          PRIMARY KEY (root_id, id)
+                      ) WITHOUT ROWID;
+                      CREATE INDEX temp.temp_sources_id ON temp_sources (id);
+                      CREATE INDEX temp.temp_sources_object ON temp_sources (object_id)";
+        Self::create(conn, create, source_ids, |n| {
+            format!(
+                "INSERT INTO temp_sources (root_id, id, object_id)
+                 SELECT root_id, id, object_id FROM sources WHERE id IN ({})",
+                vec!["?"; n].join(", ")
+            )
+        })
+    }
+
+    fn create(
+        conn: &'a Connection,
+        create: &str,
+        source_ids: &[i64],
+        insert: impl Fn(usize) -> String,
+    ) -> Result<Self> {
+        conn.execute_batch("DROP TABLE IF EXISTS temp.temp_sources")?;
+        conn.execute_batch(create)?;
+        let guard = TempSources { conn };
+
+        // A savepoint rather than a transaction: callers may already be in one
+        conn.execute_batch("SAVEPOINT temp_sources")?;
+        let result = (|| -> Result<()> {
+            // Every chunk but the last has the same size, so one statement serves them all
+            for chunk in source_ids.chunks(TEMP_SOURCES_CHUNK) {
+                conn.prepare_cached(&insert(chunk.len()))?.execute(params_from_iter(chunk))?;
+            }
+            Ok(())
+        })();
+        match result {
+            Ok(()) => conn.execute_batch("RELEASE temp_sources")?,
+            Err(_) => conn.execute_batch("ROLLBACK TO temp_sources; RELEASE temp_sources")?,
+        }
+        result.map(|()| guard)
+    }
+}
+
+impl Drop for TempSources<'_> {
+    fn drop(&mut self) {
+        let _ = self.conn.execute_batch("DROP TABLE IF EXISTS temp.temp_sources");
+    }
+}
+
+/// The layout pattern declared for a root (`canon lint --set-layout`), if any
+pub fn root_layout(conn: &Connection, root_id: i64) -> Result<Option<String>> {
+    let layout: Option<String> = conn
+        .query_row("SELECT layout FROM roots WHERE id = ?", [root_id], |row| row.get(0))
+        .with_context(|| format!("No root with id {}", root_id))?;
+    Ok(layout)
+}
+
+/// Parse root spec (id:N, path:/path or name:ALIAS) with optional role validation
 pub fn parse_root_spec(conn: &Connection, spec: &str, required_role: Option<&str>) -> Result<i64> {
     let (id, role) = if let Some(id_str) = spec.strip_prefix("id:") {
         let id: i64 = id_str.parse().context("Invalid root ID")?;
@@ -151,8 +930,8 @@ pub fn parse_root_spec(conn: &Connection, spec: &str, required_role: Option<&str
             .with_context(|| format!("No root with id {}", id))?;
         (id, role)
     } else if let Some(path) = spec.strip_prefix("path:") {
-        let realpath = fs::canonicalize(path)
-            .with_context(|| format!("Failed to resolve path: {}", path))?;
+        // Paths that don't resolve (offline roots, rclone remotes) are matched as given
+        let realpath = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
         let realpath_str = realpath
             .to_str()
             .ok_or_else(|| anyhow::anyhow!("Path contains invalid UTF-8"))?;
@@ -164,8 +943,18 @@ pub fn parse_root_spec(conn: &Connection, spec: &str, required_role: Option<&str
             )
             .with_context(|| format!("No root for path: {}", path))?;
         (id, role)
+    } else if let Some(alias) = spec.strip_prefix("name:") {
+        let (id, role): (i64, String) = conn
+            .query_row(
+                "SELECT r.id, r.role FROM roots r JOIN root_settings rs ON rs.root_id = r.id
+                 WHERE rs.key = 'alias' AND rs.value = ?",
+                [alias],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .with_context(|| format!("No root named '{}' (see canon root alias)", alias))?;
+        (id, role)
     } else {
-        bail!("Invalid format '{}'. Use id:<N> or path:<path>", spec);
+        bail!("Invalid format '{}'. Use id:<N>, path:<path> or name:<alias>", spec);
     };
 
     if let Some(req_role) = required_role {
@@ -178,12 +967,26 @@ pub fn parse_root_spec(conn: &Connection, spec: &str, required_role: Option<&str
 
 /// Resolve a path to its containing root (any role) and relative subdir.
 /// Returns Some((root_id, root_path, role, relative_subdir)) if inside a root, None otherwise.
+/// rclone paths (`remote:path`) and absolute paths that don't exist (offline
+/// roots) are matched as given, without resolving.
 pub fn resolve_root_path(conn: &Connection, path: &Path) -> Result<Option<(i64, String, String, String)>> {
-    let canon_path = fs::canonicalize(path)
-        .with_context(|| format!("Failed to resolve path: {}", path.display()))?;
-    let path_str = canon_path
+    let raw = path
         .to_str()
         .ok_or_else(|| anyhow::anyhow!("Path contains invalid UTF-8"))?;
+    let resolved = if remote::is_remote(raw) {
+        remote::normalize(raw)
+    } else if path.is_absolute() && !path.exists() {
+        // Offline roots (unmounted drives) can still be addressed by their recorded path
+        raw.trim_end_matches('/').to_string()
+    } else {
+        let canon_path = fs::canonicalize(path)
+            .with_context(|| format!("Failed to resolve path: {}", path.display()))?;
+        canon_path
+            .to_str()
+            .ok_or_else(|| anyhow::anyhow!("Path contains invalid UTF-8"))?
+            .to_string()
+    };
+    let path_str = resolved.as_str();
 
     let mut stmt = conn.prepare("SELECT id, path, role FROM roots")?;
     let roots: Vec<(i64, String, String)> = stmt
@@ -0,0 +1,386 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::db::{self, Connection, Db};
+use crate::error::{coded, ErrorCode};
+use crate::import_facts;
+use crate::output;
+
+/// Condition on `objects o`: no source, fact, collection, provenance,
+/// verification, trash or cache entry refers to it. Objects that only keep
+/// facts are left alone, since the file may turn up again.
+macro_rules! unreferenced_object {
+    () => {
+        "NOT EXISTS (SELECT 1 FROM sources WHERE object_id = o.id)
+         AND NOT EXISTS (SELECT 1 FROM facts WHERE entity_type = 'object' AND entity_id = o.id)
+         AND NOT EXISTS (SELECT 1 FROM collection_objects WHERE object_id = o.id)
+         AND NOT EXISTS (SELECT 1 FROM apply_provenance WHERE object_id = o.id)
+         AND NOT EXISTS (SELECT 1 FROM verifications WHERE object_id = o.id)
+         AND NOT EXISTS (SELECT 1 FROM trash WHERE object_id = o.id)
+         AND NOT EXISTS (SELECT 1 FROM archived_objects WHERE object_id = o.id)"
+    };
+}
+
+/// Examples listed per finding
+const EXAMPLES: usize = 5;
+
+/// Full path of `sources s JOIN roots r`
+const SOURCE_PATH: &str = "CASE WHEN s.rel_path = '' THEN r.path ELSE r.path || '/' || s.rel_path END";
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Severity {
+    /// The index contradicts itself; commands may give wrong answers
+    Error,
+    /// Likely a leftover of an interrupted or older operation
+    Warning,
+    /// Worth knowing, nothing is wrong
+    Info,
+}
+
+impl Severity {
+    fn label(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Info => "note",
+        }
+    }
+}
+
+/// A repair `--fix` may make without asking: it only drops or relinks rows
+/// that nothing can use as they are
+struct Fix {
+    description: &'static str,
+    apply: fn(&Connection) -> Result<()>,
+}
+
+struct Check {
+    id: &'static str,
+    severity: Severity,
+    /// What is wrong, after the count (e.g. "sources point to objects that don't exist")
+    problem: &'static str,
+    /// `SELECT COUNT(*)` of the affected rows
+    count: &'static str,
+    /// Example rows, one display string each
+    examples: &'static str,
+    fix: Option<Fix>,
+    /// How to resolve it by hand when there is no safe automatic fix
+    hint: Option<&'static str>,
+}
+
+/// One failed check
+#[derive(Serialize)]
+struct Finding {
+    check: &'static str,
+    severity: Severity,
+    count: i64,
+    problem: &'static str,
+    examples: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fix: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hint: Option<&'static str>,
+    fixed: bool,
+}
+
+/// Result of `canon doctor`
+#[derive(Serialize)]
+struct DoctorReport {
+    checks: usize,
+    findings: Vec<Finding>,
+}
+
+fn checks() -> Vec<Check> {
+    vec![
+        Check {
+            id: "dangling-object",
+            severity: Severity::Error,
+            problem: "sources point to objects that don't exist",
+            count: "SELECT COUNT(*) FROM sources s
+                    WHERE s.object_id IS NOT NULL AND NOT EXISTS (SELECT 1 FROM objects o WHERE o.id = s.object_id)",
+            examples: "SELECT {path} FROM sources s JOIN roots r ON s.root_id = r.id
+                       WHERE s.object_id IS NOT NULL AND NOT EXISTS (SELECT 1 FROM objects o WHERE o.id = s.object_id)",
+            fix: Some(Fix {
+                description: "unlink them; they count as unhashed until hashed again",
+                apply: |conn| {
+                    conn.execute(
+                        "UPDATE sources SET object_id = NULL
+                         WHERE object_id IS NOT NULL AND NOT EXISTS (SELECT 1 FROM objects o WHERE o.id = sources.object_id)",
+                        [],
+                    )?;
+                    Ok(())
+                },
+            }),
+            hint: None,
+        },
+        Check {
+            id: "orphaned-facts",
+            severity: Severity::Error,
+            problem: "facts belong to sources or objects that don't exist",
+            count: "SELECT COUNT(*) FROM facts f WHERE
+                        (f.entity_type = 'source' AND NOT EXISTS (SELECT 1 FROM sources WHERE id = f.entity_id))
+                     OR (f.entity_type = 'object' AND NOT EXISTS (SELECT 1 FROM objects WHERE id = f.entity_id))",
+            examples: "SELECT f.entity_type || ' ' || f.entity_id || ': ' || f.key FROM facts f WHERE
+                           (f.entity_type = 'source' AND NOT EXISTS (SELECT 1 FROM sources WHERE id = f.entity_id))
+                        OR (f.entity_type = 'object' AND NOT EXISTS (SELECT 1 FROM objects WHERE id = f.entity_id))",
+            fix: Some(Fix {
+                description: "delete them",
+                apply: |conn| {
+                    conn.execute(
+                        "DELETE FROM facts WHERE
+                             (entity_type = 'source' AND NOT EXISTS (SELECT 1 FROM sources WHERE id = facts.entity_id))
+                          OR (entity_type = 'object' AND NOT EXISTS (SELECT 1 FROM objects WHERE id = facts.entity_id))",
+                        [],
+                    )?;
+                    Ok(())
+                },
+            }),
+            hint: None,
+        },
+        Check {
+            id: "future-facts",
+            severity: Severity::Error,
+            problem: "source facts were observed at a basis_rev the source hasn't reached",
+            count: "SELECT COUNT(*) FROM facts f JOIN sources s ON f.entity_type = 'source' AND f.entity_id = s.id
+                    WHERE f.observed_basis_rev > s.basis_rev",
+            examples: "SELECT {path} || ': ' || f.key
+                       FROM facts f JOIN sources s ON f.entity_type = 'source' AND f.entity_id = s.id
+                       JOIN roots r ON s.root_id = r.id
+                       WHERE f.observed_basis_rev > s.basis_rev",
+            fix: Some(Fix {
+                description: "delete them; nothing says which version of the file they describe",
+                apply: |conn| {
+                    conn.execute(
+                        "DELETE FROM facts WHERE entity_type = 'source'
+                         AND observed_basis_rev > (SELECT basis_rev FROM sources WHERE id = facts.entity_id)",
+                        [],
+                    )?;
+                    Ok(())
+                },
+            }),
+            hint: None,
+        },
+        Check {
+            id: "archived-cache",
+            severity: Severity::Error,
+            problem: "objects have a wrong entry in the archived-object cache",
+            count: "WITH expected AS (
+                        SELECT s.object_id, COUNT(*) AS copies, SUM(r.available) AS online_copies
+                        FROM sources s JOIN roots r ON s.root_id = r.id
+                        WHERE s.object_id IS NOT NULL AND s.present = 1 AND r.role = 'archive'
+                        GROUP BY s.object_id
+                    )
+                    SELECT (SELECT COUNT(*) FROM (SELECT * FROM expected
+                                EXCEPT SELECT object_id, copies, online_copies FROM archived_objects))
+                         + (SELECT COUNT(*) FROM (SELECT object_id, copies, online_copies FROM archived_objects
+                                EXCEPT SELECT * FROM expected))",
+            examples: "WITH expected AS (
+                           SELECT s.object_id, COUNT(*) AS copies, SUM(r.available) AS online_copies
+                           FROM sources s JOIN roots r ON s.root_id = r.id
+                           WHERE s.object_id IS NOT NULL AND s.present = 1 AND r.role = 'archive'
+                           GROUP BY s.object_id
+                       )
+                       SELECT 'object ' || object_id FROM (
+                           SELECT * FROM expected EXCEPT SELECT object_id, copies, online_copies FROM archived_objects
+                           UNION
+                           SELECT * FROM (SELECT object_id, copies, online_copies FROM archived_objects
+                               EXCEPT SELECT * FROM expected)
+                       )",
+            fix: Some(Fix {
+                description: "rebuild the cache (as `canon db refresh-cache` does)",
+                apply: |conn| db::rebuild_archived_objects(conn).map(|_| ()),
+            }),
+            hint: None,
+        },
+        Check {
+            id: "unpromoted-facts",
+            severity: Severity::Warning,
+            problem: "content facts sit on hashed sources instead of their objects",
+            count: "SELECT COUNT(*) FROM facts f JOIN sources s ON f.entity_type = 'source' AND f.entity_id = s.id
+                    WHERE s.object_id IS NOT NULL AND f.key LIKE 'content.%' AND f.observed_basis_rev = s.basis_rev",
+            examples: "SELECT {path} || ': ' || f.key
+                       FROM facts f JOIN sources s ON f.entity_type = 'source' AND f.entity_id = s.id
+                       JOIN roots r ON s.root_id = r.id
+                       WHERE s.object_id IS NOT NULL AND f.key LIKE 'content.%' AND f.observed_basis_rev = s.basis_rev",
+            fix: Some(Fix {
+                description: "move them to the objects, as an import would have (facts the object already has win)",
+                apply: promote_facts,
+            }),
+            hint: None,
+        },
+        Check {
+            id: "object-fact-namespace",
+            severity: Severity::Warning,
+            problem: "object facts are outside content.*, which is what objects describe",
+            count: "SELECT COUNT(*) FROM facts WHERE entity_type = 'object' AND key NOT LIKE 'content.%'",
+            examples: "SELECT 'object ' || entity_id || ': ' || key FROM facts
+                       WHERE entity_type = 'object' AND key NOT LIKE 'content.%'",
+            fix: None,
+            hint: Some("review them with `canon facts`; facts about one file belong on its source"),
+        },
+        Check {
+            id: "case-duplicates",
+            severity: Severity::Warning,
+            problem: "present sources share a root and a path that differs only in case",
+            count: "SELECT COALESCE(SUM(n), 0) FROM (
+                        SELECT COUNT(*) AS n FROM sources WHERE present = 1
+                        GROUP BY root_id, lower(rel_path) HAVING COUNT(*) > 1
+                    )",
+            examples: "SELECT {path} FROM sources s JOIN roots r ON s.root_id = r.id
+                       WHERE s.present = 1 AND EXISTS (
+                           SELECT 1 FROM sources t WHERE t.root_id = s.root_id AND t.present = 1
+                           AND t.id != s.id AND lower(t.rel_path) = lower(s.rel_path)
+                       )
+                       ORDER BY lower(s.rel_path)",
+            fix: None,
+            hint: Some("on a case-insensitive filesystem they are one file renamed; re-scan the root so the old spelling is marked missing"),
+        },
+        Check {
+            id: "unhashed-archive",
+            severity: Severity::Warning,
+            problem: "sources in archive roots have no content hash, so nothing counts as archived by them",
+            count: "SELECT COUNT(*) FROM sources s JOIN roots r ON s.root_id = r.id
+                    WHERE r.role = 'archive' AND s.present = 1 AND s.object_id IS NULL",
+            examples: "SELECT {path} FROM sources s JOIN roots r ON s.root_id = r.id
+                       WHERE r.role = 'archive' AND s.present = 1 AND s.object_id IS NULL",
+            fix: None,
+            hint: Some("hash them: canon worklist --include-archived --where '!content.hash.sha256?' | <hasher> | canon import-facts --allow-archived"),
+        },
+        Check {
+            id: "orphan-objects",
+            severity: Severity::Info,
+            problem: "objects have no sources and nothing else refers to them",
+            count: concat!("SELECT COUNT(*) FROM objects o WHERE ", unreferenced_object!()),
+            examples: concat!(
+                "SELECT 'object ' || o.id || ' (' || o.hash_type || ':' || substr(o.hash_value, 1, 12) || ')'
+                 FROM objects o WHERE ",
+                unreferenced_object!()
+            ),
+            fix: Some(Fix {
+                description: "delete them",
+                apply: |conn| {
+                    conn.execute(concat!("DELETE FROM objects AS o WHERE ", unreferenced_object!()), [])?;
+                    Ok(())
+                },
+            }),
+            hint: None,
+        },
+        Check {
+            id: "stale-facts",
+            severity: Severity::Info,
+            problem: "source facts describe an earlier version of their file",
+            count: "SELECT COUNT(*) FROM facts f JOIN sources s ON f.entity_type = 'source' AND f.entity_id = s.id
+                    WHERE f.observed_basis_rev < s.basis_rev",
+            examples: "SELECT {path} || ': ' || f.key
+                       FROM facts f JOIN sources s ON f.entity_type = 'source' AND f.entity_id = s.id
+                       JOIN roots r ON s.root_id = r.id
+                       WHERE f.observed_basis_rev < s.basis_rev",
+            fix: None,
+            hint: Some("filters already ignore them; remove them with `canon facts prune --stale --yes`"),
+        },
+    ]
+}
+
+/// Run every consistency check and print the problems found, most serious
+/// first. With `fix`, safe repairs are made for the findings that have one.
+pub fn run(db: &Db, fix: bool) -> Result<()> {
+    let conn = db.conn();
+    let checks = checks();
+
+    let mut findings = Vec::new();
+    for check in &checks {
+        let count: i64 = conn.query_row(check.count, [], |row| row.get(0))?;
+        if count == 0 {
+            continue;
+        }
+        let sql = format!("{} LIMIT {}", check.examples.replace("{path}", SOURCE_PATH), EXAMPLES);
+        let mut stmt = conn.prepare(&sql)?;
+        let examples = stmt.query_map([], |row| row.get(0))?.collect::<Result<Vec<String>, _>>()?;
+
+        let mut fixed = false;
+        if let (true, Some(repair)) = (fix, &check.fix) {
+            (repair.apply)(conn)?;
+            fixed = true;
+        }
+        findings.push(Finding {
+            check: check.id,
+            severity: check.severity,
+            count,
+            problem: check.problem,
+            examples,
+            fix: check.fix.as_ref().map(|f| f.description),
+            hint: check.hint,
+            fixed,
+        });
+    }
+    findings.sort_by_key(|f| f.severity);
+
+    let report = DoctorReport { checks: checks.len(), findings };
+    output::emit(&report, print_report)?;
+
+    let errors = report.findings.iter().filter(|f| f.severity == Severity::Error && !f.fixed).count();
+    if errors > 0 {
+        return Err(coded(ErrorCode::Error, format!("{} consistency errors found", errors)));
+    }
+    Ok(())
+}
+
+/// Move current content facts of hashed sources to their objects
+fn promote_facts(conn: &Connection) -> Result<()> {
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT s.id, s.object_id FROM facts f JOIN sources s ON f.entity_type = 'source' AND f.entity_id = s.id
+         WHERE s.object_id IS NOT NULL AND f.key LIKE 'content.%' AND f.observed_basis_rev = s.basis_rev",
+    )?;
+    let sources = stmt
+        .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let tx = conn.unchecked_transaction()?;
+    for (source_id, object_id) in sources {
+        import_facts::promote_content_facts(&tx, source_id, object_id)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn print_report(report: &DoctorReport) {
+    if report.findings.is_empty() {
+        println!("Ran {} checks: no problems found", report.checks);
+        return;
+    }
+
+    let count = |severity| report.findings.iter().filter(|f| f.severity == severity).count();
+    println!(
+        "Ran {} checks: {} errors, {} warnings, {} notes",
+        report.checks,
+        count(Severity::Error),
+        count(Severity::Warning),
+        count(Severity::Info)
+    );
+
+    let mut fixable = 0;
+    for finding in &report.findings {
+        println!("\n[{}] {} {} ({})", finding.severity.label(), finding.count, finding.problem, finding.check);
+        for example in &finding.examples {
+            println!("    {}", example);
+        }
+        if finding.count > finding.examples.len() as i64 {
+            println!("    ... and {} more", finding.count - finding.examples.len() as i64);
+        }
+        match (finding.fix, finding.fixed) {
+            (Some(fix), true) => println!("  Fixed: {}", fix),
+            (Some(fix), false) => {
+                println!("  Fix: {}", fix);
+                fixable += 1;
+            }
+            (None, _) => {}
+        }
+        if let Some(hint) = finding.hint {
+            println!("  Hint: {}", hint);
+        }
+    }
+
+    if fixable > 0 {
+        println!("\nRun `canon doctor --fix` to make the {} safe repairs above", fixable);
+    }
+}
@@ -0,0 +1,105 @@
+use serde::Serialize;
+use std::fmt;
+
+/// Failure categories with stable process exit codes, so scripts can branch
+/// on the kind of failure rather than its message
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorCode {
+    /// Anything not covered below
+    Error,
+    /// Invalid arguments (also what clap exits with)
+    Usage,
+    /// Apply refused to start: destination collisions, content already
+    /// archived, or excluded sources in the manifest
+    PreflightConflict,
+    /// Manifest sources changed since the manifest was generated
+    StaleManifest,
+    /// The command ran, but some files failed
+    PartialFailure,
+    /// SQLite stayed busy or locked past the busy timeout
+    DbBusy,
+    /// Another canon operation holds the lock (`canon lock`)
+    Locked,
+    /// A root needed by the command is offline or not mounted
+    RootUnavailable,
+}
+
+impl ErrorCode {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorCode::Error => 1,
+            ErrorCode::Usage => 2,
+            ErrorCode::PreflightConflict => 3,
+            ErrorCode::StaleManifest => 4,
+            ErrorCode::PartialFailure => 5,
+            ErrorCode::DbBusy => 6,
+            ErrorCode::Locked => 7,
+            ErrorCode::RootUnavailable => 8,
+        }
+    }
+
+    /// The category of an error: the first `CodedError` in its chain, or a
+    /// busy/locked SQLite error, else `Error`
+    pub fn of(err: &anyhow::Error) -> ErrorCode {
+        for cause in err.chain() {
+            if let Some(coded) = cause.downcast_ref::<CodedError>() {
+                return coded.code;
+            }
+            if let Some(rusqlite::Error::SqliteFailure(e, _)) = cause.downcast_ref::<rusqlite::Error>() {
+                if matches!(e.code, rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked) {
+                    return ErrorCode::DbBusy;
+                }
+            }
+        }
+        ErrorCode::Error
+    }
+}
+
+/// An error message tagged with its category. Displays as just the message.
+#[derive(Debug)]
+pub struct CodedError {
+    pub code: ErrorCode,
+    message: String,
+}
+
+impl fmt::Display for CodedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for CodedError {}
+
+/// `bail!` with a category: `return Err(coded(ErrorCode::Locked, "..."))`
+pub fn coded(code: ErrorCode, message: impl fmt::Display) -> anyhow::Error {
+    CodedError {
+        code,
+        message: message.to_string(),
+    }
+    .into()
+}
+
+/// How a failed command is reported with `--json`: one line on stderr, so
+/// stdout still holds at most the command's own JSON result
+#[derive(Serialize)]
+pub struct ErrorReport {
+    pub code: ErrorCode,
+    pub exit_code: i32,
+    pub message: String,
+    /// Underlying causes, outermost first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub causes: Vec<String>,
+}
+
+impl ErrorReport {
+    pub fn new(err: &anyhow::Error) -> Self {
+        let code = ErrorCode::of(err);
+        ErrorReport {
+            code,
+            exit_code: code.exit_code(),
+            message: err.to_string(),
+            causes: err.chain().skip(1).map(|c| c.to_string()).collect(),
+        }
+    }
+}
@@ -1,12 +1,14 @@
 use anyhow::Result;
 use rusqlite::params;
+use serde::Serialize;
 use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::db::{Connection, Db};
-use crate::filter::{self, Filter};
+use crate::output;
+use crate::pin;
+use crate::query::{Exclusion, SourceQuery};
+use crate::util::current_timestamp;
 
-const BATCH_SIZE: i64 = 1000;
 const POLICY_EXCLUDE_KEY: &str = "policy.exclude";
 
 // ============================================================================
@@ -15,16 +17,39 @@ const POLICY_EXCLUDE_KEY: &str = "policy.exclude";
 
 pub struct SetOptions {
     pub dry_run: bool,
+    /// Only consider these sources (`--stdin`)
+    pub source_ids: Option<Vec<i64>>,
+    /// Exclude pinned sources too
+    pub include_pinned: bool,
 }
 
 pub struct ClearOptions {
     pub dry_run: bool,
+    /// Only consider these sources (`--stdin`)
+    pub source_ids: Option<Vec<i64>>,
+}
+
+/// Result of `exclude set` / `exclude clear`
+#[derive(Serialize)]
+struct ChangeReport {
+    dry_run: bool,
+    count: usize,
+    /// Affected paths (listed for dry runs only)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    paths: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ExcludedSource {
+    id: i64,
+    path: String,
 }
 
 // ============================================================================
 // Set Command
 // ============================================================================
 
+/// Mark matching sources as excluded
 pub fn set(
     db: &Db,
     scope_path: Option<&Path>,
@@ -33,41 +58,45 @@ pub fn set(
 ) -> Result<()> {
     let conn = db.conn();
 
-    // Parse filters
-    let filters: Vec<Filter> = filter_strs
-        .iter()
-        .map(|f| Filter::parse(f))
-        .collect::<Result<Vec<_>>>()?;
-
-    // Resolve scope path
-    let scope_prefix = if let Some(p) = scope_path {
-        Some(std::fs::canonicalize(p)?.to_string_lossy().to_string())
+    // Get matching sources (only from source roots, exclude already-excluded)
+    let source_ids = SourceQuery::new()
+        .scope(scope_path)?
+        .filters(conn, filter_strs)?
+        .selection(options.source_ids.as_deref())
+        .ids(conn)?;
+
+    let source_ids = if options.include_pinned {
+        source_ids
     } else {
-        None
+        let (unpinned, pinned) = pin::without_pinned(conn, source_ids)?;
+        pin::note_skipped(pinned);
+        unpinned
     };
 
-    // Get matching sources (only from source roots, exclude already-excluded)
-    let source_ids = get_matching_sources(&conn, scope_prefix.as_deref(), &filters, false)?;
-
     // Filter out already excluded sources
     let to_exclude: Vec<i64> = source_ids
         .into_iter()
-        .filter(|id| !is_excluded(&conn, *id).unwrap_or(true))
+        .filter(|id| !is_excluded(conn, *id).unwrap_or(true))
         .collect();
 
-    if to_exclude.is_empty() {
-        println!("No sources to exclude (0 matching non-excluded sources)");
-        return Ok(());
-    }
-
-    if options.dry_run {
-        println!("Would exclude {} sources:", to_exclude.len());
+    if to_exclude.is_empty() || options.dry_run {
+        let mut paths = Vec::new();
         for &id in &to_exclude {
-            if let Some(path) = get_source_path(&conn, id)? {
-                println!("  {}", path);
+            if let Some(path) = get_source_path(conn, id)? {
+                paths.push(path);
             }
         }
-        return Ok(());
+        let report = ChangeReport { dry_run: options.dry_run, count: to_exclude.len(), paths };
+        return output::emit(&report, |report| {
+            if report.count == 0 {
+                println!("No sources to exclude (0 matching non-excluded sources)");
+                return;
+            }
+            println!("Would exclude {} sources:", report.count);
+            for path in &report.paths {
+                println!("  {}", path);
+            }
+        });
     }
 
     // Insert exclusion facts
@@ -89,14 +118,15 @@ pub fn set(
         excluded_count += 1;
     }
 
-    println!("Excluded {} sources", excluded_count);
-    Ok(())
+    let report = ChangeReport { dry_run: false, count: excluded_count, paths: Vec::new() };
+    output::emit(&report, |report| println!("Excluded {} sources", report.count))
 }
 
 // ============================================================================
 // Clear Command
 // ============================================================================
 
+/// Remove exclusions from matching sources
 pub fn clear(
     db: &Db,
     scope_path: Option<&Path>,
@@ -105,33 +135,25 @@ pub fn clear(
 ) -> Result<()> {
     let conn = db.conn();
 
-    // Parse filters
-    let filters: Vec<Filter> = filter_strs
-        .iter()
-        .map(|f| Filter::parse(f))
-        .collect::<Result<Vec<_>>>()?;
-
-    // Resolve scope path
-    let scope_prefix = if let Some(p) = scope_path {
-        Some(std::fs::canonicalize(p)?.to_string_lossy().to_string())
-    } else {
-        None
-    };
-
     // Get excluded sources matching filters
-    let excluded_sources = get_excluded_sources(&conn, scope_prefix.as_deref(), &filters)?;
-
-    if excluded_sources.is_empty() {
-        println!("No excluded sources match the given filters");
-        return Ok(());
-    }
+    let excluded_sources = get_excluded_sources(conn, scope_path, filter_strs, options.source_ids.as_deref())?;
 
-    if options.dry_run {
-        println!("Would clear exclusions for {} sources:", excluded_sources.len());
-        for (_, path) in &excluded_sources {
-            println!("  {}", path);
-        }
-        return Ok(());
+    if excluded_sources.is_empty() || options.dry_run {
+        let report = ChangeReport {
+            dry_run: options.dry_run,
+            count: excluded_sources.len(),
+            paths: excluded_sources.into_iter().map(|(_, path)| path).collect(),
+        };
+        return output::emit(&report, |report| {
+            if report.count == 0 {
+                println!("No excluded sources match the given filters");
+                return;
+            }
+            println!("Would clear exclusions for {} sources:", report.count);
+            for path in &report.paths {
+                println!("  {}", path);
+            }
+        });
     }
 
     // Delete exclusion facts
@@ -145,14 +167,15 @@ pub fn clear(
         cleared_count += rows;
     }
 
-    println!("Cleared exclusions for {} sources", cleared_count);
-    Ok(())
+    let report = ChangeReport { dry_run: false, count: cleared_count, paths: Vec::new() };
+    output::emit(&report, |report| println!("Cleared exclusions for {} sources", report.count))
 }
 
 // ============================================================================
 // List Command
 // ============================================================================
 
+/// Print excluded sources
 pub fn list(
     db: &Db,
     scope_path: Option<&Path>,
@@ -160,33 +183,22 @@ pub fn list(
 ) -> Result<()> {
     let conn = db.conn();
 
-    // Parse filters
-    let filters: Vec<Filter> = filter_strs
-        .iter()
-        .map(|f| Filter::parse(f))
-        .collect::<Result<Vec<_>>>()?;
-
-    // Resolve scope path
-    let scope_prefix = if let Some(p) = scope_path {
-        Some(std::fs::canonicalize(p)?.to_string_lossy().to_string())
-    } else {
-        None
-    };
-
     // Get excluded sources matching filters
-    let excluded = get_excluded_sources(&conn, scope_prefix.as_deref(), &filters)?;
-
-    if excluded.is_empty() {
-        println!("No excluded sources match the given filters");
-        return Ok(());
-    }
-
-    println!("Excluded sources ({}):", excluded.len());
-    for (id, path) in &excluded {
-        println!("  {} (id: {})", path, id);
-    }
+    let excluded: Vec<ExcludedSource> = get_excluded_sources(conn, scope_path, filter_strs, None)?
+        .into_iter()
+        .map(|(id, path)| ExcludedSource { id, path })
+        .collect();
 
-    Ok(())
+    output::emit(&excluded, |excluded| {
+        if excluded.is_empty() {
+            println!("No excluded sources match the given filters");
+            return;
+        }
+        println!("Excluded sources ({}):", excluded.len());
+        for source in excluded {
+            println!("  {} (id: {})", source.path, source.id);
+        }
+    })
 }
 
 // ============================================================================
@@ -215,150 +227,28 @@ pub fn exclude_clause(include_excluded: bool) -> &'static str {
     }
 }
 
-/// Count excluded sources in scope
-pub fn count_excluded(conn: &Connection, scope_prefix: Option<&str>, include_archived: bool) -> Result<i64> {
-    let role_clause = if include_archived { "1=1" } else { "r.role = 'source'" };
-
-    let count: i64 = if let Some(prefix) = scope_prefix {
-        conn.query_row(
-            &format!(
-                "SELECT COUNT(*) FROM sources s
-                 JOIN roots r ON s.root_id = r.id
-                 WHERE s.present = 1 AND {}
-                   AND (r.path || '/' || s.rel_path) LIKE ? || '/%'
-                   AND EXISTS (SELECT 1 FROM facts WHERE entity_type = 'source' AND entity_id = s.id AND key = ?)",
-                role_clause
-            ),
-            params![prefix, POLICY_EXCLUDE_KEY],
-            |row| row.get(0),
-        )?
-    } else {
-        conn.query_row(
-            &format!(
-                "SELECT COUNT(*) FROM sources s
-                 JOIN roots r ON s.root_id = r.id
-                 WHERE s.present = 1 AND {}
-                   AND EXISTS (SELECT 1 FROM facts WHERE entity_type = 'source' AND entity_id = s.id AND key = ?)",
-                role_clause
-            ),
-            params![POLICY_EXCLUDE_KEY],
-            |row| row.get(0),
-        )?
-    };
-    Ok(count)
-}
-
-fn get_matching_sources(
-    conn: &Connection,
-    scope_prefix: Option<&str>,
-    filters: &[Filter],
-    include_excluded: bool,
-) -> Result<Vec<i64>> {
-    let mut all_sources = Vec::new();
-    let mut last_id: i64 = 0;
-
-    let exclude_clause = exclude_clause(include_excluded);
-
-    loop {
-        let source_ids: Vec<i64> = if let Some(prefix) = scope_prefix {
-            conn.prepare(&format!(
-                "SELECT s.id FROM sources s
-                 JOIN roots r ON s.root_id = r.id
-                 WHERE s.present = 1 AND r.role = 'source' AND {} AND s.id > ?
-                   AND (r.path || '/' || s.rel_path) LIKE ? || '/%'
-                 ORDER BY s.id LIMIT ?",
-                exclude_clause
-            ))?
-            .query_map(params![last_id, prefix, BATCH_SIZE], |row| row.get(0))?
-            .collect::<Result<Vec<_>, _>>()?
-        } else {
-            conn.prepare(&format!(
-                "SELECT s.id FROM sources s
-                 JOIN roots r ON s.root_id = r.id
-                 WHERE s.present = 1 AND r.role = 'source' AND {} AND s.id > ?
-                 ORDER BY s.id LIMIT ?",
-                exclude_clause
-            ))?
-            .query_map(params![last_id, BATCH_SIZE], |row| row.get(0))?
-            .collect::<Result<Vec<_>, _>>()?
-        };
-
-        if source_ids.is_empty() {
-            break;
-        }
-
-        last_id = *source_ids.last().unwrap();
-
-        // Apply filters
-        let filtered_ids = filter::apply_filters(conn, &source_ids, filters)?;
-        all_sources.extend(filtered_ids);
-    }
-
-    Ok(all_sources)
-}
-
+/// Excluded sources in source roots matching scope and filters, with their paths
 fn get_excluded_sources(
     conn: &Connection,
-    scope_prefix: Option<&str>,
-    filters: &[Filter],
+    scope_path: Option<&Path>,
+    filter_strs: &[String],
+    source_ids: Option<&[i64]>,
 ) -> Result<Vec<(i64, String)>> {
     let mut all_excluded = Vec::new();
-    let mut last_id: i64 = 0;
-
-    loop {
-        let batch: Vec<(i64, String)> = if let Some(prefix) = scope_prefix {
-            conn.prepare(
-                "SELECT s.id, r.path || '/' || s.rel_path as full_path
-                 FROM sources s
-                 JOIN roots r ON s.root_id = r.id
-                 WHERE s.present = 1 AND r.role = 'source' AND s.id > ?
-                   AND (r.path || '/' || s.rel_path) LIKE ? || '/%'
-                   AND EXISTS (
-                       SELECT 1 FROM facts
-                       WHERE entity_type = 'source' AND entity_id = s.id AND key = ?
-                   )
-                 ORDER BY s.id LIMIT ?"
-            )?
-            .query_map(params![last_id, prefix, POLICY_EXCLUDE_KEY, BATCH_SIZE], |row| {
-                Ok((row.get(0)?, row.get(1)?))
-            })?
-            .collect::<Result<Vec<_>, _>>()?
-        } else {
-            conn.prepare(
-                "SELECT s.id, r.path || '/' || s.rel_path as full_path
-                 FROM sources s
-                 JOIN roots r ON s.root_id = r.id
-                 WHERE s.present = 1 AND r.role = 'source' AND s.id > ?
-                   AND EXISTS (
-                       SELECT 1 FROM facts
-                       WHERE entity_type = 'source' AND entity_id = s.id AND key = ?
-                   )
-                 ORDER BY s.id LIMIT ?"
-            )?
-            .query_map(params![last_id, POLICY_EXCLUDE_KEY, BATCH_SIZE], |row| {
-                Ok((row.get(0)?, row.get(1)?))
-            })?
-            .collect::<Result<Vec<_>, _>>()?
-        };
-
-        if batch.is_empty() {
-            break;
-        }
-
-        last_id = batch.last().map(|(id, _)| *id).unwrap();
-
-        // Apply additional filters
-        let ids: Vec<i64> = batch.iter().map(|(id, _)| *id).collect();
-        let filtered_ids = filter::apply_filters(conn, &ids, filters)?;
-
-        // Keep only filtered results
-        for (id, path) in batch {
-            if filtered_ids.contains(&id) {
-                all_excluded.push((id, path));
-            }
-        }
-    }
-
+    SourceQuery::new()
+        .scope(scope_path)?
+        .filters(conn, filter_strs)?
+        .selection(source_ids)
+        .exclusion(Exclusion::Only)
+        .for_each_batch(
+            conn,
+            "s.id, r.path || '/' || s.rel_path",
+            |row| Ok((row.get(0)?, row.get(1)?)),
+            |batch| {
+                all_excluded.extend(batch);
+                Ok(())
+            },
+        )?;
     Ok(all_excluded)
 }
 
@@ -374,10 +264,3 @@ fn get_source_path(conn: &Connection, source_id: i64) -> Result<Option<String>>
         .ok();
     Ok(result)
 }
-
-fn current_timestamp() -> i64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_secs() as i64
-}
@@ -0,0 +1,161 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::db::Db;
+use crate::query::SourceQuery;
+use crate::remote;
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// File list for `rsync --files-from` (relative to the scope path, else absolute)
+    RsyncFiles,
+    /// Extended M3U playlist
+    M3u,
+    /// XMP sidecars next to the files that tag them in digiKam (--tag)
+    Digikam,
+    /// Catalog of an archive root (--archive) for `canon import-catalog`
+    Catalog,
+}
+
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// Output file for list formats (default: stdout)
+    pub output: Option<PathBuf>,
+    /// Tag the digiKam sidecars assign, e.g. "canon/best-of-2021"
+    pub tag: Option<String>,
+    pub include_archived: bool,
+    /// Only consider these sources (`--stdin`)
+    pub source_ids: Option<Vec<i64>>,
+}
+
+/// Export the sources matching scope and filters in a format other tools read
+pub fn run(db: &Db, scope_path: Option<&Path>, filter_strs: &[String], options: &ExportOptions) -> Result<()> {
+    let conn = db.conn();
+    let query = SourceQuery::new()
+        .scope(scope_path)?
+        .filters(conn, filter_strs)?
+        .include_archived(options.include_archived)
+        .selection(options.source_ids.as_deref());
+
+    let mut paths: Vec<(String, String)> = Vec::new();
+    query.for_each_batch(
+        conn,
+        "s.id, r.path, s.rel_path",
+        |row| Ok((row.get(1)?, row.get(2)?)),
+        |batch| {
+            paths.extend(batch);
+            Ok(())
+        },
+    )?;
+    paths.sort();
+
+    if options.format == ExportFormat::Digikam {
+        let tag = options.tag.as_deref().map(|t| t.trim().trim_matches('/')).unwrap_or_default();
+        if tag.is_empty() {
+            bail!("--format digikam needs a non-empty --tag");
+        }
+        return write_sidecars(&paths, tag);
+    }
+
+    let writer: Box<dyn Write> = match options.output {
+        Some(ref path) => Box::new(File::create(path).with_context(|| format!("Failed to create {}", path.display()))?),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut writer = BufWriter::new(writer);
+
+    match options.format {
+        ExportFormat::RsyncFiles => {
+            let base = query.scope_prefix();
+            for (root_path, rel_path) in &paths {
+                let full_path = format!("{}/{}", root_path, rel_path);
+                let listed = match base {
+                    Some(base) => full_path.strip_prefix(base).map(|p| p.trim_start_matches('/')).unwrap_or(&full_path),
+                    None => &full_path,
+                };
+                writeln!(writer, "{}", listed)?;
+            }
+        }
+        ExportFormat::M3u => {
+            writeln!(writer, "#EXTM3U")?;
+            for (root_path, rel_path) in &paths {
+                let title = Path::new(rel_path).file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+                writeln!(writer, "#EXTINF:-1,{}", title)?;
+                writeln!(writer, "{}/{}", root_path, rel_path)?;
+            }
+        }
+        ExportFormat::Digikam => unreachable!("handled above"),
+        ExportFormat::Catalog => unreachable!("written by archive_catalog::export"),
+    }
+    writer.flush()?;
+
+    if let Some(ref path) = options.output {
+        eprintln!("Exported {} files to {}", paths.len(), path.display());
+    }
+    Ok(())
+}
+
+/// Write `<file>.xmp` sidecars (digiKam's naming) that tag each file. digiKam
+/// picks the tag up when it re-reads metadata from the files. Existing
+/// sidecars are never overwritten.
+fn write_sidecars(paths: &[(String, String)], tag: &str) -> Result<()> {
+    let sidecar = sidecar_xmp(tag);
+    let (mut written, mut existing, mut remote_files) = (0u64, 0u64, 0u64);
+    for (root_path, rel_path) in paths {
+        if remote::is_remote(root_path) {
+            remote_files += 1;
+            continue;
+        }
+        let path = PathBuf::from(format!("{}/{}.xmp", root_path, rel_path));
+        if path.exists() {
+            existing += 1;
+            continue;
+        }
+        fs::write(&path, &sidecar).with_context(|| format!("Failed to write {}", path.display()))?;
+        written += 1;
+    }
+
+    println!("Wrote {} sidecars tagging '{}'", written, tag);
+    if existing > 0 {
+        eprintln!("Warning: Skipped {} files that already have a sidecar (not overwritten)", existing);
+    }
+    if remote_files > 0 {
+        eprintln!("Warning: Skipped {} files on remote roots", remote_files);
+    }
+    if written > 0 {
+        println!("In digiKam, select the albums and use Item > Reread Metadata From File to apply the tag.");
+    }
+    Ok(())
+}
+
+/// XMP with the tag as a digiKam tag path ("a/b"), a Lightroom hierarchical
+/// keyword ("a|b") and a plain keyword (the last level)
+fn sidecar_xmp(tag: &str) -> String {
+    let leaf = tag.rsplit('/').next().unwrap_or(tag);
+    format!(
+        r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:digiKam="http://www.digikam.org/ns/1.0/"
+    xmlns:lr="http://ns.adobe.com/lightroom/1.0/"
+    xmlns:dc="http://purl.org/dc/elements/1.1/">
+   <digiKam:TagsList><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></digiKam:TagsList>
+   <lr:hierarchicalSubject><rdf:Bag><rdf:li>{}</rdf:li></rdf:Bag></lr:hierarchicalSubject>
+   <dc:subject><rdf:Bag><rdf:li>{}</rdf:li></rdf:Bag></dc:subject>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>
+"#,
+        xml_escape(tag),
+        xml_escape(&tag.replace('/', "|")),
+        xml_escape(leaf)
+    )
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -0,0 +1,213 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::db::{Connection, Db};
+use crate::import_facts;
+use crate::query::SourceQuery;
+use crate::util::current_timestamp;
+
+pub mod exif;
+pub mod image;
+pub mod kind;
+pub mod mime;
+pub mod video;
+
+/// Facts extracted from one file, keyed in the content.* namespace
+pub type ExtractedFacts = Vec<(String, Value)>;
+
+/// A built-in fact extractor. Implementations only read the file; the
+/// pipeline takes care of source selection, parallelism and storing facts.
+pub trait Extractor: Sync {
+    /// Name shown in the summary line (e.g., "exif")
+    fn name(&self) -> &str;
+
+    /// Whether files with this extension (lowercase, without dot) are worth opening
+    fn handles_ext(&self, ext: &str) -> bool;
+
+    /// Extract facts from a file. An empty result means the file has no metadata.
+    fn extract(&self, path: &Path) -> Result<ExtractedFacts>;
+}
+
+pub struct ExtractOptions {
+    pub jobs: usize,
+    pub include_archived: bool,
+    pub include_excluded: bool,
+}
+
+struct Candidate {
+    source_id: i64,
+    path: PathBuf,
+    basis_rev: i64,
+    object_id: Option<i64>,
+}
+
+#[derive(Default)]
+struct ExtractStats {
+    processed: u64,
+    with_facts: u64,
+    without_metadata: u64,
+    failed: u64,
+    skipped_type: u64,
+    facts_written: u64,
+}
+
+/// Run an extractor over matching sources and store the facts it produces
+pub fn run(
+    db: &Db,
+    extractor: &dyn Extractor,
+    scope_path: Option<&Path>,
+    filter_strs: &[String],
+    options: &ExtractOptions,
+) -> Result<()> {
+    let conn = db.conn();
+
+    let query = SourceQuery::new()
+        .scope(scope_path)?
+        .filters(conn, filter_strs)?
+        .include_archived(options.include_archived)
+        .include_excluded(options.include_excluded);
+
+    let jobs = if options.jobs == 0 {
+        thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+    } else {
+        options.jobs
+    };
+
+    let mut stats = ExtractStats::default();
+
+    query.for_each_raw_batch(
+        conn,
+        "s.id, r.path, s.rel_path, s.basis_rev, s.object_id",
+        |row| Ok((row.get(0)?, (row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get(3)?, row.get(4)?))),
+        |rows| {
+            // Cheap extension check first, so filters only run on files we can read
+            let mut candidates: Vec<Candidate> = Vec::new();
+            for (source_id, (root_path, rel_path, basis_rev, object_id)) in rows {
+                let path = if rel_path.is_empty() {
+                    PathBuf::from(root_path)
+                } else {
+                    Path::new(&root_path).join(&rel_path)
+                };
+                let ext = path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .map(|e| e.to_lowercase())
+                    .unwrap_or_default();
+                if !extractor.handles_ext(&ext) {
+                    stats.skipped_type += 1;
+                    continue;
+                }
+                candidates.push(Candidate { source_id, path, basis_rev, object_id });
+            }
+
+            if !candidates.is_empty() {
+                let ids: Vec<i64> = candidates.iter().map(|c| c.source_id).collect();
+                let kept = query.matches(conn, &ids)?;
+                candidates.retain(|c| kept.contains(&c.source_id));
+            }
+
+            let results = extract_parallel(extractor, &candidates, jobs);
+            store_results(conn, &candidates, results, &mut stats)
+        },
+    )?;
+
+    println!(
+        "Extracted {} from {} files: {} with metadata, {} without, {} failed, {} facts written ({} skipped by file type)",
+        extractor.name(),
+        stats.processed,
+        stats.with_facts,
+        stats.without_metadata,
+        stats.failed,
+        stats.facts_written,
+        stats.skipped_type
+    );
+
+    Ok(())
+}
+
+/// Run the extractor over all candidates on `jobs` worker threads.
+/// Results are returned in candidate order.
+fn extract_parallel(
+    extractor: &dyn Extractor,
+    candidates: &[Candidate],
+    jobs: usize,
+) -> Vec<Result<ExtractedFacts>> {
+    let next = AtomicUsize::new(0);
+    let collected: Mutex<Vec<(usize, Result<ExtractedFacts>)>> =
+        Mutex::new(Vec::with_capacity(candidates.len()));
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(candidates.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(candidate) = candidates.get(i) else {
+                    break;
+                };
+                let result = extractor.extract(&candidate.path);
+                collected.lock().unwrap().push((i, result));
+            });
+        }
+    });
+
+    let mut collected = collected.into_inner().unwrap();
+    collected.sort_by_key(|(i, _)| *i);
+    collected.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Write extracted facts for one batch in a single transaction.
+/// Facts land on the object when the source is hashed, otherwise on the source
+/// (tagged with its basis_rev so they are promoted or pruned like imported facts).
+fn store_results(
+    conn: &Connection,
+    candidates: &[Candidate],
+    results: Vec<Result<ExtractedFacts>>,
+    stats: &mut ExtractStats,
+) -> Result<()> {
+    let now = current_timestamp();
+    let tx = conn.unchecked_transaction()?;
+
+    for (candidate, result) in candidates.iter().zip(results) {
+        stats.processed += 1;
+        let facts = match result {
+            Ok(facts) => facts,
+            Err(e) => {
+                eprintln!("Warning: {}: {:#}", candidate.path.display(), e);
+                stats.failed += 1;
+                continue;
+            }
+        };
+
+        if facts.is_empty() {
+            stats.without_metadata += 1;
+            continue;
+        }
+        stats.with_facts += 1;
+
+        for (key, value) in &facts {
+            match candidate.object_id {
+                Some(object_id) => {
+                    import_facts::insert_fact(&tx, "object", object_id, key, value, now, None)?;
+                }
+                None => {
+                    import_facts::insert_fact(
+                        &tx,
+                        "source",
+                        candidate.source_id,
+                        key,
+                        value,
+                        now,
+                        Some(candidate.basis_rev),
+                    )?;
+                }
+            }
+            stats.facts_written += 1;
+        }
+    }
+
+    tx.commit()?;
+    Ok(())
+}
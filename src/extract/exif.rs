@@ -0,0 +1,127 @@
+use anyhow::Result;
+use exif::{Exif, In, Reader, Tag};
+use serde_json::Value;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use super::{ExtractedFacts, Extractor};
+
+/// Containers kamadak-exif can read: JPEG, TIFF-based RAW, HEIF, PNG and WebP
+const EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "jpe", "tif", "tiff", "heic", "heif", "avif", "png", "webp",
+    "dng", "cr2", "nef", "nrw", "arw", "srw", "pef",
+];
+
+/// Extracts content.exif.* facts (capture time, camera, lens, GPS, orientation)
+pub struct ExifExtractor;
+
+impl Extractor for ExifExtractor {
+    fn name(&self) -> &str {
+        "exif"
+    }
+
+    fn handles_ext(&self, ext: &str) -> bool {
+        EXTENSIONS.contains(&ext)
+    }
+
+    fn extract(&self, path: &Path) -> Result<ExtractedFacts> {
+        let file = File::open(path)?;
+        let exif = match Reader::new().read_from_container(&mut BufReader::new(file)) {
+            Ok(exif) => exif,
+            // No EXIF block is a normal outcome, not a failure
+            Err(exif::Error::NotFound(_)) | Err(exif::Error::BlankValue(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(exif_facts(&exif))
+    }
+}
+
+fn exif_facts(exif: &Exif) -> ExtractedFacts {
+    let mut facts = Vec::new();
+
+    // Stored in EXIF format ("2024:06:01 14:30:00"); import classifies it as a time value
+    if let Some(dt) = ascii_field(exif, Tag::DateTimeOriginal) {
+        if chrono::NaiveDateTime::parse_from_str(&dt, "%Y:%m:%d %H:%M:%S").is_ok() {
+            facts.push(("content.exif.datetime_original".to_string(), Value::String(dt)));
+        }
+    }
+    if let Some(make) = ascii_field(exif, Tag::Make) {
+        facts.push(("content.exif.make".to_string(), Value::String(make)));
+    }
+    if let Some(model) = ascii_field(exif, Tag::Model) {
+        facts.push(("content.exif.model".to_string(), Value::String(model)));
+    }
+    if let Some(lens) = ascii_field(exif, Tag::LensModel) {
+        facts.push(("content.exif.lens".to_string(), Value::String(lens)));
+    }
+    if let Some(orientation) = exif
+        .get_field(Tag::Orientation, In::PRIMARY)
+        .and_then(|f| f.value.get_uint(0))
+    {
+        facts.push(("content.exif.orientation".to_string(), Value::from(orientation)));
+    }
+
+    if let Some(lat) = gps_coordinate(exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, "S") {
+        facts.push(("content.exif.gps.latitude".to_string(), Value::from(lat)));
+    }
+    if let Some(lon) = gps_coordinate(exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, "W") {
+        facts.push(("content.exif.gps.longitude".to_string(), Value::from(lon)));
+    }
+    if let Some(alt) = gps_altitude(exif) {
+        facts.push(("content.exif.gps.altitude".to_string(), Value::from(alt)));
+    }
+
+    facts
+}
+
+/// First string of an ASCII field, trimmed; None when missing or blank
+fn ascii_field(exif: &Exif, tag: Tag) -> Option<String> {
+    let field = exif.get_field(tag, In::PRIMARY)?;
+    match field.value {
+        exif::Value::Ascii(ref values) => {
+            let raw = values.first()?;
+            let s = String::from_utf8_lossy(raw)
+                .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+                .to_string();
+            if s.is_empty() {
+                None
+            } else {
+                Some(s)
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Degrees/minutes/seconds rationals to signed decimal degrees
+fn gps_coordinate(exif: &Exif, tag: Tag, ref_tag: Tag, negative_ref: &str) -> Option<f64> {
+    let field = exif.get_field(tag, In::PRIMARY)?;
+    let dms = match field.value {
+        exif::Value::Rational(ref v) if v.len() >= 3 => v,
+        _ => return None,
+    };
+    if dms.iter().take(3).any(|r| r.denom == 0) {
+        return None;
+    }
+    let degrees = dms[0].to_f64() + dms[1].to_f64() / 60.0 + dms[2].to_f64() / 3600.0;
+
+    let negative = ascii_field(exif, ref_tag)
+        .map(|r| r.eq_ignore_ascii_case(negative_ref))
+        .unwrap_or(false);
+    Some(if negative { -degrees } else { degrees })
+}
+
+/// Altitude in meters; GPSAltitudeRef 1 means below sea level
+fn gps_altitude(exif: &Exif) -> Option<f64> {
+    let field = exif.get_field(Tag::GPSAltitude, In::PRIMARY)?;
+    let altitude = match field.value {
+        exif::Value::Rational(ref v) if !v.is_empty() && v[0].denom != 0 => v[0].to_f64(),
+        _ => return None,
+    };
+    let below_sea_level = exif
+        .get_field(Tag::GPSAltitudeRef, In::PRIMARY)
+        .and_then(|f| f.value.get_uint(0))
+        == Some(1);
+    Some(if below_sea_level { -altitude } else { altitude })
+}
@@ -0,0 +1,112 @@
+use anyhow::{bail, Result};
+use exif::{Exif, In, Reader, Tag};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use super::{ExtractedFacts, Extractor};
+
+pub(super) const IMAGE_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "jpe", "png", "gif", "webp", "bmp", "heic", "heif", "avif", "tif", "tiff",
+];
+
+/// RAW formats: the first IFD is usually a preview, so dimensions come from EXIF instead
+pub(super) const RAW_EXTENSIONS: &[&str] = &[
+    "dng", "cr2", "nef", "nrw", "arw", "srw", "pef", "orf", "rw2", "raf",
+];
+
+/// Containers whose EXIF block precedes the image data
+const EXIF_HEADER_EXTENSIONS: &[&str] = &["jpg", "jpeg", "jpe", "heic", "heif", "avif"];
+
+/// How much of a TIFF-based file to read when looking for the orientation tag.
+/// IFD0 sits at the start of the file, so this avoids reading whole RAW files.
+const TIFF_HEADER_BYTES: u64 = 256 * 1024;
+
+/// Extracts content.image.width/height/orientation from file headers only
+pub struct ImageExtractor;
+
+impl Extractor for ImageExtractor {
+    fn name(&self) -> &str {
+        "image"
+    }
+
+    fn handles_ext(&self, ext: &str) -> bool {
+        IMAGE_EXTENSIONS.contains(&ext) || RAW_EXTENSIONS.contains(&ext)
+    }
+
+    fn extract(&self, path: &Path) -> Result<ExtractedFacts> {
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .unwrap_or_default();
+        let is_raw = RAW_EXTENSIONS.contains(&ext.as_str());
+        let is_tiff = is_raw || ext == "tif" || ext == "tiff";
+
+        // EXIF is optional here: a missing or broken block only costs the orientation.
+        // PNG and WebP keep EXIF after the pixel data, so they are not searched.
+        let exif = if is_tiff {
+            read_tiff_header_exif(path)
+        } else if EXIF_HEADER_EXTENSIONS.contains(&ext.as_str()) {
+            File::open(path)
+                .ok()
+                .and_then(|f| Reader::new().read_from_container(&mut BufReader::new(f)).ok())
+        } else {
+            None
+        };
+
+        let dimensions = if is_raw {
+            exif.as_ref().and_then(exif_dimensions)
+        } else {
+            match imagesize::size(path) {
+                Ok(size) => Some((size.width as u64, size.height as u64)),
+                Err(imagesize::ImageError::NotSupported) => None,
+                Err(imagesize::ImageError::CorruptedImage) => bail!("Corrupted image header"),
+                Err(imagesize::ImageError::IoError(e)) => return Err(e.into()),
+            }
+        };
+
+        let mut facts = Vec::new();
+        if let Some((width, height)) = dimensions {
+            facts.push(("content.image.width".to_string(), Value::from(width)));
+            facts.push(("content.image.height".to_string(), Value::from(height)));
+        }
+        if let Some(orientation) = exif
+            .as_ref()
+            .and_then(|e| e.get_field(Tag::Orientation, In::PRIMARY))
+            .and_then(|f| f.value.get_uint(0))
+            .filter(|o| (1..=8).contains(o))
+        {
+            facts.push(("content.image.orientation".to_string(), Value::from(orientation)));
+        }
+
+        Ok(facts)
+    }
+}
+
+/// Parse EXIF from the start of a TIFF-based file, tolerating offsets that
+/// point past the bytes read (thumbnails, maker notes).
+fn read_tiff_header_exif(path: &Path) -> Option<Exif> {
+    let mut buf = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(TIFF_HEADER_BYTES)
+        .read_to_end(&mut buf)
+        .ok()?;
+
+    match Reader::new().continue_on_error(true).read_raw(buf) {
+        Ok(exif) => Some(exif),
+        Err(e) => e.distill_partial_result(|_| {}).ok(),
+    }
+}
+
+/// Full image size recorded by the camera, as opposed to the preview in IFD0
+fn exif_dimensions(exif: &Exif) -> Option<(u64, u64)> {
+    let width = exif.get_field(Tag::PixelXDimension, In::PRIMARY)?.value.get_uint(0)?;
+    let height = exif.get_field(Tag::PixelYDimension, In::PRIMARY)?.value.get_uint(0)?;
+    if width == 0 || height == 0 {
+        return None;
+    }
+    Some((width as u64, height as u64))
+}
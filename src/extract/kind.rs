@@ -0,0 +1,130 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use super::image::{IMAGE_EXTENSIONS, RAW_EXTENSIONS};
+use super::mime::{sniff, HEADER_BYTES};
+use super::video::EXTENSIONS as VIDEO_EXTENSIONS;
+use super::{ExtractedFacts, Extractor};
+
+const AUDIO_EXTENSIONS: &[&str] = &[
+    "mp3", "m4a", "aac", "flac", "ogg", "oga", "opus", "wav", "aif", "aiff", "wma", "alac",
+];
+
+const DOCUMENT_EXTENSIONS: &[&str] = &[
+    "pdf", "doc", "docx", "odt", "rtf", "txt", "md", "xls", "xlsx", "ods", "csv", "ppt", "pptx",
+    "odp", "pages", "numbers", "key", "epub",
+];
+
+const ARCHIVE_EXTENSIONS: &[&str] = &["zip", "gz", "tgz", "tar", "bz2", "xz", "zst", "7z", "rar"];
+
+/// Display resolutions (landscape) that PNGs without a telling name are
+/// matched against: desktop monitors, laptops and common phones
+const SCREEN_SIZES: &[(u64, u64)] = &[
+    (1280, 720), (1280, 800), (1366, 768), (1440, 900), (1536, 864), (1600, 900),
+    (1680, 1050), (1920, 1080), (1920, 1200), (2560, 1440), (2560, 1600), (2880, 1800),
+    (3024, 1964), (3456, 2234), (3840, 2160), (5120, 2880),
+    (1334, 750), (1792, 828), (2208, 1242), (2340, 1080), (2400, 1080), (2436, 1125),
+    (2532, 1170), (2556, 1179), (2688, 1242), (2778, 1284), (2796, 1290), (3088, 1440),
+    (3200, 1440),
+];
+
+/// Extracts content.kind: one of photo, video, raw, screenshot, audio,
+/// document, archive or other, for filters that don't care about formats
+pub struct KindExtractor;
+
+impl Extractor for KindExtractor {
+    fn name(&self) -> &str {
+        "kind"
+    }
+
+    fn handles_ext(&self, _ext: &str) -> bool {
+        true
+    }
+
+    fn extract(&self, path: &Path) -> Result<ExtractedFacts> {
+        let mut header = Vec::new();
+        File::open(path)?.take(HEADER_BYTES).read_to_end(&mut header)?;
+        let kind = classify(path, &header);
+        Ok(vec![("content.kind".to_string(), Value::String(kind.to_string()))])
+    }
+}
+
+/// Kind of a file from its signature, falling back to the extension where
+/// the signature is ambiguous (zip-based documents, plain text, unknown data)
+fn classify(path: &Path, header: &[u8]) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+    let ext = ext.as_str();
+    let mime = sniff(header);
+
+    // TIFF-based RAW files sniff as image/tiff
+    if RAW_EXTENSIONS.contains(&ext) || mime == "image/x-canon-cr3" {
+        return "raw";
+    }
+    if mime.starts_with("image/") {
+        return if is_screenshot(path, mime) { "screenshot" } else { "photo" };
+    }
+    if mime.starts_with("video/") {
+        return "video";
+    }
+    if mime.starts_with("audio/") {
+        return "audio";
+    }
+    match mime {
+        "application/pdf" => "document",
+        "application/gzip" => "archive",
+        // Office and OpenDocument files are zip containers too
+        "application/zip" if DOCUMENT_EXTENSIONS.contains(&ext) => "document",
+        "application/zip" => "archive",
+        _ => kind_from_ext(ext),
+    }
+}
+
+fn kind_from_ext(ext: &str) -> &'static str {
+    if IMAGE_EXTENSIONS.contains(&ext) {
+        "photo"
+    } else if VIDEO_EXTENSIONS.contains(&ext) {
+        "video"
+    } else if AUDIO_EXTENSIONS.contains(&ext) {
+        "audio"
+    } else if DOCUMENT_EXTENSIONS.contains(&ext) {
+        "document"
+    } else if ARCHIVE_EXTENSIONS.contains(&ext) {
+        "archive"
+    } else {
+        "other"
+    }
+}
+
+/// Screenshot tools name their files after themselves ("Screenshot 2024-...",
+/// "Screen Shot ...", "screenshot_...") and save PNGs at the display's exact
+/// resolution; camera photos are rarely either
+fn is_screenshot(path: &Path, mime: &str) -> bool {
+    let name: String = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default()
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect();
+    if name.contains("screenshot") || name.contains("bildschirmfoto") {
+        return true;
+    }
+    if mime != "image/png" {
+        return false;
+    }
+    match imagesize::size(path) {
+        Ok(size) => {
+            let (w, h) = (size.width as u64, size.height as u64);
+            SCREEN_SIZES.iter().any(|&(sw, sh)| (w, h) == (sw, sh) || (w, h) == (sh, sw))
+        }
+        Err(_) => false,
+    }
+}
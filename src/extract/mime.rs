@@ -0,0 +1,131 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use super::{ExtractedFacts, Extractor};
+
+/// Bytes read from the start of each file; enough for every signature below
+pub(super) const HEADER_BYTES: u64 = 64;
+
+/// ISO base media (ftyp) major brands and their types
+const FTYP_BRANDS: &[(&[u8], &str)] = &[
+    (b"heic", "image/heic"),
+    (b"heix", "image/heic"),
+    (b"mif1", "image/heif"),
+    (b"msf1", "image/heif"),
+    (b"avif", "image/avif"),
+    (b"qt  ", "video/quicktime"),
+    (b"3gp4", "video/3gpp"),
+    (b"3gp5", "video/3gpp"),
+    (b"3g2a", "video/3gpp2"),
+    (b"M4A ", "audio/mp4"),
+    (b"crx ", "image/x-canon-cr3"),
+];
+
+/// Extracts content.mime by sniffing file signatures (like `file --mime-type`
+/// for common media), without running an external tool
+pub struct MimeExtractor;
+
+impl Extractor for MimeExtractor {
+    fn name(&self) -> &str {
+        "mime"
+    }
+
+    fn handles_ext(&self, _ext: &str) -> bool {
+        true
+    }
+
+    fn extract(&self, path: &Path) -> Result<ExtractedFacts> {
+        let mut header = Vec::new();
+        File::open(path)?.take(HEADER_BYTES).read_to_end(&mut header)?;
+        let mime = sniff(&header);
+        Ok(vec![("content.mime".to_string(), Value::String(mime.to_string()))])
+    }
+}
+
+/// Media type of a file from its first bytes
+pub(super) fn sniff(header: &[u8]) -> &'static str {
+    let starts = |magic: &[u8]| header.starts_with(magic);
+    let at = |offset: usize, magic: &[u8]| header.get(offset..offset + magic.len()) == Some(magic);
+
+    if header.is_empty() {
+        return "inode/x-empty";
+    }
+    if starts(b"\xFF\xD8\xFF") {
+        return "image/jpeg";
+    }
+    if starts(b"\x89PNG\r\n\x1A\n") {
+        return "image/png";
+    }
+    if starts(b"GIF87a") || starts(b"GIF89a") {
+        return "image/gif";
+    }
+    if starts(b"II*\0") || starts(b"MM\0*") {
+        return "image/tiff";
+    }
+    if starts(b"BM") && header.len() >= 14 {
+        return "image/bmp";
+    }
+    if starts(b"RIFF") {
+        if at(8, b"WEBP") {
+            return "image/webp";
+        }
+        if at(8, b"AVI ") {
+            return "video/x-msvideo";
+        }
+        if at(8, b"WAVE") {
+            return "audio/x-wav";
+        }
+    }
+    if at(4, b"ftyp") {
+        let brand = header.get(8..12).unwrap_or_default();
+        return FTYP_BRANDS
+            .iter()
+            .find(|(b, _)| *b == brand)
+            .map(|(_, mime)| *mime)
+            .unwrap_or("video/mp4");
+    }
+    if starts(b"\x1A\x45\xDF\xA3") {
+        // Matroska and WebM share the EBML header; the doctype follows shortly after
+        return if header.windows(4).any(|w| w == b"webm") {
+            "video/webm"
+        } else {
+            "video/x-matroska"
+        };
+    }
+    if starts(b"ID3") || starts(b"\xFF\xFB") || starts(b"\xFF\xF3") || starts(b"\xFF\xF2") {
+        return "audio/mpeg";
+    }
+    if starts(b"fLaC") {
+        return "audio/flac";
+    }
+    if starts(b"OggS") {
+        return "audio/ogg";
+    }
+    if starts(b"%PDF-") {
+        return "application/pdf";
+    }
+    if starts(b"PK\x03\x04") {
+        return "application/zip";
+    }
+    if starts(b"\x1F\x8B") {
+        return "application/gzip";
+    }
+    if looks_like_text(header) {
+        return "text/plain";
+    }
+    "application/octet-stream"
+}
+
+/// No NUL bytes and valid UTF-8, allowing a sequence cut off at the end of the header
+fn looks_like_text(header: &[u8]) -> bool {
+    if header.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(header) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    }
+}
@@ -0,0 +1,238 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{ExtractedFacts, Extractor};
+
+pub(super) const EXTENSIONS: &[&str] = &[
+    "mp4", "m4v", "mov", "qt", "mkv", "webm", "avi", "wmv", "mts", "m2ts", "ts",
+    "3gp", "3g2", "mpg", "mpeg", "flv",
+];
+
+/// ffprobe is killed if it takes longer than this on a single file
+const PROBE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Extracts content.video.* facts by running ffprobe on each file
+pub struct VideoExtractor {
+    ffprobe: PathBuf,
+}
+
+impl VideoExtractor {
+    /// Check that ffprobe can be run before any file is processed, so a missing
+    /// binary fails once instead of once per file.
+    pub fn new(ffprobe: &Path) -> Result<Self> {
+        let status = Command::new(ffprobe)
+            .arg("-version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .with_context(|| format!("Failed to run {} (install ffmpeg or pass --ffprobe PATH)", ffprobe.display()))?;
+        if !status.success() {
+            bail!("{} -version exited with {}", ffprobe.display(), status);
+        }
+        Ok(VideoExtractor {
+            ffprobe: ffprobe.to_path_buf(),
+        })
+    }
+}
+
+impl Extractor for VideoExtractor {
+    fn name(&self) -> &str {
+        "video"
+    }
+
+    fn handles_ext(&self, ext: &str) -> bool {
+        EXTENSIONS.contains(&ext)
+    }
+
+    fn extract(&self, path: &Path) -> Result<ExtractedFacts> {
+        let output = run_ffprobe(&self.ffprobe, path)?;
+        let probe: Probe = serde_json::from_slice(&output).context("Failed to parse ffprobe output")?;
+        Ok(video_facts(&probe))
+    }
+}
+
+// ============================================================================
+// ffprobe JSON
+// ============================================================================
+
+#[derive(Deserialize)]
+struct Probe {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Deserialize)]
+struct ProbeStream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    width: Option<i64>,
+    height: Option<i64>,
+    avg_frame_rate: Option<String>,
+    r_frame_rate: Option<String>,
+    duration: Option<String>,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct ProbeFormat {
+    format_name: Option<String>,
+    duration: Option<String>,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+/// Run ffprobe with a timeout, returning its stdout
+fn run_ffprobe(ffprobe: &Path, path: &Path) -> Result<Vec<u8>> {
+    let mut child = Command::new(ffprobe)
+        .args(["-v", "error", "-print_format", "json", "-show_format", "-show_streams"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", ffprobe.display()))?;
+
+    // Drain pipes on their own threads so a chatty ffprobe can't block on a full pipe
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let stdout_reader = thread::spawn(move || {
+        let mut buf = Vec::new();
+        stdout.read_to_end(&mut buf).map(|_| buf)
+    });
+    let stderr_reader = thread::spawn(move || {
+        let mut buf = String::new();
+        stderr.read_to_string(&mut buf).map(|_| buf)
+    });
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if started.elapsed() > PROBE_TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("ffprobe timed out after {}s", PROBE_TIMEOUT.as_secs());
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    let stdout = stdout_reader.join().expect("stdout reader panicked")?;
+    let stderr = stderr_reader.join().expect("stderr reader panicked")?;
+
+    if !status.success() {
+        // ffprobe prefixes errors with the input path, which the caller already reports
+        let prefix = format!("{}: ", path.display());
+        let message = stderr.lines().last().unwrap_or("").trim();
+        let message = message.strip_prefix(&prefix).unwrap_or(message);
+        if message.is_empty() {
+            bail!("ffprobe exited with {}", status);
+        }
+        bail!("ffprobe: {}", message);
+    }
+
+    Ok(stdout)
+}
+
+// ============================================================================
+// Normalization
+// ============================================================================
+
+fn video_facts(probe: &Probe) -> ExtractedFacts {
+    let mut facts = Vec::new();
+
+    let video = probe
+        .streams
+        .iter()
+        .find(|s| s.codec_type.as_deref() == Some("video") && s.width.is_some());
+    let audio = probe
+        .streams
+        .iter()
+        .find(|s| s.codec_type.as_deref() == Some("audio"));
+
+    // Files without a video stream (audio-only containers, broken files) get no facts
+    let Some(video) = video else {
+        return facts;
+    };
+
+    let duration = probe
+        .format
+        .as_ref()
+        .and_then(|f| parse_f64(f.duration.as_deref()))
+        .or_else(|| parse_f64(video.duration.as_deref()));
+    if let Some(duration) = duration {
+        facts.push(("content.video.duration".to_string(), Value::from(round3(duration))));
+    }
+
+    if let Some(ref codec) = video.codec_name {
+        facts.push(("content.video.codec".to_string(), Value::String(codec.clone())));
+    }
+    if let (Some(width), Some(height)) = (video.width, video.height) {
+        facts.push(("content.video.width".to_string(), Value::from(width)));
+        facts.push(("content.video.height".to_string(), Value::from(height)));
+        facts.push((
+            "content.video.resolution".to_string(),
+            Value::String(format!("{}x{}", width, height)),
+        ));
+    }
+
+    let framerate = parse_rate(video.avg_frame_rate.as_deref())
+        .or_else(|| parse_rate(video.r_frame_rate.as_deref()));
+    if let Some(fps) = framerate {
+        facts.push(("content.video.framerate".to_string(), Value::from(round3(fps))));
+    }
+
+    if let Some(codec) = audio.and_then(|a| a.codec_name.as_ref()) {
+        facts.push(("content.video.audio_codec".to_string(), Value::String(codec.clone())));
+    }
+
+    // creation_time is RFC 3339 ("2024-06-01T14:30:00.000000Z"), stored as a time value
+    let creation_time = probe
+        .format
+        .as_ref()
+        .and_then(|f| f.tags.get("creation_time"))
+        .or_else(|| video.tags.get("creation_time"));
+    if let Some(ts) = creation_time.and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok()) {
+        // Cameras without a clock write the QuickTime epoch; treat it as unknown
+        if ts.timestamp() > 0 {
+            facts.push((
+                "content.video.creation_time".to_string(),
+                Value::String(ts.to_rfc3339()),
+            ));
+        }
+    }
+
+    if let Some(container) = probe.format.as_ref().and_then(|f| f.format_name.as_ref()) {
+        facts.push(("content.video.container".to_string(), Value::String(container.clone())));
+    }
+
+    facts
+}
+
+fn parse_f64(s: Option<&str>) -> Option<f64> {
+    s.and_then(|s| s.parse::<f64>().ok()).filter(|v| v.is_finite())
+}
+
+/// ffprobe rates are fractions like "30000/1001"; "0/0" means unknown
+fn parse_rate(s: Option<&str>) -> Option<f64> {
+    let (num, den) = s?.split_once('/')?;
+    let num: f64 = num.parse().ok()?;
+    let den: f64 = den.parse().ok()?;
+    if num <= 0.0 || den <= 0.0 {
+        return None;
+    }
+    Some(num / den)
+}
+
+fn round3(v: f64) -> f64 {
+    (v * 1000.0).round() / 1000.0
+}
@@ -1,11 +1,13 @@
 use anyhow::{bail, Result};
+use serde::Serialize;
 use std::path::Path;
 
-use crate::db::{populate_temp_sources, Connection, Db};
-use crate::exclude;
-use crate::filter::{self, Filter};
-
-const BATCH_SIZE: i64 = 1000;
+use crate::db::{Connection, Db, TempSources};
+use crate::filter::LINK_GROUP_SQL;
+use crate::output;
+use crate::pin;
+use crate::query::SourceQuery;
+use crate::util::format_number;
 
 // Built-in source facts - default visible
 const BUILTIN_FACTS_DEFAULT: &[&str] = &[
@@ -21,20 +23,45 @@ const BUILTIN_FACTS_HIDDEN: &[&str] = &[
     "source.rel_path",
     "source.device",
     "source.inode",
+    "source.nlink",
+    "source.link_group",
+    "source.btime",
 ];
 
+/// Result of `canon facts`: key coverage, or the value distribution of one key
+#[derive(Serialize)]
+struct FactsReport {
+    sources: usize,
+    excluded_hidden: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+    counts: Vec<FactCount>,
+}
+
+/// A fact key (overview) or value (distribution) with the number of sources having it.
+/// `value` is None for the sources that lack the fact.
+#[derive(Serialize)]
+struct FactCount {
+    value: Option<String>,
+    count: i64,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    builtin: bool,
+}
+
+impl FactCount {
+    fn new(value: String, count: i64) -> Self {
+        FactCount { value: Some(value), count, builtin: false }
+    }
+}
+
 fn is_builtin_fact(key: &str) -> bool {
     BUILTIN_FACTS_DEFAULT.contains(&key) || BUILTIN_FACTS_HIDDEN.contains(&key)
 }
 
-pub fn run(db: &mut Db, key_arg: Option<&str>, path_arg: Option<&Path>, filter_strs: &[String], limit: usize, show_all: bool, include_archived: bool, include_excluded: bool) -> Result<()> {
-    let conn = db.conn_mut();
-
-    // Parse filters
-    let filters: Vec<Filter> = filter_strs
-        .iter()
-        .map(|f| Filter::parse(f))
-        .collect::<Result<Vec<_>>>()?;
+/// Print fact coverage for matching sources, or the value distribution of `key_arg`
+#[allow(clippy::too_many_arguments)]
+pub fn run(db: &Db, key_arg: Option<&str>, path_arg: Option<&Path>, filter_strs: &[String], limit: usize, show_all: bool, include_archived: bool, include_excluded: bool) -> Result<()> {
+    let conn = db.conn();
 
     // Disambiguate key vs path: if key looks like a path, swap them
     let (key, scope_path): (Option<&str>, Option<&Path>) = match (key_arg, path_arg) {
@@ -45,132 +72,98 @@ pub fn run(db: &mut Db, key_arg: Option<&str>, path_arg: Option<&Path>, filter_s
         (k, p) => (k, p),
     };
 
-    // Resolve scope path to realpath if provided
-    let scope_prefix = if let Some(p) = scope_path {
-        Some(std::fs::canonicalize(p)?.to_string_lossy().to_string())
-    } else {
-        None
-    };
+    let query = SourceQuery::new()
+        .scope(scope_path)?
+        .filters(conn, filter_strs)?
+        .include_archived(include_archived)
+        .include_excluded(include_excluded);
 
     // Get excluded count for reporting
     let excluded_count = if !include_excluded {
-        exclude::count_excluded(&conn, scope_prefix.as_deref(), include_archived)?
+        query.count_excluded(conn)?
     } else {
         0
     };
 
     // Get all matching source IDs
-    let source_ids = get_matching_sources(&conn, scope_prefix.as_deref(), &filters, include_archived, include_excluded)?;
+    let source_ids = query.ids(conn)?;
     let total_sources = source_ids.len();
 
-    if total_sources == 0 {
-        println!("No sources match the given filters.");
-        if !include_excluded && excluded_count > 0 {
-            println!("\n({} excluded sources hidden, use --include-excluded to show)", excluded_count);
-        }
-        return Ok(());
-    }
-
-    println!("Sources matching filters: {}\n", total_sources);
-
-    if let Some(fact_key) = key {
-        if is_builtin_fact(fact_key) {
-            show_builtin_distribution(conn, &source_ids, fact_key, total_sources, limit)?;
-        } else {
-            show_value_distribution(conn, &source_ids, fact_key, total_sources, limit)?;
-        }
-    } else {
-        show_all_keys(conn, &source_ids, total_sources, show_all)?;
-    }
-
-    // Report excluded count
-    if !include_excluded && excluded_count > 0 {
-        println!("\n({} excluded sources hidden, use --include-excluded to show)", excluded_count);
-    }
+    let counts = match key {
+        Some(fact_key) if is_builtin_fact(fact_key) => builtin_value_counts(conn, &source_ids, fact_key, limit)?,
+        Some(fact_key) => value_counts(conn, &source_ids, fact_key, total_sources, limit)?,
+        None => key_counts(conn, &source_ids, total_sources, show_all)?,
+    };
 
-    Ok(())
+    let report = FactsReport {
+        sources: total_sources,
+        excluded_hidden: excluded_count,
+        key: key.map(String::from),
+        counts,
+    };
+    output::emit(&report, |report| print_report(report, show_all))
 }
 
-fn get_matching_sources(
-    conn: &Connection,
-    scope_prefix: Option<&str>,
-    filters: &[Filter],
-    include_archived: bool,
-    include_excluded: bool,
-) -> Result<Vec<i64>> {
-    let mut all_ids = Vec::new();
-    let mut last_id: i64 = 0;
-
-    let role_clause = if include_archived {
-        "1=1" // Include all roles
+fn print_report(report: &FactsReport, show_all: bool) {
+    if report.sources == 0 {
+        println!("No sources match the given filters.");
     } else {
-        "r.role = 'source'"
-    };
-
-    let exclude_clause = exclude::exclude_clause(include_excluded);
-
-    loop {
-        // Fetch batch of source IDs
-        let batch: Vec<i64> = if let Some(prefix) = scope_prefix {
-            // Filter by path prefix
-            conn.prepare(&format!(
-                "SELECT s.id
-                 FROM sources s
-                 JOIN roots r ON s.root_id = r.id
-                 WHERE s.present = 1 AND {} AND {} AND s.id > ?
-                   AND (r.path || '/' || s.rel_path) LIKE ? || '/%'
-                 ORDER BY s.id
-                 LIMIT ?",
-                role_clause, exclude_clause
-            ))?
-            .query_map(rusqlite::params![last_id, prefix, BATCH_SIZE], |row| row.get(0))?
-            .collect::<Result<Vec<_>, _>>()?
-        } else {
-            conn.prepare(&format!(
-                "SELECT s.id
-                 FROM sources s
-                 JOIN roots r ON s.root_id = r.id
-                 WHERE s.present = 1 AND {} AND {} AND s.id > ?
-                 ORDER BY s.id
-                 LIMIT ?",
-                role_clause, exclude_clause
-            ))?
-            .query_map(rusqlite::params![last_id, BATCH_SIZE], |row| row.get(0))?
-            .collect::<Result<Vec<_>, _>>()?
-        };
-
-        if batch.is_empty() {
-            break;
+        println!("Sources matching filters: {}\n", report.sources);
+
+        match report.key {
+            Some(ref key) => {
+                let label = if is_builtin_fact(key) { format!("{} (built-in)", key) } else { key.clone() };
+                println!("{:<40} {:>10} {:>10}", label, "Count", "Coverage");
+                println!("{}", "─".repeat(62));
+
+                for row in &report.counts {
+                    let display_val = match row.value.as_deref() {
+                        None => "(no value)".to_string(),
+                        Some("") if key == "source.ext" => "(no extension)".to_string(),
+                        Some(value) if value.len() > 38 => format!("{}...", &value[..35]),
+                        Some(value) => value.to_string(),
+                    };
+                    let coverage = (row.count as f64 / report.sources as f64) * 100.0;
+                    println!("{:<40} {:>10} {:>9.1}%", display_val, row.count, coverage);
+                }
+            }
+            None => {
+                println!("{:<30} {:>10} {:>10}", "Fact", "Count", "Coverage");
+                println!("{}", "─".repeat(52));
+
+                for row in &report.counts {
+                    let coverage = (row.count as f64 / report.sources as f64) * 100.0;
+                    let suffix = if row.builtin { "  (built-in)" } else { "" };
+                    println!("{:<30} {:>10} {:>9.1}%{}", row.value.as_deref().unwrap_or_default(), row.count, coverage, suffix);
+                }
+
+                if !show_all {
+                    let hidden_count = BUILTIN_FACTS_HIDDEN.len();
+                    println!("\n({} built-in facts hidden, use --all to show)", hidden_count);
+                }
+            }
         }
-
-        let max_id = *batch.last().unwrap();
-
-        // Apply filters
-        let filtered = if filters.is_empty() {
-            batch
-        } else {
-            filter::apply_filters(conn, &batch, filters)?
-        };
-
-        all_ids.extend(filtered);
-        last_id = max_id;
     }
 
-    Ok(all_ids)
+    // Report excluded count
+    if report.excluded_hidden > 0 {
+        println!("\n({} excluded sources hidden, use --include-excluded to show)", report.excluded_hidden);
+    }
 }
 
-fn show_all_keys(conn: &mut Connection, source_ids: &[i64], total_sources: usize, show_all: bool) -> Result<()> {
+/// Number of sources having each fact key, built-ins first
+fn key_counts(conn: &Connection, source_ids: &[i64], total_sources: usize, show_all: bool) -> Result<Vec<FactCount>> {
     if source_ids.is_empty() {
-        return Ok(());
+        return Ok(Vec::new());
     }
 
-    // Build a temp table for efficiency with large source lists
-    populate_temp_sources(conn, source_ids)?;
+    // Keyed by object too, so object facts join without a sources lookup
+    let _selection = TempSources::load_keyed(conn, source_ids)?;
 
     // Query fact keys from both source and object facts
     // Count sources (not entities) - multiple sources can share an object
     // Use UNION ALL for index efficiency, dedupe once in outer SELECT DISTINCT
-    let mut results: Vec<(String, i64, bool)> = conn
+    let mut results: Vec<FactCount> = conn
         .prepare(
             "SELECT key, COUNT(*) as cnt
              FROM (
@@ -183,66 +176,45 @@ fn show_all_keys(conn: &mut Connection, source_ids: &[i64], total_sources: usize
 
                      SELECT ts.id, f.key
                      FROM temp_sources ts
-                     JOIN sources s ON s.id = ts.id
-                     JOIN facts f ON f.entity_type = 'object' AND f.entity_id = s.object_id
-                     WHERE s.object_id IS NOT NULL
+                     JOIN facts f ON f.entity_type = 'object' AND f.entity_id = ts.object_id
+                     WHERE ts.object_id IS NOT NULL
                  )
              )
              GROUP BY key
              ORDER BY cnt DESC"
         )?
-        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?, false)))?
+        .query_map([], |row| Ok(FactCount::new(row.get(0)?, row.get(1)?)))?
         .collect::<Result<Vec<_>, _>>()?;
 
-    // Clean up temp table
-    conn.execute("DROP TABLE IF EXISTS temp_sources", [])?;
-
     // Add built-in facts at the top (they always have 100% coverage)
-    let mut all_results: Vec<(String, i64, bool)> = BUILTIN_FACTS_DEFAULT
-        .iter()
-        .map(|&name| (name.to_string(), total_sources as i64, true))
+    let builtins = if show_all {
+        [BUILTIN_FACTS_DEFAULT, BUILTIN_FACTS_HIDDEN].concat()
+    } else {
+        BUILTIN_FACTS_DEFAULT.to_vec()
+    };
+    let mut all_results: Vec<FactCount> = builtins
+        .into_iter()
+        .map(|name| FactCount { builtin: true, ..FactCount::new(name.to_string(), total_sources as i64) })
         .collect();
 
-    // Add hidden built-ins if --all flag is set
-    if show_all {
-        for &name in BUILTIN_FACTS_HIDDEN {
-            all_results.push((name.to_string(), total_sources as i64, true));
-        }
-    }
-
     all_results.append(&mut results);
-
-    // Print header
-    println!("{:<30} {:>10} {:>10}", "Fact", "Count", "Coverage");
-    println!("{}", "─".repeat(52));
-
-    for (key, count, is_builtin) in &all_results {
-        let coverage = (*count as f64 / total_sources as f64) * 100.0;
-        let suffix = if *is_builtin { "  (built-in)" } else { "" };
-        println!("{:<30} {:>10} {:>9.1}%{}", key, count, coverage, suffix);
-    }
-
-    if !show_all {
-        let hidden_count = BUILTIN_FACTS_HIDDEN.len();
-        println!("\n({} built-in facts hidden, use --all to show)", hidden_count);
-    }
-
-    Ok(())
+    Ok(all_results)
 }
 
-fn show_value_distribution(
-    conn: &mut Connection,
+/// Number of sources per value of `key`, plus a `None` row for sources without it
+fn value_counts(
+    conn: &Connection,
     source_ids: &[i64],
     key: &str,
     total_sources: usize,
     limit: usize,
-) -> Result<()> {
+) -> Result<Vec<FactCount>> {
     if source_ids.is_empty() {
-        return Ok(());
+        return Ok(Vec::new());
     }
 
-    // Build temp table
-    populate_temp_sources(conn, source_ids)?;
+    // One load for both queries below
+    let _selection = TempSources::load_keyed(conn, source_ids)?;
 
     // Query value distribution
     // Count sources (not entities) - multiple sources can share an object
@@ -262,9 +234,8 @@ fn show_value_distribution(
                  SELECT ts.id,
                      COALESCE(f.value_text, CAST(f.value_num AS TEXT), datetime(f.value_time, 'unixepoch'), f.value_json) as val
                  FROM temp_sources ts
-                 JOIN sources s ON s.id = ts.id
-                 JOIN facts f ON f.entity_type = 'object' AND f.entity_id = s.object_id AND f.key = ?1
-                 WHERE s.object_id IS NOT NULL
+                 JOIN facts f ON f.entity_type = 'object' AND f.entity_id = ts.object_id AND f.key = ?1
+                 WHERE ts.object_id IS NOT NULL
              )
          )
          GROUP BY val
@@ -284,9 +255,8 @@ fn show_value_distribution(
                      SELECT ts.id,
                          COALESCE(f.value_text, CAST(f.value_num AS TEXT), datetime(f.value_time, 'unixepoch'), f.value_json) as val
                      FROM temp_sources ts
-                     JOIN sources s ON s.id = ts.id
-                     JOIN facts f ON f.entity_type = 'object' AND f.entity_id = s.object_id AND f.key = ?1
-                     WHERE s.object_id IS NOT NULL
+                     JOIN facts f ON f.entity_type = 'object' AND f.entity_id = ts.object_id AND f.key = ?1
+                     WHERE ts.object_id IS NOT NULL
                  )
              )
              GROUP BY val
@@ -296,12 +266,12 @@ fn show_value_distribution(
         )
     };
 
-    let results: Vec<(String, i64)> = conn
+    let mut results: Vec<FactCount> = conn
         .prepare(&query)?
         .query_map([key], |row| {
             let val: Option<String> = row.get(0)?;
             let cnt: i64 = row.get(1)?;
-            Ok((val.unwrap_or_else(|| "(null)".to_string()), cnt))
+            Ok(FactCount::new(val.unwrap_or_else(|| "(null)".to_string()), cnt))
         })?
         .collect::<Result<Vec<_>, _>>()?;
 
@@ -317,58 +287,36 @@ fn show_value_distribution(
 
              SELECT ts.id
              FROM temp_sources ts
-             JOIN sources s ON s.id = ts.id
-             JOIN facts f ON f.entity_type = 'object' AND f.entity_id = s.object_id AND f.key = ?1
-             WHERE s.object_id IS NOT NULL
+             JOIN facts f ON f.entity_type = 'object' AND f.entity_id = ts.object_id AND f.key = ?1
+             WHERE ts.object_id IS NOT NULL
          )",
         [key],
         |row| row.get(0),
     )?;
 
-    // Clean up temp table
-    conn.execute("DROP TABLE IF EXISTS temp_sources", [])?;
-
-    // Print header
-    println!("{:<40} {:>10} {:>10}", key, "Count", "Coverage");
-    println!("{}", "─".repeat(62));
-
-    for (value, count) in &results {
-        let display_val = if value.len() > 38 {
-            format!("{}...", &value[..35])
-        } else {
-            value.clone()
-        };
-        let coverage = (*count as f64 / total_sources as f64) * 100.0;
-        println!("{:<40} {:>10} {:>9.1}%", display_val, count, coverage);
-    }
-
-    // Show "(no value)" count
+    // Sources without the fact
     let without_fact = total_sources as i64 - sources_with_fact;
     if without_fact > 0 {
-        let coverage = (without_fact as f64 / total_sources as f64) * 100.0;
-        println!("{:<40} {:>10} {:>9.1}%", "(no value)", without_fact, coverage);
+        results.push(FactCount { value: None, count: without_fact, builtin: false });
     }
 
-    Ok(())
+    Ok(results)
 }
 
-fn show_builtin_distribution(
-    conn: &mut Connection,
+/// Number of sources per value of a built-in `source.*` fact (size and mtime are bucketed)
+fn builtin_value_counts(
+    conn: &Connection,
     source_ids: &[i64],
     key: &str,
-    total_sources: usize,
     limit: usize,
-) -> Result<()> {
+) -> Result<Vec<FactCount>> {
     use std::collections::HashMap;
 
     if source_ids.is_empty() {
-        return Ok(());
+        return Ok(Vec::new());
     }
 
-    // Build temp table
-    populate_temp_sources(conn, source_ids)?;
-
-    let label = format!("{} (built-in)", key);
+    let selection = TempSources::load(conn, source_ids)?;
 
     let mut counts: HashMap<String, i64> = HashMap::new();
 
@@ -424,6 +372,22 @@ fn show_builtin_distribution(
                 *counts.entry(year).or_insert(0) += 1;
             }
         }
+        "source.btime" => {
+            let rows: Vec<Option<i64>> = conn
+                .prepare("SELECT btime FROM sources WHERE id IN (SELECT id FROM temp_sources)")?
+                .query_map([], |row| row.get(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            for btime in rows {
+                let year = match btime {
+                    Some(btime) => chrono::DateTime::from_timestamp(btime, 0)
+                        .map(|dt| dt.format("%Y").to_string())
+                        .unwrap_or_else(|| "(unknown)".to_string()),
+                    None => "(null)".to_string(),
+                };
+                *counts.entry(year).or_insert(0) += 1;
+            }
+        }
         "source.path" => {
             let rows: Vec<(String, String)> = conn
                 .prepare(
@@ -489,38 +453,45 @@ fn show_builtin_distribution(
                 *counts.entry(val).or_insert(0) += 1;
             }
         }
-        _ => return Ok(()),
-    }
+        "source.nlink" => {
+            let rows: Vec<Option<i64>> = conn
+                .prepare("SELECT nlink FROM sources WHERE id IN (SELECT id FROM temp_sources)")?
+                .query_map([], |row| row.get(0))?
+                .collect::<Result<Vec<_>, _>>()?;
 
-    // Clean up temp table
-    conn.execute("DROP TABLE IF EXISTS temp_sources", [])?;
+            for nlink in rows {
+                let val = nlink.map(|n| n.to_string()).unwrap_or_else(|| "(null)".to_string());
+                *counts.entry(val).or_insert(0) += 1;
+            }
+        }
+        "source.link_group" => {
+            let rows: Vec<Option<i64>> = conn
+                .prepare(&format!(
+                    "SELECT {} FROM sources s WHERE s.id IN (SELECT id FROM temp_sources)",
+                    LINK_GROUP_SQL
+                ))?
+                .query_map([], |row| row.get(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            for group in rows {
+                let val = group.map(|g| g.to_string()).unwrap_or_else(|| "(none)".to_string());
+                *counts.entry(val).or_insert(0) += 1;
+            }
+        }
+        _ => return Ok(Vec::new()),
+    }
+    drop(selection);
 
     // Sort by count descending
     let mut results: Vec<(String, i64)> = counts.into_iter().collect();
-    results.sort_by(|a, b| b.1.cmp(&a.1));
+    results.sort_by_key(|r| std::cmp::Reverse(r.1));
 
     // Apply limit
     if limit > 0 && results.len() > limit {
         results.truncate(limit);
     }
 
-    // Print header
-    println!("{:<40} {:>10} {:>10}", label, "Count", "Coverage");
-    println!("{}", "─".repeat(62));
-
-    for (value, count) in &results {
-        let display_val = if value.is_empty() {
-            "(no extension)".to_string()
-        } else if value.len() > 38 {
-            format!("{}...", &value[..35])
-        } else {
-            value.clone()
-        };
-        let coverage = (*count as f64 / total_sources as f64) * 100.0;
-        println!("{:<40} {:>10} {:>9.1}%", display_val, count, coverage);
-    }
-
-    Ok(())
+    Ok(results.into_iter().map(|(value, count)| FactCount::new(value, count)).collect())
 }
 
 // ============================================================================
@@ -530,6 +501,19 @@ fn show_builtin_distribution(
 pub struct DeleteOptions {
     pub entity_type: String, // "source" or "object"
     pub dry_run: bool,
+    /// Only consider these sources (`--stdin`)
+    pub source_ids: Option<Vec<i64>>,
+    /// Delete facts of pinned sources (and their objects) too
+    pub include_pinned: bool,
+}
+
+#[derive(Serialize)]
+struct DeleteReport {
+    dry_run: bool,
+    /// Fact rows deleted (or that would be)
+    facts: i64,
+    entities: i64,
+    entity_type: String,
 }
 
 /// Check if a fact key is protected from deletion
@@ -537,8 +521,9 @@ fn is_protected_fact(key: &str) -> bool {
     key.starts_with("source.") || key.starts_with("policy.")
 }
 
+/// Delete facts with `key` on matching sources (or their objects)
 pub fn delete_facts(
-    db: &mut Db,
+    db: &Db,
     key: &str,
     scope_path: Option<&Path>,
     filter_strs: &[String],
@@ -560,31 +545,32 @@ pub fn delete_facts(
         );
     }
 
-    let conn = db.conn_mut();
-
-    // Parse filters
-    let filters: Vec<Filter> = filter_strs
-        .iter()
-        .map(|f| Filter::parse(f))
-        .collect::<Result<Vec<_>>>()?;
+    let conn = db.conn();
 
-    // Resolve scope path
-    let scope_prefix = if let Some(p) = scope_path {
-        Some(std::fs::canonicalize(p)?.to_string_lossy().to_string())
+    // Get matching source IDs (any root, excluded sources included)
+    let source_ids = SourceQuery::new()
+        .scope(scope_path)?
+        .filters(conn, filter_strs)?
+        .include_archived(true)
+        .include_excluded(true)
+        .selection(options.source_ids.as_deref())
+        .ids(conn)?;
+
+    let source_ids = if options.include_pinned {
+        source_ids
     } else {
-        None
+        let (unpinned, pinned) = pin::without_pinned(conn, source_ids)?;
+        pin::note_skipped(pinned);
+        unpinned
     };
 
-    // Get matching source IDs
-    let source_ids = get_matching_sources(&conn, scope_prefix.as_deref(), &filters, true, true)?;
-
     if source_ids.is_empty() {
-        println!("No sources match the given filters.");
-        return Ok(());
+        let report = DeleteReport { dry_run: options.dry_run, facts: 0, entities: 0, entity_type: options.entity_type.clone() };
+        return output::emit(&report, |_| println!("No sources match the given filters."));
     }
 
-    // Build temp table for efficiency
-    populate_temp_sources(conn, &source_ids)?;
+    // Keyed by object too, for the object facts branch
+    let selection = TempSources::load_keyed(conn, &source_ids)?;
 
     // Count and optionally delete based on entity type
     let (fact_count, entity_count) = if options.entity_type == "source" {
@@ -628,8 +614,7 @@ pub fn delete_facts(
         conn.execute("DELETE FROM temp_objects", [])?;
         conn.execute(
             "INSERT OR IGNORE INTO temp_objects (id)
-             SELECT DISTINCT object_id FROM sources
-             WHERE id IN (SELECT id FROM temp_sources) AND object_id IS NOT NULL",
+             SELECT DISTINCT object_id FROM temp_sources WHERE object_id IS NOT NULL",
             [],
         )?;
 
@@ -666,41 +651,53 @@ pub fn delete_facts(
         (count, entity_count)
     };
 
-    // Clean up
-    conn.execute("DROP TABLE IF EXISTS temp_sources", [])?;
+    drop(selection);
 
     // Report results
-    let entity_label = if options.entity_type == "source" {
-        "sources"
-    } else {
-        "objects"
+    let report = DeleteReport {
+        dry_run: options.dry_run,
+        facts: fact_count,
+        entities: entity_count,
+        entity_type: options.entity_type.clone(),
     };
+    output::emit(&report, |report| {
+        let entity_label = if report.entity_type == "source" {
+            "sources"
+        } else {
+            "objects"
+        };
 
-    if fact_count == 0 {
-        println!("No '{}' facts found on matching {}.", key, entity_label);
-    } else if options.dry_run {
-        println!(
-            "Would delete {} fact rows across {} {}",
-            format_number(fact_count),
-            format_number(entity_count),
-            entity_label
-        );
-    } else {
-        println!(
-            "Deleted {} fact rows across {} {}",
-            format_number(fact_count),
-            format_number(entity_count),
-            entity_label
-        );
-    }
-
-    Ok(())
+        if report.facts == 0 {
+            println!("No '{}' facts found on matching {}.", key, entity_label);
+        } else if report.dry_run {
+            println!(
+                "Would delete {} fact rows across {} {}",
+                format_number(report.facts),
+                format_number(report.entities),
+                entity_label
+            );
+        } else {
+            println!(
+                "Deleted {} fact rows across {} {}",
+                format_number(report.facts),
+                format_number(report.entities),
+                entity_label
+            );
+        }
+    })
 }
 
 // ============================================================================
 // Prune Stale Facts
 // ============================================================================
 
+#[derive(Serialize)]
+struct PruneReport {
+    dry_run: bool,
+    facts: i64,
+}
+
+/// Delete source facts observed at a basis_rev other than the source's current one
 pub fn prune_stale(db: &Db, dry_run: bool) -> Result<()> {
     let conn = db.conn();
 
@@ -714,16 +711,8 @@ pub fn prune_stale(db: &Db, dry_run: bool) -> Result<()> {
         |row| row.get(0),
     )?;
 
-    if stale_count == 0 {
-        println!("No stale facts found.");
-        return Ok(());
-    }
-
-    if dry_run {
-        println!(
-            "Would delete {} stale fact rows (observed_basis_rev mismatch)",
-            format_number(stale_count)
-        );
+    let facts = if stale_count == 0 || dry_run {
+        stale_count
     } else {
         let deleted = conn.execute(
             "DELETE FROM facts
@@ -740,23 +729,23 @@ pub fn prune_stale(db: &Db, dry_run: bool) -> Result<()> {
                )",
             [],
         )?;
-        println!(
-            "Deleted {} stale fact rows (observed_basis_rev mismatch)",
-            format_number(deleted as i64)
-        );
-    }
-
-    Ok(())
-}
+        deleted as i64
+    };
 
-fn format_number(n: i64) -> String {
-    let s = n.to_string();
-    let mut result = String::new();
-    for (i, c) in s.chars().rev().enumerate() {
-        if i > 0 && i % 3 == 0 {
-            result.push(',');
+    let report = PruneReport { dry_run, facts };
+    output::emit(&report, |report| {
+        if report.facts == 0 {
+            println!("No stale facts found.");
+        } else if report.dry_run {
+            println!(
+                "Would delete {} stale fact rows (observed_basis_rev mismatch)",
+                format_number(report.facts)
+            );
+        } else {
+            println!(
+                "Deleted {} stale fact rows (observed_basis_rev mismatch)",
+                format_number(report.facts)
+            );
         }
-        result.push(c);
-    }
-    result.chars().rev().collect()
+    })
 }
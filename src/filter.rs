@@ -1,5 +1,21 @@
-use anyhow::{bail, Result};
-use rusqlite::{params, Connection};
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use rusqlite::{params, Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::sync::Arc;
+
+use crate::cluster::Manifest;
+use crate::db;
+use crate::roots::glob_match;
+
+/// `source.link_group` of `sources s`: the lowest id among the present
+/// sources that are hard links to the same file, or NULL when it has no other
+/// indexed link
+pub(crate) const LINK_GROUP_SQL: &str = "(SELECT CASE WHEN s.present = 1 AND COUNT(*) > 1 THEN MIN(l.id) END
+    FROM sources l WHERE l.device = s.device AND l.inode = s.inode AND l.present = 1)";
 
 // ============================================================================
 // Expression AST
@@ -13,6 +29,8 @@ pub enum CompareOp {
     Ge,
     Lt,
     Le,
+    /// `~`: text matches a glob (`*`, `**`, `?`), ignoring ASCII case
+    Glob,
 }
 
 /// Filter expression AST - supports boolean logic
@@ -24,19 +42,57 @@ pub enum Expr {
     Exists { key: String },
     Compare { key: String, op: CompareOp, value: String },
     In { key: String, values: Vec<String> },
+    /// `key =~ /pattern/`: a text fact or path field contains a match
+    Matches { key: String, regex: Regex },
+    /// `in_manifest("path")`: listed in a manifest, or the same content as a listed source
+    InManifest(ManifestMembers),
+    /// `in_archive=ROOT` (`copied`) or `!=` (not copied): a hashed source has a
+    /// present copy on that archive root, resolved when the filter is parsed
+    InArchive { root_id: i64, copied: bool },
+}
+
+/// The sources and objects a manifest lists, read when the filter is parsed
+#[derive(Clone)]
+pub struct ManifestMembers {
+    path: String,
+    /// SHA-256 of the manifest file: identifies its content in cached query results
+    digest: String,
+    source_ids: Arc<HashSet<i64>>,
+    object_ids: Arc<HashSet<i64>>,
+}
+
+impl ManifestMembers {
+    fn load(path: &str) -> Result<Self> {
+        let content = fs::read(path).with_context(|| format!("Failed to read manifest: {}", path))?;
+        let manifest: Manifest = toml::from_str(std::str::from_utf8(&content)?)
+            .with_context(|| format!("Failed to parse manifest: {}", path))?;
+        Ok(ManifestMembers {
+            path: path.to_string(),
+            digest: format!("{:x}", Sha256::digest(&content)),
+            source_ids: Arc::new(manifest.sources.iter().map(|s| s.id).collect()),
+            object_ids: Arc::new(manifest.sources.iter().filter_map(|s| s.object_id).collect()),
+        })
+    }
+}
+
+impl fmt::Debug for ManifestMembers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}", self.path, self.digest)
+    }
 }
 
 // Keep Filter as alias for backwards compatibility
 pub type Filter = Expr;
 
 impl Expr {
-    /// Parse a filter expression string into an AST
-    pub fn parse(s: &str) -> Result<Self> {
+    /// Parse a filter expression string into an AST. Root specs (`in_archive`)
+    /// are resolved against the database here, so an unknown one is a parse error.
+    pub fn parse(conn: &Connection, s: &str) -> Result<Self> {
         let tokens = tokenize(s)?;
         if tokens.is_empty() {
             bail!("Empty filter expression");
         }
-        let mut parser = Parser::new(&tokens);
+        let mut parser = Parser::new(&tokens, conn);
         let expr = parser.parse_expr()?;
         if parser.pos < tokens.len() {
             bail!("Unexpected token after expression: {:?}", tokens[parser.pos]);
@@ -59,6 +115,8 @@ enum Token {
     In,
     Comma,
     Op(CompareOp),
+    Match,       // '=~'
+    Regex(String), // '/pattern/', with an 'i' suffix folded in as (?i)
     Exists,      // The '?' suffix
     Ident(String),
     Value(String),
@@ -92,6 +150,7 @@ fn tokenize(s: &str) -> Result<Vec<Token>> {
                 ">=" => { tokens.push(Token::Op(CompareOp::Ge)); i += 2; continue; }
                 "<=" => { tokens.push(Token::Op(CompareOp::Le)); i += 2; continue; }
                 "!=" => { tokens.push(Token::Op(CompareOp::Ne)); i += 2; continue; }
+                "=~" => { tokens.push(Token::Match); i += 2; continue; }
                 _ => {}
             }
         }
@@ -101,14 +160,15 @@ fn tokenize(s: &str) -> Result<Vec<Token>> {
             '>' => { tokens.push(Token::Op(CompareOp::Gt)); i += 1; continue; }
             '<' => { tokens.push(Token::Op(CompareOp::Lt)); i += 1; continue; }
             '=' => { tokens.push(Token::Op(CompareOp::Eq)); i += 1; continue; }
+            '~' => { tokens.push(Token::Op(CompareOp::Glob)); i += 1; continue; }
             '!' => { tokens.push(Token::Not); i += 1; continue; }
             _ => {}
         }
 
-        // Keywords and identifiers
+        // Keywords and identifiers (':' allows unquoted root specs like id:3)
         if chars[i].is_alphabetic() || chars[i] == '_' {
             let start = i;
-            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+            while i < chars.len() && (chars[i].is_alphanumeric() || matches!(chars[i], '_' | '.' | ':')) {
                 i += 1;
             }
             let word: String = chars[start..i].iter().collect();
@@ -151,6 +211,29 @@ fn tokenize(s: &str) -> Result<Vec<Token>> {
             continue;
         }
 
+        // Regex literals: /pattern/ or /pattern/i, with \/ for a literal '/'
+        if chars[i] == '/' {
+            i += 1;
+            let mut pattern = String::new();
+            while i < chars.len() && chars[i] != '/' {
+                if chars[i] == '\\' && chars.get(i + 1) == Some(&'/') {
+                    i += 1;
+                }
+                pattern.push(chars[i]);
+                i += 1;
+            }
+            if i >= chars.len() {
+                bail!("Unterminated regex");
+            }
+            i += 1; // skip closing slash
+            if chars.get(i) == Some(&'i') && !chars.get(i + 1).is_some_and(|c| c.is_alphanumeric()) {
+                pattern.insert_str(0, "(?i)");
+                i += 1;
+            }
+            tokens.push(Token::Regex(pattern));
+            continue;
+        }
+
         bail!("Unexpected character: {}", chars[i]);
     }
 
@@ -164,11 +247,12 @@ fn tokenize(s: &str) -> Result<Vec<Token>> {
 struct Parser<'a> {
     tokens: &'a [Token],
     pos: usize,
+    conn: &'a Connection,
 }
 
 impl<'a> Parser<'a> {
-    fn new(tokens: &'a [Token]) -> Self {
-        Parser { tokens, pos: 0 }
+    fn new(tokens: &'a [Token], conn: &'a Connection) -> Self {
+        Parser { tokens, pos: 0, conn }
     }
 
     fn peek(&self) -> Option<&Token> {
@@ -247,7 +331,8 @@ impl<'a> Parser<'a> {
         self.parse_atom()
     }
 
-    /// atom := ident '?' | ident 'IN' '(' value_list ')' | ident op value
+    /// atom := ident '?' | ident 'IN' '(' value_list ')' | ident op value | ident '=~' regex
+    ///       | 'in_manifest' '(' value ')'
     fn parse_atom(&mut self) -> Result<Expr> {
         let key = match self.advance() {
             Some(Token::Ident(k)) => k.clone(),
@@ -255,6 +340,14 @@ impl<'a> Parser<'a> {
             None => bail!("Expected identifier, got end of input"),
         };
 
+        // Manifest membership: in_manifest("manifest.toml")
+        if key == "in_manifest" && matches!(self.peek(), Some(Token::LParen)) {
+            self.advance(); // consume '('
+            let path = self.parse_value()?;
+            self.expect(&Token::RParen)?;
+            return Ok(Expr::InManifest(ManifestMembers::load(&path)?));
+        }
+
         // Check for existence test: key?
         if matches!(self.peek(), Some(Token::Exists)) {
             self.advance();
@@ -267,9 +360,28 @@ impl<'a> Parser<'a> {
             self.expect(&Token::LParen)?;
             let values = self.parse_value_list()?;
             self.expect(&Token::RParen)?;
+            for value in &values {
+                check_archive_value(&key, CompareOp::Eq, value)?;
+            }
+            if key == "in_archive" {
+                let roots = values.iter().map(|spec| self.in_archive(CompareOp::Eq, spec)).collect::<Result<_>>()?;
+                return Ok(Expr::Or(roots));
+            }
             return Ok(Expr::In { key, values });
         }
 
+        // Regex match: key =~ /pattern/ (or a quoted pattern)
+        if matches!(self.peek(), Some(Token::Match)) {
+            self.advance(); // consume =~
+            let pattern = match self.advance() {
+                Some(Token::Regex(p)) | Some(Token::Value(p)) => p.clone(),
+                Some(t) => bail!("Expected /pattern/ after '{} =~', got {:?}", key, t),
+                None => bail!("Expected /pattern/ after '{} =~', got end of input", key),
+            };
+            let regex = Regex::new(&pattern).with_context(|| format!("Invalid regex for '{}'", key))?;
+            return Ok(Expr::Matches { key, regex });
+        }
+
         // Comparison: key op value
         let op = match self.advance() {
             Some(Token::Op(op)) => *op,
@@ -278,10 +390,20 @@ impl<'a> Parser<'a> {
         };
 
         let value = self.parse_value()?;
+        check_archive_value(&key, op, &value)?;
+        if key == "in_archive" {
+            return self.in_archive(op, &value);
+        }
 
         Ok(Expr::Compare { key, op, value })
     }
 
+    /// `in_archive=SPEC` (or `!=`) with the archive root looked up once
+    fn in_archive(&self, op: CompareOp, spec: &str) -> Result<Expr> {
+        let root_id = db::parse_root_spec(self.conn, spec, Some("archive"))?;
+        Ok(Expr::InArchive { root_id, copied: op == CompareOp::Eq })
+    }
+
     fn parse_value(&mut self) -> Result<String> {
         match self.advance() {
             Some(Token::Value(v)) => Ok(v.clone()),
@@ -327,6 +449,97 @@ pub fn apply_filters(conn: &Connection, source_ids: &[i64], filters: &[Filter])
     Ok(result)
 }
 
+/// `archived`: the source's content has a present copy on an archive root
+const ARCHIVED_SQL: &str = "s.object_id IN (SELECT object_id FROM archived_objects)";
+
+/// Conditions on `sources s JOIN roots r` that every source matching all
+/// `filters` satisfies: path globs and `archived` tests ANDed at the top level.
+/// They only narrow the candidates; `apply_filters` still decides.
+pub(crate) fn sql_prefilter(filters: &[Filter]) -> Vec<String> {
+    let mut conditions = Vec::new();
+    for filter in filters {
+        collect_prefilter(filter, &mut conditions);
+    }
+    conditions
+}
+
+/// Whether any expression reads `archived.online`, which depends on the
+/// stored root availability
+pub(crate) fn uses_availability(filters: &[Filter]) -> bool {
+    fn uses(expr: &Expr) -> bool {
+        match expr {
+            Expr::And(exprs) | Expr::Or(exprs) => exprs.iter().any(uses),
+            Expr::Not(inner) => uses(inner),
+            Expr::Exists { key } | Expr::Compare { key, .. } | Expr::In { key, .. } | Expr::Matches { key, .. } => {
+                key == "archived.online"
+            }
+            Expr::InManifest(_) | Expr::InArchive { .. } => false,
+        }
+    }
+    filters.iter().any(uses)
+}
+
+fn collect_prefilter(expr: &Expr, conditions: &mut Vec<String>) {
+    match expr {
+        Expr::And(exprs) => exprs.iter().for_each(|e| collect_prefilter(e, conditions)),
+        Expr::Exists { key } if key == "archived" => conditions.push(ARCHIVED_SQL.to_string()),
+        Expr::Compare { key, op: op @ (CompareOp::Eq | CompareOp::Ne), value } if key == "archived" => {
+            let archived = archive_flag(value) == Some(1.0);
+            // Unhashed sources are neither archived nor unarchived
+            conditions.push(if archived == (*op == CompareOp::Eq) {
+                ARCHIVED_SQL.to_string()
+            } else {
+                format!("NOT {}", ARCHIVED_SQL)
+            });
+        }
+        // Exact: only hashed sources can have (or lack) a copy on the root
+        Expr::InArchive { root_id, copied: true } => conditions.push(format!(
+            "s.object_id IN (SELECT object_id FROM sources WHERE root_id = {} AND present = 1)",
+            root_id
+        )),
+        Expr::InArchive { root_id, copied: false } => conditions.push(format!(
+            "s.object_id NOT IN (SELECT object_id FROM sources WHERE root_id = {} AND present = 1 AND object_id IS NOT NULL)",
+            root_id
+        )),
+        Expr::Compare { key, op: CompareOp::Glob, value } => {
+            let column = match key.as_str() {
+                "source.rel_path" | "rel_path" => "s.rel_path",
+                "source.root" => "r.path",
+                "source.path" => "CASE WHEN s.rel_path = '' THEN r.path ELSE r.path || '/' || s.rel_path END",
+                _ => return,
+            };
+            conditions.push(format!("{} LIKE '{}' ESCAPE '\\'", column, glob_to_like(value)));
+        }
+        _ => {}
+    }
+}
+
+/// A LIKE pattern (backslash-escaped, quoted for a SQL string) matching at
+/// least what the glob matches: LIKE's wildcards also cross '/', and `**/`
+/// may match no directory at all
+fn glob_to_like(glob: &str) -> String {
+    let mut like = String::new();
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => {
+                if chars.next_if_eq(&'*').is_some() {
+                    chars.next_if_eq(&'/');
+                }
+                like.push('%');
+            }
+            '?' => like.push('_'),
+            '%' | '_' | '\\' => {
+                like.push('\\');
+                like.push(c);
+            }
+            '\'' => like.push_str("''"),
+            c => like.push(c),
+        }
+    }
+    like
+}
+
 /// Evaluate an expression against a single source
 fn eval_expr(conn: &Connection, source_id: i64, expr: &Expr) -> Result<bool> {
     match expr {
@@ -350,6 +563,9 @@ fn eval_expr(conn: &Connection, source_id: i64, expr: &Expr) -> Result<bool> {
         Expr::Exists { key } => check_fact_exists(conn, source_id, key),
         Expr::Compare { key, op, value } => check_fact_compare(conn, source_id, key, *op, value),
         Expr::In { key, values } => check_fact_in(conn, source_id, key, values),
+        Expr::Matches { key, regex } => check_fact_matches(conn, source_id, key, regex),
+        Expr::InManifest(members) => check_in_manifest(conn, source_id, members),
+        Expr::InArchive { root_id, copied } => check_in_archive(conn, source_id, *root_id, *copied),
     }
 }
 
@@ -398,18 +614,31 @@ fn check_fact_exists(conn: &Connection, source_id: i64, key: &str) -> Result<boo
     match key {
         "source.ext" | "source.size" | "source.mtime" | "source.path" |
         "source.root" | "source.rel_path" | "source.device" | "source.inode" => Ok(true),
+        "source.nlink" => Ok(conn.query_row("SELECT nlink IS NOT NULL FROM sources WHERE id = ?", [source_id], |row| row.get(0))?),
+        "source.btime" => Ok(conn.query_row("SELECT btime IS NOT NULL FROM sources WHERE id = ?", [source_id], |row| row.get(0))?),
+        "source.link_group" => Ok(link_group(conn, source_id)?.is_some()),
         "content.hash.sha256" => Ok(object_id.is_some()),
+        "archived" | "archived.copies" | "in_archive" => Ok(archive_status(conn, source_id)?.is_some_and(|s| s.copies > 0)),
+        "archived.online" => Ok(archive_status(conn, source_id)?.is_some_and(|s| s.online_copies > 0)),
         // Legacy names
-        "ext" | "size" | "mtime" | "root_id" | "basis_rev" | "object_id" => Ok(true),
+        "ext" | "rel_path" | "size" | "mtime" | "root_id" | "basis_rev" | "object_id" => Ok(true),
         "hash" | "content_hash" | "content_hash.sha256" => Ok(object_id.is_some()),
         _ => Ok(false),
     }
 }
 
-fn check_fact_compare(conn: &Connection, source_id: i64, key: &str, op: CompareOp, value: &str) -> Result<bool> {
-    // Handle built-in source.* fields first
-    match key {
-        // Text fields
+fn link_group(conn: &Connection, source_id: i64) -> Result<Option<i64>> {
+    let group = conn.query_row(
+        &format!("SELECT {} FROM sources s WHERE s.id = ?", LINK_GROUP_SQL),
+        [source_id],
+        |row| row.get(0),
+    )?;
+    Ok(group)
+}
+
+/// Text of a built-in source.* field, or None when `key` isn't one
+fn builtin_text(conn: &Connection, source_id: i64, key: &str) -> Result<Option<String>> {
+    let text = match key {
         "source.ext" | "ext" => {
             let rel_path: String = conn.query_row(
                 "SELECT rel_path FROM sources WHERE id = ?",
@@ -420,7 +649,7 @@ fn check_fact_compare(conn: &Connection, source_id: i64, key: &str, op: CompareO
                 .extension()
                 .and_then(|e| e.to_str())
                 .unwrap_or("");
-            return Ok(compare_text(ext, op, value));
+            Some(ext.to_string())
         }
         "source.root" => {
             let root_path: String = conn.query_row(
@@ -428,7 +657,7 @@ fn check_fact_compare(conn: &Connection, source_id: i64, key: &str, op: CompareO
                 [source_id],
                 |row| row.get(0),
             )?;
-            return Ok(compare_text(&root_path, op, value));
+            Some(root_path)
         }
         "source.path" => {
             let (root_path, rel_path): (String, String) = conn.query_row(
@@ -441,17 +670,27 @@ fn check_fact_compare(conn: &Connection, source_id: i64, key: &str, op: CompareO
             } else {
                 format!("{}/{}", root_path, rel_path)
             };
-            return Ok(compare_text(&full_path, op, value));
+            Some(full_path)
         }
-        "source.rel_path" => {
+        "source.rel_path" | "rel_path" => {
             let rel_path: String = conn.query_row(
                 "SELECT rel_path FROM sources WHERE id = ?",
                 [source_id],
                 |row| row.get(0),
             )?;
-            return Ok(compare_text(&rel_path, op, value));
+            Some(rel_path)
         }
+        _ => None,
+    };
+    Ok(text)
+}
 
+fn check_fact_compare(conn: &Connection, source_id: i64, key: &str, op: CompareOp, value: &str) -> Result<bool> {
+    // Handle built-in source.* fields first
+    if let Some(text) = builtin_text(conn, source_id, key)? {
+        return Ok(compare_text(&text, op, value));
+    }
+    match key {
         // Numeric fields
         "source.size" | "size" => {
             let v: i64 = conn.query_row(
@@ -469,6 +708,14 @@ fn check_fact_compare(conn: &Connection, source_id: i64, key: &str, op: CompareO
             )?;
             return Ok(compare_numeric(v as f64, op, value));
         }
+        "source.btime" => {
+            let btime: Option<i64> = conn.query_row(
+                "SELECT btime FROM sources WHERE id = ?",
+                [source_id],
+                |row| row.get(0),
+            )?;
+            return Ok(btime.map(|b| compare_numeric(b as f64, op, value)).unwrap_or(false));
+        }
         "source.device" => {
             let device: Option<i64> = conn.query_row(
                 "SELECT device FROM sources WHERE id = ?",
@@ -485,6 +732,18 @@ fn check_fact_compare(conn: &Connection, source_id: i64, key: &str, op: CompareO
             )?;
             return Ok(inode.map(|i| compare_numeric(i as f64, op, value)).unwrap_or(false));
         }
+        "source.nlink" => {
+            let nlink: Option<i64> = conn.query_row(
+                "SELECT nlink FROM sources WHERE id = ?",
+                [source_id],
+                |row| row.get(0),
+            )?;
+            return Ok(nlink.map(|n| compare_numeric(n as f64, op, value)).unwrap_or(false));
+        }
+        "source.link_group" => {
+            let group = link_group(conn, source_id)?;
+            return Ok(group.map(|g| compare_numeric(g as f64, op, value)).unwrap_or(false));
+        }
         "root_id" => {
             let v: i64 = conn.query_row(
                 "SELECT root_id FROM sources WHERE id = ?",
@@ -493,9 +752,40 @@ fn check_fact_compare(conn: &Connection, source_id: i64, key: &str, op: CompareO
             )?;
             return Ok(compare_numeric(v as f64, op, value));
         }
+
+        // Archive status, known only for hashed sources
+        "archived" | "archived.online" | "archived.copies" => {
+            let Some(status) = archive_status(conn, source_id)? else {
+                return Ok(false);
+            };
+            let flag = match key {
+                "archived" => status.copies > 0,
+                "archived.online" => status.online_copies > 0,
+                _ => return Ok(compare_numeric(status.copies as f64, op, value)),
+            };
+            // Only = and != true/false get past parsing
+            return Ok((archive_flag(value) == Some(1.0)) == (flag == (op == CompareOp::Eq)));
+        }
         _ => {}
     }
 
+    check_fact_value(conn, source_id, key, |fact| compare_fact_value(fact, op, value))
+}
+
+/// `key =~ /pattern/`: built-in text fields, then text facts (or any item of a list)
+fn check_fact_matches(conn: &Connection, source_id: i64, key: &str, regex: &Regex) -> Result<bool> {
+    if let Some(text) = builtin_text(conn, source_id, key)? {
+        return Ok(regex.is_match(&text));
+    }
+    check_fact_value(conn, source_id, key, |fact| match fact {
+        FactValue::Text(t) => regex.is_match(t),
+        FactValue::List(items) => items.iter().any(|item| regex.is_match(item)),
+        FactValue::Num(_) | FactValue::Time(_) => false,
+    })
+}
+
+/// Whether the source's fact, or else its object's, satisfies `matches`
+fn check_fact_value(conn: &Connection, source_id: i64, key: &str, matches: impl Fn(&FactValue) -> bool) -> Result<bool> {
     // Get object_id for checking object facts
     let object_id: Option<i64> = conn
         .query_row(
@@ -507,14 +797,14 @@ fn check_fact_compare(conn: &Connection, source_id: i64, key: &str, op: CompareO
 
     // Check source facts then object facts
     if let Some(fact_value) = get_fact_value(conn, "source", source_id, key)? {
-        if compare_fact_value(&fact_value, op, value) {
+        if matches(&fact_value) {
             return Ok(true);
         }
     }
 
     if let Some(obj_id) = object_id {
         if let Some(fact_value) = get_fact_value(conn, "object", obj_id, key)? {
-            if compare_fact_value(&fact_value, op, value) {
+            if matches(&fact_value) {
                 return Ok(true);
             }
         }
@@ -523,6 +813,77 @@ fn check_fact_compare(conn: &Connection, source_id: i64, key: &str, op: CompareO
     Ok(false)
 }
 
+/// Present copies of a hashed source's content on archive roots (all roots,
+/// and those currently available)
+struct ArchiveStatus {
+    copies: i64,
+    online_copies: i64,
+}
+
+/// Archive status of a source, or None when it isn't hashed
+fn archive_status(conn: &Connection, source_id: i64) -> Result<Option<ArchiveStatus>> {
+    let status = conn.query_row(
+        "SELECT s.object_id IS NOT NULL, COALESCE(ao.copies, 0), COALESCE(ao.online_copies, 0)
+         FROM sources s LEFT JOIN archived_objects ao ON ao.object_id = s.object_id
+         WHERE s.id = ?",
+        [source_id],
+        |row| Ok((row.get::<_, bool>(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    Ok(match status {
+        (true, copies, online_copies) => Some(ArchiveStatus { copies, online_copies }),
+        (false, _, _) => None,
+    })
+}
+
+/// `in_archive=ROOT`: a hashed source has a present copy on that archive root
+/// (`copied` false: has none there)
+fn check_in_archive(conn: &Connection, source_id: i64, root_id: i64, copied: bool) -> Result<bool> {
+    let has_copy: Option<bool> = conn
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM sources a WHERE a.object_id = s.object_id AND a.root_id = ?2 AND a.present = 1)
+             FROM sources s WHERE s.id = ?1 AND s.object_id IS NOT NULL",
+            params![source_id, root_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(has_copy == Some(copied))
+}
+
+/// 1 or 0 for the values `archived` and `archived.online` compare against
+fn archive_flag(value: &str) -> Option<f64> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "1" => Some(1.0),
+        "false" | "no" | "0" => Some(0.0),
+        _ => None,
+    }
+}
+
+/// Reject comparisons the archive pseudo-facts can't answer when the filter is parsed
+fn check_archive_value(key: &str, op: CompareOp, value: &str) -> Result<()> {
+    match key {
+        "archived" | "archived.online" if archive_flag(value).is_none() || !matches!(op, CompareOp::Eq | CompareOp::Ne) => {
+            bail!("Invalid {} comparison. Use {}=true or {}=false", key, key, key)
+        }
+        "in_archive" if !matches!(op, CompareOp::Eq | CompareOp::Ne) => {
+            bail!("Invalid in_archive comparison. Use in_archive=ROOT (id:N, path:PATH or name:ALIAS) or !=")
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_in_manifest(conn: &Connection, source_id: i64, members: &ManifestMembers) -> Result<bool> {
+    if members.source_ids.contains(&source_id) {
+        return Ok(true);
+    }
+    if members.object_ids.is_empty() {
+        return Ok(false);
+    }
+    let object_id: Option<i64> = conn
+        .query_row("SELECT object_id FROM sources WHERE id = ?", [source_id], |row| row.get(0))
+        .unwrap_or(None);
+    Ok(object_id.is_some_and(|id| members.object_ids.contains(&id)))
+}
+
 fn check_fact_in(conn: &Connection, source_id: i64, key: &str, values: &[String]) -> Result<bool> {
     // Check if fact value matches any of the provided values
     for value in values {
@@ -537,41 +898,58 @@ fn check_fact_in(conn: &Connection, source_id: i64, key: &str, values: &[String]
 // Value Handling
 // ============================================================================
 
-/// Stored fact value - can be text, number, or timestamp
+/// Stored fact value - can be text, number, timestamp, or a JSON list (e.g.
+/// keywords), which matches when any of its items does
 enum FactValue {
     Text(String),
     Num(f64),
     Time(i64),
+    List(Vec<String>),
 }
 
+/// (value_text, value_num, value_time, value_json) of a fact row
+type StoredValue = (Option<String>, Option<f64>, Option<i64>, Option<String>);
+
 fn get_fact_value(conn: &Connection, entity_type: &str, entity_id: i64, key: &str) -> Result<Option<FactValue>> {
-    let result: Option<(Option<String>, Option<f64>, Option<i64>)> = conn
+    let result: Option<StoredValue> = conn
         .query_row(
-            "SELECT value_text, value_num, value_time FROM facts
+            "SELECT value_text, value_num, value_time, value_json FROM facts
              WHERE entity_type = ? AND entity_id = ? AND key = ?",
             params![entity_type, entity_id, key],
-            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
         )
         .ok();
 
-    Ok(result.and_then(|(text, num, time)| {
-        if let Some(t) = text {
-            Some(FactValue::Text(t))
-        } else if let Some(n) = num {
-            Some(FactValue::Num(n))
-        } else if let Some(ts) = time {
-            Some(FactValue::Time(ts))
-        } else {
-            None
-        }
+    Ok(result.and_then(|(text, num, time, json)| {
+        text.map(FactValue::Text)
+            .or_else(|| num.map(FactValue::Num))
+            .or_else(|| time.map(FactValue::Time))
+            .or_else(|| json.and_then(|json| json_list(&json)).map(FactValue::List))
     }))
 }
 
+/// Items of a JSON array, as text
+fn json_list(json: &str) -> Option<Vec<String>> {
+    let items = serde_json::from_str::<Vec<serde_json::Value>>(json).ok()?;
+    Some(
+        items
+            .into_iter()
+            .map(|item| match item {
+                serde_json::Value::String(s) => s,
+                other => other.to_string(),
+            })
+            .collect(),
+    )
+}
+
 fn compare_fact_value(fact: &FactValue, op: CompareOp, filter_value: &str) -> bool {
     match fact {
         FactValue::Text(t) => compare_text(t, op, filter_value),
         FactValue::Num(n) => compare_numeric(*n, op, filter_value),
         FactValue::Time(ts) => compare_numeric(*ts as f64, op, filter_value),
+        // != means no item equals the value
+        FactValue::List(items) if op == CompareOp::Ne => !items.iter().any(|item| compare_text(item, CompareOp::Eq, filter_value)),
+        FactValue::List(items) => items.iter().any(|item| compare_text(item, op, filter_value)),
     }
 }
 
@@ -583,6 +961,7 @@ fn compare_text(stored: &str, op: CompareOp, filter_value: &str) -> bool {
         CompareOp::Ge => stored >= filter_value,
         CompareOp::Lt => stored < filter_value,
         CompareOp::Le => stored <= filter_value,
+        CompareOp::Glob => glob_match(&filter_value.to_ascii_lowercase(), &stored.to_ascii_lowercase()),
     }
 }
 
@@ -599,6 +978,8 @@ fn compare_numeric(stored: f64, op: CompareOp, filter_value: &str) -> bool {
         CompareOp::Ge => stored >= filter_num,
         CompareOp::Lt => stored < filter_num,
         CompareOp::Le => stored <= filter_num,
+        // Globs only match text
+        CompareOp::Glob => false,
     }
 }
 
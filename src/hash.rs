@@ -0,0 +1,601 @@
+use anyhow::Result;
+use rusqlite::params;
+use serde::Serialize;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::apply::{self, ContentHashes, QUICKHASH_KEY};
+use crate::db::{Connection, Db};
+use crate::import_facts;
+use crate::output;
+use crate::platform;
+use crate::query::SourceQuery;
+use crate::remote;
+use crate::summary;
+use crate::util::{current_timestamp, format_bytes};
+
+/// How often the progress line is redrawn
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Files listed as slowest in the summary
+const SLOWEST_FILES: usize = 5;
+
+pub struct HashOptions {
+    pub jobs: usize,
+    pub include_archived: bool,
+    pub include_excluded: bool,
+    /// Only record quickhashes (`content.quickhash`), reading just the ends of each file
+    pub quick: bool,
+    /// Only fully hash sources whose quickhash matches other content
+    pub quick_matches: bool,
+    /// Only retry sources that failed before; by default they are skipped
+    pub retry_failed: bool,
+}
+
+struct Candidate {
+    source_id: i64,
+    path: PathBuf,
+    size: i64,
+    mtime: i64,
+    basis_rev: i64,
+}
+
+/// What hashing one file came to
+enum Hashed<T> {
+    Done(T),
+    /// Size or mtime no longer match the index, so the hash wouldn't describe
+    /// the indexed version
+    Changed,
+    Failed(anyhow::Error),
+}
+
+/// Result of `canon hash`
+#[derive(Default, Serialize)]
+struct HashReport {
+    /// Files hashed and linked to objects
+    hashed: u64,
+    bytes: u64,
+    /// Of those, content seen for the first time
+    new_objects: u64,
+    /// Changed on disk since the last scan; left for the next scan
+    changed: u64,
+    failed: u64,
+    /// Matching sources skipped because they already have a hash
+    already_hashed: u64,
+    /// Matching sources on rclone remotes, which get hashes from the backend
+    remote: u64,
+    /// Matching sources skipped because reading them failed before (--retry-failed)
+    previously_failed: u64,
+    /// --quick: matching sources skipped because their quickhash is current
+    #[serde(skip_serializing_if = "is_zero")]
+    already_quickhashed: u64,
+    /// --quick-matches: matching sources skipped because no other content
+    /// shares their quickhash, or because they have none yet
+    #[serde(skip_serializing_if = "is_zero")]
+    unmatched: u64,
+    /// --quick: sources fingerprinted now or before whose quickhash matches
+    /// other content
+    #[serde(skip_serializing_if = "is_zero")]
+    quickhash_matches: u64,
+    duration_ms: u64,
+    /// Worker time spent waiting for reads, summed over workers
+    read_ms: u64,
+    /// Worker time spent hashing what was read
+    hash_ms: u64,
+    /// The files that took longest, slowest first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    slowest: Vec<SlowFile>,
+}
+
+#[derive(Clone, Serialize)]
+struct SlowFile {
+    path: String,
+    bytes: u64,
+    ms: u64,
+}
+
+/// A matching source as `run` selects it: (id, root path, rel_path, size,
+/// mtime, basis_rev), then whether it is hashed, has a current quickhash,
+/// shares it with other content, and failed before
+type SourceRow = ((i64, String, String, i64, i64, i64), bool, bool, bool, bool);
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+/// Progress counters and timings shared by the workers
+struct Progress {
+    /// Files and bytes processed, whatever the outcome
+    files: AtomicU64,
+    bytes: AtomicU64,
+    /// Files and bytes to process, when counted up front for the ETA
+    total: Option<(u64, u64)>,
+    started: Instant,
+    verb: &'static str,
+    reading: AtomicU64,
+    busy: AtomicU64,
+    slowest: Mutex<Vec<SlowFile>>,
+    /// When the progress line was last drawn, when there is a terminal to draw it on
+    drawn: Option<Mutex<Instant>>,
+}
+
+impl Progress {
+    fn add(&self, bytes: u64) {
+        let files = self.files.fetch_add(1, Ordering::Relaxed) + 1;
+        let bytes = self.bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        let Some(ref drawn) = self.drawn else {
+            return;
+        };
+        let mut drawn = drawn.lock().unwrap();
+        if drawn.elapsed() >= PROGRESS_INTERVAL {
+            *drawn = Instant::now();
+            match self.total {
+                Some((total_files, total_bytes)) => eprint!(
+                    "\r{}: {}/{} files, {}/{} ({}, ETA {})\x1b[K",
+                    self.verb,
+                    files,
+                    total_files,
+                    format_bytes(bytes),
+                    format_bytes(total_bytes),
+                    self.rate(bytes),
+                    self.eta(bytes, total_bytes)
+                ),
+                None => eprint!("\r{}: {} files, {} ({})\x1b[K", self.verb, files, format_bytes(bytes), self.rate(bytes)),
+            }
+            let _ = io::stderr().flush();
+        }
+    }
+
+    /// Record how long one file took, and how much of that went to reads
+    fn time(&self, candidate: &Candidate, took: Duration, reading: Duration) {
+        self.reading.fetch_add(reading.as_nanos() as u64, Ordering::Relaxed);
+        self.busy.fetch_add(took.as_nanos() as u64, Ordering::Relaxed);
+        let ms = took.as_millis() as u64;
+        let mut slowest = self.slowest.lock().unwrap();
+        if slowest.len() < SLOWEST_FILES || slowest.last().is_some_and(|slow| slow.ms < ms) {
+            let path = candidate.path.display().to_string();
+            slowest.push(SlowFile { path, bytes: candidate.size as u64, ms });
+            slowest.sort_by_key(|slow| std::cmp::Reverse(slow.ms));
+            slowest.truncate(SLOWEST_FILES);
+        }
+    }
+
+    fn rate(&self, bytes: u64) -> String {
+        let secs = self.started.elapsed().as_secs_f64();
+        if secs < 0.001 {
+            return "-".to_string();
+        }
+        format!("{}/s", format_bytes((bytes as f64 / secs) as u64))
+    }
+
+    /// Time left at the rate so far
+    fn eta(&self, bytes: u64, total_bytes: u64) -> String {
+        let secs = self.started.elapsed().as_secs_f64();
+        if bytes == 0 || secs < 0.001 {
+            return "-".to_string();
+        }
+        let left = total_bytes.saturating_sub(bytes) as f64 / (bytes as f64 / secs);
+        format_duration(Duration::from_secs_f64(left))
+    }
+
+    fn finish(&self) {
+        if self.drawn.is_some() {
+            eprint!("\r\x1b[K");
+        }
+    }
+}
+
+/// Hash unhashed local sources that match the scope and filters on `jobs`
+/// worker threads (SHA-256, BLAKE3 and quickhash in one read), link each to
+/// its object (created if the content is new) and promote its content facts,
+/// as importing `content.hash.sha256` would. With `quick`, only record their
+/// quickhashes; with `quick_matches`, only hash those whose quickhash matches
+/// other content.
+pub fn run(db: &Db, scope_path: Option<&Path>, filter_strs: &[String], options: &HashOptions) -> Result<()> {
+    let conn = db.conn();
+
+    let query = SourceQuery::new()
+        .scope(scope_path)?
+        .filters(conn, filter_strs)?
+        .include_archived(options.include_archived)
+        .include_excluded(options.include_excluded);
+
+    let jobs = if options.jobs == 0 {
+        thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+    } else {
+        options.jobs
+    };
+
+    // Whether the source has a current quickhash, and whether other content shares it
+    let columns = format!(
+        "s.id, r.path, s.rel_path, s.size, s.mtime, s.basis_rev, s.object_id IS NOT NULL,
+         EXISTS (SELECT 1 FROM facts q WHERE q.entity_type = 'source' AND q.entity_id = s.id
+             AND q.key = '{}' AND q.observed_basis_rev = s.basis_rev),
+         {},
+         EXISTS (SELECT 1 FROM hash_failures hf WHERE hf.source_id = s.id AND hf.basis_rev = s.basis_rev)",
+        QUICKHASH_KEY,
+        if options.quick_matches { summary::quickhash_match_condition(summary::QUICKHASH_MATCHES_ANY) } else { "0".into() }
+    );
+    // On a terminal, count what there is to do first, for the ETA
+    let terminal = io::stderr().is_terminal();
+    let total = if terminal {
+        let (mut files, mut bytes) = (0, 0);
+        query.for_each_batch(conn, &columns, source_row, |rows| {
+            for candidate in select(rows, options, &mut HashReport::default()) {
+                files += 1;
+                bytes += candidate.size as u64;
+            }
+            Ok(())
+        })?;
+        Some((files, bytes))
+    } else {
+        None
+    };
+
+    let progress = Progress {
+        files: AtomicU64::new(0),
+        bytes: AtomicU64::new(0),
+        total,
+        started: Instant::now(),
+        verb: if options.quick { "Fingerprinting" } else { "Hashing" },
+        reading: AtomicU64::new(0),
+        busy: AtomicU64::new(0),
+        slowest: Mutex::new(Vec::new()),
+        drawn: terminal.then(|| Mutex::new(Instant::now())),
+    };
+    let mut report = HashReport::default();
+
+    query.for_each_batch(conn, &columns, source_row, |rows| {
+        let candidates = select(rows, options, &mut report);
+        if options.quick {
+            let results = hash_parallel(&candidates, jobs, &progress, |path| {
+                let started = Instant::now();
+                let quickhash = apply::quick_hash_file(path)?;
+                Ok((quickhash, started.elapsed()))
+            });
+            store_quickhashes(conn, &candidates, results, &mut report)
+        } else {
+            let results = hash_parallel(&candidates, jobs, &progress, apply::hash_file_timed);
+            store_results(conn, &candidates, results, &mut report)
+        }
+    })?;
+    if options.quick {
+        report.quickhash_matches = count_quickhash_matches(conn, &query)?;
+    }
+    progress.finish();
+    report.duration_ms = progress.started.elapsed().as_millis() as u64;
+    let reading = Duration::from_nanos(progress.reading.load(Ordering::Relaxed));
+    let busy = Duration::from_nanos(progress.busy.load(Ordering::Relaxed));
+    report.read_ms = reading.as_millis() as u64;
+    report.hash_ms = busy.saturating_sub(reading).as_millis() as u64;
+    report.slowest = progress.slowest.into_inner().unwrap();
+
+    output::emit(&report, |report| {
+        if options.quick {
+            print_quick_summary(report);
+        } else if report.hashed + report.changed + report.failed == 0 {
+            println!("Nothing to hash ({} matching sources already hashed)", report.already_hashed);
+        } else {
+            let secs = report.duration_ms as f64 / 1000.0;
+            let rate = if secs > 0.0 { (report.bytes as f64 / secs) as u64 } else { report.bytes };
+            println!(
+                "Hashed {} files ({} in {:.1}s, {}/s): {} new objects, {} changed since scan, {} failed",
+                report.hashed,
+                format_bytes(report.bytes),
+                secs,
+                format_bytes(rate),
+                report.new_objects,
+                report.changed,
+                report.failed
+            );
+        }
+        if !options.quick && report.hashed > 0 {
+            print_timings(report, jobs);
+        }
+        if report.previously_failed > 0 {
+            eprintln!(
+                "Note: skipped {} sources that failed before (canon hash --retry-failed tries them again)",
+                report.previously_failed
+            );
+        }
+        if report.unmatched > 0 {
+            eprintln!(
+                "Note: skipped {} sources whose quickhash matches no other content, or that have none yet (canon hash --quick)",
+                report.unmatched
+            );
+        }
+        if report.changed > 0 {
+            eprintln!("Note: {} files changed since they were scanned; scan them again to hash them", report.changed);
+        }
+        if report.remote > 0 {
+            eprintln!(
+                "Note: skipped {} sources on rclone remotes (scan them to take hashes from the backend)",
+                report.remote
+            );
+        }
+    })
+}
+
+/// Read one row of `run`'s columns
+fn source_row(row: &rusqlite::Row) -> rusqlite::Result<SourceRow> {
+    Ok((
+        (row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?),
+        row.get(6)?,
+        row.get(7)?,
+        row.get(8)?,
+        row.get(9)?,
+    ))
+}
+
+/// The sources of one batch to hash, counting the others in `report`
+fn select(rows: Vec<SourceRow>, options: &HashOptions, report: &mut HashReport) -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+    for ((source_id, root_path, rel_path, size, mtime, basis_rev), hashed, quickhashed, matched, failed) in rows {
+        if hashed {
+            report.already_hashed += 1;
+        } else if remote::is_remote(&root_path) {
+            report.remote += 1;
+        } else if failed != options.retry_failed {
+            if failed {
+                report.previously_failed += 1;
+            }
+        } else if options.quick && quickhashed {
+            report.already_quickhashed += 1;
+        } else if options.quick_matches && !matched {
+            report.unmatched += 1;
+        } else {
+            let path = Path::new(&root_path).join(&rel_path);
+            candidates.push(Candidate { source_id, path, size, mtime, basis_rev });
+        }
+    }
+    candidates
+}
+
+/// Where the workers' time went, and the slowest files: reads dominating
+/// means the storage is the limit, hashing dominating means the CPU is
+fn print_timings(report: &HashReport, jobs: usize) {
+    if let Some(read_pct) = (report.read_ms * 100).checked_div(report.read_ms + report.hash_ms) {
+        let bound = if report.read_ms >= report.hash_ms {
+            "disk-bound".to_string()
+        } else if jobs < thread::available_parallelism().map(|n| n.get()).unwrap_or(1) {
+            format!("CPU-bound; more than -j {} may help", jobs)
+        } else {
+            "CPU-bound".to_string()
+        };
+        println!("Worker time: {}% reading, {}% hashing ({})", read_pct, 100 - read_pct, bound);
+    }
+    if !report.slowest.is_empty() {
+        println!("Slowest files:");
+        for slow in &report.slowest {
+            let secs = slow.ms as f64 / 1000.0;
+            let rate = (slow.bytes * 1000).checked_div(slow.ms).unwrap_or(slow.bytes);
+            println!(
+                "  {:>7.1}s  {:>9}  {:>11}  {}",
+                secs,
+                format_bytes(slow.bytes),
+                format!("{}/s", format_bytes(rate)),
+                slow.path
+            );
+        }
+    }
+}
+
+fn print_quick_summary(report: &HashReport) {
+    if report.hashed + report.changed + report.failed == 0 {
+        println!(
+            "Nothing to fingerprint ({} matching sources already have a quickhash, {} a full hash)",
+            report.already_quickhashed, report.already_hashed
+        );
+    } else {
+        println!(
+            "Fingerprinted {} files in {:.1}s: {} changed since scan, {} failed",
+            report.hashed,
+            report.duration_ms as f64 / 1000.0,
+            report.changed,
+            report.failed
+        );
+    }
+    if report.quickhash_matches > 0 {
+        println!(
+            "{} unhashed files share a quickhash with other content; canon hash --quick-matches hashes just those",
+            report.quickhash_matches
+        );
+    }
+}
+
+/// Unhashed sources among those matching `query` whose quickhash matches other content
+fn count_quickhash_matches(conn: &Connection, query: &SourceQuery) -> Result<u64> {
+    let mut count = 0;
+    query.for_each_batch(
+        conn,
+        &format!(
+            "s.id, s.object_id IS NULL AND {}",
+            summary::quickhash_match_condition(summary::QUICKHASH_MATCHES_ANY)
+        ),
+        |row| row.get::<_, bool>(1),
+        |rows| {
+            count += rows.into_iter().filter(|&matched| matched).count() as u64;
+            Ok(())
+        },
+    )?;
+    Ok(count)
+}
+
+/// Hash all candidates with `hash`, which also returns its time spent
+/// reading, on `jobs` worker threads. Results are returned in candidate order.
+fn hash_parallel<T: Send>(
+    candidates: &[Candidate],
+    jobs: usize,
+    progress: &Progress,
+    hash: fn(&Path) -> Result<(T, Duration)>,
+) -> Vec<Hashed<T>> {
+    let next = AtomicUsize::new(0);
+    let collected: Mutex<Vec<(usize, Hashed<T>)>> = Mutex::new(Vec::with_capacity(candidates.len()));
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(candidates.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(candidate) = candidates.get(i) else {
+                    break;
+                };
+                let result = hash_candidate(candidate, hash, progress);
+                progress.add(candidate.size as u64);
+                collected.lock().unwrap().push((i, result));
+            });
+        }
+    });
+
+    let mut collected = collected.into_inner().unwrap();
+    collected.sort_by_key(|(i, _)| *i);
+    collected.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Hash one file, checking before and after that it is still the version
+/// the index has
+fn hash_candidate<T>(candidate: &Candidate, hash: fn(&Path) -> Result<(T, Duration)>, progress: &Progress) -> Hashed<T> {
+    let unchanged = || -> Result<bool> {
+        let metadata = fs::metadata(&candidate.path)?;
+        Ok(metadata.len() as i64 == candidate.size && platform::mtime(&metadata) == candidate.mtime)
+    };
+    match unchanged() {
+        Ok(true) => {}
+        Ok(false) => return Hashed::Changed,
+        Err(e) => return Hashed::Failed(e.context(format!("Failed to stat {}", candidate.path.display()))),
+    }
+    let started = Instant::now();
+    let hashes = match hash(&candidate.path) {
+        Ok((hashes, reading)) => {
+            progress.time(candidate, started.elapsed(), reading);
+            hashes
+        }
+        Err(e) => return Hashed::Failed(e),
+    };
+    match unchanged() {
+        Ok(true) => Hashed::Done(hashes),
+        _ => Hashed::Changed,
+    }
+}
+
+/// Link one batch's hashes in a single transaction
+fn store_results(
+    conn: &Connection,
+    candidates: &[Candidate],
+    results: Vec<Hashed<ContentHashes>>,
+    report: &mut HashReport,
+) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    let mut known =
+        conn.prepare_cached("SELECT EXISTS (SELECT 1 FROM objects WHERE hash_type = 'sha256' AND hash_value = ?)")?;
+
+    for (candidate, result) in candidates.iter().zip(results) {
+        let hashes = match result {
+            Hashed::Done(hashes) => hashes,
+            Hashed::Changed => {
+                report.changed += 1;
+                continue;
+            }
+            Hashed::Failed(e) => {
+                eprintln!("Warning: {:#}", e);
+                record_failure(conn, candidate, &e)?;
+                report.failed += 1;
+                continue;
+            }
+        };
+        // A scan may have changed the source since the batch was read
+        let still_indexed: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sources WHERE id = ? AND size = ? AND mtime = ? AND present = 1)",
+            params![candidate.source_id, candidate.size, candidate.mtime],
+            |row| row.get(0),
+        )?;
+        if !still_indexed {
+            report.changed += 1;
+            continue;
+        }
+        if !known.query_row([&hashes.sha256], |row| row.get::<_, bool>(0))? {
+            report.new_objects += 1;
+        }
+        import_facts::link_hash(conn, candidate.source_id, &hashes)?;
+        conn.execute("DELETE FROM hash_failures WHERE source_id = ?", [candidate.source_id])?;
+        report.hashed += 1;
+        report.bytes += candidate.size as u64;
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+/// Record one batch's quickhashes on their sources, for the version that was read
+fn store_quickhashes(
+    conn: &Connection,
+    candidates: &[Candidate],
+    results: Vec<Hashed<String>>,
+    report: &mut HashReport,
+) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    let now = current_timestamp();
+
+    for (candidate, result) in candidates.iter().zip(results) {
+        let quickhash = match result {
+            Hashed::Done(quickhash) => quickhash,
+            Hashed::Changed => {
+                report.changed += 1;
+                continue;
+            }
+            Hashed::Failed(e) => {
+                eprintln!("Warning: {:#}", e);
+                record_failure(conn, candidate, &e)?;
+                report.failed += 1;
+                continue;
+            }
+        };
+        let still_indexed: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sources WHERE id = ? AND basis_rev = ? AND present = 1)",
+            params![candidate.source_id, candidate.basis_rev],
+            |row| row.get(0),
+        )?;
+        if !still_indexed {
+            report.changed += 1;
+            continue;
+        }
+        let value = serde_json::Value::String(quickhash);
+        import_facts::insert_fact(conn, "source", candidate.source_id, QUICKHASH_KEY, &value, now, Some(candidate.basis_rev))?;
+        conn.execute("DELETE FROM hash_failures WHERE source_id = ?", [candidate.source_id])?;
+        report.hashed += 1;
+        report.bytes += candidate.size as u64;
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+/// Remember that reading a source failed, so later runs skip it until it
+/// changes or --retry-failed
+fn record_failure(conn: &Connection, candidate: &Candidate, error: &anyhow::Error) -> Result<()> {
+    conn.execute(
+        "INSERT INTO hash_failures (source_id, basis_rev, error, attempts, failed_at) VALUES (?, ?, ?, 1, ?)
+         ON CONFLICT(source_id) DO UPDATE SET
+           basis_rev = excluded.basis_rev,
+           error = excluded.error,
+           attempts = CASE WHEN basis_rev = excluded.basis_rev THEN attempts + 1 ELSE 1 END,
+           failed_at = excluded.failed_at",
+        params![candidate.source_id, candidate.basis_rev, format!("{:#}", error), current_timestamp()],
+    )?;
+    Ok(())
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}h {}m", secs / 3600, secs % 3600 / 60)
+    } else if secs >= 60 {
+        format!("{}m {}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
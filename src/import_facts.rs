@@ -1,38 +1,80 @@
 use anyhow::{Context, Result};
+use clap::ValueEnum;
 use rusqlite::{params, OptionalExtension};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::io::{self, BufRead};
-use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::apply::{ContentHashes, QUICKHASH_KEY};
 use crate::db::{Connection, Db};
+use crate::util::current_timestamp;
+
+/// What `canon import-facts` reads
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ImportFormat {
+    /// `{source_id, basis_rev, facts}` objects, one per line on stdin
+    Jsonl,
+    /// A digiKam database (digikam4.db)
+    Digikam,
+    /// A Lightroom Classic catalog (.lrcat)
+    Lightroom,
+}
 
+/// One line of import-facts input
 #[derive(Deserialize)]
-struct FactImport {
-    source_id: i64,
-    basis_rev: i64,
+pub struct FactImport {
+    pub source_id: i64,
+    pub basis_rev: i64,
     #[serde(default = "current_timestamp")]
-    observed_at: i64,
-    facts: HashMap<String, Value>,
+    pub observed_at: i64,
+    pub facts: HashMap<String, Value>,
+}
+
+/// Counts of what an import did and skipped, for its summary
+#[derive(Default, Serialize)]
+pub struct ImportStats {
+    pub lines_processed: u64,
+    pub facts_imported: u64,
+    pub skipped_stale: u64,
+    pub skipped_reserved: u64,
+    pub skipped_archived: u64,
+    pub objects_created: u64,
+    pub facts_promoted: u64,
 }
 
-fn current_timestamp() -> i64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_secs() as i64
+/// Imports fact records one at a time, for callers that produce them in-process
+/// (e.g. `canonargs --import`) rather than as JSONL
+pub struct Importer<'a> {
+    conn: &'a Connection,
+    allow_archived: bool,
+    stats: ImportStats,
 }
 
-#[derive(Default)]
-struct ImportStats {
-    lines_processed: u64,
-    facts_imported: u64,
-    skipped_stale: u64,
-    skipped_reserved: u64,
-    skipped_archived: u64,
-    objects_created: u64,
-    facts_promoted: u64,
+impl<'a> Importer<'a> {
+    pub fn new(db: &'a Db, allow_archived: bool) -> Self {
+        Self {
+            conn: db.conn(),
+            allow_archived,
+            stats: ImportStats::default(),
+        }
+    }
+
+    /// Import one record. Problems with the record (unknown source, stale
+    /// basis_rev, database errors) are reported as warnings and counted.
+    pub fn import(&mut self, import: &FactImport) {
+        self.stats.lines_processed += 1;
+        if let Err(e) = process_import(self.conn, import, &mut self.stats, self.allow_archived) {
+            eprintln!(
+                "Warning: Failed to process source_id {}: {}",
+                import.source_id, e
+            );
+        }
+    }
+
+    pub fn finish(self) -> ImportStats {
+        self.stats
+    }
 }
 
 /// Normalize a fact key to use the content.* namespace.
@@ -49,38 +91,34 @@ fn normalize_fact_key(key: &str) -> Result<String, &'static str> {
     Ok(format!("content.{}", key))
 }
 
+/// Import fact JSONL from stdin
 pub fn run(db: &Db, allow_archived: bool) -> Result<()> {
-    let conn = db.conn();
-    let stdin = io::stdin();
-    let mut stats = ImportStats::default();
+    import_from(db, io::stdin().lock(), allow_archived)
+}
+
+/// Import fact JSONL from any reader (one `{source_id, basis_rev, facts}` object per line)
+pub fn import_from<R: BufRead>(db: &Db, reader: R, allow_archived: bool) -> Result<()> {
+    let mut importer = Importer::new(db, allow_archived);
 
-    for line in stdin.lock().lines() {
-        let line = line.context("Failed to read line from stdin")?;
+    for line in reader.lines() {
+        let line = line.context("Failed to read input line")?;
         if line.trim().is_empty() {
             continue;
         }
 
-        stats.lines_processed += 1;
-
         let import: FactImport = match serde_json::from_str(&line) {
             Ok(i) => i,
             Err(e) => {
-                eprintln!("Warning: Failed to parse line {}: {}", stats.lines_processed, e);
+                importer.stats.lines_processed += 1;
+                eprintln!("Warning: Failed to parse line {}: {}", importer.stats.lines_processed, e);
                 continue;
             }
         };
 
-        match process_import(&conn, &import, &mut stats, allow_archived) {
-            Ok(_) => {}
-            Err(e) => {
-                eprintln!(
-                    "Warning: Failed to process source_id {}: {}",
-                    import.source_id, e
-                );
-            }
-        }
+        importer.import(&import);
     }
 
+    let stats = importer.finish();
     println!(
         "Processed {} lines: {} facts imported, {} skipped (stale), {} skipped (reserved), {} skipped (archived), {} objects created, {} facts promoted",
         stats.lines_processed,
@@ -151,28 +189,41 @@ fn process_import(conn: &Connection, import: &FactImport, stats: &mut ImportStat
         .find(|(k, _)| k == "content.hash.sha256")
         .map(|(_, v)| *v);
 
+    let blake3_value = normalized_facts
+        .iter()
+        .find(|(k, _)| k == "content.hash.blake3")
+        .and_then(|(_, v)| v.as_str());
+
     if let Some(hash_val) = hash_value {
         if let Some(hash_str) = hash_val.as_str() {
             object_id = Some(get_or_create_object(conn, "sha256", hash_str, stats)?);
-
-            // Link source to object if not already linked
-            if current_object_id != object_id {
-                conn.execute(
-                    "UPDATE sources SET object_id = ? WHERE id = ?",
-                    params![object_id, import.source_id],
-                )?;
-            }
         }
+    } else if let Some(blake3) = blake3_value {
+        // Without a SHA-256 a BLAKE3 can only link to content already known;
+        // otherwise it waits as a source fact like any other content fact
+        let known = object_by_hash(conn, "blake3", blake3)?;
+        object_id = known.or(object_id);
+    }
+
+    // Link source to object if not already linked
+    if current_object_id != object_id {
+        conn.execute(
+            "UPDATE sources SET object_id = ? WHERE id = ?",
+            params![object_id, import.source_id],
+        )?;
+    }
+    if let (Some(obj_id), Some(blake3)) = (object_id, blake3_value) {
+        record_object_hash(conn, obj_id, "blake3", blake3)?;
     }
 
     // Import facts - all imported facts are content facts (stored on object when available)
     for (key, value) in &normalized_facts {
-        if object_id.is_some() {
+        if let Some(obj_id) = object_id {
             // Store as object fact
             insert_fact(
                 conn,
                 "object",
-                object_id.unwrap(),
+                obj_id,
                 key,
                 value,
                 import.observed_at,
@@ -196,14 +247,52 @@ fn process_import(conn: &Connection, import: &FactImport, stats: &mut ImportStat
     }
 
     // If we just linked an object, promote any existing content facts from source to object
-    if object_id.is_some() && current_object_id.is_none() {
-        let promoted = promote_content_facts(conn, import.source_id, object_id.unwrap())?;
+    if let (Some(obj_id), None) = (object_id, current_object_id) {
+        let promoted = promote_content_facts(conn, import.source_id, obj_id)?;
         stats.facts_promoted += promoted;
     }
 
     Ok(())
 }
 
+/// Link a source to the object with these hashes (created from the SHA-256
+/// if needed) and promote the source's content facts, as importing
+/// `content.hash.sha256` and `content.hash.blake3` would. Returns the object id.
+pub(crate) fn link_hash(conn: &Connection, source_id: i64, hashes: &ContentHashes) -> Result<i64> {
+    let mut stats = ImportStats::default();
+    let object_id = get_or_create_object(conn, "sha256", &hashes.sha256, &mut stats)?;
+    conn.execute("UPDATE sources SET object_id = ? WHERE id = ?", params![object_id, source_id])?;
+    let now = current_timestamp();
+    insert_fact(conn, "object", object_id, "content.hash.sha256", &Value::String(hashes.sha256.clone()), now, None)?;
+    insert_fact(conn, "object", object_id, "content.hash.blake3", &Value::String(hashes.blake3.clone()), now, None)?;
+    insert_fact(conn, "object", object_id, QUICKHASH_KEY, &Value::String(hashes.quickhash.clone()), now, None)?;
+    record_object_hash(conn, object_id, "blake3", &hashes.blake3)?;
+    promote_content_facts(conn, source_id, object_id)?;
+    Ok(object_id)
+}
+
+/// The object with this hash, by any algorithm it was recorded under
+fn object_by_hash(conn: &Connection, algorithm: &str, value: &str) -> Result<Option<i64>> {
+    let id = conn
+        .query_row(
+            "SELECT object_id FROM object_hashes WHERE algorithm = ? AND value = ?",
+            params![algorithm, value],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(id)
+}
+
+/// Record another hash of an object. A value already recorded for a
+/// different object is left alone.
+fn record_object_hash(conn: &Connection, object_id: i64, algorithm: &str, value: &str) -> Result<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO object_hashes (object_id, algorithm, value) VALUES (?, ?, ?)",
+        params![object_id, algorithm, value],
+    )?;
+    Ok(())
+}
+
 fn get_or_create_object(
     conn: &Connection,
     hash_type: &str,
@@ -238,7 +327,7 @@ fn is_content_fact(key: &str) -> bool {
     key.starts_with("content.")
 }
 
-fn insert_fact(
+pub(crate) fn insert_fact(
     conn: &Connection,
     entity_type: &str,
     entity_id: i64,
@@ -275,7 +364,29 @@ fn insert_fact(
     Ok(())
 }
 
-fn classify_value(value: &Value) -> (Option<String>, Option<f64>, Option<i64>, Option<String>) {
+/// A stored fact as the JSON value that `insert_fact` would store the same
+/// way, from a row of (value_text, value_num, value_time, value_json). Times
+/// become RFC 3339 strings, so facts passed through other tools (canonargs,
+/// catalogs) come back unchanged.
+pub(crate) fn stored_value(row: &rusqlite::Row) -> rusqlite::Result<Value> {
+    let (text, num, time, json): (Option<String>, Option<f64>, Option<i64>, Option<String>) =
+        (row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?);
+    Ok(if let Some(t) = text {
+        Value::String(t)
+    } else if let Some(n) = num {
+        serde_json::json!(n)
+    } else if let Some(t) = time {
+        chrono::DateTime::from_timestamp(t, 0)
+            .map(|dt| Value::String(dt.to_rfc3339()))
+            .unwrap_or_else(|| serde_json::json!(t))
+    } else if let Some(j) = json {
+        serde_json::from_str(&j).unwrap_or(Value::String(j))
+    } else {
+        Value::Null
+    })
+}
+
+pub(crate) fn classify_value(value: &Value) -> (Option<String>, Option<f64>, Option<i64>, Option<String>) {
     match value {
         Value::String(s) => {
             // Try to parse as timestamp (ISO 8601 format)
@@ -307,7 +418,10 @@ fn classify_value(value: &Value) -> (Option<String>, Option<f64>, Option<i64>, O
     }
 }
 
-fn promote_content_facts(conn: &Connection, source_id: i64, object_id: i64) -> Result<u64> {
+/// (fact id, key, value_text, value_num, value_time, value_json, observed_at)
+type StoredFactRow = (i64, String, Option<String>, Option<f64>, Option<i64>, Option<String>, i64);
+
+pub(crate) fn promote_content_facts(conn: &Connection, source_id: i64, object_id: i64) -> Result<u64> {
     // Find content facts on this source that should be promoted
     let mut stmt = conn.prepare(
         "SELECT id, key, value_text, value_num, value_time, value_json, observed_at
@@ -315,7 +429,7 @@ fn promote_content_facts(conn: &Connection, source_id: i64, object_id: i64) -> R
          WHERE entity_type = 'source' AND entity_id = ?"
     )?;
 
-    let facts: Vec<(i64, String, Option<String>, Option<f64>, Option<i64>, Option<String>, i64)> = stmt
+    let facts: Vec<StoredFactRow> = stmt
         .query_map([source_id], |row| {
             Ok((
                 row.get(0)?,
@@ -0,0 +1,115 @@
+//! Core of canon: the database layer, filter engine, scanning, fact extraction
+//! and the manifest (cluster/apply) workflow.
+//!
+//! The `canon` binary is a thin CLI over this crate. Other tools can drive the
+//! same logic directly:
+//!
+//! ```no_run
+//! use std::path::{Path, PathBuf};
+//!
+//! let db = canon_core::db::open(Path::new("/tmp/canon.db"), false, false)?;
+//! canon_core::scan::run(&db, &[PathBuf::from("/photos")], "source", true, &Default::default())?;
+//!
+//! let filter = canon_core::filter::Filter::parse(db.conn(), "source.ext=jpg")?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! Command entry points (`scan::run`, `coverage::run`, ...) report their
+//! results on stdout and warnings on stderr, exactly as the CLI does.
+
+/// Apply a manifest: copy, rename or move files into an archive root
+pub mod apply;
+/// Archive catalogs: export an archive root's index, import it elsewhere
+pub mod archive_catalog;
+/// Burst grouping for `cluster generate --group-by burst`
+pub mod burst;
+/// Keyword, people and rating import from digiKam and Lightroom catalogs
+pub mod catalog;
+/// Generate manifests from filtered sources
+pub mod cluster;
+/// Named collections of objects that survive file moves
+pub mod collection;
+/// Shell completion scripts and candidates (`canon completions`, `canon __complete`)
+pub mod complete;
+/// Archive coverage statistics
+pub mod coverage;
+/// Database schema, connection setup and root path resolution
+pub mod db;
+/// Cross-table consistency checks and safe repairs (`canon doctor`)
+pub mod doctor;
+/// Error categories and their exit codes
+pub mod error;
+/// Source exclusions (`policy.exclude`)
+pub mod exclude;
+/// Export selections as rsync file lists, playlists and digiKam sidecars
+pub mod export;
+/// Built-in fact extractors (EXIF, image headers, ffprobe)
+pub mod extract;
+/// Fact overview, deletion and pruning
+pub mod facts;
+/// Filter expression parser and evaluator
+pub mod filter;
+/// `canon hash`: SHA-256 unhashed sources on a worker pool
+pub mod hash;
+/// Import facts from JSONL
+pub mod import_facts;
+/// Archive layout and stray-file checks
+pub mod lint;
+/// Advisory locks between concurrent scan/apply/organize runs
+pub mod lock;
+/// List sources
+pub mod ls;
+/// File type sniffing from leading bytes for `scan --magic` (`source.magic`)
+pub mod magic;
+/// Object inspection (`canon object show`/`ls`)
+pub mod object;
+/// In-place renames within source roots
+pub mod organize;
+/// Text or JSON rendering of command results (`--json`)
+pub mod output;
+/// Pinned sources, protected from bulk exclude, fact deletion and moves
+pub mod pin;
+/// Declarative multi-step runs (`canon run`)
+pub mod pipeline;
+/// Platform differences: file identity, timestamps, cross-device renames
+pub mod platform;
+/// Shared source selection (scope, role, exclusion, filters, batching)
+pub mod query;
+/// Opt-in cache of filter results across commands (`--query-cache`)
+pub mod query_cache;
+/// Compare an archive root's index with the filesystem (`canon reconcile`)
+pub mod reconcile;
+/// rclone-backed remote roots (`remote:path`)
+pub mod remote;
+/// Shareable Markdown/HTML coverage reports
+pub mod report;
+/// Per-root settings: labels, scan ignore patterns, inode tracking
+pub mod roots;
+/// Discover files on disk and track them as sources
+pub mod scan;
+/// Local web UI for coverage and duplicate triage
+pub mod serve;
+/// Point-in-time snapshots of the index
+pub mod snapshot;
+/// Path ordering for `ls` and manifests (`--sort path --natural`)
+pub mod sort;
+/// One-screen overview of the index
+pub mod status;
+/// Archive files of unknown provenance (`canon strays`)
+pub mod strays;
+/// Destination suggestions from existing archive layouts (`canon suggest`)
+pub mod suggest;
+/// Shared coverage, duplicate and per-year queries (status, report, serve)
+pub mod summary;
+/// Age/kind/size-based placement across archive tiers (`canon tier suggest`)
+pub mod tier;
+/// Trash for files canon removes (`canon trash list|restore|purge`)
+pub mod trash;
+/// Timestamps and size, number and date formatting shared by commands
+pub mod util;
+/// Hash verification of indexed files
+pub mod verify;
+/// JSONL worklists for external processors
+pub mod worklist;
+/// Extended attributes (user.* attributes, Finder tags) read by `scan --xattrs`
+pub mod xattr;
@@ -0,0 +1,140 @@
+use anyhow::{bail, Result};
+use rusqlite::params;
+use std::path::Path;
+
+use crate::apply::expand_pattern;
+use crate::cluster::fetch_source;
+use crate::db::{self, parse_root_spec, Db};
+
+pub struct LintOptions {
+    /// Declare (Some(pattern)) or clear (Some("")) the root's layout before linting
+    pub set_layout: Option<String>,
+}
+
+#[derive(Default)]
+struct LintStats {
+    ok: u64,
+    deviating: u64,
+    unchecked: u64,
+    strays: u64,
+    unknown_provenance: u64,
+}
+
+/// Check files in an archive root against its declared layout, and flag files
+/// that no `canon apply` run placed there (hand-copied strays)
+pub fn run(db: &Db, archive_spec: &str, options: &LintOptions) -> Result<()> {
+    let conn = db.conn();
+    let root_id = parse_root_spec(conn, archive_spec, Some("archive"))?;
+
+    if let Some(ref layout) = options.set_layout {
+        let layout = layout.trim_matches('/');
+        let value = (!layout.is_empty()).then_some(layout);
+        conn.execute("UPDATE roots SET layout = ? WHERE id = ?", params![value, root_id])?;
+        match value {
+            Some(layout) => println!("Layout set: {}", layout),
+            None => println!("Layout cleared"),
+        }
+    }
+
+    let root_path: String = conn.query_row("SELECT path FROM roots WHERE id = ?", [root_id], |row| row.get(0))?;
+    let layout = db::root_layout(conn, root_id)?;
+    match layout {
+        Some(ref layout) => println!("Linting {} against layout {}", root_path, layout),
+        None => println!(
+            "Linting {} (no layout declared; set one with --set-layout '{{year}}/{{month}}/{{filename}}')",
+            root_path
+        ),
+    }
+
+    let files: Vec<(i64, String, bool, bool)> = conn
+        .prepare(
+            "SELECT id, rel_path, apply_run_id IS NOT NULL, provenance_unknown
+             FROM sources
+             WHERE root_id = ? AND present = 1
+             ORDER BY rel_path",
+        )?
+        .query_map([root_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut stats = LintStats::default();
+    for (source_id, rel_path, applied, provenance_unknown) in &files {
+        if !applied {
+            if *provenance_unknown {
+                stats.unknown_provenance += 1;
+            } else {
+                println!("STRAY: {} (not placed by canon apply)", rel_path);
+                stats.strays += 1;
+            }
+        }
+
+        let Some(ref layout) = layout else {
+            continue;
+        };
+        let Some(source) = fetch_source(conn, *source_id)? else {
+            continue;
+        };
+        match expand_pattern(layout, &source, Path::new(rel_path)) {
+            Ok(expected) if expected == *rel_path => stats.ok += 1,
+            Ok(expected) => {
+                println!("DEVIATES: {} (expected {})", rel_path, expected);
+                stats.deviating += 1;
+            }
+            // Facts needed by the layout are missing: only the shape can be checked
+            Err(_) if matches_shape(layout, rel_path) => stats.unchecked += 1,
+            Err(_) => {
+                println!("DEVIATES: {} (does not match {})", rel_path, layout);
+                stats.deviating += 1;
+            }
+        }
+    }
+
+    if layout.is_some() {
+        println!(
+            "Checked {} files: {} ok, {} deviating, {} unchecked (missing facts), {} strays",
+            files.len(),
+            stats.ok,
+            stats.deviating,
+            stats.unchecked,
+            stats.strays
+        );
+    } else {
+        println!("Checked {} files: {} strays", files.len(), stats.strays);
+    }
+    if stats.unknown_provenance > 0 {
+        println!(
+            "Note: {} files were indexed before apply runs were recorded; whether canon apply placed them is unknown",
+            stats.unknown_provenance
+        );
+    }
+
+    if stats.deviating > 0 || stats.strays > 0 {
+        bail!(
+            "{} files deviate from the layout, {} files were not placed by canon apply",
+            stats.deviating,
+            stats.strays
+        );
+    }
+    Ok(())
+}
+
+/// Whether `path` has the shape of `layout`: literal text matches exactly and
+/// each `{placeholder}` stands for one or more characters within a path component
+fn matches_shape(layout: &str, path: &str) -> bool {
+    match layout.find('{') {
+        None => layout == path,
+        Some(start) => {
+            let Some(len) = layout[start..].find('}') else {
+                return layout == path;
+            };
+            let (literal, rest) = (&layout[..start], &layout[start + len + 1..]);
+            let Some(path) = path.strip_prefix(literal) else {
+                return false;
+            };
+            // Try every non-empty expansion of the placeholder within the component
+            let component_end = path.find('/').unwrap_or(path.len());
+            (1..=component_end)
+                .filter(|&end| path.is_char_boundary(end))
+                .any(|end| matches_shape(rest, &path[end..]))
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use anyhow::Result;
+use rusqlite::params;
+
+use crate::db::{Connection, Db};
+use crate::error::{coded, ErrorCode};
+use crate::util::{current_timestamp, format_timestamp};
+
+/// A running operation recorded in the `locks` table
+struct Lock {
+    id: i64,
+    operation: String,
+    pid: i64,
+    host: String,
+    started_at: i64,
+}
+
+impl Lock {
+    /// The holder is on this machine and no longer running
+    fn is_stale(&self) -> bool {
+        self.host == hostname() && !process_alive(self.pid)
+    }
+}
+
+/// Record `operation` (e.g. "apply") as running, refusing to start while another
+/// locked operation is. Locks left by processes on this host that have exited
+/// are cleared. Returns the lock id to pass to `release`.
+///
+/// Every locked operation excludes every other: the commands that take a lock
+/// all move files or rewrite source paths. `lock_operation` in src/main.rs
+/// decides which commands those are.
+pub fn acquire(conn: &Connection, operation: &str) -> Result<i64> {
+    // BEGIN IMMEDIATE takes the write lock up front, so two processes cannot
+    // both see an empty table and insert
+    conn.execute_batch("BEGIN IMMEDIATE")?;
+    let result = try_acquire(conn, operation);
+    match result {
+        Ok(_) => conn.execute_batch("COMMIT")?,
+        Err(_) => conn.execute_batch("ROLLBACK")?,
+    }
+    result
+}
+
+fn try_acquire(conn: &Connection, operation: &str) -> Result<i64> {
+    for lock in list_locks(conn)? {
+        if lock.is_stale() {
+            eprintln!(
+                "Warning: Clearing stale lock: {} (pid {}, started {})",
+                lock.operation,
+                lock.pid,
+                format_timestamp(lock.started_at)
+            );
+            conn.execute("DELETE FROM locks WHERE id = ?", [lock.id])?;
+            continue;
+        }
+        return Err(coded(
+            ErrorCode::Locked,
+            format!(
+                "canon {} is already running (pid {} on {}, started {}). Wait for it to finish, or run `canon lock break` if it is no longer running",
+                lock.operation,
+                lock.pid,
+                lock.host,
+                format_timestamp(lock.started_at)
+            ),
+        ));
+    }
+
+    conn.execute(
+        "INSERT INTO locks (operation, pid, host, started_at) VALUES (?, ?, ?, ?)",
+        params![operation, std::process::id(), hostname(), current_timestamp()],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Drop a lock taken with `acquire`
+pub fn release(conn: &Connection, lock_id: i64) -> Result<()> {
+    conn.execute("DELETE FROM locks WHERE id = ?", [lock_id])?;
+    Ok(())
+}
+
+/// `canon lock status`: print running operations
+pub fn status(db: &Db) -> Result<()> {
+    let locks = list_locks(db.conn())?;
+    if locks.is_empty() {
+        println!("No operations running");
+        return Ok(());
+    }
+    for lock in &locks {
+        println!(
+            "{:<10} pid {:<8} {:<20} started {}{}",
+            lock.operation,
+            lock.pid,
+            lock.host,
+            format_timestamp(lock.started_at),
+            if lock.is_stale() { "  (stale: process has exited)" } else { "" }
+        );
+    }
+    Ok(())
+}
+
+/// `canon lock break`: remove all locks, e.g. after a crash on another host
+pub fn break_locks(db: &Db) -> Result<()> {
+    let removed = db.conn().execute("DELETE FROM locks", [])?;
+    println!("Removed {} locks", removed);
+    Ok(())
+}
+
+fn list_locks(conn: &Connection) -> Result<Vec<Lock>> {
+    let locks = conn
+        .prepare("SELECT id, operation, pid, host, started_at FROM locks ORDER BY id")?
+        .query_map([], |row| {
+            Ok(Lock {
+                id: row.get(0)?,
+                operation: row.get(1)?,
+                pid: row.get(2)?,
+                host: row.get(3)?,
+                started_at: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(locks)
+}
+
+#[cfg(unix)]
+fn process_alive(pid: i64) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // Signal 0 only checks for existence; EPERM means it exists under another user
+    let exists = unsafe { libc::kill(pid, 0) } == 0;
+    exists || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+fn process_alive(_pid: i64) -> bool {
+    // Can't tell: treat the lock as held until broken by hand
+    true
+}
+
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    let ok = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } == 0;
+    if !ok {
+        return String::new();
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+#[cfg(not(unix))]
+fn hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_default()
+}
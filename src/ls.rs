@@ -1,13 +1,11 @@
 use anyhow::Result;
-use rusqlite::params;
 use std::path::Path;
 
-use crate::db::{Connection, Db};
-use crate::exclude;
-use crate::filter::{self, Filter};
-
-const BATCH_SIZE: i64 = 1000;
+use crate::db::{refresh_root_availability, Connection, Db, TempSources};
+use crate::query::SourceQuery;
+use crate::sort::PathOrder;
 
+#[allow(clippy::too_many_arguments)]
 pub fn run(
     db: &Db,
     scope_path: Option<&Path>,
@@ -18,23 +16,21 @@ pub fn run(
     include_archived: bool,
     include_excluded: bool,
     use_relative_paths: bool,
+    sort_by_path: Option<PathOrder>,
 ) -> Result<()> {
     let archived_only = archived_mode.is_some();
     let show_archive_paths = archived_mode == Some("show");
     let conn = db.conn();
+    // Archive paths are marked "(offline)" from the stored availability
+    if archived_only {
+        refresh_root_availability(conn)?;
+    }
 
-    // Parse filters
-    let filters: Vec<Filter> = filter_strs
-        .iter()
-        .map(|f| Filter::parse(f))
-        .collect::<Result<Vec<_>>>()?;
-
-    // Resolve scope path to realpath if provided
-    let scope_prefix = if let Some(p) = scope_path {
-        Some(std::fs::canonicalize(p)?.to_string_lossy().to_string())
-    } else {
-        None
-    };
+    let query = SourceQuery::new()
+        .scope(scope_path)?
+        .filters(conn, filter_strs)?
+        .include_archived(include_archived)
+        .include_excluded(include_excluded);
 
     // Get cwd for relative path display (must be canonicalized to match DB paths)
     let cwd = if use_relative_paths {
@@ -48,13 +44,13 @@ pub fn run(
 
     // Get excluded count for reporting
     let excluded_count = if !include_excluded {
-        exclude::count_excluded(conn, scope_prefix.as_deref(), include_archived)?
+        query.count_excluded(conn)?
     } else {
         0
     };
 
     // Get all matching source IDs
-    let source_ids = get_matching_sources(conn, scope_prefix.as_deref(), &filters, include_archived, include_excluded)?;
+    let source_ids = query.ids(conn)?;
 
     if source_ids.is_empty() {
         eprintln!("No sources match the given filters.");
@@ -68,9 +64,15 @@ pub fn run(
     // Each entry is (source_path, optional_archive_path)
     let mut output_lines: Vec<(String, Option<String>)> = Vec::new();
     let mut unhashed_count = 0usize;
+    let mut offline_only_count = 0usize;
 
-    for source_id in &source_ids {
-        let (full_path, object_id) = get_source_path(conn, *source_id)?;
+    let mut rows = listed_sources(conn, &source_ids)?;
+    if let Some(order) = sort_by_path {
+        rows.sort_by(|a, b| order.compare(&a.full_path, &b.full_path));
+    }
+
+    for row in rows {
+        let ListedSource { full_path, object_id, archived, archived_online } = row;
         let formatted_source = format_path(&full_path, cwd.as_deref());
 
         // Check archive status if filtering
@@ -87,7 +89,10 @@ pub fn run(
                         for archive_path in archive_paths {
                             output_lines.push((formatted_source.clone(), Some(archive_path)));
                         }
-                    } else if check_archived(conn, obj_id)? {
+                    } else if archived {
+                        if !archived_online {
+                            offline_only_count += 1;
+                        }
                         output_lines.push((formatted_source, None));
                     }
                 }
@@ -98,8 +103,8 @@ pub fn run(
                     // Unhashed - skip but track count (can't determine archive status)
                     unhashed_count += 1;
                 }
-                Some(obj_id) => {
-                    if !check_archived(conn, obj_id)? {
+                Some(_) => {
+                    if !archived {
                         output_lines.push((formatted_source, None));
                     }
                 }
@@ -134,6 +139,9 @@ pub fn run(
     if !include_excluded && excluded_count > 0 {
         footer_parts.push(format!("{} excluded hidden", excluded_count));
     }
+    if offline_only_count > 0 {
+        footer_parts.push(format!("{} only in offline archives", offline_only_count));
+    }
     if (archived_only || unarchived_only) && unhashed_count > 0 {
         footer_parts.push(format!("{} unhashed skipped, use --unhashed to see", unhashed_count));
     }
@@ -147,112 +155,55 @@ pub fn run(
     Ok(())
 }
 
-fn get_matching_sources(
-    conn: &Connection,
-    scope_prefix: Option<&str>,
-    filters: &[Filter],
-    include_archived: bool,
-    include_excluded: bool,
-) -> Result<Vec<i64>> {
-    let mut all_ids = Vec::new();
-    let mut last_id: i64 = 0;
-
-    let role_clause = if include_archived {
-        "1=1" // Include all roles
-    } else {
-        "r.role = 'source'"
-    };
-
-    let exclude_clause = exclude::exclude_clause(include_excluded);
-
-    loop {
-        // Fetch batch of source IDs
-        let batch: Vec<i64> = if let Some(prefix) = scope_prefix {
-            // Filter by path prefix
-            conn.prepare(&format!(
-                "SELECT s.id
-                 FROM sources s
-                 JOIN roots r ON s.root_id = r.id
-                 WHERE s.present = 1 AND {} AND {} AND s.id > ?
-                   AND (r.path || '/' || s.rel_path) LIKE ? || '/%'
-                 ORDER BY s.id
-                 LIMIT ?",
-                role_clause, exclude_clause
-            ))?
-            .query_map(params![last_id, prefix, BATCH_SIZE], |row| row.get(0))?
-            .collect::<Result<Vec<_>, _>>()?
-        } else {
-            conn.prepare(&format!(
-                "SELECT s.id
-                 FROM sources s
-                 JOIN roots r ON s.root_id = r.id
-                 WHERE s.present = 1 AND {} AND {} AND s.id > ?
-                 ORDER BY s.id
-                 LIMIT ?",
-                role_clause, exclude_clause
-            ))?
-            .query_map(params![last_id, BATCH_SIZE], |row| row.get(0))?
-            .collect::<Result<Vec<_>, _>>()?
-        };
-
-        if batch.is_empty() {
-            break;
-        }
-
-        let max_id = *batch.last().unwrap();
-
-        // Apply filters
-        let filtered = if filters.is_empty() {
-            batch
-        } else {
-            filter::apply_filters(conn, &batch, filters)?
-        };
-
-        all_ids.extend(filtered);
-        last_id = max_id;
-    }
-
-    Ok(all_ids)
+/// A matching source with what the listing modes need to know about it
+struct ListedSource {
+    full_path: String,
+    object_id: Option<i64>,
+    archived: bool,
+    /// Archived with a copy on an archive root that is currently available
+    archived_online: bool,
 }
 
-fn get_source_path(conn: &Connection, source_id: i64) -> Result<(String, Option<i64>)> {
-    let (root_path, rel_path, object_id): (String, String, Option<i64>) = conn.query_row(
-        "SELECT r.path, s.rel_path, s.object_id
-         FROM sources s
+/// Path and archive status of each source, in id order, from one query over
+/// the selection rather than several per source
+fn listed_sources(conn: &Connection, source_ids: &[i64]) -> Result<Vec<ListedSource>> {
+    let _selection = TempSources::load(conn, source_ids)?;
+    let mut stmt = conn.prepare(
+        "SELECT r.path, s.rel_path, s.object_id, ao.object_id IS NOT NULL, COALESCE(ao.online_copies > 0, 0)
+         FROM temp_sources ts
+         JOIN sources s ON s.id = ts.id
          JOIN roots r ON s.root_id = r.id
-         WHERE s.id = ?",
-        [source_id],
-        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
-    )?;
-
-    let full_path = if rel_path.is_empty() {
-        root_path
-    } else {
-        format!("{}/{}", root_path, rel_path)
-    };
-
-    Ok((full_path, object_id))
-}
-
-fn check_archived(conn: &Connection, object_id: i64) -> Result<bool> {
-    let exists: bool = conn.query_row(
-        "SELECT EXISTS(
-            SELECT 1 FROM sources s
-            JOIN roots r ON s.root_id = r.id
-            WHERE s.object_id = ? AND r.role = 'archive' AND s.present = 1
-        )",
-        [object_id],
-        |row| row.get(0),
+         LEFT JOIN archived_objects ao ON ao.object_id = s.object_id
+         ORDER BY ts.id",
     )?;
-
-    Ok(exists)
+    let sources = stmt
+        .query_map([], |row| {
+            let root_path: String = row.get(0)?;
+            let rel_path: String = row.get(1)?;
+            let full_path = if rel_path.is_empty() {
+                root_path
+            } else {
+                format!("{}/{}", root_path, rel_path)
+            };
+            Ok(ListedSource {
+                full_path,
+                object_id: row.get(2)?,
+                archived: row.get(3)?,
+                archived_online: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(sources)
 }
 
+/// Archive locations of an object, as `[label] rel/path` for labeled roots;
+/// copies on unavailable roots are marked "(offline)"
 fn get_archive_paths(conn: &Connection, object_id: i64) -> Result<Vec<String>> {
     let mut stmt = conn.prepare(
-        "SELECT r.path, s.rel_path
+        "SELECT r.path, s.rel_path, r.available, rs.value
          FROM sources s
          JOIN roots r ON s.root_id = r.id
+         LEFT JOIN root_settings rs ON rs.root_id = r.id AND rs.key = 'label'
          WHERE s.object_id = ? AND r.role = 'archive' AND s.present = 1
          ORDER BY r.path, s.rel_path",
     )?;
@@ -261,11 +212,14 @@ fn get_archive_paths(conn: &Connection, object_id: i64) -> Result<Vec<String>> {
         .query_map([object_id], |row| {
             let root_path: String = row.get(0)?;
             let rel_path: String = row.get(1)?;
-            if rel_path.is_empty() {
-                Ok(root_path)
-            } else {
-                Ok(format!("{}/{}", root_path, rel_path))
-            }
+            let available: bool = row.get(2)?;
+            let label: Option<String> = row.get(3)?;
+            let path = match (label, rel_path.is_empty()) {
+                (Some(label), _) => format!("[{}] {}", label, rel_path),
+                (None, true) => root_path,
+                (None, false) => format!("{}/{}", root_path, rel_path),
+            };
+            Ok(if available { path } else { format!("{} (offline)", path) })
         })?
         .collect::<Result<Vec<_>, _>>()?;
 
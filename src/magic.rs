@@ -0,0 +1,131 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Fact recording the file type `scan --magic` read from a file's first bytes
+pub const MAGIC_FACT_KEY: &str = "source.magic";
+
+/// How much of a file is read to tell its type
+const SNIFF_BYTES: usize = 4096;
+
+/// The type of the file at `path` from its leading bytes: a short lowercase
+/// name such as "jpeg", "mp4" or "zip", "empty" for an empty file, or
+/// "unknown" when no signature matches. Formats built on another one are
+/// named by the outer format where the first bytes can't tell them apart
+/// (most raw formats are "tiff", office documents and EPUBs are "zip").
+pub fn sniff(path: &Path) -> Result<&'static str> {
+    let mut file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut head = Vec::with_capacity(SNIFF_BYTES);
+    file.by_ref()
+        .take(SNIFF_BYTES as u64)
+        .read_to_end(&mut head)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(identify(&head))
+}
+
+fn identify(head: &[u8]) -> &'static str {
+    if head.is_empty() {
+        return "empty";
+    }
+    let at = |offset: usize, signature: &[u8]| head.get(offset..offset + signature.len()) == Some(signature);
+
+    if at(0, b"\xFF\xD8\xFF") {
+        return "jpeg";
+    }
+    if at(0, b"\x89PNG\r\n\x1A\n") {
+        return "png";
+    }
+    if at(0, b"GIF87a") || at(0, b"GIF89a") {
+        return "gif";
+    }
+    if at(0, b"II*\0") || at(0, b"MM\0*") {
+        return if at(8, b"CR") { "cr2" } else { "tiff" };
+    }
+    if at(0, b"IIRO") || at(0, b"IIRS") {
+        return "orf";
+    }
+    if at(0, b"IIU\0") {
+        return "rw2";
+    }
+    if at(0, b"FUJIFILMCCD-RAW") {
+        return "raf";
+    }
+    if at(0, b"BM") && at(6, b"\0\0\0\0") {
+        return "bmp";
+    }
+    if at(0, b"8BPS") {
+        return "psd";
+    }
+    if at(4, b"ftyp") {
+        return match head.get(8..12).unwrap_or_default() {
+            b"heic" | b"heix" | b"heim" | b"heis" | b"hevc" | b"hevx" | b"mif1" | b"msf1" => "heic",
+            b"avif" | b"avis" => "avif",
+            b"crx " => "cr3",
+            b"qt  " => "mov",
+            b"M4A " | b"M4B " => "m4a",
+            b"3gp4" | b"3gp5" | b"3gp6" | b"3g2a" => "3gp",
+            _ => "mp4",
+        };
+    }
+    if at(4, b"moov") || at(4, b"mdat") || at(4, b"wide") {
+        return "mov";
+    }
+    if at(0, b"RIFF") {
+        return match head.get(8..12).unwrap_or_default() {
+            b"WEBP" => "webp",
+            b"AVI " => "avi",
+            b"WAVE" => "wav",
+            _ => "riff",
+        };
+    }
+    if at(0, b"\x1A\x45\xDF\xA3") {
+        return if head.windows(4).any(|window| window == b"webm") { "webm" } else { "mkv" };
+    }
+    if at(0, b"ID3") || at(0, b"\xFF\xFB") || at(0, b"\xFF\xF3") || at(0, b"\xFF\xF2") {
+        return "mp3";
+    }
+    if at(0, b"fLaC") {
+        return "flac";
+    }
+    if at(0, b"OggS") {
+        return "ogg";
+    }
+    if at(0, b"%PDF-") {
+        return "pdf";
+    }
+    if at(0, b"PK\x03\x04") || at(0, b"PK\x05\x06") {
+        return "zip";
+    }
+    if at(0, b"\x1F\x8B") {
+        return "gzip";
+    }
+    if at(0, b"BZh") {
+        return "bzip2";
+    }
+    if at(0, b"\xFD7zXZ\0") {
+        return "xz";
+    }
+    if at(0, b"\x28\xB5\x2F\xFD") {
+        return "zstd";
+    }
+    if at(0, b"7z\xBC\xAF\x27\x1C") {
+        return "7z";
+    }
+    if at(0, b"Rar!\x1A\x07") {
+        return "rar";
+    }
+    if at(257, b"ustar") {
+        return "tar";
+    }
+    if at(0, b"SQLite format 3\0") {
+        return "sqlite";
+    }
+    if at(0, b"\x7FELF") {
+        return "elf";
+    }
+    if at(0, b"MZ") {
+        return "exe";
+    }
+    "unknown"
+}
@@ -1,17 +1,10 @@
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
 use std::path::PathBuf;
 
-mod apply;
-mod cluster;
-mod coverage;
-mod db;
-mod exclude;
-mod facts;
-mod filter;
-mod import_facts;
-mod ls;
-mod scan;
-mod worklist;
+use canon_core::error::{coded, ErrorCode, ErrorReport};
+use canon_core::{
+    apply, archive_catalog, burst, catalog, cluster, collection, complete, coverage, db, doctor, exclude, export, extract, facts, hash, import_facts, lint, lock, ls, object, organize, output, pipeline, query, query_cache, pin, reconcile, report, roots, scan, serve, snapshot, sort, status, strays, suggest, tier, trash, verify, worklist,
+};
 
 #[derive(Parser)]
 #[command(name = "canon")]
@@ -25,6 +18,19 @@ struct Cli {
     #[arg(long, global = true)]
     debug_sql: bool,
 
+    /// Aggregate SQL query shapes and print a timing summary at exit
+    #[arg(long, global = true)]
+    profile: bool,
+
+    /// Cache --where results in the database so repeated queries skip re-evaluating
+    /// unchanged filters (also enabled by CANON_QUERY_CACHE=1)
+    #[arg(long, global = true)]
+    query_cache: bool,
+
+    /// Print results as JSON (scan, facts, coverage, exclude, pin, apply, verify, object, organize, suggest, tier, trash, reconcile, strays, import-catalog, doctor)
+    #[arg(long, global = true)]
+    json: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -42,6 +48,54 @@ enum Commands {
         /// Add path as a new root (required when path is not inside an existing root)
         #[arg(long)]
         add: bool,
+        /// Write worklist entries for new, updated and moved files to stdout (summary goes to stderr)
+        #[arg(long)]
+        emit_worklist: bool,
+        /// Report what would be new, updated, moved and missing (with example paths) without changing the index
+        #[arg(long)]
+        dry_run: bool,
+        /// Number of threads listing directories, and of threads stat'ing files, on local roots (0 = number of CPUs)
+        #[arg(short = 'j', long, default_value = "1")]
+        jobs: usize,
+        /// Skip paths matching a glob, like the root's ignore setting (repeatable, e.g. node_modules, '*.tmp')
+        #[arg(long, value_name = "GLOB")]
+        exclude: Vec<String>,
+        /// Compute SHA-256 of new, changed and unhashed files during the scan and link them to objects
+        #[arg(long)]
+        hash: bool,
+        /// What to do with symlinks, overriding the root's symlinks setting (default: skip)
+        #[arg(long, value_enum)]
+        symlinks: Option<roots::Symlinks>,
+        /// Skip dotfiles and hidden directories (.DS_Store, .Trashes, ...), like the root's skip-hidden setting
+        #[arg(long)]
+        skip_hidden: bool,
+        /// Record extended attributes (user.* on Linux, Finder tags) as source.xattr.* facts, like the root's xattrs setting
+        #[arg(long)]
+        xattrs: bool,
+        /// Read the type of new and changed files from their first bytes into source.magic facts (jpeg, mp4, zip...), like the root's magic setting
+        #[arg(long)]
+        magic: bool,
+        /// Don't list directories whose mtime is unchanged since the last --fast scan (misses files edited in place)
+        #[arg(long)]
+        fast: bool,
+        /// Files written to the index per transaction (0 = one transaction per root)
+        #[arg(long, value_name = "N", default_value_t = scan::DEFAULT_COMMIT_EVERY)]
+        commit_every: usize,
+        /// Leave out files smaller than SIZE (e.g. 1 to skip empty files, 20KB for thumbnails)
+        #[arg(long, value_name = "SIZE")]
+        min_size: Option<String>,
+        /// Leave out files larger than SIZE (e.g. 4GB for disk images)
+        #[arg(long, value_name = "SIZE")]
+        max_size: Option<String>,
+        /// Delete sources missing for at least AGE (default 90d; e.g. 30d, 1y, 0 for all) with their facts; list them first with --dry-run
+        #[arg(long, value_name = "AGE", num_args = 0..=1, require_equals = true, default_missing_value = scan::DEFAULT_PRUNE_AGE)]
+        prune: Option<String>,
+        /// Also write the report --json prints (per-root counts, unreadable paths, unmounted roots, duration) to FILE
+        #[arg(long, value_name = "FILE")]
+        report: Option<PathBuf>,
+        /// Continue an interrupted scan of the same paths after the last file it committed (walks on one thread)
+        #[arg(long, conflicts_with_all = ["dry_run", "fast"])]
+        resume: bool,
     },
     /// Output sources as JSONL worklist
     Worklist {
@@ -56,12 +110,58 @@ enum Commands {
         /// Include excluded sources (by default they are skipped)
         #[arg(long)]
         include_excluded: bool,
+        /// One entry per object (the copy in a source root, else the first); unhashed sources are all listed
+        #[arg(long)]
+        unique_objects: bool,
+        /// Comma-separated fact keys to carry in each entry's "facts" (e.g. exif.datetime_original,mime)
+        #[arg(long, value_delimiter = ',')]
+        with_facts: Vec<String>,
+    },
+    /// Hash unhashed files and link them to objects (no worklist/import-facts round trip)
+    Hash {
+        /// Directory path to scope the query (resolved to realpath)
+        path: Option<PathBuf>,
+        /// Filter expressions (e.g., "source.ext=jpg")
+        #[arg(long = "where")]
+        filters: Vec<String>,
+        /// Number of files hashed at once (0 = number of CPUs)
+        #[arg(short = 'j', long, default_value = "1")]
+        jobs: usize,
+        /// Include sources from archive roots (by default only source roots)
+        #[arg(long)]
+        include_archived: bool,
+        /// Include excluded sources (by default they are skipped)
+        #[arg(long)]
+        include_excluded: bool,
+        /// Only record a quickhash (size and first/last 64 KB) of each file, as content.quickhash
+        #[arg(long, conflicts_with = "quick_matches")]
+        quick: bool,
+        /// Only hash files whose quickhash matches other content (likely duplicates or archived)
+        #[arg(long)]
+        quick_matches: bool,
+        /// Only retry files that failed to read in earlier runs (skipped by default)
+        #[arg(long)]
+        retry_failed: bool,
     },
-    /// Import facts from JSONL on stdin
+    /// Import facts from JSONL on stdin, or keywords/people/ratings from a photo catalog
     ImportFacts {
         /// Allow importing facts for sources in archive roots
         #[arg(long)]
         allow_archived: bool,
+        /// Input format
+        #[arg(long, value_enum, default_value = "jsonl")]
+        format: import_facts::ImportFormat,
+        /// Catalog file (digikam4.db or .lrcat) for --format digikam|lightroom
+        #[arg(required_if_eq_any = [("format", "digikam"), ("format", "lightroom")])]
+        catalog: Option<PathBuf>,
+        /// Rewrite catalog path prefixes before matching (OLD=NEW, can repeat)
+        #[arg(long, value_name = "OLD=NEW")]
+        path_map: Vec<String>,
+    },
+    /// Load an archive catalog written by `canon export --format catalog` (the drive need not be mounted)
+    ImportCatalog {
+        /// Catalog file (.jsonl or .jsonl.gz)
+        catalog: PathBuf,
     },
     /// List sources matching filters
     Ls {
@@ -85,6 +185,12 @@ enum Commands {
         /// Include excluded sources (by default they are skipped)
         #[arg(long)]
         include_excluded: bool,
+        /// Output order (default: id, the order sources were indexed)
+        #[arg(long, value_enum)]
+        sort: Option<sort::SortKey>,
+        /// Compare paths naturally: IMG_9 before IMG_10, ignoring case (implies --sort path)
+        #[arg(long)]
+        natural: bool,
     },
     /// Show fact coverage and value distribution
     #[command(args_conflicts_with_subcommands = true)]
@@ -119,7 +225,7 @@ enum Commands {
         /// Filter expressions (e.g., "source.ext=jpg" or "content.hash.sha256?")
         #[arg(long = "where")]
         filters: Vec<String>,
-        /// Filter coverage relative to a specific archive (id:N or path:/foo/bar)
+        /// Filter coverage relative to a specific archive (id:N, path:/foo/bar or name:ALIAS)
         #[arg(long)]
         archive: Option<String>,
         /// Include sources from archive roots (by default only source roots)
@@ -136,7 +242,7 @@ enum Commands {
     },
     /// Apply a manifest to copy/move files
     Apply {
-        /// Path to the manifest file
+        /// Path to the manifest file (- reads it from stdin)
         manifest: PathBuf,
         /// Show what would be done without making changes
         #[arg(long)]
@@ -144,10 +250,10 @@ enum Commands {
         /// Allow copying files that exist in other archives (but not destination archive)
         #[arg(long)]
         allow_cross_archive_duplicates: bool,
-        /// Only apply sources from these roots (id:N or path:/foo/bar, can repeat)
+        /// Only apply sources from these roots (id:N, path:/foo/bar or name:ALIAS, can repeat)
         #[arg(long)]
         root: Vec<String>,
-        /// Use rename instead of copy (Unix only, fails if cross-device, never copies)
+        /// Use rename instead of copy (fails if cross-device, never copies)
         #[arg(long, conflicts_with = "move_files")]
         rename: bool,
         /// Move files: rename, or copy+delete if cross-device (requires --yes)
@@ -156,12 +262,490 @@ enum Commands {
         /// Confirm destructive operations (required for --move)
         #[arg(long)]
         yes: bool,
+        /// Allow --rename/--move to take pinned sources
+        #[arg(long)]
+        include_pinned: bool,
+        /// Hash unhashed sources while transferring them, skipping any already archived
+        #[arg(long)]
+        hash_missing: bool,
+        /// Remote roots: hash uploads by downloading them when the backend has no SHA-256
+        /// (otherwise --move keeps sources whose upload can't be hash-checked)
+        #[arg(long)]
+        download_verify: bool,
+    },
+    /// Pin sources so exclude, facts delete and apply --move/--rename leave them alone
+    Pin {
+        /// Directory path to scope the operation (resolved to realpath)
+        path: Option<PathBuf>,
+        /// Filter expressions (e.g., "source.ext=jpg")
+        #[arg(long = "where")]
+        filters: Vec<String>,
+        /// Show what would be pinned without making changes
+        #[arg(long)]
+        dry_run: bool,
+        /// Read source ids or paths from stdin (one per line, or NUL-separated)
+        #[arg(long)]
+        stdin: bool,
+    },
+    /// Remove pins from sources
+    Unpin {
+        /// Directory path to scope the operation (resolved to realpath)
+        path: Option<PathBuf>,
+        /// Filter expressions to match pinned sources
+        #[arg(long = "where")]
+        filters: Vec<String>,
+        /// Show what would be unpinned without making changes
+        #[arg(long)]
+        dry_run: bool,
+        /// Read source ids or paths from stdin (one per line, or NUL-separated)
+        #[arg(long)]
+        stdin: bool,
     },
     /// Manage source exclusions
     Exclude {
         #[command(subcommand)]
         action: ExcludeAction,
     },
+    /// Extract facts from file contents with built-in extractors
+    Extract {
+        #[command(subcommand)]
+        action: ExtractAction,
+    },
+    /// Generate a shareable coverage report (Markdown or HTML)
+    Report {
+        /// Output format: 'md' or 'html' (default: from the output file extension, else md)
+        #[arg(long)]
+        format: Option<String>,
+        /// Output file (default: stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Export matching sources as an rsync file list, M3U playlist or digiKam tags, or an archive as a catalog
+    Export {
+        /// Directory path to scope the operation (resolved to realpath)
+        path: Option<PathBuf>,
+        /// Filter expressions (e.g., "source.ext=jpg")
+        #[arg(long = "where")]
+        filters: Vec<String>,
+        /// What to produce
+        #[arg(long, value_enum)]
+        format: export::ExportFormat,
+        /// Output file for rsync-files, m3u and catalog (default: stdout; a catalog ending in .gz is gzipped)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Tag to assign with --format digikam (use '/' for hierarchy, e.g. "canon/best")
+        #[arg(long, required_if_eq("format", "digikam"))]
+        tag: Option<String>,
+        /// Archive root to catalog with --format catalog (id:N, path:PATH or name:ALIAS)
+        #[arg(
+            long,
+            required_if_eq("format", "catalog"),
+            conflicts_with_all = ["path", "filters", "tag", "include_archived", "stdin"]
+        )]
+        archive: Option<String>,
+        /// Include sources from archive roots (by default only source roots)
+        #[arg(long)]
+        include_archived: bool,
+        /// Read source ids or paths from stdin (one per line, or NUL-separated)
+        #[arg(long)]
+        stdin: bool,
+    },
+    /// Serve a local web UI for coverage, duplicates and fact distributions
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: String,
+    },
+    /// Show a one-screen summary of roots, progress and index health
+    Status,
+    /// Check the index for inconsistencies between tables and list fixes
+    Doctor {
+        /// Make the repairs that are safe to automate
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Re-check files of a root or directory against their indexed hashes
+    ///
+    /// Local files are read and hashed again; remote (rclone) roots are checked
+    /// with `rclone hashsum`. A directory covers only the files under it.
+    /// Every check is recorded as the file's verification history.
+    Verify {
+        /// Root or subtree to verify (a local path or remote:path)
+        #[arg(required_unless_present = "archive", conflicts_with = "archive")]
+        path: Option<PathBuf>,
+        /// Verify a whole archive root instead (id:N, path:/foo/bar or name:ALIAS)
+        #[arg(long, value_name = "ROOT")]
+        archive: Option<String>,
+        /// Download and hash content when the backend has no native SHA-256
+        #[arg(long)]
+        download: bool,
+        /// Verify files checked longest ago (or never) first (local roots)
+        #[arg(long)]
+        oldest_first: bool,
+        /// Stop before reading more than SIZE in this run (e.g. 500GB; local roots)
+        #[arg(long, value_name = "SIZE")]
+        max_bytes: Option<String>,
+    },
+    /// Rename files within their source root according to a pattern
+    Organize {
+        /// Directory path to scope the operation (resolved to realpath)
+        path: Option<PathBuf>,
+        /// Destination pattern relative to the root (e.g., "{year}/{month}/{filename}")
+        #[arg(long)]
+        pattern: String,
+        /// Filter expressions (e.g., "source.ext=jpg")
+        #[arg(long = "where")]
+        filters: Vec<String>,
+        /// Show what would be renamed without making changes (the default)
+        #[arg(long, conflicts_with = "yes")]
+        dry_run: bool,
+        /// Rename files
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Propose archive destinations for unarchived sources and write them as manifests
+    Suggest {
+        /// Directory path to scope the operation (resolved to realpath)
+        path: Option<PathBuf>,
+        /// Filter expressions (e.g., "source.ext=jpg")
+        #[arg(long = "where")]
+        filters: Vec<String>,
+        /// Output manifest file (numbered when there are several destinations)
+        #[arg(short, long, default_value = "manifest.toml")]
+        output: PathBuf,
+    },
+    /// Plan moves between archive tiers from an age/kind/size policy
+    Tier {
+        #[command(subcommand)]
+        action: TierAction,
+    },
+    /// Run the steps of a pipeline file (scan, process, extract, cluster, apply)
+    Run {
+        /// Pipeline file (TOML)
+        pipeline: PathBuf,
+    },
+    /// Check an archive root against its layout and flag files not placed by apply
+    Lint {
+        /// Archive root to check (id:N, path:/foo/bar or name:ALIAS)
+        #[arg(long)]
+        archive: String,
+        /// Declare the root's expected layout first (e.g., "{year}/{month}/{filename}"; "" clears it)
+        #[arg(long)]
+        set_layout: Option<String>,
+    },
+    /// Compare an archive root's index with the filesystem: strays, deleted and modified files
+    Reconcile {
+        /// Archive root to check (id:N, path:/foo/bar or name:ALIAS)
+        #[arg(long)]
+        archive: String,
+        /// Update the index: scan the root and unlink modified files from their objects
+        #[arg(long, conflicts_with = "flag")]
+        rescan: bool,
+        /// Record a reconcile.status fact (modified/deleted) on drifted sources instead
+        #[arg(long)]
+        flag: bool,
+    },
+    /// List archive files the index can't account for (not applied, content known nowhere else)
+    Strays {
+        /// Archive root to check (id:N, path:/foo/bar or name:ALIAS)
+        #[arg(long)]
+        archive: String,
+    },
+    /// Manage roots: list them, show one, change its settings
+    #[command(alias = "roots")]
+    Root {
+        #[command(subcommand)]
+        action: RootAction,
+    },
+    /// Group objects (content, not paths) into named collections
+    Collection {
+        #[command(subcommand)]
+        action: CollectionAction,
+    },
+    /// Inspect objects (content identified by hash)
+    Object {
+        #[command(subcommand)]
+        action: ObjectAction,
+    },
+    /// Record and compare point-in-time snapshots of the index
+    Snapshot {
+        #[command(subcommand)]
+        action: SnapshotAction,
+    },
+    /// Database maintenance
+    Db {
+        #[command(subcommand)]
+        action: DbAction,
+    },
+    /// List, restore or purge files canon moved to the trash instead of deleting
+    Trash {
+        #[command(subcommand)]
+        action: TrashAction,
+    },
+    /// Show or clear locks held by running scan/apply/organize/run commands
+    Lock {
+        #[command(subcommand)]
+        action: LockAction,
+    },
+    /// Print a shell completion script (e.g. `source <(canon completions bash)`)
+    Completions {
+        #[arg(value_enum)]
+        shell: complete::Shell,
+    },
+    /// Completion candidates for the command line in `words` (used by the scripts)
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        words: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ExtractAction {
+    /// Read EXIF metadata (capture time, camera, lens, GPS, orientation)
+    Exif(ExtractArgs),
+    /// Read image dimensions and orientation from file headers
+    Image(ExtractArgs),
+    /// Classify files (content.kind: photo, video, raw, screenshot, audio, document, archive, other)
+    Kind(ExtractArgs),
+    /// Detect the media type (content.mime) from file signatures
+    Mime(ExtractArgs),
+    /// Probe videos with ffprobe (duration, codec, resolution, framerate, creation time)
+    Video {
+        #[command(flatten)]
+        args: ExtractArgs,
+        /// ffprobe executable to run
+        #[arg(long, default_value = "ffprobe")]
+        ffprobe: PathBuf,
+    },
+}
+
+#[derive(clap::Args)]
+struct ExtractArgs {
+    /// Directory path to scope the query (resolved to realpath)
+    path: Option<PathBuf>,
+    /// Filter expressions (e.g., "!content.exif.datetime_original?" or "source.ext=jpg")
+    #[arg(long = "where")]
+    filters: Vec<String>,
+    /// Number of worker threads (0 = number of CPUs)
+    #[arg(short = 'j', long, default_value = "0")]
+    jobs: usize,
+    /// Include sources from archive roots (by default only source roots)
+    #[arg(long)]
+    include_archived: bool,
+    /// Include excluded sources (by default they are skipped)
+    #[arg(long)]
+    include_excluded: bool,
+}
+
+impl ExtractArgs {
+    fn options(&self) -> extract::ExtractOptions {
+        extract::ExtractOptions {
+            jobs: self.jobs,
+            include_archived: self.include_archived,
+            include_excluded: self.include_excluded,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum SnapshotAction {
+    /// Record a snapshot of the current index state
+    Create {
+        /// Snapshot name (e.g., "pre-vacation-import")
+        name: String,
+    },
+    /// List recorded snapshots
+    List,
+    /// Show what changed since a snapshot (compared to now, or to another snapshot)
+    Diff {
+        /// Snapshot to compare from
+        from: String,
+        /// Snapshot to compare to (defaults to the current state)
+        to: Option<String>,
+        /// List the paths in each category
+        #[arg(long)]
+        list: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum RootAction {
+    /// List roots with their role, source counts and last scan time
+    List,
+    /// Show one root: status, counts, last scan and settings
+    Show {
+        /// Root (id:N, path:/foo/bar or name:ALIAS)
+        root: String,
+    },
+    /// Mark a root offline: scan skips it, coverage flags it and apply won't write to it
+    Offline {
+        /// Root (id:N, path:/foo/bar or name:ALIAS)
+        root: String,
+    },
+    /// Clear a root's offline marker
+    Online {
+        /// Root (id:N, path:/foo/bar or name:ALIAS)
+        root: String,
+    },
+    /// Name a root, so it can be given as name:ALIAS wherever a root is expected
+    Alias {
+        /// Short name (letters, digits, '-', '_' and '.')
+        name: String,
+        /// Root (id:N, path:/foo/bar or name:ALIAS)
+        root: String,
+    },
+    /// Change a root's role, keeping its sources, hashes and facts
+    SetRole {
+        /// Root (id:N, path:/foo/bar or name:ALIAS)
+        root: String,
+        /// New role: 'source' or 'archive'
+        role: String,
+    },
+    /// Point a root at the directory its files moved to, keeping sources, hashes and facts
+    Relocate {
+        /// Root (id:N, path:/old/path or name:ALIAS)
+        root: String,
+        /// Directory the root's files are now in
+        new_path: PathBuf,
+    },
+    /// Remove a root from the index with its sources and their facts (files on disk are left alone)
+    Remove {
+        /// Root (id:N, path:/foo/bar or name:ALIAS)
+        root: String,
+        /// Execute removal (default is dry-run)
+        #[arg(long)]
+        yes: bool,
+        /// Keep objects (and their facts) that only this root's sources referred to
+        #[arg(long)]
+        keep_objects: bool,
+    },
+    /// Set settings of a root (key= clears one) and print its settings
+    Set {
+        /// Root (id:N, path:/foo/bar or name:ALIAS)
+        root: String,
+        /// Settings: label=NAME, alias=NAME, ignore=GLOB[,GLOB...], hash=sha256, hash-on-scan=on|off, layout=PATTERN, inode-tracking=on|off, symlinks=skip|follow|record, skip-hidden=on|off, xattrs=on|off, magic=on|off
+        #[arg(value_name = "KEY=VALUE")]
+        settings: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum DbAction {
+    /// Rebuild the archived-object cache from the sources table
+    RefreshCache,
+}
+
+#[derive(Subcommand)]
+enum TrashAction {
+    /// List trashed files with original path, hash and reason
+    List,
+    /// Move trashed files back to their original paths and index them again
+    Restore {
+        /// Trash item ids (from `canon trash list`)
+        #[arg(required = true)]
+        ids: Vec<i64>,
+    },
+    /// Permanently delete trashed files
+    Purge {
+        /// Only purge items trashed at least this long ago (e.g. 30d, 12h, 2w; 0 for all)
+        #[arg(long)]
+        older_than: String,
+        /// Show what would be purged without deleting anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TierAction {
+    /// Write one manifest per policy tier with the sources that belong there
+    Suggest {
+        /// Tier policy file (TOML, one [[tier]] per target)
+        #[arg(long)]
+        policy: PathBuf,
+        /// Directory path to scope the operation (resolved to realpath)
+        path: Option<PathBuf>,
+        /// Filter expressions applied before the policy (e.g., "source.ext=jpg")
+        #[arg(long = "where")]
+        filters: Vec<String>,
+        /// Output manifest name; each tier writes <stem>-<tier>.toml
+        #[arg(short, long, default_value = "tier.toml")]
+        output: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum LockAction {
+    /// List running operations that hold a lock
+    Status,
+    /// Remove all locks (only when the holding process is known to be gone)
+    Break,
+}
+
+#[derive(Subcommand)]
+enum ObjectAction {
+    /// Show an object's facts, sources (present and missing) and verification history
+    Show {
+        /// Content hash (sha256 or blake3), or a unique prefix of at least 6 characters
+        hash: String,
+    },
+    /// List objects referenced by sources matching filters
+    Ls {
+        /// Filter expressions (e.g., "source.ext=jpg")
+        #[arg(long = "where")]
+        filters: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CollectionAction {
+    /// Create an empty collection
+    Create {
+        /// Collection name (e.g., "best-of-2021")
+        name: String,
+    },
+    /// Add the content of matching sources to a collection
+    Add {
+        /// Collection name
+        name: String,
+        /// Directory path to scope the operation (resolved to realpath)
+        path: Option<PathBuf>,
+        /// Filter expressions (e.g., "source.ext=jpg")
+        #[arg(long = "where")]
+        filters: Vec<String>,
+    },
+    /// Remove the content of matching sources from a collection
+    Remove {
+        /// Collection name
+        name: String,
+        /// Directory path to scope the operation (resolved to realpath)
+        path: Option<PathBuf>,
+        /// Filter expressions
+        #[arg(long = "where")]
+        filters: Vec<String>,
+    },
+    /// List collections, or the files of one collection
+    List {
+        /// Collection to list (omit to list all collections)
+        name: Option<String>,
+    },
+    /// Export a collection as JSONL (hash, size, current path), or with --dest as a manifest for apply
+    Export {
+        /// Collection name
+        name: String,
+        /// Write a TOML manifest copying a present copy of each member here (a path inside an archive root)
+        #[arg(long)]
+        dest: Option<PathBuf>,
+        /// Output file (default: stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Delete a collection (files are not touched)
+    Delete {
+        /// Collection name
+        name: String,
+    },
 }
 
 #[derive(Subcommand)]
@@ -176,6 +760,12 @@ enum ExcludeAction {
         /// Show what would be excluded without making changes
         #[arg(long)]
         dry_run: bool,
+        /// Read source ids or paths from stdin (one per line, or NUL-separated)
+        #[arg(long)]
+        stdin: bool,
+        /// Exclude pinned sources too (skipped by default)
+        #[arg(long)]
+        include_pinned: bool,
     },
     /// Remove exclusions from sources
     Clear {
@@ -187,6 +777,9 @@ enum ExcludeAction {
         /// Show what would be cleared without making changes
         #[arg(long)]
         dry_run: bool,
+        /// Read source ids or paths from stdin (one per line, or NUL-separated)
+        #[arg(long)]
+        stdin: bool,
     },
     /// List excluded sources
     List {
@@ -215,6 +808,12 @@ enum FactsAction {
         /// Execute deletion (default is dry-run)
         #[arg(long)]
         yes: bool,
+        /// Read source ids or paths from stdin (one per line, or NUL-separated)
+        #[arg(long)]
+        stdin: bool,
+        /// Delete facts of pinned sources too (skipped by default)
+        #[arg(long)]
+        include_pinned: bool,
     },
     /// Prune stale or orphaned facts
     Prune {
@@ -232,11 +831,12 @@ enum ClusterAction {
     /// Generate a new manifest
     Generate {
         /// Filter expressions (e.g., "content_hash.sha256?" or "exif.model=iPhone")
-        #[arg(long = "where", required = true)]
+        #[arg(long = "where", required_unless_present = "stdin")]
         filters: Vec<String>,
-        /// Destination path (must be inside an archive root)
+        /// Destination path (must be inside an archive root). With --split-by-volume,
+        /// repeat once per volume, in fill order
         #[arg(long, required = true)]
-        dest: PathBuf,
+        dest: Vec<PathBuf>,
         /// Output manifest file
         #[arg(short, long, default_value = "manifest.toml")]
         output: PathBuf,
@@ -246,69 +846,266 @@ enum ClusterAction {
         /// Show which files were excluded because they're already archived
         #[arg(long)]
         show_archived: bool,
+        /// Bin-pack sources across volumes (one --dest each), one manifest per volume
+        #[arg(long, requires = "volume_size")]
+        split_by_volume: bool,
+        /// Capacity of each volume (e.g., "3.5TB", "500GB", "2TiB")
+        #[arg(long, requires = "split_by_volume")]
+        volume_size: Option<String>,
+        /// Read source ids or paths from stdin (one per line, or NUL-separated)
+        #[arg(long)]
+        stdin: bool,
+        /// Group sources, exposing {burst}, {burst_index} and {burst_size} to the pattern
+        #[arg(long, value_enum)]
+        group_by: Option<burst::GroupBy>,
+        /// Longest interval between consecutive frames of a burst (e.g., "2s", "1m")
+        #[arg(long, default_value = "2s", requires = "group_by")]
+        gap: String,
+        /// Frames to keep per burst: all, first, largest, or a fact key (highest value wins)
+        #[arg(long, default_value = "all", requires = "group_by")]
+        burst_keep: String,
+        /// Order of the manifest's sources (default: id, the order sources were indexed)
+        #[arg(long, value_enum)]
+        sort: Option<sort::SortKey>,
+        /// Compare paths naturally: IMG_9 before IMG_10, ignoring case (implies --sort path;
+        /// also orders volume contents and same-second burst frames)
+        #[arg(long)]
+        natural: bool,
     },
 }
 
-fn main() -> anyhow::Result<()> {
+fn main() {
     let cli = Cli::parse();
+    let json = cli.json;
+    if let Err(err) = run(cli) {
+        let report = ErrorReport::new(&err);
+        if json {
+            eprintln!("{}", serde_json::to_string(&report).expect("error report serializes"));
+        } else {
+            eprintln!("Error: {:?}", err);
+        }
+        std::process::exit(report.exit_code);
+    }
+}
+
+fn run(cli: Cli) -> anyhow::Result<()> {
+    // Neither needs the --db of this invocation (__complete finds the one being typed)
+    match cli.command {
+        Commands::Completions { shell } => {
+            print!("{}", complete::script(shell));
+            return Ok(());
+        }
+        Commands::Complete { ref words } => return complete::run(&Cli::command(), words),
+        _ => {}
+    }
 
-    let db_path = cli.db.unwrap_or_else(|| {
-        let mut path = dirs::home_dir().expect("Could not determine home directory");
-        path.push(".canon");
-        path.push("canon.db");
-        path
-    });
+    if cli.json {
+        if !supports_json(&cli.command) {
+            return Err(coded(ErrorCode::Usage, "--json is not supported by this command"));
+        }
+        output::set_format(output::Format::Json);
+    }
 
-    let mut db = db::open(&db_path, cli.debug_sql)?;
+    let cache_env = std::env::var(query_cache::CACHE_ENV).is_ok_and(|v| !v.is_empty() && v != "0");
+    query_cache::set_enabled(cli.query_cache || cache_env);
 
-    match cli.command {
-        Commands::Scan { paths, role, add } => {
-            scan::run(&db, &paths, &role, add)?;
+    let db_path = cli.db.unwrap_or_else(db::default_path);
+
+    let mut db = db::open(&db_path, cli.debug_sql, cli.profile)?;
+
+    // Commands that move files or rewrite source paths must not overlap
+    let lock_id = match lock_operation(&cli.command) {
+        Some(operation) => Some(lock::acquire(db.conn(), operation)?),
+        None => None,
+    };
+
+    let result = run_command(cli.command, &mut db);
+
+    if let Some(lock_id) = lock_id {
+        lock::release(db.conn(), lock_id)?;
+    }
+
+    if cli.profile {
+        db::print_profile_report();
+    }
+
+    result
+}
+
+/// The operation name to lock for commands that change files or source paths
+fn lock_operation(command: &Commands) -> Option<&'static str> {
+    match command {
+        Commands::Scan { dry_run: false, .. } => Some("scan"),
+        Commands::Apply { dry_run: false, .. } => Some("apply"),
+        Commands::Organize { yes: true, .. } => Some("organize"),
+        Commands::Run { .. } => Some("run"),
+        Commands::Reconcile { rescan: true, .. } => Some("reconcile"),
+        Commands::Trash { action: TrashAction::Restore { .. } } => Some("trash"),
+        Commands::Trash { action: TrashAction::Purge { dry_run: false, .. } } => Some("trash"),
+        Commands::Root { action: RootAction::SetRole { .. } } => Some("root set-role"),
+        Commands::Root { action: RootAction::Relocate { .. } } => Some("root relocate"),
+        Commands::Root { action: RootAction::Remove { yes: true, .. } } => Some("root remove"),
+        _ => None,
+    }
+}
+
+/// Commands that report through `output` (or always print JSON/JSONL)
+fn supports_json(command: &Commands) -> bool {
+    matches!(
+        command,
+        Commands::Scan { .. }
+            | Commands::Worklist { .. }
+            | Commands::Hash { .. }
+            | Commands::Facts { .. }
+            | Commands::Coverage { .. }
+            | Commands::Apply { .. }
+            | Commands::Exclude { .. }
+            | Commands::Pin { .. }
+            | Commands::Unpin { .. }
+            | Commands::Verify { .. }
+            | Commands::Object { .. }
+            | Commands::Organize { .. }
+            | Commands::Suggest { .. }
+            | Commands::Tier { .. }
+            | Commands::Trash { .. }
+            | Commands::Reconcile { .. }
+            | Commands::Strays { .. }
+            | Commands::ImportCatalog { .. }
+            | Commands::Doctor { .. }
+            | Commands::Collection { action: CollectionAction::Export { .. } }
+            | Commands::Root { action: RootAction::List | RootAction::Show { .. } | RootAction::Remove { .. } }
+    )
+}
+
+/// Source ids listed on stdin, when `--stdin` is given
+fn read_stdin_selection(db: &db::Db, stdin: bool) -> anyhow::Result<Option<Vec<i64>>> {
+    if !stdin {
+        return Ok(None);
+    }
+    Ok(Some(query::read_source_ids(db.conn(), std::io::stdin().lock())?))
+}
+
+/// Whether `--sort`/`--natural` ask for path order (`--natural` implies it)
+fn sort_by_path(sort: Option<sort::SortKey>, natural: bool) -> anyhow::Result<bool> {
+    match sort {
+        Some(sort::SortKey::Id) if natural => anyhow::bail!("--natural orders paths; use it with --sort path"),
+        Some(key) => Ok(key == sort::SortKey::Path),
+        None => Ok(natural),
+    }
+}
+
+fn run_command(command: Commands, db: &mut db::Db) -> anyhow::Result<()> {
+    match command {
+        Commands::Scan {
+            paths,
+            role,
+            add,
+            emit_worklist,
+            dry_run,
+            jobs,
+            exclude,
+            hash,
+            symlinks,
+            skip_hidden,
+            xattrs,
+            magic,
+            fast,
+            commit_every,
+            min_size,
+            max_size,
+            prune,
+            report,
+            resume,
+        } => {
+            let options = scan::ScanOptions {
+                emit_worklist,
+                dry_run,
+                jobs,
+                exclude,
+                hash,
+                symlinks,
+                skip_hidden,
+                xattrs,
+                magic,
+                fast,
+                commit_every,
+                min_size: min_size.as_deref().map(cluster::parse_size).transpose()?,
+                max_size: max_size.as_deref().map(cluster::parse_size).transpose()?,
+                prune: prune.as_deref().map(trash::parse_age).transpose()?,
+                report,
+                resume,
+            };
+            scan::run(db, &paths, &role, add, &options)?;
+        }
+        Commands::Worklist { path, filters, include_archived, include_excluded, unique_objects, with_facts } => {
+            let options = worklist::WorklistOptions { include_archived, include_excluded, unique_objects, with_facts };
+            worklist::run(db, path.as_deref(), &filters, &options)?;
+        }
+        Commands::Hash { path, filters, jobs, include_archived, include_excluded, quick, quick_matches, retry_failed } => {
+            let options =
+                hash::HashOptions { jobs, include_archived, include_excluded, quick, quick_matches, retry_failed };
+            hash::run(db, path.as_deref(), &filters, &options)?;
         }
-        Commands::Worklist { path, filters, include_archived, include_excluded } => {
-            worklist::run(&db, path.as_deref(), &filters, include_archived, include_excluded)?;
+        Commands::ImportFacts { allow_archived, format, catalog: catalog_path, path_map } => {
+            let kind = match format {
+                import_facts::ImportFormat::Jsonl => {
+                    if catalog_path.is_some() || !path_map.is_empty() {
+                        return Err(coded(ErrorCode::Usage, "A catalog file and --path-map need --format digikam or lightroom"));
+                    }
+                    return import_facts::run(db, allow_archived);
+                }
+                import_facts::ImportFormat::Digikam => catalog::CatalogKind::Digikam,
+                import_facts::ImportFormat::Lightroom => catalog::CatalogKind::Lightroom,
+            };
+            let catalog_path = catalog_path.expect("clap requires a catalog for catalog formats");
+            catalog::import(db, kind, &catalog_path, &path_map, allow_archived)?;
         }
-        Commands::ImportFacts { allow_archived } => {
-            import_facts::run(&db, allow_archived)?;
+        Commands::ImportCatalog { catalog } => {
+            archive_catalog::import(db, &catalog)?;
         }
-        Commands::Ls { path, filters, archived, unarchived, unhashed, include_archived, include_excluded } => {
+        Commands::Ls { path, filters, archived, unarchived, unhashed, include_archived, include_excluded, sort, natural } => {
+            let sort_by_path = sort_by_path(sort, natural)?.then_some(sort::PathOrder::natural(natural));
             // If no path given, check if cwd is inside a root
-            let (scope_path, use_relative) = if path.is_none() {
-                let cwd = std::env::current_dir()?;
-                match db::resolve_root_path(db.conn(), &cwd)? {
-                    Some(_) => (Some(cwd), true),   // Inside root: scope to cwd, relative
-                    None => (None, false),           // Outside root: all sources, absolute
+            let (scope_path, use_relative) = match path {
+                None => {
+                    let cwd = std::env::current_dir()?;
+                    match db::resolve_root_path(db.conn(), &cwd)? {
+                        Some(_) => (Some(cwd), true),   // Inside root: scope to cwd, relative
+                        None => (None, false),           // Outside root: all sources, absolute
+                    }
+                }
+                Some(p) => {
+                    let use_rel = !p.starts_with("/");
+                    (Some(p), use_rel)
                 }
-            } else {
-                let use_rel = !path.as_ref().unwrap().starts_with("/");
-                (path, use_rel)
             };
-            ls::run(&db, scope_path.as_deref(), &filters, archived.as_deref(), unarchived, unhashed, include_archived, include_excluded, use_relative)?;
+            ls::run(db, scope_path.as_deref(), &filters, archived.as_deref(), unarchived, unhashed, include_archived, include_excluded, use_relative, sort_by_path)?;
         }
         Commands::Facts { action, key, path, filters, limit, all, include_archived, include_excluded } => {
             match action {
-                Some(FactsAction::Delete { key, path, filters, on, yes }) => {
+                Some(FactsAction::Delete { key, path, filters, on, yes, stdin, include_pinned }) => {
                     let options = facts::DeleteOptions {
                         entity_type: on,
                         dry_run: !yes,
+                        source_ids: read_stdin_selection(db, stdin)?,
+                        include_pinned,
                     };
-                    facts::delete_facts(&mut db, &key, path.as_deref(), &filters, &options)?;
+                    facts::delete_facts(db, &key, path.as_deref(), &filters, &options)?;
                 }
                 Some(FactsAction::Prune { stale, yes }) => {
                     if stale {
-                        facts::prune_stale(&db, !yes)?;
+                        facts::prune_stale(db, !yes)?;
                     } else {
-                        eprintln!("Error: --stale flag is required for prune command");
-                        std::process::exit(1);
+                        return Err(coded(ErrorCode::Usage, "--stale flag is required for prune command"));
                     }
                 }
                 None => {
-                    facts::run(&mut db, key.as_deref(), path.as_deref(), &filters, limit, all, include_archived, include_excluded)?;
+                    facts::run(db, key.as_deref(), path.as_deref(), &filters, limit, all, include_archived, include_excluded)?;
                 }
             }
         }
         Commands::Coverage { path, filters, archive, include_archived, include_excluded } => {
-            coverage::run(&mut db, path.as_deref(), &filters, archive.as_deref(), include_archived, include_excluded)?;
+            coverage::run(db, path.as_deref(), &filters, archive.as_deref(), include_archived, include_excluded)?;
         }
         Commands::Cluster { action } => match action {
             ClusterAction::Generate {
@@ -317,12 +1114,39 @@ fn main() -> anyhow::Result<()> {
                 output,
                 include_archived,
                 show_archived,
+                split_by_volume,
+                volume_size,
+                stdin,
+                group_by,
+                gap,
+                burst_keep,
+                sort,
+                natural,
             } => {
+                let burst = match group_by {
+                    Some(burst::GroupBy::Burst) => Some(burst::BurstOptions {
+                        gap: burst::parse_gap(&gap)?,
+                        keep: burst::BurstKeep::parse(&burst_keep)?,
+                    }),
+                    None => None,
+                };
                 let options = cluster::GenerateOptions {
                     include_archived,
                     show_archived,
+                    source_ids: read_stdin_selection(db, stdin)?,
+                    burst,
+                    sort_by_path: sort_by_path(sort, natural)?,
+                    path_order: sort::PathOrder::natural(natural),
                 };
-                cluster::generate(&db, &filters, &dest, &output, &options)?;
+                if split_by_volume {
+                    let volume_size = cluster::parse_size(volume_size.as_deref().unwrap_or_default())?;
+                    cluster::generate_volumes(db, &filters, &dest, &output, volume_size, &options)?;
+                } else {
+                    if dest.len() > 1 {
+                        anyhow::bail!("Multiple --dest values require --split-by-volume");
+                    }
+                    cluster::generate(db, &filters, &dest[0], &output, &options)?;
+                }
             }
         },
         Commands::Apply {
@@ -333,6 +1157,9 @@ fn main() -> anyhow::Result<()> {
             rename,
             move_files,
             yes: _,
+            include_pinned,
+            hash_missing,
+            download_verify,
         } => {
             let transfer_mode = if rename {
                 apply::TransferMode::Rename
@@ -346,20 +1173,216 @@ fn main() -> anyhow::Result<()> {
                 allow_cross_archive_duplicates,
                 roots: root,
                 transfer_mode,
+                include_pinned,
+                hash_missing,
+                download_verify,
             };
-            apply::run(&db, &manifest, &options)?;
+            apply::run(db, &manifest, &options)?;
+        }
+        Commands::Pin { path, filters, dry_run, stdin } => {
+            let source_ids = read_stdin_selection(db, stdin)?;
+            let options = pin::PinOptions { dry_run, source_ids };
+            pin::pin(db, path.as_deref(), &filters, &options)?;
+        }
+        Commands::Unpin { path, filters, dry_run, stdin } => {
+            let source_ids = read_stdin_selection(db, stdin)?;
+            let options = pin::PinOptions { dry_run, source_ids };
+            pin::unpin(db, path.as_deref(), &filters, &options)?;
         }
         Commands::Exclude { action } => match action {
-            ExcludeAction::Set { path, filters, dry_run } => {
-                let options = exclude::SetOptions { dry_run };
-                exclude::set(&db, path.as_deref(), &filters, &options)?;
+            ExcludeAction::Set { path, filters, dry_run, stdin, include_pinned } => {
+                let source_ids = read_stdin_selection(db, stdin)?;
+                let options = exclude::SetOptions { dry_run, source_ids, include_pinned };
+                exclude::set(db, path.as_deref(), &filters, &options)?;
             }
-            ExcludeAction::Clear { path, filters, dry_run } => {
-                let options = exclude::ClearOptions { dry_run };
-                exclude::clear(&db, path.as_deref(), &filters, &options)?;
+            ExcludeAction::Clear { path, filters, dry_run, stdin } => {
+                let source_ids = read_stdin_selection(db, stdin)?;
+                let options = exclude::ClearOptions { dry_run, source_ids };
+                exclude::clear(db, path.as_deref(), &filters, &options)?;
             }
             ExcludeAction::List { path, filters } => {
-                exclude::list(&db, path.as_deref(), &filters)?;
+                exclude::list(db, path.as_deref(), &filters)?;
+            }
+        },
+        Commands::Extract { action } => match action {
+            ExtractAction::Exif(args) => {
+                extract::run(db, &extract::exif::ExifExtractor, args.path.as_deref(), &args.filters, &args.options())?;
+            }
+            ExtractAction::Image(args) => {
+                extract::run(db, &extract::image::ImageExtractor, args.path.as_deref(), &args.filters, &args.options())?;
+            }
+            ExtractAction::Kind(args) => {
+                extract::run(db, &extract::kind::KindExtractor, args.path.as_deref(), &args.filters, &args.options())?;
+            }
+            ExtractAction::Mime(args) => {
+                extract::run(db, &extract::mime::MimeExtractor, args.path.as_deref(), &args.filters, &args.options())?;
+            }
+            ExtractAction::Video { args, ffprobe } => {
+                let extractor = extract::video::VideoExtractor::new(&ffprobe)?;
+                extract::run(db, &extractor, args.path.as_deref(), &args.filters, &args.options())?;
+            }
+        },
+        Commands::Report { format, output } => {
+            let format = match (format, &output) {
+                (Some(f), _) => report::ReportFormat::parse(&f)?,
+                (None, Some(path)) => report::ReportFormat::from_path(path),
+                (None, None) => report::ReportFormat::Markdown,
+            };
+            let options = report::ReportOptions { format };
+            report::run(db, output.as_deref(), &options)?;
+        }
+        Commands::Export { format: export::ExportFormat::Catalog, output, archive, .. } => {
+            let archive = archive.expect("clap requires --archive for --format catalog");
+            archive_catalog::export(db, &archive, output.as_deref())?;
+        }
+        Commands::Export { archive: Some(_), .. } => {
+            return Err(coded(ErrorCode::Usage, "--archive is only used with --format catalog"));
+        }
+        Commands::Export { path, filters, format, output, tag, include_archived, stdin, archive: None } => {
+            let source_ids = read_stdin_selection(db, stdin)?;
+            let options = export::ExportOptions { format, output, tag, include_archived, source_ids };
+            export::run(db, path.as_deref(), &filters, &options)?;
+        }
+        Commands::Serve { addr } => {
+            serve::run(db, &addr)?;
+        }
+        Commands::Status => {
+            status::run(db)?;
+        }
+        Commands::Doctor { fix } => {
+            doctor::run(db, fix)?;
+        }
+        Commands::Verify { path, archive, download, oldest_first, max_bytes } => {
+            let max_bytes = max_bytes.as_deref().map(cluster::parse_size).transpose()?;
+            let options = verify::VerifyOptions { download, archive, oldest_first, max_bytes };
+            verify::run(db, path.as_deref(), &options)?;
+        }
+        Commands::Organize { path, pattern, filters, dry_run: _, yes } => {
+            let options = organize::OrganizeOptions { pattern, yes };
+            organize::run(db, path.as_deref(), &filters, &options)?;
+        }
+        Commands::Suggest { path, filters, output } => {
+            let options = suggest::SuggestOptions { output };
+            suggest::run(db, path.as_deref(), &filters, &options)?;
+        }
+        Commands::Tier { action: TierAction::Suggest { policy, path, filters, output } } => {
+            let options = tier::TierOptions { output };
+            tier::suggest(db, &policy, path.as_deref(), &filters, &options)?;
+        }
+        Commands::Run { pipeline } => {
+            pipeline::run(db, &pipeline)?;
+        }
+        Commands::Lint { archive, set_layout } => {
+            let options = lint::LintOptions { set_layout };
+            lint::run(db, &archive, &options)?;
+        }
+        Commands::Root { action } => match action {
+            RootAction::List => {
+                roots::list(db)?;
+            }
+            RootAction::Show { root } => {
+                roots::show(db, &root)?;
+            }
+            RootAction::Offline { root } => {
+                roots::set_offline(db, &root, true)?;
+            }
+            RootAction::Online { root } => {
+                roots::set_offline(db, &root, false)?;
+            }
+            RootAction::Alias { name, root } => {
+                roots::alias(db, &name, &root)?;
+            }
+            RootAction::SetRole { root, role } => {
+                roots::set_role(db, &root, &role)?;
+            }
+            RootAction::Relocate { root, new_path } => {
+                roots::relocate(db, &root, &new_path)?;
+            }
+            RootAction::Remove { root, yes, keep_objects } => {
+                let options = roots::RemoveOptions { dry_run: !yes, keep_objects };
+                roots::remove(db, &root, &options)?;
+            }
+            RootAction::Set { root, settings } => {
+                roots::set(db, &root, &settings)?;
+            }
+        },
+        Commands::Object { action } => match action {
+            ObjectAction::Show { hash } => {
+                object::show(db, &hash)?;
+            }
+            ObjectAction::Ls { filters } => {
+                object::ls(db, &filters)?;
+            }
+        },
+        Commands::Collection { action } => match action {
+            CollectionAction::Create { name } => {
+                collection::create(db, &name)?;
+            }
+            CollectionAction::Add { name, path, filters } => {
+                collection::add(db, &name, path.as_deref(), &filters)?;
+            }
+            CollectionAction::Remove { name, path, filters } => {
+                collection::remove(db, &name, path.as_deref(), &filters)?;
+            }
+            CollectionAction::List { name } => {
+                collection::list(db, name.as_deref())?;
+            }
+            CollectionAction::Export { name, dest, output } => {
+                collection::export(db, &name, dest.as_deref(), output.as_deref())?;
+            }
+            CollectionAction::Delete { name } => {
+                collection::delete(db, &name)?;
+            }
+        },
+        Commands::Snapshot { action } => match action {
+            SnapshotAction::Create { name } => {
+                snapshot::create(db, &name)?;
+            }
+            SnapshotAction::List => {
+                snapshot::list(db)?;
+            }
+            SnapshotAction::Diff { from, to, list } => {
+                let options = snapshot::DiffOptions { list };
+                snapshot::diff(db, &from, to.as_deref(), &options)?;
+            }
+        },
+        Commands::Completions { .. } | Commands::Complete { .. } => {
+            unreachable!("handled before the database is opened")
+        }
+        Commands::Reconcile { archive, rescan, flag } => {
+            let action = if rescan {
+                reconcile::ReconcileAction::Rescan
+            } else if flag {
+                reconcile::ReconcileAction::Flag
+            } else {
+                reconcile::ReconcileAction::Report
+            };
+            reconcile::run(db, &archive, action)?;
+        }
+        Commands::Strays { archive } => strays::run(db, &archive)?,
+        Commands::Trash { action } => match action {
+            TrashAction::List => {
+                trash::list(db)?;
+            }
+            TrashAction::Restore { ids } => {
+                trash::restore(db, &ids)?;
+            }
+            TrashAction::Purge { older_than, dry_run } => {
+                let older_than = trash::parse_age(&older_than)?;
+                trash::purge(db, older_than, dry_run)?;
+            }
+        },
+        Commands::Lock { action } => match action {
+            LockAction::Status => {
+                lock::status(db)?;
+            }
+            LockAction::Break => {
+                lock::break_locks(db)?;
+            }
+        },
+        Commands::Db { action } => match action {
+            DbAction::RefreshCache => {
+                db::refresh_cache(db)?;
             }
         },
     }
@@ -0,0 +1,332 @@
+use anyhow::{bail, Result};
+use rusqlite::params;
+use serde::Serialize;
+
+use crate::db::{Connection, Db};
+use crate::output;
+use crate::query::SourceQuery;
+use crate::util::{format_bytes, format_timestamp};
+
+/// Shortest hash prefix accepted by `object show`
+const MIN_PREFIX_LEN: usize = 6;
+
+/// Everything known about one object
+#[derive(Serialize)]
+struct ObjectReport {
+    hash_type: String,
+    hash_value: String,
+    size: Option<i64>,
+    facts: Vec<ObjectFact>,
+    sources: Vec<ObjectSource>,
+    verifications: Vec<Verification>,
+    provenance: Vec<Provenance>,
+}
+
+#[derive(Serialize)]
+struct ObjectFact {
+    key: String,
+    value: String,
+    observed_at: i64,
+}
+
+/// A source (file) with this content, present or not
+#[derive(Serialize)]
+struct ObjectSource {
+    id: i64,
+    path: String,
+    role: String,
+    present: bool,
+    /// Whether the source's root is mounted/reachable
+    available: bool,
+    last_seen_at: i64,
+}
+
+#[derive(Serialize)]
+struct Verification {
+    path: String,
+    verified_at: i64,
+    result: String,
+}
+
+/// How `canon apply` placed a copy of the object in an archive
+#[derive(Serialize)]
+struct Provenance {
+    apply_run_id: i64,
+    manifest: String,
+    manifest_sha256: Option<String>,
+    source_id: i64,
+    source_path: String,
+    dest_source_id: i64,
+    dest_path: String,
+    transfer: String,
+    verify: String,
+    applied_at: i64,
+}
+
+/// One line of `object ls`
+#[derive(Serialize)]
+struct ObjectSummary {
+    hash_type: String,
+    hash_value: String,
+    size: Option<i64>,
+    /// Present sources in source roots
+    sources: i64,
+    /// Present copies in archive roots
+    archive_copies: i64,
+}
+
+// ============================================================================
+// Show Command
+// ============================================================================
+
+/// Print an object's facts, every source that references it (present or
+/// missing, in source and archive roots), its verification history and how
+/// apply placed its archive copies.
+/// `hash` may be a unique prefix of the hash value.
+pub fn show(db: &Db, hash: &str) -> Result<()> {
+    let conn = db.conn();
+    let (object_id, hash_type, hash_value) = find_object(conn, hash)?;
+
+    let facts = conn
+        .prepare(
+            "SELECT key,
+                    COALESCE(value_text, CAST(value_num AS TEXT), datetime(value_time, 'unixepoch'), value_json),
+                    observed_at
+             FROM facts
+             WHERE entity_type = 'object' AND entity_id = ?
+             ORDER BY key",
+        )?
+        .query_map([object_id], |row| {
+            Ok(ObjectFact {
+                key: row.get(0)?,
+                value: row.get(1)?,
+                observed_at: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let sources = conn
+        .prepare(
+            "SELECT s.id, r.path || '/' || s.rel_path, r.role, s.present, r.available, s.last_seen_at
+             FROM sources s
+             JOIN roots r ON s.root_id = r.id
+             WHERE s.object_id = ?
+             ORDER BY s.present DESC, r.role DESC, r.path, s.rel_path",
+        )?
+        .query_map([object_id], |row| {
+            Ok(ObjectSource {
+                id: row.get(0)?,
+                path: row.get(1)?,
+                role: row.get(2)?,
+                present: row.get(3)?,
+                available: row.get(4)?,
+                last_seen_at: row.get(5)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let verifications = conn
+        .prepare(
+            "SELECT r.path || '/' || s.rel_path, v.verified_at, v.result
+             FROM verifications v
+             JOIN sources s ON v.source_id = s.id
+             JOIN roots r ON s.root_id = r.id
+             WHERE v.object_id = ?
+             ORDER BY v.verified_at DESC, v.id DESC",
+        )?
+        .query_map([object_id], |row| {
+            Ok(Verification {
+                path: row.get(0)?,
+                verified_at: row.get(1)?,
+                result: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let provenance = conn
+        .prepare(
+            "SELECT p.apply_run_id, a.manifest, a.manifest_sha256,
+                    p.source_id, sr.path || '/' || s.rel_path,
+                    p.dest_source_id, dr.path || '/' || d.rel_path,
+                    p.transfer, p.verify, p.applied_at
+             FROM apply_provenance p
+             JOIN apply_runs a ON p.apply_run_id = a.id
+             JOIN sources s ON p.source_id = s.id
+             JOIN roots sr ON s.root_id = sr.id
+             JOIN sources d ON p.dest_source_id = d.id
+             JOIN roots dr ON d.root_id = dr.id
+             WHERE p.object_id = ?
+             ORDER BY p.applied_at DESC, p.id DESC",
+        )?
+        .query_map([object_id], |row| {
+            Ok(Provenance {
+                apply_run_id: row.get(0)?,
+                manifest: row.get(1)?,
+                manifest_sha256: row.get(2)?,
+                source_id: row.get(3)?,
+                source_path: row.get(4)?,
+                dest_source_id: row.get(5)?,
+                dest_path: row.get(6)?,
+                transfer: row.get(7)?,
+                verify: row.get(8)?,
+                applied_at: row.get(9)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let size: Option<i64> = conn.query_row(
+        "SELECT MAX(size) FROM sources WHERE object_id = ?",
+        [object_id],
+        |row| row.get(0),
+    )?;
+
+    let report = ObjectReport {
+        hash_type,
+        hash_value,
+        size,
+        facts,
+        sources,
+        verifications,
+        provenance,
+    };
+    output::emit(&report, print_report)
+}
+
+fn print_report(report: &ObjectReport) {
+    println!("Object {} ({})", report.hash_value, report.hash_type);
+    if let Some(size) = report.size {
+        println!("Size: {}", format_bytes(size as u64));
+    }
+
+    println!("\nFacts ({}):", report.facts.len());
+    for fact in &report.facts {
+        println!("  {} = {}  (observed {})", fact.key, fact.value, format_timestamp(fact.observed_at));
+    }
+
+    println!("\nSources ({}):", report.sources.len());
+    for source in &report.sources {
+        let state = if source.present { "present" } else { "missing" };
+        let note = if !source.present {
+            format!(" (last seen {})", format_timestamp(source.last_seen_at))
+        } else if !source.available {
+            " (offline)".to_string()
+        } else {
+            String::new()
+        };
+        println!("  {:<8} {:<8} {}{}", state, source.role, source.path, note);
+    }
+
+    if report.verifications.is_empty() {
+        println!("\nNever verified");
+    } else {
+        println!("\nVerifications ({}):", report.verifications.len());
+        for v in &report.verifications {
+            println!("  {}  {:<8} {}", format_timestamp(v.verified_at), v.result, v.path);
+        }
+    }
+
+    if !report.provenance.is_empty() {
+        println!("\nApplied ({}):", report.provenance.len());
+        for p in &report.provenance {
+            println!("  {}  {:<6} {:<10} {} -> {}", format_timestamp(p.applied_at), p.transfer, p.verify, p.source_path, p.dest_path);
+            let hash = p.manifest_sha256.as_deref().map(|h| format!(" (sha256 {})", &h[..h.len().min(12)])).unwrap_or_default();
+            println!("  {:<16}  run {}, manifest {}{}", "", p.apply_run_id, p.manifest, hash);
+        }
+    }
+}
+
+// ============================================================================
+// Ls Command
+// ============================================================================
+
+/// List objects referenced by sources matching the filters (any root, excluded
+/// sources included), with their size and number of copies
+pub fn ls(db: &Db, filter_strs: &[String]) -> Result<()> {
+    let conn = db.conn();
+
+    let mut object_ids = Vec::new();
+    SourceQuery::new()
+        .filters(conn, filter_strs)?
+        .include_archived(true)
+        .include_excluded(true)
+        .for_each_batch(
+            conn,
+            "s.id, s.object_id",
+            |row| row.get::<_, Option<i64>>(1),
+            |batch| {
+                object_ids.extend(batch.into_iter().flatten());
+                Ok(())
+            },
+        )?;
+    object_ids.sort_unstable();
+    object_ids.dedup();
+
+    let mut stmt = conn.prepare(
+        "SELECT o.hash_type, o.hash_value,
+                (SELECT MAX(s.size) FROM sources s WHERE s.object_id = o.id),
+                (SELECT COUNT(*) FROM sources s JOIN roots r ON s.root_id = r.id
+                 WHERE s.object_id = o.id AND s.present = 1 AND r.role = 'source'),
+                COALESCE(ao.copies, 0)
+         FROM objects o
+         LEFT JOIN archived_objects ao ON ao.object_id = o.id
+         WHERE o.id = ?",
+    )?;
+    let mut objects = Vec::with_capacity(object_ids.len());
+    for object_id in object_ids {
+        objects.push(stmt.query_row(params![object_id], |row| {
+            Ok(ObjectSummary {
+                hash_type: row.get(0)?,
+                hash_value: row.get(1)?,
+                size: row.get(2)?,
+                sources: row.get(3)?,
+                archive_copies: row.get(4)?,
+            })
+        })?);
+    }
+
+    output::emit(&objects, |objects| {
+        // Print output (to stdout for pipe-friendliness), footer to stderr
+        for object in objects {
+            println!(
+                "{}\t{}\t{} sources\t{} archive copies",
+                object.hash_value,
+                object
+                    .size
+                    .map(|size| format_bytes(size as u64))
+                    .unwrap_or_default(),
+                object.sources,
+                object.archive_copies
+            );
+        }
+        eprintln!("{} objects", objects.len());
+    })
+}
+
+// ============================================================================
+// Helper Functions
+// ============================================================================
+
+/// Resolve a full hash or unique hash prefix, of any recorded algorithm, to
+/// (id, hash_type, hash_value)
+fn find_object(conn: &Connection, hash: &str) -> Result<(i64, String, String)> {
+    let hash = hash.to_lowercase();
+    if hash.len() < MIN_PREFIX_LEN {
+        bail!("Hash '{}' is too short (at least {} characters)", hash, MIN_PREFIX_LEN);
+    }
+
+    let matches: Vec<(i64, String, String)> = conn
+        .prepare(
+            "SELECT DISTINCT o.id, o.hash_type, o.hash_value
+             FROM object_hashes h JOIN objects o ON o.id = h.object_id
+             WHERE substr(h.value, 1, length(?1)) = ?1
+             LIMIT 2",
+        )?
+        .query_map([&hash], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match matches.len() {
+        0 => bail!("No object with hash '{}'", hash),
+        1 => Ok(matches.into_iter().next().expect("one match")),
+        _ => bail!("Hash prefix '{}' is ambiguous; give more characters", hash),
+    }
+}
@@ -0,0 +1,207 @@
+use anyhow::{Context, Result};
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::apply::expand_pattern;
+use crate::cluster::fetch_source;
+use crate::db::{refresh_root_availability, Connection, Db};
+use crate::error::{coded, ErrorCode};
+use crate::output;
+use crate::query::SourceQuery;
+use crate::remote;
+use crate::util::current_timestamp;
+
+pub struct OrganizeOptions {
+    /// Destination pattern relative to each file's root (e.g., "{year}/{month}/{filename}")
+    pub pattern: String,
+    /// Rename files (otherwise only show the plan)
+    pub yes: bool,
+}
+
+#[derive(Default, Serialize)]
+struct OrganizeStats {
+    dry_run: bool,
+    renamed: u64,
+    unchanged: u64,
+    /// Facts needed by the pattern are missing
+    unresolved: u64,
+    /// Destination taken on disk, in the index or by another file in this run
+    conflicts: u64,
+    missing: u64,
+    errors: u64,
+    files: Vec<Renamed>,
+}
+
+#[derive(Serialize)]
+struct Renamed {
+    source: String,
+    dest: String,
+}
+
+/// Rename matching files within their own root to the path the pattern gives
+/// them, keeping their source records (facts, object, basis_rev) attached.
+/// Only source roots are organized; archive layouts are `canon lint`'s concern.
+pub fn run(db: &Db, scope_path: Option<&Path>, filter_strs: &[String], options: &OrganizeOptions) -> Result<()> {
+    let conn = db.conn();
+    refresh_root_availability(conn)?;
+
+    let mut candidates: Vec<(i64, String, String, bool)> = Vec::new();
+    SourceQuery::new()
+        .scope(scope_path)?
+        .filters(conn, filter_strs)?
+        .for_each_batch(
+            conn,
+            "s.id, r.path, s.rel_path, r.available",
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            |batch| {
+                candidates.extend(batch);
+                Ok(())
+            },
+        )?;
+    candidates.sort_by(|a, b| (&a.1, &a.2).cmp(&(&b.1, &b.2)));
+
+    let mut stats = OrganizeStats {
+        dry_run: !options.yes,
+        ..Default::default()
+    };
+    let mut planned: HashSet<(String, String)> = HashSet::new();
+    let now = current_timestamp();
+
+    for (source_id, root_path, rel_path, available) in candidates {
+        let src_path = Path::new(&root_path).join(&rel_path);
+        if remote::is_remote(&root_path) || !available {
+            eprintln!("Warning: Root '{}' is remote or unavailable, skipping {}", root_path, rel_path);
+            stats.missing += 1;
+            continue;
+        }
+        if !src_path.exists() {
+            eprintln!("Warning: missing on disk: {}", src_path.display());
+            stats.missing += 1;
+            continue;
+        }
+
+        let Some(source) = fetch_source(conn, source_id)? else {
+            continue;
+        };
+        let new_rel = match expand_pattern(&options.pattern, &source, &src_path) {
+            Ok(expanded) => expanded.trim_start_matches('/').to_string(),
+            Err(e) => {
+                eprintln!("Warning: {}: {}", src_path.display(), e);
+                stats.unresolved += 1;
+                continue;
+            }
+        };
+        if new_rel == rel_path {
+            stats.unchanged += 1;
+            continue;
+        }
+
+        let dest_path = Path::new(&root_path).join(&new_rel);
+        if !planned.insert((root_path.clone(), new_rel.clone()))
+            || dest_path.exists()
+            || path_indexed(conn, source.root_id, &new_rel)?
+        {
+            eprintln!(
+                "Warning: destination taken, skipping: {} -> {}",
+                src_path.display(),
+                dest_path.display()
+            );
+            stats.conflicts += 1;
+            continue;
+        }
+
+        let renamed = Renamed {
+            source: src_path.display().to_string(),
+            dest: dest_path.display().to_string(),
+        };
+        if stats.dry_run {
+            if !output::is_json() {
+                println!("RENAME: {} -> {}", renamed.source, renamed.dest);
+            }
+        } else {
+            if let Err(e) = rename_source(conn, source_id, &src_path, &dest_path, &new_rel, now) {
+                eprintln!("Error processing {}: {:#}", src_path.display(), e);
+                stats.errors += 1;
+                continue;
+            }
+            if !output::is_json() {
+                println!("Renamed: {} -> {}", renamed.source, renamed.dest);
+            }
+            remove_empty_parents(&src_path, Path::new(&root_path));
+        }
+        stats.renamed += 1;
+        stats.files.push(renamed);
+    }
+
+    output::emit(&stats, |stats| {
+        let mode = if stats.dry_run { " (dry-run)" } else { "" };
+        println!(
+            "Organized{}: {} renamed, {} unchanged, {} unresolved (missing facts), {} conflicts, {} missing, {} errors",
+            mode, stats.renamed, stats.unchanged, stats.unresolved, stats.conflicts, stats.missing, stats.errors
+        );
+        if stats.dry_run && stats.renamed > 0 {
+            println!("Use --yes to rename files");
+        }
+    })?;
+
+    if stats.errors > 0 {
+        return Err(coded(ErrorCode::PartialFailure, format!("{} files failed to rename", stats.errors)));
+    }
+    Ok(())
+}
+
+/// Move the file and point its source record at the new path. A rename keeps
+/// device, inode, size and mtime, so the basis (and facts observed against it)
+/// stays valid, as for moves detected by scan.
+fn rename_source(
+    conn: &Connection,
+    source_id: i64,
+    src_path: &Path,
+    dest_path: &Path,
+    new_rel: &str,
+    now: i64,
+) -> Result<()> {
+    if let Some(parent) = dest_path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+    fs::rename(src_path, dest_path)
+        .with_context(|| format!("Failed to rename {} to {}", src_path.display(), dest_path.display()))?;
+
+    let updated = conn.execute(
+        "UPDATE sources SET rel_path = ?, last_seen_at = ? WHERE id = ?",
+        params![new_rel, now, source_id],
+    );
+    if let Err(e) = updated {
+        // Keep disk and index in agreement
+        fs::rename(dest_path, src_path).ok();
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Whether the root already has a source record (present or not) at `rel_path`
+fn path_indexed(conn: &Connection, root_id: i64, rel_path: &str) -> Result<bool> {
+    let found = conn
+        .query_row(
+            "SELECT 1 FROM sources WHERE root_id = ? AND rel_path = ?",
+            params![root_id, rel_path],
+            |_| Ok(()),
+        )
+        .optional()?;
+    Ok(found.is_some())
+}
+
+/// Remove directories left empty by a rename, up to (not including) the root
+fn remove_empty_parents(path: &Path, root: &Path) {
+    let mut dir = path.parent();
+    while let Some(d) = dir {
+        if d == root || !d.starts_with(root) || fs::remove_dir(d).is_err() {
+            break;
+        }
+        dir = d.parent();
+    }
+}
@@ -0,0 +1,38 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::sync::OnceLock;
+
+/// How command results are printed on stdout
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    /// Human-readable tables and summaries (default)
+    Text,
+    /// One JSON document per command invocation
+    Json,
+}
+
+static FORMAT: OnceLock<Format> = OnceLock::new();
+
+/// Select the output format for this process. Only the first call takes effect.
+pub fn set_format(format: Format) {
+    let _ = FORMAT.set(format);
+}
+
+pub fn format() -> Format {
+    FORMAT.get().copied().unwrap_or(Format::Text)
+}
+
+pub fn is_json() -> bool {
+    format() == Format::Json
+}
+
+/// Report a command's result: printed as JSON in JSON mode, otherwise rendered
+/// by `text`. Per-item lines that belong to the result (e.g. "Copied: a -> b")
+/// should be collected into `value` rather than printed as they happen.
+pub fn emit<T: Serialize>(value: &T, text: impl FnOnce(&T)) -> Result<()> {
+    match format() {
+        Format::Json => println!("{}", serde_json::to_string_pretty(value)?),
+        Format::Text => text(value),
+    }
+    Ok(())
+}
@@ -0,0 +1,157 @@
+use anyhow::Result;
+use rusqlite::params;
+use serde::Serialize;
+use std::path::Path;
+
+use crate::db::{Connection, Db};
+use crate::output;
+use crate::query::SourceQuery;
+use crate::util::current_timestamp;
+
+pub const POLICY_PINNED_KEY: &str = "policy.pinned";
+
+pub struct PinOptions {
+    pub dry_run: bool,
+    /// Only consider these sources (`--stdin`)
+    pub source_ids: Option<Vec<i64>>,
+}
+
+/// Result of `canon pin` / `canon unpin`
+#[derive(Serialize)]
+struct PinReport {
+    dry_run: bool,
+    count: usize,
+    /// Affected paths (listed for dry runs only)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    paths: Vec<String>,
+}
+
+/// Pin matching sources (in any root, excluded or not), protecting them from
+/// bulk operations that don't pass --include-pinned
+pub fn pin(db: &Db, scope_path: Option<&Path>, filter_strs: &[String], options: &PinOptions) -> Result<()> {
+    let conn = db.conn();
+    let to_pin: Vec<i64> = matching_sources(conn, scope_path, filter_strs, options)?
+        .into_iter()
+        .filter(|id| !is_pinned(conn, *id).unwrap_or(true))
+        .collect();
+
+    if to_pin.is_empty() || options.dry_run {
+        let report = PinReport { dry_run: options.dry_run, count: to_pin.len(), paths: source_paths(conn, &to_pin)? };
+        return output::emit(&report, |report| {
+            if report.count == 0 {
+                println!("No sources to pin (0 matching unpinned sources)");
+                return;
+            }
+            println!("Would pin {} sources:", report.count);
+            for path in &report.paths {
+                println!("  {}", path);
+            }
+        });
+    }
+
+    let now = current_timestamp();
+    for source_id in &to_pin {
+        conn.execute(
+            "INSERT INTO facts (entity_type, entity_id, key, value_text, observed_at, observed_basis_rev)
+             SELECT 'source', id, ?, 'true', ?, basis_rev FROM sources WHERE id = ?",
+            params![POLICY_PINNED_KEY, now, source_id],
+        )?;
+    }
+
+    let report = PinReport { dry_run: false, count: to_pin.len(), paths: Vec::new() };
+    output::emit(&report, |report| println!("Pinned {} sources", report.count))
+}
+
+/// Remove pins from matching sources
+pub fn unpin(db: &Db, scope_path: Option<&Path>, filter_strs: &[String], options: &PinOptions) -> Result<()> {
+    let conn = db.conn();
+    let pinned: Vec<i64> = matching_sources(conn, scope_path, filter_strs, options)?
+        .into_iter()
+        .filter(|id| is_pinned(conn, *id).unwrap_or(false))
+        .collect();
+
+    if pinned.is_empty() || options.dry_run {
+        let report = PinReport { dry_run: options.dry_run, count: pinned.len(), paths: source_paths(conn, &pinned)? };
+        return output::emit(&report, |report| {
+            if report.count == 0 {
+                println!("No pinned sources match the given filters");
+                return;
+            }
+            println!("Would unpin {} sources:", report.count);
+            for path in &report.paths {
+                println!("  {}", path);
+            }
+        });
+    }
+
+    for source_id in &pinned {
+        conn.execute(
+            "DELETE FROM facts WHERE entity_type = 'source' AND entity_id = ? AND key = ?",
+            params![source_id, POLICY_PINNED_KEY],
+        )?;
+    }
+
+    let report = PinReport { dry_run: false, count: pinned.len(), paths: Vec::new() };
+    output::emit(&report, |report| println!("Unpinned {} sources", report.count))
+}
+
+fn matching_sources(
+    conn: &Connection,
+    scope_path: Option<&Path>,
+    filter_strs: &[String],
+    options: &PinOptions,
+) -> Result<Vec<i64>> {
+    SourceQuery::new()
+        .scope(scope_path)?
+        .filters(conn, filter_strs)?
+        .include_archived(true)
+        .include_excluded(true)
+        .selection(options.source_ids.as_deref())
+        .ids(conn)
+}
+
+// ============================================================================
+// Helper Functions
+// ============================================================================
+
+/// Check if a source is pinned
+pub fn is_pinned(conn: &Connection, source_id: i64) -> Result<bool> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM facts WHERE entity_type = 'source' AND entity_id = ? AND key = ?)",
+        params![source_id, POLICY_PINNED_KEY],
+        |row| row.get(0),
+    )?;
+    Ok(exists)
+}
+
+/// Split source ids into unpinned ones and the number of pinned ones left out
+pub fn without_pinned(conn: &Connection, source_ids: Vec<i64>) -> Result<(Vec<i64>, usize)> {
+    let mut unpinned = Vec::with_capacity(source_ids.len());
+    let mut pinned = 0;
+    for id in source_ids {
+        if is_pinned(conn, id)? {
+            pinned += 1;
+        } else {
+            unpinned.push(id);
+        }
+    }
+    Ok((unpinned, pinned))
+}
+
+/// Tell the user pinned sources were left alone, and how to include them
+pub fn note_skipped(pinned: usize) {
+    if pinned > 0 {
+        eprintln!("Note: Skipped {} pinned sources (use --include-pinned to include them)", pinned);
+    }
+}
+
+fn source_paths(conn: &Connection, source_ids: &[i64]) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT r.path || '/' || s.rel_path FROM sources s JOIN roots r ON s.root_id = r.id WHERE s.id = ?",
+    )?;
+    let mut paths = Vec::with_capacity(source_ids.len());
+    for id in source_ids {
+        paths.push(stmt.query_row([id], |row| row.get(0))?);
+    }
+    Ok(paths)
+}
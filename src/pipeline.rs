@@ -0,0 +1,285 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use crate::apply::{self, ApplyOptions, TransferMode};
+use crate::cluster::{self, GenerateOptions};
+use crate::db::Db;
+use crate::error::{coded, ErrorCode};
+use crate::extract::{self, ExtractOptions, Extractor};
+use crate::import_facts;
+use crate::scan;
+use crate::worklist;
+
+/// A pipeline file: steps run in order, e.g.
+///
+/// ```toml
+/// [[step]]
+/// type = "scan"
+/// paths = ["/photos"]
+///
+/// [[step]]
+/// name = "hash"
+/// type = "process"
+/// where = ["!content.hash.sha256?"]
+/// command = ["scripts/hash-worklist.sh"]
+/// ```
+#[derive(Deserialize)]
+struct Pipeline {
+    #[serde(default)]
+    step: Vec<Step>,
+}
+
+#[derive(Deserialize)]
+struct Step {
+    /// Label for progress output (defaults to the step type)
+    name: Option<String>,
+    #[serde(default)]
+    on_failure: FailurePolicy,
+    #[serde(flatten)]
+    kind: StepKind,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum FailurePolicy {
+    /// Abort the pipeline (default)
+    #[default]
+    Stop,
+    /// Report the failure and run the remaining steps
+    Continue,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum StepKind {
+    /// `canon scan` of existing roots
+    Scan {
+        paths: Vec<PathBuf>,
+        #[serde(default = "default_role")]
+        role: String,
+        /// `canon scan --hash`
+        #[serde(default)]
+        hash: bool,
+    },
+    /// Feed a worklist to an external processor and import the facts it prints
+    /// (`canon worklist | command | canon import-facts`)
+    Process {
+        path: Option<PathBuf>,
+        #[serde(rename = "where", default)]
+        filters: Vec<String>,
+        command: Vec<String>,
+        #[serde(default)]
+        include_archived: bool,
+    },
+    /// `canon extract <extractor>`
+    Extract {
+        extractor: String,
+        path: Option<PathBuf>,
+        #[serde(rename = "where", default)]
+        filters: Vec<String>,
+        #[serde(default)]
+        jobs: usize,
+    },
+    /// `canon cluster generate`
+    Cluster {
+        #[serde(rename = "where")]
+        filters: Vec<String>,
+        dest: PathBuf,
+        #[serde(default = "default_manifest")]
+        output: PathBuf,
+    },
+    /// `canon apply`
+    Apply {
+        #[serde(default = "default_manifest")]
+        manifest: PathBuf,
+        /// "copy" (default), "rename" or "move"
+        #[serde(default = "default_mode")]
+        mode: String,
+    },
+}
+
+fn default_role() -> String {
+    "source".to_string()
+}
+
+fn default_manifest() -> PathBuf {
+    PathBuf::from("manifest.toml")
+}
+
+fn default_mode() -> String {
+    "copy".to_string()
+}
+
+impl StepKind {
+    fn type_name(&self) -> &'static str {
+        match self {
+            StepKind::Scan { .. } => "scan",
+            StepKind::Process { .. } => "process",
+            StepKind::Extract { .. } => "extract",
+            StepKind::Cluster { .. } => "cluster",
+            StepKind::Apply { .. } => "apply",
+        }
+    }
+}
+
+/// Run the steps of a pipeline file in order. Relative paths in the file
+/// (including a processor command containing `/`) are resolved against the
+/// file's directory, so the pipeline behaves the same from cron.
+pub fn run(db: &Db, pipeline_path: &Path) -> Result<()> {
+    let content = fs::read_to_string(pipeline_path)
+        .with_context(|| format!("Failed to read pipeline: {}", pipeline_path.display()))?;
+    let pipeline: Pipeline = toml::from_str(&content)
+        .with_context(|| format!("Failed to parse pipeline: {}", pipeline_path.display()))?;
+    if pipeline.step.is_empty() {
+        bail!("Pipeline {} has no [[step]] entries", pipeline_path.display());
+    }
+    let base = pipeline_path.parent().unwrap_or(Path::new("")).to_path_buf();
+
+    // Manifests written by cluster steps in this run. If clustering matched
+    // nothing, no manifest is written and the apply step has nothing to do.
+    let mut cleared_manifests: HashSet<PathBuf> = HashSet::new();
+    let mut failed: Vec<String> = Vec::new();
+    let total = pipeline.step.len();
+
+    for (i, step) in pipeline.step.iter().enumerate() {
+        let label = step.name.clone().unwrap_or_else(|| step.kind.type_name().to_string());
+        println!("==> [{}/{}] {}", i + 1, total, label);
+
+        if let Err(e) = run_step(db, &step.kind, &base, &mut cleared_manifests) {
+            eprintln!("Error: step '{}' failed: {:#}", label, e);
+            if step.on_failure == FailurePolicy::Stop {
+                bail!("Pipeline stopped at step {}/{} ('{}')", i + 1, total, label);
+            }
+            failed.push(label);
+        }
+    }
+
+    println!("Pipeline finished: {} ok, {} failed", total - failed.len(), failed.len());
+    if !failed.is_empty() {
+        return Err(coded(ErrorCode::PartialFailure, format!("Failed steps: {}", failed.join(", "))));
+    }
+    Ok(())
+}
+
+fn run_step(db: &Db, kind: &StepKind, base: &Path, cleared_manifests: &mut HashSet<PathBuf>) -> Result<()> {
+    let resolve = |path: &Path| base.join(path);
+
+    match kind {
+        StepKind::Scan { paths, role, hash } => {
+            let paths: Vec<PathBuf> = paths.iter().map(|p| resolve(p)).collect();
+            let options = scan::ScanOptions { hash: *hash, ..Default::default() };
+            scan::run(db, &paths, role, false, &options)
+        }
+        StepKind::Process { path, filters, command, include_archived } => {
+            let path = path.as_deref().map(resolve);
+            run_processor(db, path.as_deref(), filters, command, *include_archived, base)
+        }
+        StepKind::Extract { extractor, path, filters, jobs } => {
+            let path = path.as_deref().map(resolve);
+            let options = ExtractOptions {
+                jobs: *jobs,
+                include_archived: false,
+                include_excluded: false,
+            };
+            let extractor: Box<dyn Extractor> = match extractor.as_str() {
+                "exif" => Box::new(extract::exif::ExifExtractor),
+                "image" => Box::new(extract::image::ImageExtractor),
+                "kind" => Box::new(extract::kind::KindExtractor),
+                "mime" => Box::new(extract::mime::MimeExtractor),
+                "video" => Box::new(extract::video::VideoExtractor::new(Path::new("ffprobe"))?),
+                other => bail!("Unknown extractor '{}'. Must be 'exif', 'image', 'kind', 'mime' or 'video'", other),
+            };
+            extract::run(db, extractor.as_ref(), path.as_deref(), filters, &options)
+        }
+        StepKind::Cluster { filters, dest, output } => {
+            let output = resolve(output);
+            // Never leave a previous run's manifest behind for the apply step
+            if output.exists() {
+                fs::remove_file(&output)
+                    .with_context(|| format!("Failed to remove old manifest: {}", output.display()))?;
+            }
+            cleared_manifests.insert(output.clone());
+            let options = GenerateOptions {
+                include_archived: false,
+                show_archived: false,
+                source_ids: None,
+                burst: None,
+                sort_by_path: false,
+                path_order: Default::default(),
+            };
+            cluster::generate(db, filters, &resolve(dest), &output, &options)
+        }
+        StepKind::Apply { manifest, mode } => {
+            let manifest = resolve(manifest);
+            if !manifest.exists() && cleared_manifests.contains(&manifest) {
+                println!("Nothing to apply (no sources were clustered)");
+                return Ok(());
+            }
+            let transfer_mode = match mode.as_str() {
+                "copy" => TransferMode::Copy,
+                "rename" => TransferMode::Rename,
+                "move" => TransferMode::Move,
+                other => bail!("Unknown apply mode '{}'. Must be 'copy', 'rename' or 'move'", other),
+            };
+            let options = ApplyOptions {
+                dry_run: false,
+                allow_cross_archive_duplicates: false,
+                roots: Vec::new(),
+                transfer_mode,
+                include_pinned: false,
+                hash_missing: false,
+                download_verify: false,
+            };
+            apply::run(db, &manifest, &options)
+        }
+    }
+}
+
+/// Pipe the worklist through `command` and import the facts it prints
+fn run_processor(
+    db: &Db,
+    scope_path: Option<&Path>,
+    filters: &[String],
+    command: &[String],
+    include_archived: bool,
+    base: &Path,
+) -> Result<()> {
+    let Some(program) = command.first() else {
+        bail!("process step needs a command");
+    };
+    let program = if program.contains('/') { base.join(program) } else { PathBuf::from(program) };
+
+    // The worklist is built up front: the database can't be shared with the
+    // thread feeding the processor while facts are imported
+    let mut worklist = Vec::new();
+    let options = worklist::WorklistOptions { include_archived, ..Default::default() };
+    worklist::write_to(db, scope_path, filters, &options, &mut worklist)?;
+
+    let mut child = Command::new(&program)
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to execute: {}", program.display()))?;
+
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let feeder = thread::spawn(move || stdin.write_all(&worklist));
+
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let imported = import_facts::import_from(db, BufReader::new(stdout), include_archived);
+
+    let status = child.wait()?;
+    // A processor that exits early closes its stdin; that is its own failure to report
+    feeder.join().expect("worklist writer panicked").ok();
+    imported?;
+    if !status.success() {
+        bail!("{} exited with {}", program.display(), status);
+    }
+    Ok(())
+}
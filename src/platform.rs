@@ -0,0 +1,206 @@
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+use std::fs::Metadata;
+use std::io;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// What identifies a file across renames, and how many names it has
+#[derive(Clone, Copy)]
+pub struct FileId {
+    /// st_dev on Unix, the volume serial number on Windows
+    pub device: i64,
+    /// st_ino on Unix, the file index on Windows
+    pub inode: i64,
+    /// Hard links to the file, counting this one
+    pub links: i64,
+}
+
+impl FileId {
+    /// (device, inode) as the sources table stores them
+    pub fn device_inode(self) -> (i64, i64) {
+        (self.device, self.inode)
+    }
+}
+
+/// The file's identity, or None where the platform has none or the file
+/// can't be opened to ask
+#[cfg(unix)]
+pub fn file_id(_path: &Path, metadata: &Metadata) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some(FileId { device: metadata.dev() as i64, inode: metadata.ino() as i64, links: metadata.nlink() as i64 })
+}
+
+#[cfg(windows)]
+pub fn file_id(path: &Path, _metadata: &Metadata) -> Option<FileId> {
+    windows::file_id(path)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn file_id(_path: &Path, _metadata: &Metadata) -> Option<FileId> {
+    None
+}
+
+/// Modification time in whole seconds since the epoch, as the index stores it
+#[cfg(unix)]
+pub fn mtime(metadata: &Metadata) -> i64 {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.mtime()
+}
+
+#[cfg(not(unix))]
+pub fn mtime(metadata: &Metadata) -> i64 {
+    mtime_ns(metadata).div_euclid(1_000_000_000)
+}
+
+/// Modification time in nanoseconds since the epoch
+#[cfg(unix)]
+pub fn mtime_ns(metadata: &Metadata) -> i64 {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.mtime() * 1_000_000_000 + metadata.mtime_nsec()
+}
+
+#[cfg(not(unix))]
+pub fn mtime_ns(metadata: &Metadata) -> i64 {
+    match metadata.modified() {
+        Ok(time) => match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => since.as_nanos() as i64,
+            Err(e) => -(e.duration().as_nanos() as i64),
+        },
+        Err(_) => 0,
+    }
+}
+
+/// An identifier of the volume (filesystem) holding `path` that survives
+/// unmounting and remounting: the filesystem UUID on Linux (for filesystems
+/// listed in /dev/disk/by-uuid) and macOS (`path` should be the mount point),
+/// the volume serial number on Windows. None where there is none to read.
+#[cfg(target_os = "linux")]
+pub fn volume_id(path: &Path) -> Option<String> {
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+    let device = std::fs::metadata(path).ok()?.dev();
+    std::fs::read_dir("/dev/disk/by-uuid").ok()?.flatten().find_map(|entry| {
+        let metadata = std::fs::metadata(entry.path()).ok()?;
+        (metadata.file_type().is_block_device() && metadata.rdev() == device)
+            .then(|| entry.file_name().to_string_lossy().into_owned())
+    })
+}
+
+#[cfg(target_os = "macos")]
+pub fn volume_id(path: &Path) -> Option<String> {
+    let output = std::process::Command::new("diskutil").arg("info").arg("-plist").arg(path).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let plist = String::from_utf8_lossy(&output.stdout);
+    let after_key = plist.split("<key>VolumeUUID</key>").nth(1)?;
+    let value = after_key.trim_start().strip_prefix("<string>")?.split("</string>").next()?;
+    Some(value.to_string())
+}
+
+#[cfg(windows)]
+pub fn volume_id(path: &Path) -> Option<String> {
+    windows::file_id(path).map(|file_id| format!("{:08X}", file_id.device as u32))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn volume_id(_path: &Path) -> Option<String> {
+    None
+}
+
+/// Creation (birth) time in whole seconds since the epoch, where the platform
+/// and filesystem record one (statx on Linux, st_birthtime on macOS and BSD,
+/// the creation time on Windows)
+pub fn btime(metadata: &Metadata) -> Option<i64> {
+    let created = metadata.created().ok()?;
+    Some(match created.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs_f64().ceil() as i64),
+    })
+}
+
+/// Whether a rename failed only because source and destination are on
+/// different filesystems (EXDEV, ERROR_NOT_SAME_DEVICE), so copying and
+/// deleting would work
+pub fn is_cross_device(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::CrossesDevices
+}
+
+/// A path relative to a root as the index stores it: UTF-8, with '/'
+/// separators on every platform
+pub fn rel_path_str(rel_path: &Path) -> Result<Cow<'_, str>> {
+    let rel_path = rel_path.to_str().context("Path is not valid UTF-8")?;
+    if cfg!(windows) && rel_path.contains('\\') {
+        Ok(Cow::Owned(rel_path.replace('\\', "/")))
+    } else {
+        Ok(Cow::Borrowed(rel_path))
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::c_void;
+    use std::fs::OpenOptions;
+    use std::mem::MaybeUninit;
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::AsRawHandle;
+    use std::path::Path;
+
+    use super::FileId;
+
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct FileTime {
+        low: u32,
+        high: u32,
+    }
+
+    /// BY_HANDLE_FILE_INFORMATION
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct FileInformation {
+        attributes: u32,
+        creation_time: FileTime,
+        last_access_time: FileTime,
+        last_write_time: FileTime,
+        volume_serial_number: u32,
+        size_high: u32,
+        size_low: u32,
+        number_of_links: u32,
+        index_high: u32,
+        index_low: u32,
+    }
+
+    /// FILE_READ_ATTRIBUTES: all GetFileInformationByHandle needs
+    const READ_ATTRIBUTES: u32 = 0x80;
+    /// FILE_FLAG_BACKUP_SEMANTICS: lets directories be opened too
+    const BACKUP_SEMANTICS: u32 = 0x0200_0000;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetFileInformationByHandle(file: *mut c_void, info: *mut FileInformation) -> i32;
+    }
+
+    pub fn file_id(path: &Path) -> Option<FileId> {
+        let file = OpenOptions::new()
+            .access_mode(READ_ATTRIBUTES)
+            .custom_flags(BACKUP_SEMANTICS)
+            .open(path)
+            .ok()?;
+        let mut info = MaybeUninit::<FileInformation>::zeroed();
+        if unsafe { GetFileInformationByHandle(file.as_raw_handle(), info.as_mut_ptr()) } == 0 {
+            return None;
+        }
+        let info = unsafe { info.assume_init() };
+        let index = ((info.index_high as u64) << 32) | info.index_low as u64;
+        Some(FileId {
+            device: info.volume_serial_number as i64,
+            inode: index as i64,
+            links: info.number_of_links as i64,
+        })
+    }
+}
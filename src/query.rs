@@ -0,0 +1,326 @@
+use anyhow::Result;
+use rusqlite::{params, OptionalExtension, Row};
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::Path;
+
+use crate::db::{self, Connection};
+use crate::exclude;
+use crate::filter::{self, Filter};
+use crate::query_cache;
+use crate::remote;
+
+const BATCH_SIZE: i64 = 1000;
+
+/// How excluded sources (`policy.exclude`) are treated
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Exclusion {
+    /// Leave excluded sources out (default)
+    Skip,
+    /// Match excluded sources like any other
+    Include,
+    /// Match only excluded sources
+    Only,
+}
+
+/// Selects present sources by root role, path scope, exclusion and filter
+/// expressions, walking them in id order in batches. Commands build one of
+/// these instead of writing their own batching loop so scope and exclusion
+/// behave the same everywhere.
+#[derive(Clone)]
+pub struct SourceQuery {
+    scope_prefix: Option<String>,
+    include_archived: bool,
+    exclusion: Exclusion,
+    root_id: Option<i64>,
+    selection: Option<HashSet<i64>>,
+    filters: Vec<Filter>,
+}
+
+impl Default for SourceQuery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SourceQuery {
+    /// Present, non-excluded sources in source roots
+    pub fn new() -> Self {
+        SourceQuery {
+            scope_prefix: None,
+            include_archived: false,
+            exclusion: Exclusion::Skip,
+            root_id: None,
+            selection: None,
+            filters: Vec::new(),
+        }
+    }
+
+    /// Limit to sources under `path` (resolved to its realpath)
+    pub fn scope(mut self, path: Option<&Path>) -> Result<Self> {
+        self.scope_prefix = match path {
+            Some(p) => Some(std::fs::canonicalize(p)?.to_string_lossy().to_string()),
+            None => None,
+        };
+        Ok(self)
+    }
+
+    /// Parse and add filter expressions (all must match)
+    pub fn filters(mut self, conn: &Connection, filter_strs: &[String]) -> Result<Self> {
+        for f in filter_strs {
+            self.filters.push(Filter::parse(conn, f)?);
+        }
+        Ok(self)
+    }
+
+    /// Also match sources in archive roots
+    pub fn include_archived(mut self, include: bool) -> Self {
+        self.include_archived = include;
+        self
+    }
+
+    /// Shorthand for `exclusion(Exclusion::Include)` when `include` is set
+    pub fn include_excluded(self, include: bool) -> Self {
+        self.exclusion(if include { Exclusion::Include } else { Exclusion::Skip })
+    }
+
+    pub fn exclusion(mut self, exclusion: Exclusion) -> Self {
+        self.exclusion = exclusion;
+        self
+    }
+
+    /// Limit to one root (any role)
+    pub fn root(mut self, root_id: i64) -> Self {
+        self.root_id = Some(root_id);
+        self.include_archived = true;
+        self
+    }
+
+    /// Limit to the given source ids (e.g. from `read_source_ids`), if any
+    pub fn selection(mut self, source_ids: Option<&[i64]>) -> Self {
+        self.selection = source_ids.map(|ids| ids.iter().copied().collect());
+        self
+    }
+
+    /// The resolved scope path, if any
+    pub fn scope_prefix(&self) -> Option<&str> {
+        self.scope_prefix.as_deref()
+    }
+
+    /// Ids of all matching sources, in id order
+    pub fn ids(&self, conn: &Connection) -> Result<Vec<i64>> {
+        let mut ids = Vec::new();
+        self.for_each_batch(conn, "s.id", |row| row.get::<_, i64>(0), |batch| {
+            ids.extend(batch);
+            Ok(())
+        })?;
+        Ok(ids)
+    }
+
+    /// Call `f` with each batch of matching rows. `columns` is the SELECT list
+    /// over `sources s JOIN roots r` and must start with `s.id`.
+    pub fn for_each_batch<T, M, F>(&self, conn: &Connection, columns: &str, mut map: M, mut f: F) -> Result<()>
+    where
+        M: FnMut(&Row) -> rusqlite::Result<T>,
+        F: FnMut(Vec<T>) -> Result<()>,
+    {
+        self.refresh_availability(conn)?;
+        // Only complete runs over the query's own sources are worth caching
+        let cache_key = (query_cache::enabled() && !self.filters.is_empty() && self.selection.is_none())
+            .then(|| self.cache_key());
+        let Some(cache_key) = cache_key else {
+            return self.raw_batches(conn, columns, |row| Ok((row.get::<_, i64>(0)?, map(row)?)), |batch| {
+                let ids: Vec<i64> = batch.iter().map(|(id, _)| *id).collect();
+                let kept = self.matches(conn, &ids)?;
+                f(batch.into_iter().filter(|(id, _)| kept.contains(id)).map(|(_, row)| row).collect())
+            });
+        };
+
+        let state = query_cache::state(conn)?;
+        let cached = query_cache::lookup(conn, &cache_key, state)?;
+        let mut matched = Vec::new();
+        self.raw_batches(conn, columns, |row| Ok((row.get::<_, i64>(0)?, map(row)?)), |batch| {
+            let kept = match cached {
+                Some(ref cached) => batch.iter().map(|(id, _)| *id).filter(|id| cached.contains(id)).collect(),
+                None => {
+                    let ids: Vec<i64> = batch.iter().map(|(id, _)| *id).collect();
+                    let kept = self.matches(conn, &ids)?;
+                    matched.extend(ids.iter().filter(|id| kept.contains(id)));
+                    kept
+                }
+            };
+            f(batch.into_iter().filter(|(id, _)| kept.contains(id)).map(|(_, row)| row).collect())
+        })?;
+
+        // Whatever `f` changed bumped the generation past `state`, so its own
+        // writes can't leave a stale entry behind
+        if cached.is_none() {
+            if let Err(e) = query_cache::store(conn, &cache_key, state, &matched) {
+                eprintln!("Warning: Failed to cache query results: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Identifies the query's result in the cache: the parsed filters (so
+    /// spacing and quoting don't matter) and everything that picks candidates
+    fn cache_key(&self) -> String {
+        format!(
+            "filters={:?} scope={:?} archived={} exclusion={:?} root={:?}",
+            self.filters, self.scope_prefix, self.include_archived, self.exclusion, self.root_id
+        )
+    }
+
+    /// Like `for_each_batch`, but without evaluating the filter expressions, for
+    /// callers that run a cheaper check first and then call `matches`
+    pub fn for_each_raw_batch<T, M, F>(&self, conn: &Connection, columns: &str, map: M, f: F) -> Result<()>
+    where
+        M: FnMut(&Row) -> rusqlite::Result<(i64, T)>,
+        F: FnMut(Vec<(i64, T)>) -> Result<()>,
+    {
+        self.refresh_availability(conn)?;
+        self.raw_batches(conn, columns, map, f)
+    }
+
+    /// Filters on `archived.online` read the stored root availability, which
+    /// isn't refreshed on open
+    fn refresh_availability(&self, conn: &Connection) -> Result<()> {
+        if filter::uses_availability(&self.filters) {
+            db::refresh_root_availability(conn)?;
+        }
+        Ok(())
+    }
+
+    fn raw_batches<T, M, F>(&self, conn: &Connection, columns: &str, mut map: M, mut f: F) -> Result<()>
+    where
+        M: FnMut(&Row) -> rusqlite::Result<(i64, T)>,
+        F: FnMut(Vec<(i64, T)>) -> Result<()>,
+    {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM sources s
+             JOIN roots r ON s.root_id = r.id
+             WHERE {}{} AND s.id > ?1
+             ORDER BY s.id
+             LIMIT ?4",
+            columns,
+            self.where_clause(),
+            self.filter_clause()
+        ))?;
+
+        let mut last_id: i64 = 0;
+        loop {
+            let batch: Vec<(i64, T)> = stmt
+                .query_map(params![last_id, self.scope_prefix, self.root_id, BATCH_SIZE], &mut map)?
+                .collect::<Result<Vec<_>, _>>()?;
+            let Some(&(max_id, _)) = batch.last() else {
+                break;
+            };
+            last_id = max_id;
+            match self.selection {
+                Some(ref selected) => f(batch.into_iter().filter(|(id, _)| selected.contains(id)).collect())?,
+                None => f(batch)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// The subset of `source_ids` that satisfies the filter expressions
+    pub fn matches(&self, conn: &Connection, source_ids: &[i64]) -> Result<HashSet<i64>> {
+        if self.filters.is_empty() {
+            return Ok(source_ids.iter().copied().collect());
+        }
+        Ok(filter::apply_filters(conn, source_ids, &self.filters)?.into_iter().collect())
+    }
+
+    /// Number of excluded sources within the same roots and scope (filters are
+    /// not evaluated), for "N excluded hidden" notes
+    pub fn count_excluded(&self, conn: &Connection) -> Result<i64> {
+        let query = self.clone().exclusion(Exclusion::Only);
+        // ?1 (pagination) is unused here but still bound
+        let count = conn.query_row(
+            &format!(
+                "SELECT COUNT(*) FROM sources s
+                 JOIN roots r ON s.root_id = r.id
+                 WHERE {}",
+                query.where_clause()
+            ),
+            params![0, query.scope_prefix, query.root_id],
+            |row| row.get(0),
+        )?;
+        Ok(count)
+    }
+
+    /// Conditions the filter expressions push down to SQL, each prefixed with AND
+    fn filter_clause(&self) -> String {
+        filter::sql_prefilter(&self.filters).iter().map(|c| format!(" AND {}", c)).collect()
+    }
+
+    /// Conditions on `s`/`r`; binds ?2 (scope prefix) and ?3 (root id), either NULL
+    fn where_clause(&self) -> String {
+        let role_clause = if self.include_archived {
+            "1=1" // Include all roles
+        } else {
+            "r.role = 'source'"
+        };
+        let exclude_clause = match self.exclusion {
+            Exclusion::Skip => exclude::exclude_clause(false).to_string(),
+            Exclusion::Include => exclude::exclude_clause(true).to_string(),
+            Exclusion::Only => format!("NOT ({})", exclude::exclude_clause(false)),
+        };
+        // Exact prefix comparison: LIKE would treat % and _ in paths as wildcards
+        format!(
+            "s.present = 1 AND {} AND {}
+               AND (?2 IS NULL OR substr(r.path || '/' || s.rel_path, 1, length(?2) + 1) = ?2 || '/')
+               AND (?3 IS NULL OR s.root_id = ?3)",
+            role_clause, exclude_clause
+        )
+    }
+}
+
+/// Read a source selection from `input`: source ids or file paths, one per line,
+/// or NUL-separated when the input contains a NUL (`find -print0`, `xargs -0`).
+/// Entries made only of digits are ids; anything else is resolved as a path
+/// (relative to the current directory). Paths that are not indexed sources are
+/// skipped with a warning.
+pub fn read_source_ids(conn: &Connection, mut input: impl Read) -> Result<Vec<i64>> {
+    let mut buf = String::new();
+    input.read_to_string(&mut buf)?;
+    let separator = if buf.contains('\0') { '\0' } else { '\n' };
+
+    let mut ids = Vec::new();
+    for entry in buf.split(separator) {
+        let entry = entry.strip_suffix('\r').unwrap_or(entry);
+        if entry.is_empty() {
+            continue;
+        }
+        if entry.bytes().all(|b| b.is_ascii_digit()) {
+            ids.push(entry.parse()?);
+            continue;
+        }
+        match source_id_for_path(conn, Path::new(entry))? {
+            Some(id) => ids.push(id),
+            None => eprintln!("Warning: Not an indexed source: {}", entry),
+        }
+    }
+    Ok(ids)
+}
+
+fn source_id_for_path(conn: &Connection, path: &Path) -> Result<Option<i64>> {
+    // Made absolute so a file that is gone is still matched by its indexed path
+    let path = if path.to_str().is_some_and(remote::is_remote) {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir()?.join(path)
+    };
+    let Some((root_id, _, _, rel_path)) = db::resolve_root_path(conn, &path)? else {
+        return Ok(None);
+    };
+    let id = conn
+        .query_row(
+            "SELECT id FROM sources WHERE root_id = ? AND rel_path = ? AND present = 1",
+            params![root_id, rel_path],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(id)
+}
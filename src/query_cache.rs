@@ -0,0 +1,90 @@
+use anyhow::Result;
+use rusqlite::{params, OptionalExtension};
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+use crate::db::Connection;
+use crate::util::current_timestamp;
+
+/// Environment variable that enables the cache like `--query-cache` does
+pub const CACHE_ENV: &str = "CANON_QUERY_CACHE";
+
+static ENABLED: OnceLock<bool> = OnceLock::new();
+
+/// Turn the cache on or off for this process. Only the first call takes effect.
+pub fn set_enabled(enabled: bool) {
+    let _ = ENABLED.set(enabled);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.get().copied().unwrap_or(false)
+}
+
+/// What a cached result is valid for: the index as of a generation (bumped by
+/// triggers on fact, source and root changes) and a highest source id (new
+/// sources don't bump the generation)
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) struct CacheState {
+    generation: i64,
+    max_source_id: i64,
+}
+
+pub(crate) fn state(conn: &Connection) -> Result<CacheState> {
+    Ok(conn.query_row(
+        "SELECT (SELECT generation FROM query_generation WHERE id = 1),
+                (SELECT COALESCE(MAX(id), 0) FROM sources)",
+        [],
+        |row| Ok(CacheState { generation: row.get(0)?, max_source_id: row.get(1)? }),
+    )?)
+}
+
+/// Matched source ids stored for `key`, if they are still valid in `current`
+pub(crate) fn lookup(conn: &Connection, key: &str, current: CacheState) -> Result<Option<HashSet<i64>>> {
+    let entry: Option<(i64, i64, i64)> = conn
+        .query_row(
+            "SELECT id, generation, max_source_id FROM query_cache WHERE key = ?",
+            [key],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?;
+    let Some((cache_id, generation, max_source_id)) = entry else {
+        return Ok(None);
+    };
+    if (CacheState { generation, max_source_id }) != current {
+        return Ok(None);
+    }
+
+    let mut stmt = conn.prepare("SELECT source_id FROM query_cache_matches WHERE cache_id = ?")?;
+    let ids = stmt.query_map([cache_id], |row| row.get(0))?.collect::<Result<_, _>>()?;
+    Ok(Some(ids))
+}
+
+/// Store the matched ids of a complete run of `key`, evaluated in `state`.
+/// Entries from older generations can never match again and are dropped.
+pub(crate) fn store(conn: &Connection, key: &str, state: CacheState, matched: &[i64]) -> Result<()> {
+    // A savepoint rather than a transaction: callers may already be in one
+    conn.execute_batch("SAVEPOINT query_cache")?;
+    let result = (|| -> Result<()> {
+        conn.execute(
+            "DELETE FROM query_cache_matches WHERE cache_id IN
+                (SELECT id FROM query_cache WHERE key = ?1 OR generation != ?2)",
+            params![key, state.generation],
+        )?;
+        conn.execute("DELETE FROM query_cache WHERE key = ?1 OR generation != ?2", params![key, state.generation])?;
+        conn.execute(
+            "INSERT INTO query_cache (key, generation, max_source_id, created_at) VALUES (?, ?, ?, ?)",
+            params![key, state.generation, state.max_source_id, current_timestamp()],
+        )?;
+        let cache_id = conn.last_insert_rowid();
+        let mut stmt = conn.prepare("INSERT INTO query_cache_matches (cache_id, source_id) VALUES (?, ?)")?;
+        for id in matched {
+            stmt.execute(params![cache_id, id])?;
+        }
+        Ok(())
+    })();
+    match result {
+        Ok(()) => conn.execute_batch("RELEASE query_cache")?,
+        Err(_) => conn.execute_batch("ROLLBACK TO query_cache; RELEASE query_cache")?,
+    }
+    result
+}
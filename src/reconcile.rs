@@ -0,0 +1,223 @@
+use anyhow::{Context, Result};
+use rusqlite::params;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use crate::db::{parse_root_spec, root_available, Connection, Db};
+use crate::error::{coded, ErrorCode};
+use crate::output;
+use crate::platform;
+use crate::remote;
+use crate::roots::{self, ScanPolicy};
+use crate::scan::{self, ScanStats};
+use crate::util::current_timestamp;
+
+/// Fact set on drifted sources by `reconcile --flag`
+pub const RECONCILE_STATUS_KEY: &str = "reconcile.status";
+
+/// Paths listed per category in text output (JSON lists all)
+const EXAMPLES: usize = 20;
+
+/// What to do about differences besides reporting them
+#[derive(Clone, Copy, PartialEq)]
+pub enum ReconcileAction {
+    Report,
+    /// Scan the root and unlink modified files from their objects
+    Rescan,
+    /// Record `reconcile.status` facts on modified and deleted sources
+    Flag,
+}
+
+/// A file whose size or mtime no longer matches the index
+#[derive(Serialize)]
+struct ModifiedFile {
+    path: String,
+    indexed_size: i64,
+    size: i64,
+    indexed_mtime: i64,
+    mtime: i64,
+}
+
+/// Result of `canon reconcile`
+#[derive(Serialize)]
+struct ReconcileReport {
+    root: String,
+    /// Files on disk the index doesn't know (or has marked missing)
+    strays: Vec<String>,
+    /// Indexed files that are gone from disk
+    deleted: Vec<String>,
+    modified: Vec<ModifiedFile>,
+    unchanged: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rescan: Option<ScanStats>,
+    /// Sources given a `reconcile.status` fact
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flagged: Option<usize>,
+}
+
+/// Compare the index's view of an archive root with what is on disk, then
+/// optionally bring the index up to date or flag the drifted sources
+pub fn run(db: &Db, archive_spec: &str, action: ReconcileAction) -> Result<()> {
+    let conn = db.conn();
+    let (root_id, root_path) = local_archive_root(conn, archive_spec)?;
+
+    // (id, size, mtime, present) of every source the index has for this root
+    let mut indexed: HashMap<String, (i64, i64, i64, bool)> = HashMap::new();
+    let mut stmt = conn.prepare("SELECT rel_path, id, size, mtime, present FROM sources WHERE root_id = ?")?;
+    for row in stmt.query_map([root_id], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))))? {
+        let (rel_path, source): (String, _) = row?;
+        indexed.insert(rel_path, source);
+    }
+
+    let mut report = ReconcileReport {
+        root: root_path.clone(),
+        strays: Vec::new(),
+        deleted: Vec::new(),
+        modified: Vec::new(),
+        unchanged: 0,
+        rescan: None,
+        flagged: None,
+    };
+    let mut modified_ids = Vec::new();
+    let mut seen = HashSet::new();
+
+    let root = Path::new(&root_path);
+    let policy = ScanPolicy::load(conn, root_id)?;
+    scan::walk_files(root, root, &policy, 1, |path, metadata| {
+        let rel_path = platform::rel_path_str(path.strip_prefix(root).context("Failed to strip root prefix")?)?;
+        let rel_path = &*rel_path;
+
+        match indexed.get(rel_path) {
+            Some(&(id, size, mtime, true)) => {
+                seen.insert(id);
+                if metadata.len() as i64 != size || platform::mtime(&metadata) != mtime {
+                    modified_ids.push(id);
+                    report.modified.push(ModifiedFile {
+                        path: rel_path.to_string(),
+                        indexed_size: size,
+                        size: metadata.len() as i64,
+                        indexed_mtime: mtime,
+                        mtime: platform::mtime(&metadata),
+                    });
+                } else {
+                    report.unchanged += 1;
+                }
+            }
+            _ => report.strays.push(rel_path.to_string()),
+        }
+        Ok(())
+    })?;
+
+    let mut deleted_ids = Vec::new();
+    for (rel_path, &(id, _, _, present)) in &indexed {
+        if present && !seen.contains(&id) && !policy.skips(rel_path) {
+            deleted_ids.push(id);
+            report.deleted.push(rel_path.clone());
+        }
+    }
+    report.strays.sort();
+    report.deleted.sort();
+    report.modified.sort_by(|a, b| a.path.cmp(&b.path));
+
+    match action {
+        ReconcileAction::Report => {}
+        ReconcileAction::Rescan => {
+            // Changed content must not keep counting as an archived copy of the old object
+            for id in &modified_ids {
+                conn.execute("UPDATE sources SET object_id = NULL WHERE id = ?", [id])?;
+            }
+            report.rescan = Some(scan::scan_root(conn, root_id, root, None, &scan::ScanOptions::default(), current_timestamp())?);
+        }
+        ReconcileAction::Flag => {
+            report.flagged = Some(flag(conn, root_id, &modified_ids, &deleted_ids)?);
+        }
+    }
+
+    output::emit(&report, print_report)
+}
+
+/// Resolve an archive root whose files can be walked: local and mounted.
+/// Returns (root id, root path).
+pub(crate) fn local_archive_root(conn: &Connection, archive_spec: &str) -> Result<(i64, String)> {
+    let root_id = parse_root_spec(conn, archive_spec, Some("archive"))?;
+    let (root_path, mount_point): (String, Option<String>) = conn.query_row(
+        "SELECT path, mount_point FROM roots WHERE id = ?",
+        [root_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    if remote::is_remote(&root_path) {
+        return Err(coded(
+            ErrorCode::Usage,
+            format!("{} is a remote root; use `canon scan` and `canon verify` for remotes", root_path),
+        ));
+    }
+    let offline = roots::setting(conn, root_id, "offline")?.as_deref() == Some("on");
+    if offline || !root_available(&root_path, mount_point.as_deref()) {
+        return Err(coded(ErrorCode::RootUnavailable, format!("Root '{}' is unavailable (not mounted?)", root_path)));
+    }
+    Ok((root_id, root_path))
+}
+
+/// Replace the root's `reconcile.status` facts with the current differences
+fn flag(conn: &Connection, root_id: i64, modified_ids: &[i64], deleted_ids: &[i64]) -> Result<usize> {
+    conn.execute(
+        "DELETE FROM facts WHERE entity_type = 'source' AND key = ?
+         AND entity_id IN (SELECT id FROM sources WHERE root_id = ?)",
+        params![RECONCILE_STATUS_KEY, root_id],
+    )?;
+    let now = current_timestamp();
+    let mut stmt = conn.prepare(
+        "INSERT INTO facts (entity_type, entity_id, key, value_text, observed_at, observed_basis_rev)
+         SELECT 'source', id, ?, ?, ?, basis_rev FROM sources WHERE id = ?",
+    )?;
+    for (status, ids) in [("modified", modified_ids), ("deleted", deleted_ids)] {
+        for id in ids {
+            stmt.execute(params![RECONCILE_STATUS_KEY, status, now, id])?;
+        }
+    }
+    Ok(modified_ids.len() + deleted_ids.len())
+}
+
+fn print_report(report: &ReconcileReport) {
+    println!("Reconciled {}", report.root);
+    print_paths("Strays (on disk, not indexed)", &report.strays);
+    print_paths("Deleted (indexed, gone from disk)", &report.deleted);
+    let modified: Vec<String> = report
+        .modified
+        .iter()
+        .map(|m| format!("{} (size {} -> {}, mtime {} -> {})", m.path, m.indexed_size, m.size, m.indexed_mtime, m.mtime))
+        .collect();
+    print_paths("Modified (size or mtime changed)", &modified);
+    println!("{} unchanged", report.unchanged);
+
+    if let Some(ref stats) = report.rescan {
+        println!(
+            "\nRescanned: {} new, {} updated, {} moved, {} missing",
+            stats.new, stats.updated, stats.moved, stats.missing
+        );
+        if !report.modified.is_empty() {
+            println!(
+                "Modified files were unlinked from their objects; re-hash them (canon ls {} --unhashed --include-archived)",
+                report.root
+            );
+        }
+    } else if let Some(flagged) = report.flagged {
+        println!("\nFlagged {} sources with {} (modified or deleted)", flagged, RECONCILE_STATUS_KEY);
+    } else if !report.strays.is_empty() || !report.deleted.is_empty() || !report.modified.is_empty() {
+        println!("\nUse --rescan to update the index, or --flag to mark the differences as facts");
+    }
+}
+
+fn print_paths(title: &str, paths: &[String]) {
+    if paths.is_empty() {
+        return;
+    }
+    println!("\n{} ({}):", title, paths.len());
+    for path in paths.iter().take(EXAMPLES) {
+        println!("  {}", path);
+    }
+    if paths.len() > EXAMPLES {
+        println!("  ... and {} more", paths.len() - EXAMPLES);
+    }
+}
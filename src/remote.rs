@@ -0,0 +1,204 @@
+use anyhow::{bail, Context, Result};
+use rusqlite::params;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::db::Connection;
+
+/// Environment variable naming the rclone executable (default: `rclone` on PATH)
+pub const RCLONE_ENV: &str = "CANON_RCLONE";
+
+/// A file listed on a remote, relative to the listed directory
+pub struct RemoteFile {
+    pub path: String,
+    pub size: i64,
+    pub mtime: i64,
+    /// Native SHA-256 from the backend, when it provides one
+    pub sha256: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct LsJsonEntry {
+    path: String,
+    size: i64,
+    mod_time: String,
+    #[serde(default)]
+    is_dir: bool,
+    #[serde(default)]
+    hashes: HashMap<String, String>,
+}
+
+/// True for rclone paths (`remote:path`: remote name, colon, path on the remote).
+/// Local roots are always absolute, and single-letter prefixes are left alone so
+/// Windows drive letters never match.
+pub fn is_remote(path: &str) -> bool {
+    match path.split_once(':') {
+        Some((name, _)) => name.len() > 1 && !name.contains('/') && !name.contains('\\'),
+        None => false,
+    }
+}
+
+/// Normalize a remote path for storage/comparison (no trailing slash)
+pub fn normalize(path: &str) -> String {
+    path.trim_end_matches('/').to_string()
+}
+
+/// Join a remote directory and a relative path
+pub fn join(base: &str, rel: &str) -> String {
+    if rel.is_empty() {
+        base.to_string()
+    } else if base.ends_with(':') || base.ends_with('/') {
+        format!("{}{}", base, rel)
+    } else {
+        format!("{}/{}", base, rel)
+    }
+}
+
+/// Link a source to the object with the given SHA-256, creating the object if needed
+pub(crate) fn link_object(conn: &Connection, source_id: i64, sha256: &str) -> Result<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO objects (hash_type, hash_value) VALUES ('sha256', ?)",
+        [sha256],
+    )?;
+    conn.execute(
+        "UPDATE sources SET object_id =
+             (SELECT id FROM objects WHERE hash_type = 'sha256' AND hash_value = ?1)
+         WHERE id = ?2",
+        params![sha256, source_id],
+    )?;
+    Ok(())
+}
+
+pub struct Rclone {
+    bin: PathBuf,
+}
+
+impl Rclone {
+    /// Locate rclone (`$CANON_RCLONE` or `rclone`) and check that it runs
+    pub fn new() -> Result<Self> {
+        let bin = env::var_os(RCLONE_ENV)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("rclone"));
+        let status = Command::new(&bin)
+            .arg("version")
+            .output()
+            .with_context(|| {
+                format!(
+                    "Failed to run '{}'. Install rclone or set {} to its path",
+                    bin.display(),
+                    RCLONE_ENV
+                )
+            })?
+            .status;
+        if !status.success() {
+            bail!("'{} version' failed ({})", bin.display(), status);
+        }
+        Ok(Rclone { bin })
+    }
+
+    /// Recursively list files under `remote` (`rclone lsjson -R --files-only --hash`)
+    pub fn list(&self, remote: &str) -> Result<Vec<RemoteFile>> {
+        let stdout = self.run(&["lsjson", "-R", "--files-only", "--hash", "--hash-type", "sha256", remote])?;
+        let entries: Vec<LsJsonEntry> = serde_json::from_slice(&stdout)
+            .with_context(|| format!("Failed to parse rclone lsjson output for {}", remote))?;
+        entries
+            .into_iter()
+            .filter(|e| !e.is_dir)
+            .map(|e| to_remote_file(e, remote))
+            .collect()
+    }
+
+    /// Stat a single remote file; None when it does not exist
+    pub fn stat(&self, remote: &str) -> Result<Option<RemoteFile>> {
+        let output = Command::new(&self.bin)
+            .args(["lsjson", "--stat", "--hash", "--hash-type", "sha256", remote])
+            .output()
+            .with_context(|| format!("Failed to run {}", self.bin.display()))?;
+        if !output.status.success() {
+            // rclone exits 3 (directory not found) or 4 (file not found)
+            if matches!(output.status.code(), Some(3) | Some(4)) {
+                return Ok(None);
+            }
+            bail!(
+                "rclone lsjson --stat {} failed: {}",
+                remote,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        let entry: LsJsonEntry = serde_json::from_slice(&output.stdout)
+            .with_context(|| format!("Failed to parse rclone lsjson output for {}", remote))?;
+        Ok(Some(to_remote_file(entry, remote)?))
+    }
+
+    /// Copy one local file to an exact remote path (`rclone copyto`)
+    pub fn copyto(&self, local: &Path, remote: &str) -> Result<()> {
+        let local = local.to_str().context("Path is not valid UTF-8")?;
+        self.run(&["copyto", local, remote])?;
+        Ok(())
+    }
+
+    /// SHA-256 of every file under `remote`, keyed by relative path (None when
+    /// the backend has no hash for a file). With `download`, hashes are computed
+    /// from the content instead of trusting the backend.
+    pub fn hashsum(&self, remote: &str, download: bool) -> Result<HashMap<String, Option<String>>> {
+        let mut args = vec!["hashsum", "sha256", remote];
+        if download {
+            args.push("--download");
+        }
+        let stdout = self.run(&args)?;
+
+        let mut hashes = HashMap::new();
+        for line in String::from_utf8_lossy(&stdout).lines() {
+            // "<hash>  <path>"; files without a hash get a blank 64-column hash field
+            let (hash, path) = match (line.get(..64), line.get(64..66), line.get(66..)) {
+                (Some(hash), Some("  "), Some(path)) => (hash.trim(), path),
+                _ => match line.split_once("  ") {
+                    Some((hash, path)) => (hash.trim(), path),
+                    None => continue,
+                },
+            };
+            let hash = (hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit()))
+                .then(|| hash.to_ascii_lowercase());
+            hashes.insert(path.to_string(), hash);
+        }
+        Ok(hashes)
+    }
+
+    fn run(&self, args: &[&str]) -> Result<Vec<u8>> {
+        let output = Command::new(&self.bin)
+            .args(args)
+            .output()
+            .with_context(|| format!("Failed to run {}", self.bin.display()))?;
+        if !output.status.success() {
+            bail!(
+                "rclone {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(output.stdout)
+    }
+}
+
+fn to_remote_file(entry: LsJsonEntry, remote: &str) -> Result<RemoteFile> {
+    let mtime = chrono::DateTime::parse_from_rfc3339(&entry.mod_time)
+        .with_context(|| format!("Invalid ModTime '{}' for {}", entry.mod_time, join(remote, &entry.path)))?
+        .timestamp();
+    // Hash names are "sha256" in current rclone, "SHA-256" in older releases
+    let sha256 = entry
+        .hashes
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("sha256") || k.eq_ignore_ascii_case("sha-256"))
+        .map(|(_, v)| v.to_ascii_lowercase())
+        .filter(|v| !v.is_empty());
+    Ok(RemoteFile {
+        path: entry.path,
+        size: entry.size,
+        mtime,
+        sha256,
+    })
+}
@@ -0,0 +1,289 @@
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::db::Db;
+use crate::summary;
+use crate::util::{format_bytes, format_number};
+
+const TOP_DUPLICATES: i64 = 10;
+const TOP_UNARCHIVED_DIRS: i64 = 20;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+impl ReportFormat {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s {
+            "md" | "markdown" => Ok(ReportFormat::Markdown),
+            "html" => Ok(ReportFormat::Html),
+            _ => bail!("Invalid report format '{}'. Must be 'md' or 'html'", s),
+        }
+    }
+
+    /// Guess the format from an output file extension (.html/.htm, else Markdown)
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("html") | Some("htm") => ReportFormat::Html,
+            _ => ReportFormat::Markdown,
+        }
+    }
+}
+
+pub struct ReportOptions {
+    pub format: ReportFormat,
+}
+
+/// A report section: a heading, an optional explanatory paragraph and a table
+struct Section {
+    title: &'static str,
+    note: Option<String>,
+    headers: Vec<&'static str>,
+    /// Right-align these columns (numbers)
+    numeric: Vec<bool>,
+    rows: Vec<Vec<String>>,
+}
+
+/// Generate a shareable coverage report. Writes to `output`, or stdout when None.
+pub fn run(db: &Db, output: Option<&Path>, options: &ReportOptions) -> Result<()> {
+    let conn = db.conn();
+
+    let roots = summary::root_coverage(conn)?;
+    let years = summary::year_breakdown(conn)?;
+    let dup_totals = summary::duplicate_totals(conn)?;
+    let dup_sets = summary::duplicate_sets(conn, TOP_DUPLICATES)?;
+    let dirs = summary::unarchived_dirs(conn, TOP_UNARCHIVED_DIRS)?;
+
+    let mut sections = Vec::new();
+
+    // Overview over source roots: what is (not yet) safely archived
+    let source_roots: Vec<_> = roots.iter().filter(|r| r.role == "source").collect();
+    let files: i64 = source_roots.iter().map(|r| r.included()).sum();
+    let hashed: i64 = source_roots.iter().map(|r| r.hashed).sum();
+    let archived: i64 = source_roots.iter().map(|r| r.archived).sum();
+    let archive_files: i64 = roots.iter().filter(|r| r.role == "archive").map(|r| r.included()).sum();
+    sections.push(Section {
+        title: "Overview",
+        note: None,
+        headers: vec!["", "Files", "Share"],
+        numeric: vec![false, true, true],
+        rows: vec![
+            vec!["Files in source roots".into(), format_number(files), String::new()],
+            vec!["Content hashed".into(), format_number(hashed), format_pct(hashed, files)],
+            vec!["In an archive".into(), format_number(archived), format_pct(archived, files)],
+            vec!["Not yet archived".into(), format_number(files - archived), format_pct(files - archived, files)],
+            vec!["Files in archive roots".into(), format_number(archive_files), String::new()],
+        ],
+    });
+
+    sections.push(Section {
+        title: "Coverage per root",
+        note: Some("Archived is the share of hashed files whose content exists in an archive root.".into()),
+        headers: vec!["Root", "Role", "Files", "Size", "Hashed", "Archived", "Missing"],
+        numeric: vec![false, false, true, true, true, true, true],
+        rows: roots
+            .iter()
+            .map(|r| {
+                vec![
+                    r.path.clone(),
+                    r.role.clone(),
+                    format_number(r.included()),
+                    format_bytes(r.bytes as u64),
+                    format_pct(r.hashed, r.included()),
+                    if r.role == "source" { format_pct(r.archived, r.hashed) } else { "-".into() },
+                    format_number(r.missing),
+                ]
+            })
+            .collect(),
+    });
+
+    sections.push(Section {
+        title: "By year",
+        note: Some("Year taken from the photo/video capture date when known, otherwise the file modification time.".into()),
+        headers: vec!["Year", "Files", "Size", "Hashed", "Archived"],
+        numeric: vec![false, true, true, true, true],
+        rows: years
+            .iter()
+            .map(|y| {
+                vec![
+                    y.year.map(|y| y.to_string()).unwrap_or_else(|| "unknown".into()),
+                    format_number(y.sources),
+                    format_bytes(y.bytes as u64),
+                    format_pct(y.hashed, y.sources),
+                    format_pct(y.archived, y.sources),
+                ]
+            })
+            .collect(),
+    });
+
+    let mut dup_note = format!(
+        "{} files have duplicate copies: {} extra copies using {}.",
+        format_number(dup_totals.sets),
+        format_number(dup_totals.extra_copies),
+        format_bytes(dup_totals.reclaimable_bytes as u64)
+    );
+    if dup_totals.quickhash_matches > 0 {
+        dup_note.push_str(&format!(
+            " {} unhashed files match other content by quickhash and are likely duplicates too.",
+            format_number(dup_totals.quickhash_matches)
+        ));
+    }
+    dup_note.push_str(" Largest sets:");
+    sections.push(Section {
+        title: "Duplicates",
+        note: Some(dup_note),
+        headers: vec!["Copies", "Size each", "Example"],
+        numeric: vec![true, true, false],
+        rows: dup_sets
+            .iter()
+            .map(|d| {
+                vec![
+                    d.copies.to_string(),
+                    format_bytes(d.size as u64),
+                    d.sources.first().map(|s| s.path.clone()).unwrap_or_default(),
+                ]
+            })
+            .collect(),
+    });
+
+    sections.push(Section {
+        title: "Biggest unarchived directories",
+        note: Some("Directories in source roots with the most content not yet in any archive.".into()),
+        headers: vec!["Directory", "Files", "Size", "Unhashed"],
+        numeric: vec![false, true, true, true],
+        rows: dirs
+            .iter()
+            .map(|d| {
+                vec![
+                    d.path.clone(),
+                    format_number(d.files),
+                    format_bytes(d.bytes as u64),
+                    format_number(d.unhashed),
+                ]
+            })
+            .collect(),
+    });
+
+    let generated = chrono::Local::now().format("%Y-%m-%d %H:%M").to_string();
+    let rendered = match options.format {
+        ReportFormat::Markdown => render_markdown(&sections, &generated),
+        ReportFormat::Html => render_html(&sections, &generated),
+    };
+
+    match output {
+        Some(path) => {
+            fs::write(path, rendered)
+                .with_context(|| format!("Failed to write report to {}", path.display()))?;
+            println!("Wrote report: {}", path.display());
+        }
+        None => print!("{}", rendered),
+    }
+
+    Ok(())
+}
+
+// ============================================================================
+// Rendering
+// ============================================================================
+
+fn render_markdown(sections: &[Section], generated: &str) -> String {
+    let mut out = format!("# Archive report\n\nGenerated {}\n", generated);
+
+    for section in sections {
+        out.push_str(&format!("\n## {}\n\n", section.title));
+        if let Some(ref note) = section.note {
+            out.push_str(&format!("{}\n\n", note));
+        }
+        if section.rows.is_empty() {
+            out.push_str("_None._\n");
+            continue;
+        }
+        out.push_str(&format!("| {} |\n", section.headers.join(" | ")));
+        let separators: Vec<&str> = section
+            .numeric
+            .iter()
+            .map(|&n| if n { "---:" } else { "---" })
+            .collect();
+        out.push_str(&format!("| {} |\n", separators.join(" | ")));
+        for row in &section.rows {
+            let cells: Vec<String> = row.iter().map(|c| c.replace('|', "\\|")).collect();
+            out.push_str(&format!("| {} |\n", cells.join(" | ")));
+        }
+    }
+
+    out
+}
+
+fn render_html(sections: &[Section], generated: &str) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>Archive report</title>\n<style>\n\
+         body { font: 15px/1.5 system-ui, sans-serif; max-width: 960px; margin: 30px auto; padding: 0 20px; color: #222; }\n\
+         table { border-collapse: collapse; width: 100%; margin-bottom: 10px; }\n\
+         th, td { padding: 5px 10px; border-bottom: 1px solid #e4e4e4; text-align: left; }\n\
+         th { background: #f4f4f6; }\n\
+         .num { text-align: right; font-variant-numeric: tabular-nums; }\n\
+         .muted { color: #777; }\n\
+         </style>\n</head>\n<body>\n",
+    );
+    out.push_str(&format!(
+        "<h1>Archive report</h1>\n<p class=\"muted\">Generated {}</p>\n",
+        escape_html(generated)
+    ));
+
+    for section in sections {
+        out.push_str(&format!("<h2>{}</h2>\n", escape_html(section.title)));
+        if let Some(ref note) = section.note {
+            out.push_str(&format!("<p>{}</p>\n", escape_html(note)));
+        }
+        if section.rows.is_empty() {
+            out.push_str("<p class=\"muted\">None.</p>\n");
+            continue;
+        }
+        out.push_str("<table>\n<tr>");
+        for (header, &numeric) in section.headers.iter().zip(&section.numeric) {
+            out.push_str(&format!("<th{}>{}</th>", class_attr(numeric), escape_html(header)));
+        }
+        out.push_str("</tr>\n");
+        for row in &section.rows {
+            out.push_str("<tr>");
+            for (cell, &numeric) in row.iter().zip(&section.numeric) {
+                out.push_str(&format!("<td{}>{}</td>", class_attr(numeric), escape_html(cell)));
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</table>\n");
+    }
+
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn class_attr(numeric: bool) -> &'static str {
+    if numeric {
+        " class=\"num\""
+    } else {
+        ""
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// ============================================================================
+// Helper Functions
+// ============================================================================
+
+fn format_pct(part: i64, whole: i64) -> String {
+    if whole == 0 {
+        "-".to_string()
+    } else {
+        format!("{:.1}%", (part as f64 / whole as f64) * 100.0)
+    }
+}
@@ -0,0 +1,935 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::atomic::AtomicU64;
+use std::sync::Mutex;
+
+use crate::db::{find_mount_point, parse_root_spec, refresh_one_root_availability, Connection, Db};
+use crate::output;
+use crate::platform;
+use crate::remote;
+use crate::scan;
+use crate::summary::{self, RootCoverage};
+use crate::util::{format_bytes, format_number, format_timestamp};
+
+/// Settings `canon root set` accepts
+const KEYS: &[&str] = &[
+    "label",
+    "alias",
+    "ignore",
+    "hash",
+    "hash-on-scan",
+    "layout",
+    "inode-tracking",
+    "move-detection",
+    "symlinks",
+    "skip-hidden",
+    "xattrs",
+    "magic",
+    "volume",
+    "offline",
+];
+
+/// Hash algorithms objects can be identified by
+const HASH_ALGORITHMS: &[&str] = &["sha256"];
+
+/// Set (`key=value`) or clear (`key=`) settings of a root, then print all of
+/// the root's settings
+pub fn set(db: &Db, root_spec: &str, assignments: &[String]) -> Result<()> {
+    let conn = db.conn();
+    let root_id = parse_root_spec(conn, root_spec, None)?;
+
+    // Validate everything before writing anything
+    let mut changes = Vec::new();
+    for assignment in assignments {
+        let Some((key, value)) = assignment.split_once('=') else {
+            bail!("Invalid setting '{}'. Use key=value (or key= to clear)", assignment);
+        };
+        let key = key.trim();
+        if !KEYS.contains(&key) {
+            bail!("Unknown root setting '{}'. Known settings: {}", key, KEYS.join(", "));
+        }
+        let value = value.trim();
+        let value = if value.is_empty() { None } else { Some(normalize(key, value)?) };
+        changes.push((key, value));
+    }
+
+    for (key, value) in &changes {
+        match (*key, value) {
+            ("alias", Some(alias)) => check_alias_free(conn, alias, root_id)?,
+            ("layout", Some(_)) => {
+                let role: String = conn.query_row("SELECT role FROM roots WHERE id = ?", [root_id], |row| row.get(0))?;
+                if role != "archive" {
+                    bail!("Root {} is {} {} root; layout only applies to archive roots", root_id, article(&role), role);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let offline_changed = changes.iter().any(|(key, _)| *key == "offline");
+    for (key, value) in changes {
+        if key == "layout" {
+            conn.execute("UPDATE roots SET layout = ? WHERE id = ?", params![value, root_id])?;
+            continue;
+        }
+        match value {
+            Some(value) => conn.execute(
+                "INSERT INTO root_settings (root_id, key, value) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(root_id, key) DO UPDATE SET value = ?3",
+                params![root_id, key, value],
+            )?,
+            None => conn.execute(
+                "DELETE FROM root_settings WHERE root_id = ? AND key = ?",
+                params![root_id, key],
+            )?,
+        };
+    }
+    if offline_changed {
+        refresh_one_root_availability(conn, root_id)?;
+    }
+
+    let root_path: String = conn.query_row("SELECT path FROM roots WHERE id = ?", [root_id], |row| row.get(0))?;
+    println!("Root {} ({})", root_id, root_path);
+    let settings = settings(conn, root_id)?;
+    if settings.is_empty() {
+        println!("  (no settings)");
+    }
+    for (key, value) in settings {
+        println!("  {} = {}", key, value);
+    }
+    Ok(())
+}
+
+/// Mark a root offline (`canon root offline`) or clear the marker (`canon root
+/// online`): the `offline` setting, which makes the root unavailable whatever
+/// is at its path
+pub fn set_offline(db: &Db, root_spec: &str, offline: bool) -> Result<()> {
+    let conn = db.conn();
+    let root_id = parse_root_spec(conn, root_spec, None)?;
+    if offline {
+        conn.execute(
+            "INSERT INTO root_settings (root_id, key, value) VALUES (?, 'offline', 'on')
+             ON CONFLICT(root_id, key) DO UPDATE SET value = 'on'",
+            [root_id],
+        )?;
+    } else {
+        conn.execute("DELETE FROM root_settings WHERE root_id = ? AND key = 'offline'", [root_id])?;
+    }
+    refresh_one_root_availability(conn, root_id)?;
+
+    let (path, available): (String, bool) =
+        conn.query_row("SELECT path, available FROM roots WHERE id = ?", [root_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    let root = display_name(conn, root_id, &path)?;
+    if offline {
+        println!("Root {} ({}) is offline: scan skips it and apply won't write to it", root_id, root);
+    } else if available {
+        println!("Root {} ({}) is online", root_id, root);
+    } else {
+        println!("Root {} ({}) is no longer marked offline", root_id, root);
+        eprintln!("Note: {} isn't reachable (not mounted?), so the root stays unavailable until it is", path);
+    }
+    Ok(())
+}
+
+/// Give a root a short name to refer to it by (`name:ALIAS` wherever a root
+/// spec is accepted), replacing any alias it had
+pub fn alias(db: &Db, name: &str, root_spec: &str) -> Result<()> {
+    let conn = db.conn();
+    let root_id = parse_root_spec(conn, root_spec, None)?;
+    let name = normalize("alias", name.trim())?;
+    if name.is_empty() {
+        bail!("Alias must not be empty (clear one with `canon root set {} alias=`)", root_spec);
+    }
+    check_alias_free(conn, &name, root_id)?;
+    conn.execute(
+        "INSERT INTO root_settings (root_id, key, value) VALUES (?1, 'alias', ?2)
+         ON CONFLICT(root_id, key) DO UPDATE SET value = ?2",
+        params![root_id, name],
+    )?;
+    let root_path: String = conn.query_row("SELECT path FROM roots WHERE id = ?", [root_id], |row| row.get(0))?;
+    println!("name:{} -> root {} ({})", name, root_id, root_path);
+    Ok(())
+}
+
+/// Aliases name one root each
+fn check_alias_free(conn: &Connection, alias: &str, root_id: i64) -> Result<()> {
+    let owner: Option<i64> = conn
+        .query_row(
+            "SELECT root_id FROM root_settings WHERE key = 'alias' AND value = ? AND root_id != ?",
+            params![alias, root_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(owner) = owner {
+        bail!("Alias '{}' already names root {} (clear it with `canon root set id:{} alias=`)", alias, owner, owner);
+    }
+    Ok(())
+}
+
+/// Check a setting's value, returning it in its stored form
+fn normalize(key: &str, value: &str) -> Result<String> {
+    match key {
+        "hash" => {
+            let algorithm = value.to_ascii_lowercase();
+            if !HASH_ALGORITHMS.contains(&algorithm.as_str()) {
+                bail!("Unsupported hash algorithm '{}'. Supported: {}", value, HASH_ALGORITHMS.join(", "));
+            }
+            Ok(algorithm)
+        }
+        "inode-tracking" | "hash-on-scan" | "skip-hidden" | "xattrs" | "magic" | "offline" => {
+            match value.to_ascii_lowercase().as_str() {
+                "on" | "true" | "yes" => Ok("on".to_string()),
+                "off" | "false" | "no" => Ok("off".to_string()),
+                _ => bail!("Invalid {} '{}'. Use on or off", key, value),
+            }
+        }
+        "move-detection" => {
+            let policy = value.to_ascii_lowercase();
+            if MoveDetection::parse(&policy).is_none() {
+                bail!("Invalid move-detection '{}'. Use size-or-root, size or any", value);
+            }
+            Ok(policy)
+        }
+        "symlinks" => {
+            let policy = value.to_ascii_lowercase();
+            if Symlinks::parse(&policy).is_none() {
+                bail!("Invalid symlinks '{}'. Use skip, follow or record", value);
+            }
+            Ok(policy)
+        }
+        "alias" => {
+            if !value.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) {
+                bail!("Invalid alias '{}'. Use letters, digits, '-', '_' and '.'", value);
+            }
+            Ok(value.to_string())
+        }
+        "layout" => Ok(value.trim_matches('/').to_string()),
+        "ignore" => {
+            let patterns: Vec<&str> = value.split(',').map(str::trim).filter(|p| !p.is_empty()).collect();
+            Ok(patterns.join(","))
+        }
+        _ => Ok(value.to_string()),
+    }
+}
+
+/// All settings of a root, sorted by key
+pub fn settings(conn: &Connection, root_id: i64) -> Result<Vec<(String, String)>> {
+    // The layout lives on the root row, where `canon lint --set-layout` has always kept it
+    let mut stmt = conn.prepare(
+        "SELECT key, value FROM root_settings WHERE root_id = ?1
+         UNION ALL SELECT 'layout', layout FROM roots WHERE id = ?1 AND layout IS NOT NULL
+         ORDER BY key",
+    )?;
+    let settings = stmt
+        .query_map([root_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(settings)
+}
+
+pub fn setting(conn: &Connection, root_id: i64, key: &str) -> Result<Option<String>> {
+    let value = conn
+        .query_row(
+            "SELECT value FROM root_settings WHERE root_id = ? AND key = ?",
+            params![root_id, key],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(value)
+}
+
+/// Labels of all roots that have one, by root id
+pub fn labels(conn: &Connection) -> Result<HashMap<i64, String>> {
+    let mut stmt = conn.prepare("SELECT root_id, value FROM root_settings WHERE key = 'label'")?;
+    let labels = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<HashMap<_, _>, _>>()?;
+    Ok(labels)
+}
+
+/// How a root is shown to people: its label, or its path if it has none
+pub fn display_name(conn: &Connection, root_id: i64, root_path: &str) -> Result<String> {
+    Ok(setting(conn, root_id, "label")?.unwrap_or_else(|| root_path.to_string()))
+}
+
+// ============================================================================
+// canon root list / show
+// ============================================================================
+
+/// A registered root with its source counts
+#[derive(Serialize)]
+struct RootInfo {
+    #[serde(flatten)]
+    coverage: RootCoverage,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    /// When a scan of the root (or a subtree of it) last finished; for roots
+    /// last scanned before that was recorded, when a file was last seen
+    last_scan: Option<i64>,
+}
+
+/// Result of `canon root list`
+#[derive(Serialize)]
+struct RootList {
+    roots: Vec<RootInfo>,
+}
+
+/// Result of `canon root show`
+#[derive(Serialize)]
+struct RootDetail {
+    #[serde(flatten)]
+    root: RootInfo,
+    /// Mount point the root was last found under (None for remotes)
+    mount_point: Option<String>,
+    settings: BTreeMap<String, String>,
+}
+
+fn root_infos(conn: &Connection) -> Result<Vec<RootInfo>> {
+    let labels = labels(conn)?;
+    let mut last_scan = conn.prepare(
+        "SELECT COALESCE(scanned_at, (SELECT MAX(last_seen_at) FROM sources WHERE root_id = roots.id))
+         FROM roots WHERE id = ?",
+    )?;
+    summary::root_coverage(conn)?
+        .into_iter()
+        .map(|coverage| {
+            Ok(RootInfo {
+                label: labels.get(&coverage.id).cloned(),
+                last_scan: last_scan.query_row([coverage.id], |row| row.get(0))?,
+                coverage,
+            })
+        })
+        .collect()
+}
+
+/// List registered roots: path, role, source counts and last scan time
+pub fn list(db: &Db) -> Result<()> {
+    let report = RootList { roots: root_infos(db.conn())? };
+
+    output::emit(&report, |report| {
+        if report.roots.is_empty() {
+            println!("No roots yet. Add one with: canon scan --add <path>");
+            return;
+        }
+        println!("{:>4}  {:<7}  {:>9}  {:>9}  {:>7}  {:<16}  PATH", "ID", "ROLE", "SOURCES", "SIZE", "MISSING", "LAST SCAN");
+        for root in &report.roots {
+            let coverage = &root.coverage;
+            let mut path = coverage.path.clone();
+            if let Some(ref label) = root.label {
+                path.push_str(&format!(" [{}]", label));
+            }
+            if !coverage.available {
+                path.push_str(" (offline)");
+            }
+            println!(
+                "{:>4}  {:<7}  {:>9}  {:>9}  {:>7}  {:<16}  {}",
+                coverage.id,
+                coverage.role,
+                format_number(coverage.sources),
+                format_bytes(coverage.bytes as u64),
+                format_number(coverage.missing),
+                root.last_scan.map(format_timestamp).unwrap_or_else(|| "never".to_string()),
+                path
+            );
+        }
+    })
+}
+
+/// Show one root (id:N, path:/foo/bar or name:ALIAS): status, counts, last scan and settings
+pub fn show(db: &Db, root_spec: &str) -> Result<()> {
+    let conn = db.conn();
+    let root_id = parse_root_spec(conn, root_spec, None)?;
+    let root = root_infos(conn)?
+        .into_iter()
+        .find(|root| root.coverage.id == root_id)
+        .expect("parse_root_spec returns a registered root");
+    let mount_point = conn.query_row("SELECT mount_point FROM roots WHERE id = ?", [root_id], |row| row.get(0))?;
+    let report = RootDetail { root, mount_point, settings: settings(conn, root_id)?.into_iter().collect() };
+
+    output::emit(&report, |report| {
+        let coverage = &report.root.coverage;
+        match report.root.label {
+            Some(ref label) => println!("Root {}: {} [{}]", coverage.id, coverage.path, label),
+            None => println!("Root {}: {}", coverage.id, coverage.path),
+        }
+        println!("  Role:         {}", coverage.role);
+        println!("  Status:       {}", if coverage.available { "available" } else { "offline" });
+        if let Some(ref mount_point) = report.mount_point {
+            println!("  Mount point:  {}", mount_point);
+        }
+        println!("  Sources:      {} ({})", format_number(coverage.sources), format_bytes(coverage.bytes as u64));
+        println!("  Hashed:       {} ({:.1}%)", format_number(coverage.hashed), pct(coverage.hashed, coverage.included()));
+        if coverage.role == "source" {
+            println!("  Archived:     {} ({:.1}% of hashed)", format_number(coverage.archived), pct(coverage.archived, coverage.hashed));
+        }
+        println!("  Excluded:     {}", format_number(coverage.excluded));
+        println!("  Missing:      {}", format_number(coverage.missing));
+        match report.root.last_scan {
+            Some(last_scan) => println!("  Last scan:    {}", format_timestamp(last_scan)),
+            None => println!("  Last scan:    never"),
+        }
+        if report.settings.is_empty() {
+            println!("  Settings:     (none)");
+        } else {
+            println!("  Settings:");
+            for (key, value) in &report.settings {
+                println!("    {} = {}", key, value);
+            }
+        }
+    })
+}
+
+// ============================================================================
+// canon root set-role
+// ============================================================================
+
+/// Change a root between source and archive in place, keeping its sources,
+/// hashes and facts. Warns about what the change does to coverage: content
+/// that starts or stops counting as archived, and files that can't serve as
+/// archive copies or that `canon lint` will flag.
+pub fn set_role(db: &Db, root_spec: &str, role: &str) -> Result<()> {
+    if role != "source" && role != "archive" {
+        bail!("Invalid role '{}'. Must be 'source' or 'archive'", role);
+    }
+    let conn = db.conn();
+    let root_id = parse_root_spec(conn, root_spec, None)?;
+    let (path, old_role): (String, String) =
+        conn.query_row("SELECT path, role FROM roots WHERE id = ?", [root_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    if old_role == role {
+        println!("Root {} ({}) is already {} {} root", root_id, path, article(role), role);
+        return Ok(());
+    }
+
+    // Present, included sources in the other source roots whose archived status flips
+    let other_sources = "SELECT COUNT(*) FROM sources s JOIN roots r ON s.root_id = r.id
+         WHERE r.role = 'source' AND r.id != ?1 AND s.present = 1 AND s.object_id IS NOT NULL
+           AND NOT EXISTS (SELECT 1 FROM facts f WHERE f.entity_type = 'source' AND f.entity_id = s.id
+                           AND f.key = 'policy.exclude')";
+    let here = "SELECT 1 FROM sources h WHERE h.root_id = ?1 AND h.present = 1 AND h.object_id = s.object_id";
+    let flipped: i64 = if role == "archive" {
+        // Not archived anywhere yet, but present here
+        conn.query_row(
+            &format!(
+                "{} AND NOT EXISTS (SELECT 1 FROM archived_objects WHERE object_id = s.object_id) AND EXISTS ({})",
+                other_sources, here
+            ),
+            [root_id],
+            |row| row.get(0),
+        )?
+    } else {
+        // Archived only here: every present archive copy is in this root
+        conn.query_row(
+            &format!(
+                "{} AND (SELECT copies FROM archived_objects WHERE object_id = s.object_id)
+                     = (SELECT COUNT(*) FROM ({}))",
+                other_sources, here
+            ),
+            [root_id],
+            |row| row.get(0),
+        )?
+    };
+    let (files, unhashed, unapplied): (i64, i64, i64) = conn.query_row(
+        "SELECT COUNT(*), COALESCE(SUM(object_id IS NULL), 0), COALESCE(SUM(apply_run_id IS NULL), 0)
+         FROM sources WHERE root_id = ? AND present = 1",
+        [root_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    let volume_plans: i64 =
+        conn.query_row("SELECT COUNT(*) FROM volume_assignments WHERE archive_root_id = ?", [root_id], |row| {
+            row.get(0)
+        })?;
+
+    // The archived-object cache follows the role change through its trigger
+    conn.execute("UPDATE roots SET role = ? WHERE id = ?", params![role, root_id])?;
+    println!("Root {} ({}) is now {} {} root (was {})", root_id, path, article(role), role, old_role);
+
+    if role == "archive" {
+        if flipped > 0 {
+            println!(
+                "  {} sources in other roots now count as archived (their content is in this root)",
+                format_number(flipped)
+            );
+        }
+        if unhashed > 0 {
+            eprintln!(
+                "Warning: {} files in it have no hash and don't count as archive copies until hashed (canon scan --hash)",
+                format_number(unhashed)
+            );
+        }
+        if unapplied > 0 {
+            eprintln!(
+                "Note: {} files in it weren't placed by canon apply; canon lint will list them as strays",
+                format_number(unapplied)
+            );
+        }
+    } else {
+        if flipped > 0 {
+            eprintln!(
+                "Warning: {} sources in other roots no longer count as archived (this root held their only archive copy)",
+                format_number(flipped)
+            );
+        }
+        if files > 0 {
+            eprintln!(
+                "Note: its {} files now count toward coverage and worklists as sources to archive",
+                format_number(files)
+            );
+        }
+        if volume_plans > 0 {
+            eprintln!(
+                "Warning: {} volume plan entries (cluster generate --split-by-volume) still target this root",
+                format_number(volume_plans)
+            );
+        }
+    }
+    Ok(())
+}
+
+fn article(role: &str) -> &'static str {
+    if role == "archive" {
+        "an"
+    } else {
+        "a"
+    }
+}
+
+// ============================================================================
+// canon root relocate
+// ============================================================================
+
+/// How many indexed files `relocate` looks for at the new path
+const RELOCATE_SAMPLE: i64 = 20;
+
+/// Point a root at the directory its files now live in (a mount point that
+/// moved), keeping its sources, hashes and facts. Paths are stored relative to
+/// the root, so only the root row changes; its mount point and volume are
+/// re-detected at the new path.
+pub fn relocate(db: &Db, root_spec: &str, new_path: &Path) -> Result<()> {
+    let conn = db.conn();
+    let root_id = parse_root_spec(conn, root_spec, None)?;
+    let old_path: String = conn.query_row("SELECT path FROM roots WHERE id = ?", [root_id], |row| row.get(0))?;
+    if remote::is_remote(&old_path) {
+        bail!("Root {} is an rclone remote; only local roots can be relocated", root_id);
+    }
+    let new_path = std::fs::canonicalize(new_path)
+        .with_context(|| format!("Failed to resolve path: {}", new_path.display()))?;
+    if !new_path.is_dir() {
+        bail!("{} is not a directory", new_path.display());
+    }
+    let new_path_str = new_path.to_str().context("Path is not valid UTF-8")?;
+    if new_path_str == old_path {
+        bail!("Root {} is already at {}", root_id, old_path);
+    }
+    if let Some(other) = conn
+        .query_row("SELECT id FROM roots WHERE path = ?", [new_path_str], |row| row.get::<_, i64>(0))
+        .optional()?
+    {
+        bail!("{} is already root {}", new_path_str, other);
+    }
+    scan::check_overlapping_roots(conn, &new_path, Some(root_id))?;
+
+    // A sample of indexed files that should be there if this is the same tree
+    let sample = conn
+        .prepare("SELECT rel_path FROM sources WHERE root_id = ? AND present = 1 ORDER BY id LIMIT ?")?
+        .query_map(params![root_id, RELOCATE_SAMPLE], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    let found = sample.iter().filter(|rel_path| new_path.join(rel_path).exists()).count();
+
+    let tx = conn.unchecked_transaction()?;
+    conn.execute(
+        "UPDATE roots SET path = ?, mount_point = ? WHERE id = ?",
+        params![new_path_str, find_mount_point(&new_path), root_id],
+    )?;
+    let old_volume = setting(conn, root_id, "volume")?;
+    let new_volume = platform::volume_id(&new_path);
+    if old_volume.is_some() && old_volume != new_volume {
+        // Recorded by the next scan if the new volume can't be identified
+        conn.execute("DELETE FROM root_settings WHERE root_id = ? AND key = 'volume'", [root_id])?;
+        if let Some(ref volume) = new_volume {
+            conn.execute(
+                "INSERT INTO root_settings (root_id, key, value) VALUES (?, 'volume', ?)",
+                params![root_id, volume],
+            )?;
+        }
+    }
+    refresh_one_root_availability(conn, root_id)?;
+    tx.commit()?;
+
+    println!("Relocated root {}: {} -> {}", root_id, old_path, new_path_str);
+    if let (Some(old_volume), Some(new_volume)) = (&old_volume, &new_volume) {
+        if old_volume != new_volume {
+            println!("  Volume changed from {} to {}", old_volume, new_volume);
+        }
+    }
+    if !sample.is_empty() && found == 0 {
+        eprintln!(
+            "Warning: none of {} indexed files sampled exist under {}; the next scan will report them missing",
+            sample.len(),
+            new_path_str
+        );
+    }
+    Ok(())
+}
+
+// ============================================================================
+// canon root remove
+// ============================================================================
+
+/// Options for `canon root remove`
+pub struct RemoveOptions {
+    /// Report what would be removed without changing the database
+    pub dry_run: bool,
+    /// Keep objects (and their facts) no other root refers to
+    pub keep_objects: bool,
+}
+
+/// Result of `canon root remove`
+#[derive(Serialize)]
+struct RemoveReport {
+    dry_run: bool,
+    root_id: i64,
+    path: String,
+    sources: usize,
+    source_facts: usize,
+    /// Objects only this root's sources referred to, deleted with their facts
+    objects: usize,
+    object_facts: usize,
+    /// Objects kept: still referred to by other roots, collections or the
+    /// trash, or unreferenced but kept with --keep-objects
+    objects_kept: usize,
+    /// Verification, apply provenance, trash and volume plan rows
+    history: usize,
+}
+
+/// Remove a root from the index: its sources and their facts, its settings,
+/// history rows that point at its sources, and objects nothing else refers to.
+/// Files on disk are left alone. Runs in one transaction that a dry run rolls
+/// back, so the counts are exactly what removal would delete.
+pub fn remove(db: &Db, root_spec: &str, options: &RemoveOptions) -> Result<()> {
+    let conn = db.conn();
+    let root_id = parse_root_spec(conn, root_spec, None)?;
+    let path: String = conn.query_row("SELECT path FROM roots WHERE id = ?", [root_id], |row| row.get(0))?;
+
+    let tx = conn.unchecked_transaction()?;
+    let object_ids = conn
+        .prepare("SELECT DISTINCT object_id FROM sources WHERE root_id = ? AND object_id IS NOT NULL")?
+        .query_map([root_id], |row| row.get::<_, i64>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let root_sources = "SELECT id FROM sources WHERE root_id = ?1";
+    let source_facts = conn.execute(
+        &format!("DELETE FROM facts WHERE entity_type = 'source' AND entity_id IN ({})", root_sources),
+        [root_id],
+    )?;
+    let mut history = 0;
+    for sql in [
+        format!("DELETE FROM verifications WHERE source_id IN ({})", root_sources),
+        format!(
+            "DELETE FROM apply_provenance WHERE source_id IN ({0}) OR dest_source_id IN ({0})",
+            root_sources
+        ),
+        format!("DELETE FROM trash WHERE root_id = ?1 OR source_id IN ({})", root_sources),
+        format!(
+            "DELETE FROM volume_assignments WHERE archive_root_id = ?1 OR source_id IN ({})",
+            root_sources
+        ),
+        format!("DELETE FROM hash_failures WHERE source_id IN ({})", root_sources),
+    ] {
+        history += conn.execute(&sql, [root_id])?;
+    }
+    let sources = conn.execute("DELETE FROM sources WHERE root_id = ?", [root_id])?;
+    for table in ["root_settings", "dirs", "scan_checkpoints"] {
+        conn.execute(&format!("DELETE FROM {} WHERE root_id = ?", table), [root_id])?;
+    }
+    conn.execute("DELETE FROM roots WHERE id = ?", [root_id])?;
+
+    let (mut objects, mut object_facts) = (0, 0);
+    if !options.keep_objects {
+        let mut referenced = conn.prepare(
+            "SELECT EXISTS (SELECT 1 FROM sources WHERE object_id = ?1)
+                 OR EXISTS (SELECT 1 FROM collection_objects WHERE object_id = ?1)
+                 OR EXISTS (SELECT 1 FROM trash WHERE object_id = ?1)
+                 OR EXISTS (SELECT 1 FROM apply_provenance WHERE object_id = ?1)",
+        )?;
+        for &object_id in &object_ids {
+            if referenced.query_row([object_id], |row| row.get(0))? {
+                continue;
+            }
+            object_facts +=
+                conn.execute("DELETE FROM facts WHERE entity_type = 'object' AND entity_id = ?", [object_id])?;
+            conn.execute("DELETE FROM verifications WHERE object_id = ?", [object_id])?;
+            conn.execute("DELETE FROM objects WHERE id = ?", [object_id])?;
+            objects += 1;
+        }
+    }
+    if !options.dry_run {
+        tx.commit()?;
+    }
+
+    let report = RemoveReport {
+        dry_run: options.dry_run,
+        root_id,
+        path,
+        sources,
+        source_facts,
+        objects,
+        object_facts,
+        objects_kept: object_ids.len() - objects,
+        history,
+    };
+    output::emit(&report, |report| {
+        let verb = if report.dry_run { "Would remove" } else { "Removed" };
+        println!("{} root {}: {}", verb, report.root_id, report.path);
+        println!(
+            "  {} sources ({} facts), {} objects only this root had ({} facts), {} history rows",
+            format_number(report.sources as i64),
+            format_number(report.source_facts as i64),
+            format_number(report.objects as i64),
+            format_number(report.object_facts as i64),
+            format_number(report.history as i64)
+        );
+        if report.objects_kept > 0 && options.keep_objects {
+            println!("  Keeping {} objects (--keep-objects)", format_number(report.objects_kept as i64));
+        } else if report.objects_kept > 0 {
+            println!(
+                "  Keeping {} objects other roots, collections or the trash still refer to",
+                format_number(report.objects_kept as i64)
+            );
+        }
+        if report.dry_run {
+            println!("Dry run: nothing was removed. Run with --yes to remove the root.");
+        }
+    })
+}
+
+fn pct(part: i64, whole: i64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / whole as f64
+    }
+}
+
+// ============================================================================
+// Scan policy
+// ============================================================================
+
+/// What `canon scan` skips and records for one root
+pub struct ScanPolicy {
+    ignore: Vec<String>,
+    /// Whether device/inode are recorded to detect moves (off for filesystems
+    /// with unstable inode numbers, e.g. some network mounts)
+    pub inode_tracking: bool,
+    pub move_detection: MoveDetection,
+    pub symlinks: Symlinks,
+    /// Whether dotfiles and dot-directories are skipped
+    pub skip_hidden: bool,
+    /// Whether extended attributes are recorded as `source.xattr.*` facts
+    pub xattrs: bool,
+    /// Whether new and changed files' types are read from their first bytes
+    /// into `source.magic` facts
+    pub magic: bool,
+    /// Whether new, changed and unhashed files are hashed (`scan --hash`)
+    pub hash_on_scan: bool,
+    /// Files smaller than this many bytes are left out (`scan --min-size`)
+    pub min_size: Option<u64>,
+    /// Files larger than this many bytes are left out (`scan --max-size`)
+    pub max_size: Option<u64>,
+    /// Hidden entries skipped by walks using this policy (a directory counts
+    /// once, as it isn't entered)
+    pub hidden_skipped: AtomicU64,
+    /// Files and directories that scans using this policy couldn't read
+    pub warnings: Mutex<Vec<ScanWarning>>,
+}
+
+/// A path a scan left out because it couldn't be read, stat'ed or hashed
+#[derive(Serialize)]
+pub struct ScanWarning {
+    pub path: String,
+    pub message: String,
+}
+
+/// When a file at a new path whose (device, inode) is already indexed counts
+/// as that source moved, rather than a new file that got a freed inode number
+#[derive(Clone, Copy, PartialEq)]
+pub enum MoveDetection {
+    /// The size matches, or the indexed file was in the same root (default)
+    SizeOrRoot,
+    /// The size matches
+    Size,
+    /// Any (device, inode) match
+    Any,
+}
+
+impl MoveDetection {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "size-or-root" => Some(MoveDetection::SizeOrRoot),
+            "size" => Some(MoveDetection::Size),
+            "any" => Some(MoveDetection::Any),
+            _ => None,
+        }
+    }
+
+    /// Whether an indexed source with the file's (device, inode) is the file
+    pub fn accepts(self, same_size: bool, same_root: bool) -> bool {
+        match self {
+            MoveDetection::SizeOrRoot => same_size || same_root,
+            MoveDetection::Size => same_size,
+            MoveDetection::Any => true,
+        }
+    }
+}
+
+/// What a scan does with symbolic links
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Symlinks {
+    /// Leave them out (default)
+    Skip,
+    /// Index what they point to at the link's path, unless the target overlaps
+    /// the root or a directory already followed (no cycles, nothing twice)
+    Follow,
+    /// Index the link itself, with its target in `source.symlink_target`
+    Record,
+}
+
+impl Symlinks {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "skip" => Some(Symlinks::Skip),
+            "follow" => Some(Symlinks::Follow),
+            "record" => Some(Symlinks::Record),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Symlinks::Skip => "skip",
+            Symlinks::Follow => "follow",
+            Symlinks::Record => "record",
+        }
+    }
+}
+
+impl ScanPolicy {
+    pub fn load(conn: &Connection, root_id: i64) -> Result<Self> {
+        let ignore = setting(conn, root_id, "ignore")?
+            .map(|patterns| patterns.split(',').map(str::to_string).collect())
+            .unwrap_or_default();
+        let inode_tracking = setting(conn, root_id, "inode-tracking")?.as_deref() != Some("off");
+        let move_detection = setting(conn, root_id, "move-detection")?
+            .and_then(|value| MoveDetection::parse(&value))
+            .unwrap_or(MoveDetection::SizeOrRoot);
+        let symlinks = setting(conn, root_id, "symlinks")?
+            .and_then(|value| Symlinks::parse(&value))
+            .unwrap_or(Symlinks::Skip);
+        let skip_hidden = setting(conn, root_id, "skip-hidden")?.as_deref() == Some("on");
+        let xattrs = setting(conn, root_id, "xattrs")?.as_deref() == Some("on");
+        let magic = setting(conn, root_id, "magic")?.as_deref() == Some("on");
+        let hash_on_scan = setting(conn, root_id, "hash-on-scan")?.as_deref() == Some("on");
+        Ok(ScanPolicy {
+            ignore,
+            inode_tracking,
+            move_detection,
+            symlinks,
+            skip_hidden,
+            xattrs,
+            magic,
+            hash_on_scan,
+            min_size: None,
+            max_size: None,
+            hidden_skipped: AtomicU64::new(0),
+            warnings: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// What decides which entries a walk yields, so `scan --fast` can tell
+    /// whether directories recorded under another policy must be listed again
+    pub fn fingerprint(&self) -> String {
+        let mut fingerprint =
+            format!("ignore={};skip-hidden={};symlinks={}", self.ignore.join(","), self.skip_hidden, self.symlinks.as_str());
+        if self.min_size.is_some() || self.max_size.is_some() {
+            let bound = |size: Option<u64>| size.map(|size| size.to_string()).unwrap_or_default();
+            fingerprint.push_str(&format!(";size={}..{}", bound(self.min_size), bound(self.max_size)));
+        }
+        fingerprint
+    }
+
+    /// Print a warning about `path` and keep it for the scan's report
+    pub fn warn(&self, path: &Path, message: String) {
+        eprintln!("Warning: {}", message);
+        let path = path.display().to_string();
+        self.warnings.lock().unwrap().push(ScanWarning { path, message });
+    }
+
+    /// Whether a file of `size` bytes is outside the size limits
+    pub fn outside_size_limits(&self, size: u64) -> bool {
+        self.min_size.is_some_and(|min| size < min) || self.max_size.is_some_and(|max| size > max)
+    }
+
+    /// Ignore `patterns` too (e.g. `canon scan --exclude`), with the same syntax
+    /// as the `ignore` setting
+    pub fn ignore_also(&mut self, patterns: &[String]) {
+        self.ignore.extend(patterns.iter().map(|p| p.trim().to_string()).filter(|p| !p.is_empty()));
+    }
+
+    /// Whether a path (relative to the root) is left out of scans: ignored, or
+    /// hidden while hidden files are skipped
+    pub fn skips(&self, rel_path: &str) -> bool {
+        self.ignores(rel_path) || self.hides(rel_path)
+    }
+
+    /// Whether hidden files are skipped and the path has a component starting with '.'
+    pub fn hides(&self, rel_path: &str) -> bool {
+        self.skip_hidden && rel_path.split('/').any(|component| component.starts_with('.'))
+    }
+
+    /// Whether a path (relative to the root) is ignored. Patterns containing
+    /// '/' match the whole relative path; others match any single component,
+    /// so `@eaDir` skips those directories wherever they are.
+    pub fn ignores(&self, rel_path: &str) -> bool {
+        self.ignore.iter().any(|pattern| {
+            if pattern.contains('/') {
+                glob_match(pattern.trim_matches('/'), rel_path)
+            } else {
+                rel_path.split('/').any(|component| glob_match(pattern, component))
+            }
+        })
+    }
+}
+
+/// Match `text` against a glob: `?` is any character but '/', `*` any run of
+/// them, `**` any run including '/', and `**/` any run of directories, including none
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_match_at(&pattern, &text)
+}
+
+fn glob_match_at(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => {
+            let crosses_dirs = pattern.get(1) == Some(&'*');
+            let rest = if crosses_dirs { &pattern[2..] } else { &pattern[1..] };
+            if crosses_dirs && rest.first() == Some(&'/') && glob_match_at(&rest[1..], text) {
+                return true;
+            }
+            for skip in 0..=text.len() {
+                if glob_match_at(rest, &text[skip..]) {
+                    return true;
+                }
+                if skip < text.len() && text[skip] == '/' && !crosses_dirs {
+                    return false;
+                }
+            }
+            false
+        }
+        Some('?') => !text.is_empty() && text[0] != '/' && glob_match_at(&pattern[1..], &text[1..]),
+        Some(&c) => text.first() == Some(&c) && glob_match_at(&pattern[1..], &text[1..]),
+    }
+}
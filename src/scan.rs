@@ -1,41 +1,365 @@
 use anyhow::{bail, Context, Result};
 use rusqlite::{params, OptionalExtension};
-use std::collections::HashSet;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::os::unix::fs::MetadataExt;
+use std::io;
 use std::path::{Path, PathBuf};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::Ordering;
+use std::sync::{mpsc, Condvar, Mutex};
+use std::thread;
+use std::time::Instant;
 use walkdir::WalkDir;
 
-use crate::db::{resolve_root_path, Connection, Db};
+use crate::apply;
+use crate::db::{find_mount_point, resolve_root_path, root_available, Connection, Db};
+use crate::output;
+use crate::pin;
+use crate::platform;
+use crate::remote::{self, Rclone};
+use crate::import_facts;
+use crate::magic::{self, MAGIC_FACT_KEY};
+use crate::roots::{self, MoveDetection, ScanPolicy, ScanWarning, Symlinks};
+use crate::trash;
+use crate::worklist;
+use crate::xattr::{self, XATTR_FACT_PREFIX};
+use crate::util::current_timestamp;
 
-#[derive(Default)]
-struct ScanStats {
-    scanned: u64,
-    new: u64,
-    updated: u64,
-    moved: u64,
-    unchanged: u64,
-    missing: u64,
+/// Fact recording where a source was before scan detected it moved
+pub const PREVIOUS_PATH_KEY: &str = "source.previous_path";
+
+/// Fact recording what a symlink indexed with `--symlinks record` points to
+pub const SYMLINK_TARGET_KEY: &str = "source.symlink_target";
+
+#[derive(Default, Serialize)]
+pub(crate) struct ScanStats {
+    pub(crate) scanned: u64,
+    pub(crate) new: u64,
+    pub(crate) updated: u64,
+    pub(crate) moved: u64,
+    pub(crate) unchanged: u64,
+    pub(crate) missing: u64,
+    /// Files hashed and linked to objects by `--hash` (or the root's `hash-on-scan`)
+    pub(crate) hashed: u64,
+    /// Hidden files and directories skipped (a directory counts once)
+    pub(crate) hidden_skipped: u64,
+    /// Files left out by `--min-size`/`--max-size`
+    pub(crate) size_skipped: u64,
+    /// Directories `--fast` didn't list because their mtime hadn't changed
+    pub(crate) dirs_unchanged: u64,
+    /// Long-missing sources deleted by `--prune`
+    pub(crate) pruned: u64,
+    /// Long-missing sources `--prune` kept (pinned, in the trash or recorded by apply)
+    pub(crate) prune_kept: u64,
+    /// New, updated and moved sources, in scan order
+    #[serde(skip)]
+    pub(crate) changed: Vec<i64>,
+    /// The first few paths of each kind of change (reported by `--dry-run`)
+    #[serde(skip)]
+    examples: ScanExamples,
+    /// Paths that couldn't be read (reported once for the whole scan)
+    #[serde(skip)]
+    pub(crate) warnings: Vec<ScanWarning>,
+}
+
+/// Paths per kind of change, at most `EXAMPLES` of each
+#[derive(Default, Serialize)]
+struct ScanExamples {
+    new: Vec<String>,
+    updated: Vec<String>,
+    moved: Vec<String>,
+    missing: Vec<String>,
+    /// Every pruned path, so a dry run lists what `--prune` would delete
+    pruned: Vec<String>,
 }
 
-pub fn run(db: &Db, paths: &[PathBuf], role: &str, add_root: bool) -> Result<()> {
+/// Paths listed per kind of change by `scan --dry-run`
+const EXAMPLES: usize = 20;
+
+/// Files per index transaction unless `--commit-every` says otherwise
+pub const DEFAULT_COMMIT_EVERY: usize = 1000;
+
+/// How long a source must have been missing for a bare `--prune` to delete it
+pub const DEFAULT_PRUNE_AGE: &str = "90d";
+
+/// Groups a scan's index writes into transactions of `every` files rather
+/// than committing each statement on its own, which dominates scan time. A
+/// savepoint rather than a transaction: a dry run already holds one. On an
+/// error the open batch is rolled back; batches before it stay committed.
+struct CommitBatch<'a> {
+    conn: &'a Connection,
+    every: usize,
+    pending: usize,
+    open: bool,
+    checkpoint: Option<Checkpoint>,
+}
+
+/// A scan's row in `scan_checkpoints`, updated with the last file passed
+/// whenever a batch commits
+struct Checkpoint {
+    root_id: i64,
+    prefix: String,
+    last_path: Option<String>,
+}
+
+impl<'a> CommitBatch<'a> {
+    fn begin(conn: &'a Connection, every: usize) -> Result<Self> {
+        conn.execute_batch("SAVEPOINT scan_batch")?;
+        Ok(CommitBatch { conn, every, pending: 0, open: true, checkpoint: None })
+    }
+
+    /// Record the scan's progress in `scan_checkpoints` from now on, starting
+    /// after `last_path` (None for a scan from the start)
+    fn checkpoint(
+        &mut self,
+        root_id: i64,
+        prefix: &str,
+        started_at: i64,
+        policy: &str,
+        last_path: Option<String>,
+    ) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO scan_checkpoints (root_id, prefix, started_at, policy, last_path)
+             VALUES (?, ?, ?, ?, ?)",
+            params![root_id, prefix, started_at, policy, last_path],
+        )?;
+        self.checkpoint = Some(Checkpoint { root_id, prefix: prefix.to_string(), last_path });
+        Ok(())
+    }
+
+    /// Note the file just processed, which a resumed scan starts after
+    fn passed(&mut self, rel_path: &str) {
+        if let Some(checkpoint) = &mut self.checkpoint {
+            checkpoint.last_path = Some(rel_path.to_string());
+        }
+    }
+
+    /// Count `files` processed, committing once a batch is full
+    fn add(&mut self, files: usize) -> Result<()> {
+        self.pending += files;
+        if self.every > 0 && self.pending >= self.every {
+            if let Some(checkpoint) = &self.checkpoint {
+                self.conn
+                    .prepare_cached("UPDATE scan_checkpoints SET last_path = ? WHERE root_id = ? AND prefix = ?")?
+                    .execute(params![checkpoint.last_path, checkpoint.root_id, checkpoint.prefix])?;
+            }
+            self.conn.execute_batch("RELEASE scan_batch; SAVEPOINT scan_batch")?;
+            self.pending = 0;
+        }
+        Ok(())
+    }
+
+    /// Commit the rest, ending the checkpoint: the scan is complete
+    fn commit(mut self) -> Result<()> {
+        self.open = false;
+        if let Some(checkpoint) = &self.checkpoint {
+            self.conn.execute(
+                "DELETE FROM scan_checkpoints WHERE root_id = ? AND prefix = ?",
+                params![checkpoint.root_id, checkpoint.prefix],
+            )?;
+        }
+        self.conn.execute_batch("RELEASE scan_batch")?;
+        Ok(())
+    }
+}
+
+impl Drop for CommitBatch<'_> {
+    fn drop(&mut self) {
+        if self.open {
+            let _ = self.conn.execute_batch("ROLLBACK TO scan_batch; RELEASE scan_batch");
+        }
+    }
+}
+
+impl ScanStats {
+    /// Count one scanned file, remembering its path if it changed
+    fn record(&mut self, result: &ProcessResult, path: impl FnOnce() -> String) {
+        let (count, examples) = match result.action {
+            FileAction::New => (&mut self.new, &mut self.examples.new),
+            FileAction::Updated => (&mut self.updated, &mut self.examples.updated),
+            FileAction::Moved => (&mut self.moved, &mut self.examples.moved),
+            FileAction::Unchanged => {
+                self.unchanged += 1;
+                return;
+            }
+        };
+        *count += 1;
+        if examples.len() < EXAMPLES {
+            examples.push(path());
+        }
+        self.changed.push(result.source_id);
+    }
+
+    fn add(&mut self, other: &ScanStats) {
+        self.scanned += other.scanned;
+        self.new += other.new;
+        self.updated += other.updated;
+        self.moved += other.moved;
+        self.unchanged += other.unchanged;
+        self.missing += other.missing;
+        self.hashed += other.hashed;
+        self.hidden_skipped += other.hidden_skipped;
+        self.size_skipped += other.size_skipped;
+        self.dirs_unchanged += other.dirs_unchanged;
+        self.pruned += other.pruned;
+        self.prune_kept += other.prune_kept;
+        self.changed.extend(&other.changed);
+        self.examples.pruned.extend(other.examples.pruned.iter().cloned());
+        for (all, more) in [
+            (&mut self.examples.new, &other.examples.new),
+            (&mut self.examples.updated, &other.examples.updated),
+            (&mut self.examples.moved, &other.examples.moved),
+            (&mut self.examples.missing, &other.examples.missing),
+        ] {
+            all.extend(more.iter().take(EXAMPLES - all.len()).cloned());
+        }
+    }
+}
+
+/// Result of `canon scan` (`--json`, `--report`): totals, the same counts per
+/// scanned path, and what couldn't be scanned
+#[derive(Serialize)]
+struct ScanReport<'a> {
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    dry_run: bool,
+    #[serde(flatten)]
+    stats: &'a ScanStats,
+    /// Example paths per kind of change (dry runs)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    examples: Option<&'a ScanExamples>,
+    roots: &'a [RootReport],
+    /// Roots skipped because they weren't mounted
+    unavailable: &'a [String],
+    /// Files and directories that couldn't be read, stat'ed or hashed
+    warnings: Vec<&'a ScanWarning>,
+    duration_ms: u64,
+}
+
+/// Counts for one scanned path
+#[derive(Serialize)]
+struct RootReport {
+    root: String,
+    /// The scanned path, when it is a subtree of the root
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subtree: Option<String>,
+    #[serde(flatten)]
+    stats: ScanStats,
+}
+
+pub struct ScanOptions {
+    /// Write worklist entries for new, updated and moved files to stdout (the
+    /// summary goes to stderr)
+    pub emit_worklist: bool,
+    /// Report what would change, with example paths, and leave the index as it was
+    pub dry_run: bool,
+    /// Threads listing directories of local roots, and as many stat'ing the
+    /// files found (0 = number of CPUs); files are still written to the index
+    /// one at a time
+    pub jobs: usize,
+    /// Globs skipped for this scan on top of the roots' `ignore` settings
+    pub exclude: Vec<String>,
+    /// Hash new, updated and unhashed local files and link them to objects,
+    /// as `import-facts` of `content.hash.sha256` would
+    pub hash: bool,
+    /// Overrides the roots' `symlinks` settings for this scan
+    pub symlinks: Option<Symlinks>,
+    /// Skip dotfiles and dot-directories, as if every root had `skip-hidden=on`
+    pub skip_hidden: bool,
+    /// Record extended attributes as facts, as if every root had `xattrs=on`
+    pub xattrs: bool,
+    /// Read new and changed files' types from their first bytes into
+    /// `source.magic`, as if every root had `magic=on`
+    pub magic: bool,
+    /// Don't list directories whose mtime is unchanged since the last fast
+    /// scan (local roots; walks on one thread)
+    pub fast: bool,
+    /// Files per index transaction (0 = one transaction per root)
+    pub commit_every: usize,
+    /// Leave out files smaller than this many bytes
+    pub min_size: Option<u64>,
+    /// Leave out files larger than this many bytes
+    pub max_size: Option<u64>,
+    /// Delete sources under the scanned paths that have been missing for at
+    /// least this many seconds, with their source facts
+    pub prune: Option<i64>,
+    /// Also write the JSON report to this file
+    pub report: Option<PathBuf>,
+    /// Continue an interrupted scan of the same path after the last file it
+    /// committed (local roots; walks on one thread)
+    pub resume: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            emit_worklist: false,
+            dry_run: false,
+            jobs: 1,
+            exclude: Vec::new(),
+            hash: false,
+            symlinks: None,
+            skip_hidden: false,
+            xattrs: false,
+            magic: false,
+            fast: false,
+            commit_every: DEFAULT_COMMIT_EVERY,
+            min_size: None,
+            max_size: None,
+            prune: None,
+            report: None,
+            resume: false,
+        }
+    }
+}
+
+/// Scan paths (new roots with `add_root`, or existing roots/subtrees) and update sources.
+/// rclone paths (`remote:path`) are listed with `rclone lsjson`.
+pub fn run(db: &Db, paths: &[PathBuf], role: &str, add_root: bool, options: &ScanOptions) -> Result<()> {
     // Validate role
     if role != "source" && role != "archive" {
         bail!("Invalid role '{}'. Must be 'source' or 'archive'", role);
     }
+    if let (Some(min), Some(max)) = (options.min_size, options.max_size) {
+        if min > max {
+            bail!("--min-size ({} bytes) is larger than --max-size ({} bytes)", min, max);
+        }
+    }
 
     let conn = db.conn();
     let now = current_timestamp();
+    let started = Instant::now();
+
+    // A dry run scans for real inside a transaction that is never committed,
+    // so it reports exactly what a scan would do
+    let dry_run_tx = if options.dry_run { Some(conn.unchecked_transaction()?) } else { None };
 
     let mut total_stats = ScanStats::default();
+    let mut root_reports: Vec<RootReport> = Vec::new();
+    let mut unavailable: Vec<String> = Vec::new();
+    let mut rclone: Option<Rclone> = None;
 
     for path in paths {
-        let canonical = fs::canonicalize(path)
-            .with_context(|| format!("Failed to canonicalize path: {}", path.display()))?;
+        let path_str = path.to_str().context("Path is not valid UTF-8")?;
+        let is_remote = remote::is_remote(path_str);
+        // An unmounted root's directory may be gone entirely (nothing to canonicalize)
+        if !is_remote && !path.exists() {
+            if let Some(root) = unavailable_root_at(conn, path_str)? {
+                eprintln!("Warning: Root '{}' is unavailable (not mounted?), skipping", root);
+                unavailable.push(root);
+                continue;
+            }
+        }
+
+        let canonical = if is_remote {
+            PathBuf::from(remote::normalize(path_str))
+        } else {
+            fs::canonicalize(path)
+                .with_context(|| format!("Failed to canonicalize path: {}", path.display()))?
+        };
 
         // Check if path is inside an existing root
-        let (root_id, root_path, scan_prefix) = match resolve_root_path(&conn, &canonical)? {
+        let (root_id, root_path, scan_prefix) = match resolve_root_path(conn, &canonical)? {
             Some((id, root_path, existing_role, rel_path)) => {
                 // Path is inside an existing root
                 if add_root {
@@ -49,7 +373,7 @@ pub fn run(db: &Db, paths: &[PathBuf], role: &str, add_root: bool) -> Result<()>
                 // Check role matches if scanning the root itself (not a subtree)
                 if rel_path.is_empty() && existing_role != role {
                     bail!(
-                        "Root '{}' has role '{}', cannot scan with --role {}",
+                        "Root '{}' has role '{}', cannot scan with --role {} (change it with `canon root set-role`)",
                         root_path,
                         existing_role,
                         role
@@ -70,51 +394,227 @@ pub fn run(db: &Db, paths: &[PathBuf], role: &str, add_root: bool) -> Result<()>
                         canonical.display()
                     );
                 }
-                check_overlapping_roots(&conn, &canonical)?;
-                let root_id = create_root(&conn, &canonical, role)?;
+                if is_remote && canonical.to_string_lossy().ends_with(':') {
+                    bail!(
+                        "Remote root '{}' must include a path (e.g. '{}photos')",
+                        canonical.display(),
+                        canonical.display()
+                    );
+                }
+                check_overlapping_roots(conn, &canonical, None)?;
+                let root_id = create_root(conn, &canonical, role)?;
                 (root_id, canonical.clone(), None)
             }
         };
 
-        let stats = scan_root(&conn, root_id, &root_path, scan_prefix.as_deref(), now)?;
+        // An unmounted or different drive must not turn every indexed file into "missing"
+        if let Some(reason) = ensure_available(conn, root_id, &root_path, is_remote)? {
+            let root_path = root_path.to_str().context("Path is not valid UTF-8")?;
+            let root = roots::display_name(conn, root_id, root_path)?;
+            eprintln!("Warning: Root '{}' {}, skipping", root, reason);
+            unavailable.push(root);
+            continue;
+        }
+
+        let stats = if is_remote {
+            if rclone.is_none() {
+                rclone = Some(Rclone::new()?);
+            }
+            let rclone = rclone.as_ref().expect("rclone initialized above");
+            let root_path = root_path.to_str().context("Path is not valid UTF-8")?;
+            scan_remote_root(conn, rclone, root_id, root_path, scan_prefix.as_deref(), options, now)?
+        } else {
+            scan_root(conn, root_id, &root_path, scan_prefix.as_deref(), options, now)?
+        };
+
+        total_stats.add(&stats);
+        let root = root_path.to_str().context("Path is not valid UTF-8")?.to_string();
+        let subtree = scan_prefix.map(|prefix| format!("{}/{}", root, prefix));
+        root_reports.push(RootReport { root, subtree, stats });
+    }
 
-        total_stats.scanned += stats.scanned;
-        total_stats.new += stats.new;
-        total_stats.updated += stats.updated;
-        total_stats.moved += stats.moved;
-        total_stats.unchanged += stats.unchanged;
-        total_stats.missing += stats.missing;
+    let report = ScanReport {
+        dry_run: options.dry_run,
+        stats: &total_stats,
+        examples: options.dry_run.then_some(&total_stats.examples),
+        roots: &root_reports,
+        unavailable: &unavailable,
+        warnings: root_reports.iter().flat_map(|root| &root.stats.warnings).collect(),
+        duration_ms: started.elapsed().as_millis() as u64,
+    };
+    if let Some(ref report_path) = options.report {
+        let json = serde_json::to_string_pretty(&report)?;
+        fs::write(report_path, json + "\n")
+            .with_context(|| format!("Failed to write scan report: {}", report_path.display()))?;
     }
 
-    println!(
-        "Scanned {} files: {} new, {} updated, {} moved, {} unchanged, {} missing",
-        total_stats.scanned,
-        total_stats.new,
-        total_stats.updated,
-        total_stats.moved,
-        total_stats.unchanged,
-        total_stats.missing
-    );
+    if options.emit_worklist {
+        worklist::write_changed_to(db, &total_stats.changed, io::stdout().lock())?;
+        eprintln!("{}", summary(&total_stats, options.dry_run));
+    } else if options.dry_run {
+        output::emit(&report, |report| {
+            println!("{}", summary(report.stats, true));
+            let examples = &report.stats.examples;
+            print_examples("New", report.stats.new, &examples.new);
+            print_examples("Updated", report.stats.updated, &examples.updated);
+            print_examples("Moved", report.stats.moved, &examples.moved);
+            print_examples("Missing", report.stats.missing, &examples.missing);
+            print_examples("Pruned", report.stats.pruned, &examples.pruned);
+        })?;
+    } else {
+        output::emit(&report, |report| println!("{}", summary(report.stats, false)))?;
+    }
 
+    // Rolled back: nothing the dry run did is kept
+    drop(dry_run_tx);
     Ok(())
 }
 
+fn summary(stats: &ScanStats, dry_run: bool) -> String {
+    let mut summary = format!(
+        "{} {} files: {} new, {} updated, {} moved, {} unchanged, {} missing",
+        if dry_run { "Would scan" } else { "Scanned" },
+        stats.scanned,
+        stats.new,
+        stats.updated,
+        stats.moved,
+        stats.unchanged,
+        stats.missing
+    );
+    if stats.hashed > 0 {
+        summary.push_str(&format!(", {} hashed", stats.hashed));
+    }
+    if stats.hidden_skipped > 0 {
+        summary.push_str(&format!(", {} hidden skipped", stats.hidden_skipped));
+    }
+    if stats.size_skipped > 0 {
+        summary.push_str(&format!(", {} skipped by size", stats.size_skipped));
+    }
+    if stats.pruned > 0 || stats.prune_kept > 0 {
+        summary.push_str(&format!(", {} pruned", stats.pruned));
+    }
+    if stats.prune_kept > 0 {
+        summary.push_str(&format!(
+            " ({} long-missing kept: pinned, in the trash or recorded by apply)",
+            stats.prune_kept
+        ));
+    }
+    if stats.dirs_unchanged > 0 {
+        summary.push_str(&format!(" ({} unchanged directories not listed)", stats.dirs_unchanged));
+    }
+    summary
+}
+
+fn print_examples(title: &str, count: u64, paths: &[String]) {
+    if count == 0 {
+        return;
+    }
+    println!("\n{} ({}):", title, count);
+    for path in paths {
+        println!("  {}", path);
+    }
+    if count > paths.len() as u64 {
+        println!("  ... and {} more", count - paths.len() as u64);
+    }
+}
+
 fn create_root(conn: &Connection, path: &Path, role: &str) -> Result<i64> {
     let path_str = path.to_str().context("Path is not valid UTF-8")?;
+    let mount_point = if remote::is_remote(path_str) {
+        None
+    } else {
+        find_mount_point(path)
+    };
 
     conn.execute(
-        "INSERT INTO roots (path, role) VALUES (?, ?)",
-        params![path_str, role],
+        "INSERT INTO roots (path, role, mount_point) VALUES (?, ?, ?)",
+        params![path_str, role, mount_point],
     )?;
     Ok(conn.last_insert_rowid())
 }
 
-fn check_overlapping_roots(conn: &Connection, new_path: &Path) -> Result<()> {
+/// The registered root containing `path` (matched textually), if that root is unavailable
+fn unavailable_root_at(conn: &Connection, path: &str) -> Result<Option<String>> {
+    let path = path.trim_end_matches('/');
+    let roots: Vec<(i64, String, Option<String>)> = conn
+        .prepare("SELECT id, path, mount_point FROM roots")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let unavailable = roots
+        .into_iter()
+        .find(|(_, root, _)| path == root || path.starts_with(&format!("{}/", root)))
+        .filter(|(_, root, mount_point)| !root_available(root, mount_point.as_deref()));
+    match unavailable {
+        Some((id, root, _)) => Ok(Some(roots::display_name(conn, id, &root)?)),
+        None => Ok(None),
+    }
+}
+
+/// Re-check a root's availability before scanning it, recording its mount point
+/// if the root predates mount point tracking and its volume the first time the
+/// volume can be identified. Returns why the root can't be scanned, if it can't:
+/// marked offline, not mounted, or a different volume than the one indexed.
+fn ensure_available(conn: &Connection, root_id: i64, root_path: &Path, is_remote: bool) -> Result<Option<String>> {
+    if roots::setting(conn, root_id, "offline")?.as_deref() == Some("on") {
+        return Ok(Some("is marked offline (canon root online to clear)".to_string()));
+    }
+    if is_remote {
+        return Ok(None);
+    }
+    let path_str = root_path.to_str().context("Path is not valid UTF-8")?;
+    let mut mount_point: Option<String> = conn.query_row(
+        "SELECT mount_point FROM roots WHERE id = ?",
+        [root_id],
+        |row| row.get(0),
+    )?;
+
+    if mount_point.is_none() && root_path.is_dir() {
+        mount_point = find_mount_point(root_path);
+        conn.execute(
+            "UPDATE roots SET mount_point = ? WHERE id = ?",
+            params![mount_point, root_id],
+        )?;
+    }
+
+    let available = root_available(path_str, mount_point.as_deref());
+    conn.execute(
+        "UPDATE roots SET available = ? WHERE id = ?",
+        params![available, root_id],
+    )?;
+    if !available {
+        return Ok(Some("is unavailable (not mounted?)".to_string()));
+    }
+
+    // The same mount point can hold another disk (the wrong card in the reader)
+    let volume_path = mount_point.as_deref().map(Path::new).unwrap_or(root_path);
+    let Some(volume) = platform::volume_id(volume_path) else {
+        return Ok(None);
+    };
+    match roots::setting(conn, root_id, "volume")? {
+        Some(recorded) if recorded != volume => Ok(Some(format!(
+            "is on volume {}, not volume {} it was indexed on (run `canon root set id:{} volume=` if it moved to this disk)",
+            volume, recorded, root_id
+        ))),
+        Some(_) => Ok(None),
+        None => {
+            conn.execute(
+                "INSERT INTO root_settings (root_id, key, value) VALUES (?, 'volume', ?)",
+                params![root_id, volume],
+            )?;
+            Ok(None)
+        }
+    }
+}
+
+/// Fail if `new_path` is inside a registered root or contains one, other than
+/// the root `except` (the one being moved there, for `canon root relocate`)
+pub(crate) fn check_overlapping_roots(conn: &Connection, new_path: &Path, except: Option<i64>) -> Result<()> {
     let new_path_str = new_path.to_str().context("Path is not valid UTF-8")?;
 
-    let mut stmt = conn.prepare("SELECT path FROM roots")?;
+    let mut stmt = conn.prepare("SELECT path FROM roots WHERE id IS NOT ?")?;
     let roots: Vec<String> = stmt
-        .query_map([], |row| row.get(0))?
+        .query_map([except], |row| row.get(0))?
         .collect::<Result<Vec<_>, _>>()?;
 
     for existing in roots {
@@ -146,15 +646,28 @@ fn check_overlapping_roots(conn: &Connection, new_path: &Path) -> Result<()> {
     Ok(())
 }
 
-fn scan_root(
+pub(crate) fn scan_root(
     conn: &Connection,
     root_id: i64,
     root_path: &Path,
     scan_prefix: Option<&str>,
+    options: &ScanOptions,
     now: i64,
 ) -> Result<ScanStats> {
     let mut stats = ScanStats::default();
     let mut seen_source_ids: HashSet<i64> = HashSet::new();
+    let mut policy = ScanPolicy::load(conn, root_id)?;
+    policy.ignore_also(&options.exclude);
+    if let Some(symlinks) = options.symlinks {
+        policy.symlinks = symlinks;
+    }
+    policy.skip_hidden |= options.skip_hidden;
+    policy.xattrs |= options.xattrs;
+    policy.magic |= options.magic;
+    policy.hash_on_scan |= options.hash;
+    policy.min_size = options.min_size;
+    policy.max_size = options.max_size;
+    let mut batch = CommitBatch::begin(conn, options.commit_every)?;
 
     // Determine the actual path to walk
     let walk_path = match scan_prefix {
@@ -162,64 +675,711 @@ fn scan_root(
         None => root_path.to_path_buf(),
     };
 
-    for entry in WalkDir::new(&walk_path).follow_links(false) {
-        let entry = match entry {
-            Ok(e) => e,
-            Err(e) => {
-                eprintln!("Warning: {}", e);
-                continue;
+    // A sequential full scan walks in a fixed order and records how far it
+    // got, so an interrupted one can be resumed after its last committed file.
+    // Sources the interrupted scan saw have its start time as last_seen_at,
+    // which keeps them from being marked missing when the resumed scan ends.
+    let checkpointed = !options.dry_run && !options.fast && (options.resume || options.jobs == 1);
+    let mut resume_after: Option<PathBuf> = None;
+    let mut seen_since: Option<i64> = None;
+    if checkpointed {
+        let prefix = scan_prefix.unwrap_or("");
+        let fingerprint = policy.fingerprint();
+        let mut started_at = now;
+        let mut last_path = None;
+        if options.resume {
+            let checkpoint: Option<(i64, String, Option<String>)> = conn
+                .query_row(
+                    "SELECT started_at, policy, last_path FROM scan_checkpoints WHERE root_id = ? AND prefix = ?",
+                    params![root_id, prefix],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                )
+                .optional()?;
+            match checkpoint {
+                Some((previous_start, policy, previous_last)) if policy == fingerprint => {
+                    if let Some(ref rel_path) = previous_last {
+                        eprintln!("Resuming scan of {} after {}", walk_path.display(), rel_path);
+                        resume_after = Some(root_path.join(rel_path));
+                    }
+                    started_at = previous_start;
+                    seen_since = Some(previous_start);
+                    last_path = previous_last;
+                }
+                Some(_) => eprintln!(
+                    "Warning: The scan of {} was interrupted under different settings (ignore, symlinks, skip-hidden or size limits); scanning it all",
+                    walk_path.display()
+                ),
+                None => eprintln!("Note: no interrupted scan of {} to resume; scanning it all", walk_path.display()),
             }
-        };
-
-        if !entry.file_type().is_file() {
-            continue;
         }
+        batch.checkpoint(root_id, prefix, started_at, &fingerprint, last_path)?;
+    }
 
-        let full_path = entry.path();
+    let mut visit = |entry: Walked| -> Result<()> {
+        let (full_path, metadata) = match entry {
+            Walked::File(full_path, metadata) => (full_path, metadata),
+            Walked::UnchangedDir(rel_dir) => {
+                let ids = keep_dir_sources(conn, root_id, rel_dir, now)?;
+                stats.scanned += ids.len() as u64;
+                stats.unchanged += ids.len() as u64;
+                stats.dirs_unchanged += 1;
+                batch.add(ids.len())?;
+                seen_source_ids.extend(ids);
+                return Ok(());
+            }
+        };
+        if policy.outside_size_limits(metadata.len()) {
+            stats.size_skipped += 1;
+            return Ok(());
+        }
         let rel_path = full_path
             .strip_prefix(root_path)
             .context("Failed to strip root prefix")?;
 
-        let rel_path_str = rel_path.to_str().context("Path is not valid UTF-8")?;
+        let rel_path_str = platform::rel_path_str(rel_path)?;
+        let rel_path_str = &*rel_path_str;
 
-        let metadata = match fs::metadata(full_path) {
-            Ok(m) => m,
-            Err(e) => {
-                eprintln!("Warning: Failed to stat {}: {}", full_path.display(), e);
-                continue;
-            }
+        let file_id = platform::file_id(full_path, &metadata);
+        let (device, inode) = match file_id {
+            Some(file_id) if policy.inode_tracking => (Some(file_id.device), Some(file_id.inode)),
+            _ => (None, None),
         };
-
-        let device = metadata.dev() as i64;
-        let inode = metadata.ino() as i64;
-        let size = metadata.size() as i64;
-        let mtime = metadata.mtime();
+        let nlink = file_id.map(|file_id| file_id.links);
+        let size = metadata.len() as i64;
+        let mtime = platform::mtime(&metadata);
+        let btime = platform::btime(&metadata);
 
         stats.scanned += 1;
 
+        let move_detection = policy.move_detection;
         let result = process_file(
             conn,
             root_id,
+            Some(root_path),
             rel_path_str,
             device,
             inode,
+            nlink,
             size,
             mtime,
+            btime,
+            move_detection,
             now,
         )?;
+        record_symlink_target(conn, &result, full_path, &metadata, now)?;
+        if policy.xattrs {
+            record_xattrs(conn, &policy, &result, full_path, &metadata, now)?;
+        }
+        // A dry run's index changes are rolled back, so hashing would be wasted
+        // reads. A recorded symlink has no content of its own to hash.
+        if policy.hash_on_scan
+            && !options.dry_run
+            && !metadata.is_symlink()
+            && hash_source(conn, &policy, &result, full_path)?
+        {
+            stats.hashed += 1;
+        }
+        if policy.magic && !options.dry_run && !metadata.is_symlink() {
+            record_magic(conn, &policy, &result, full_path, now)?;
+        }
 
         seen_source_ids.insert(result.source_id);
+        stats.record(&result, || full_path.display().to_string());
+        batch.passed(rel_path_str);
+        batch.add(1)
+    };
+    if options.fast && walk_path.is_dir() {
+        walk_files_fast(conn, root_id, root_path, &walk_path, &policy, visit)?;
+    } else if checkpointed {
+        let after = resume_after.as_deref();
+        walk_files_after(root_path, &walk_path, &policy, after, |path, metadata| visit(Walked::File(path, metadata)))?;
+    } else {
+        walk_files(root_path, &walk_path, &policy, options.jobs, |path, metadata| visit(Walked::File(path, metadata)))?;
+    }
+
+    stats.hidden_skipped = policy.hidden_skipped.load(Ordering::Relaxed);
+    stats.warnings = policy.warnings.into_inner().unwrap();
+
+    // Mark missing files (scoped to prefix if scanning subtree)
+    let missing = mark_missing(conn, root_id, scan_prefix, &seen_source_ids, seen_since, now)?;
+    stats.missing = missing.len() as u64;
+    stats.examples.missing = missing
+        .into_iter()
+        .take(EXAMPLES)
+        .map(|rel_path| root_path.join(rel_path).display().to_string())
+        .collect();
+    if let Some(age) = options.prune {
+        let (pruned, kept) = prune_missing(conn, root_id, scan_prefix, now - age)?;
+        stats.pruned = pruned.len() as u64;
+        stats.prune_kept = kept;
+        stats.examples.pruned =
+            pruned.into_iter().map(|rel_path| root_path.join(rel_path).display().to_string()).collect();
+    }
+    conn.execute("UPDATE roots SET scanned_at = ? WHERE id = ?", params![now, root_id])?;
+    batch.commit()?;
+
+    Ok(stats)
+}
+
+/// Walk a local root (or the subtree at `walk_path`) the way scan sees it:
+/// ignored directories and the root's trash are pruned rather than walked,
+/// and symlinks are followed only as far as `follows` allows. Entries come in
+/// path order; with `after`, everything up to and including that path is
+/// left out, without listing directories that lie wholly before it.
+fn walk<'a>(
+    root_path: &'a Path,
+    walk_path: &Path,
+    policy: &'a ScanPolicy,
+    after: Option<&'a Path>,
+) -> impl Iterator<Item = walkdir::Result<walkdir::DirEntry>> + 'a {
+    let follow = policy.symlinks == Symlinks::Follow;
+    let mut followed = HashSet::new();
+    WalkDir::new(walk_path)
+        .follow_links(follow)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(move |entry| {
+            let path = entry.path();
+            after.is_none_or(|after| path > after || (path != after && after.starts_with(path)))
+                && is_walked(root_path, policy, path)
+                && (!follow || !entry.path_is_symlink() || follows(root_path, policy, path, &mut followed))
+        })
+}
 
-        match result.action {
-            FileAction::New => stats.new += 1,
-            FileAction::Updated => stats.updated += 1,
-            FileAction::Moved => stats.moved += 1,
-            FileAction::Unchanged => stats.unchanged += 1,
+/// Whether a walk following symlinks takes the link at `path`. Its target must
+/// not overlap the root or a directory already followed, which would index
+/// files twice or walk in a cycle; broken links are left out.
+fn follows(root_path: &Path, policy: &ScanPolicy, path: &Path, followed: &mut HashSet<PathBuf>) -> bool {
+    let target = match fs::canonicalize(path) {
+        Ok(target) => target,
+        Err(e) => {
+            policy.warn(path, format!("Failed to follow {}: {}", path.display(), e));
+            return false;
         }
+    };
+    let overlaps = |dir: &Path| target.starts_with(dir) || dir.starts_with(&target);
+    if overlaps(root_path) || followed.iter().any(|dir| overlaps(dir)) {
+        return false;
+    }
+    if target.is_dir() {
+        followed.insert(target);
     }
+    true
+}
 
-    // Mark missing files (scoped to prefix if scanning subtree)
-    stats.missing = mark_missing(conn, root_id, scan_prefix, &seen_source_ids, now)?;
+/// Whether a walk enters (or, for files, reports) `path`. Hidden entries left
+/// out are counted in the policy.
+fn is_walked(root_path: &Path, policy: &ScanPolicy, path: &Path) -> bool {
+    let Some(rel_path) = path.strip_prefix(root_path).ok().and_then(|rel_path| platform::rel_path_str(rel_path).ok()) else {
+        return true;
+    };
+    let rel_path = &*rel_path;
+    if rel_path.is_empty() {
+        return true;
+    }
+    if rel_path == trash::TRASH_DIR || policy.ignores(rel_path) {
+        return false;
+    }
+    if policy.hides(rel_path) {
+        policy.hidden_skipped.fetch_add(1, Ordering::Relaxed);
+        return false;
+    }
+    true
+}
+
+/// Call `f` with the path and metadata of every regular file `walk` would
+/// yield (and of every symlink itself, when they are recorded). With more
+/// than one job, directories are listed and files stat'ed on two pools of
+/// worker threads (what takes the time on network mounts) while `f` runs on
+/// the calling thread, in no particular order.
+pub(crate) fn walk_files(
+    root_path: &Path,
+    walk_path: &Path,
+    policy: &ScanPolicy,
+    jobs: usize,
+    f: impl FnMut(&Path, fs::Metadata) -> Result<()>,
+) -> Result<()> {
+    let jobs = if jobs == 0 {
+        thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+    } else {
+        jobs
+    };
+    if jobs > 1 && walk_path.is_dir() {
+        return walk_files_parallel(root_path, walk_path, policy, jobs, f);
+    }
+    walk_files_after(root_path, walk_path, policy, None, f)
+}
+
+/// Walk like `walk_files` on one thread, in path order, starting after the
+/// file at `after`
+fn walk_files_after(
+    root_path: &Path,
+    walk_path: &Path,
+    policy: &ScanPolicy,
+    after: Option<&Path>,
+    mut f: impl FnMut(&Path, fs::Metadata) -> Result<()>,
+) -> Result<()> {
+    for entry in walk(root_path, walk_path, policy, after) {
+        let entry = match entry {
+            Ok(e) => e,
+            // A link back to an ancestor, which `follows` rules out anyway
+            Err(e) if e.loop_ancestor().is_some() => continue,
+            Err(e) => {
+                policy.warn(e.path().unwrap_or(walk_path), e.to_string());
+                continue;
+            }
+        };
+        // Followed links have their target's type; others are links
+        let metadata = if entry.file_type().is_file() {
+            fs::metadata(entry.path())
+        } else if entry.file_type().is_symlink() && policy.symlinks == Symlinks::Record {
+            fs::symlink_metadata(entry.path())
+        } else {
+            continue;
+        };
+        let metadata = match metadata {
+            Ok(m) => m,
+            Err(e) => {
+                policy.warn(entry.path(), format!("Failed to stat {}: {}", entry.path().display(), e));
+                continue;
+            }
+        };
+        f(entry.path(), metadata)?;
+    }
+    Ok(())
+}
+
+/// What `walk_files_fast` reports
+enum Walked<'a> {
+    File(&'a Path, fs::Metadata),
+    /// A directory (relative to the root) whose entries are as recorded
+    UnchangedDir(&'a str),
+}
+
+/// Walk like `walk_files` on one thread, but skip listing directories whose
+/// mtime matches the one recorded by the last fast scan under the same
+/// policy. A directory's mtime changes when entries are added, removed or
+/// renamed in it, so such a directory still holds the files the index has
+/// there (reported as `UnchangedDir`) and the subdirectories recorded under
+/// it, which are walked in turn. Edits in place don't touch the directory,
+/// so files changed that way are only seen by a full scan. The mtimes are
+/// taken before listing and recorded afterwards.
+fn walk_files_fast(
+    conn: &Connection,
+    root_id: i64,
+    root_path: &Path,
+    walk_path: &Path,
+    policy: &ScanPolicy,
+    mut f: impl FnMut(Walked) -> Result<()>,
+) -> Result<()> {
+    let fingerprint = policy.fingerprint();
+    let walk_rel = platform::rel_path_str(walk_path.strip_prefix(root_path)?)?.into_owned();
+    let under_walk = |rel_path: &str| {
+        walk_rel.is_empty() || rel_path == walk_rel || rel_path.starts_with(&format!("{}/", walk_rel))
+    };
+
+    let mut recorded: HashMap<String, i64> = HashMap::new();
+    let mut stale: HashSet<String> = HashSet::new();
+    let mut stmt = conn.prepare("SELECT rel_path, mtime, policy = ? FROM dirs WHERE root_id = ?")?;
+    for row in stmt.query_map(params![fingerprint, root_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))? {
+        let (rel_path, mtime, same_policy): (String, i64, bool) = row?;
+        if !under_walk(&rel_path) {
+            continue;
+        }
+        stale.insert(rel_path.clone());
+        if same_policy {
+            recorded.insert(rel_path, mtime);
+        }
+    }
+    let mut subdirs: HashMap<&str, Vec<&str>> = HashMap::new();
+    for rel_path in recorded.keys().filter(|rel_path| !rel_path.is_empty()) {
+        let parent = rel_path.rsplit_once('/').map(|(parent, _)| parent).unwrap_or("");
+        subdirs.entry(parent).or_default().push(rel_path);
+    }
+
+    let mut listed: Vec<(String, i64)> = Vec::new();
+    let mut followed = HashSet::new();
+    let mut pending = vec![walk_path.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let rel_dir = platform::rel_path_str(dir.strip_prefix(root_path)?)?.into_owned();
+        let mtime = match fs::metadata(&dir) {
+            Ok(metadata) => platform::mtime_ns(&metadata),
+            Err(e) => {
+                policy.warn(&dir, format!("Failed to stat {}: {}", dir.display(), e));
+                continue;
+            }
+        };
+        stale.remove(&rel_dir);
+
+        if recorded.get(&rel_dir) == Some(&mtime) {
+            f(Walked::UnchangedDir(&rel_dir))?;
+            for subdir in subdirs.get(rel_dir.as_str()).into_iter().flatten() {
+                let path = root_path.join(subdir);
+                let is_link = fs::symlink_metadata(&path).is_ok_and(|m| m.is_symlink());
+                if !is_link || follows(root_path, policy, &path, &mut followed) {
+                    pending.push(path);
+                }
+            }
+            listed.push((rel_dir, mtime));
+            continue;
+        }
+
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                policy.warn(&dir, format!("Failed to read {}: {}", dir.display(), e));
+                continue;
+            }
+        };
+        for entry in entries {
+            let entry = match entry {
+                Ok(e) => e,
+                Err(e) => {
+                    policy.warn(&dir, format!("Failed to read {}: {}", dir.display(), e));
+                    continue;
+                }
+            };
+            let path = entry.path();
+            let Ok(mut file_type) = entry.file_type() else {
+                continue;
+            };
+            if !is_walked(root_path, policy, &path) {
+                continue;
+            }
+            // Same treatment of symlinks as `walk`
+            let mut record = false;
+            if file_type.is_symlink() {
+                match policy.symlinks {
+                    Symlinks::Skip => continue,
+                    Symlinks::Record => record = true,
+                    Symlinks::Follow => {
+                        if !follows(root_path, policy, &path, &mut followed) {
+                            continue;
+                        }
+                        match fs::metadata(&path) {
+                            Ok(m) => file_type = m.file_type(),
+                            Err(_) => continue,
+                        }
+                    }
+                }
+            }
+            if file_type.is_dir() {
+                pending.push(path);
+            } else if file_type.is_file() || record {
+                let metadata = if record { fs::symlink_metadata(&path) } else { fs::metadata(&path) };
+                match metadata {
+                    Ok(metadata) => f(Walked::File(&path, metadata))?,
+                    Err(e) => policy.warn(&path, format!("Failed to stat {}: {}", path.display(), e)),
+                }
+            }
+        }
+        listed.push((rel_dir, mtime));
+    }
+
+    let mut upsert = conn.prepare(
+        "INSERT INTO dirs (root_id, rel_path, mtime, policy) VALUES (?, ?, ?, ?)
+         ON CONFLICT(root_id, rel_path) DO UPDATE SET mtime = excluded.mtime, policy = excluded.policy",
+    )?;
+    for (rel_path, mtime) in &listed {
+        upsert.execute(params![root_id, rel_path, mtime, fingerprint])?;
+    }
+    // Gone, or no longer walked
+    let mut delete = conn.prepare("DELETE FROM dirs WHERE root_id = ? AND rel_path = ?")?;
+    for rel_path in &stale {
+        delete.execute(params![root_id, rel_path])?;
+    }
+    Ok(())
+}
+
+/// Refresh `last_seen_at` of the present sources directly in `rel_dir` (a
+/// directory `scan --fast` didn't list) and return their ids
+fn keep_dir_sources(conn: &Connection, root_id: i64, rel_dir: &str, now: i64) -> Result<Vec<i64>> {
+    // Paths in the directory sort between "dir/" and "dir0" ('0' follows '/')
+    let (low, high) = if rel_dir.is_empty() {
+        (String::new(), "\u{10FFFF}".to_string())
+    } else {
+        (format!("{}/", rel_dir), format!("{}0", rel_dir))
+    };
+    let ids: Vec<i64> = conn
+        .prepare_cached(
+            "SELECT id FROM sources
+             WHERE root_id = ?1 AND present = 1 AND rel_path >= ?2 AND rel_path < ?3
+               AND instr(substr(rel_path, length(?2) + 1), '/') = 0",
+        )?
+        .query_map(params![root_id, low, high], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    let mut update = conn.prepare_cached("UPDATE sources SET last_seen_at = ? WHERE id = ?")?;
+    for id in &ids {
+        update.execute(params![now, id])?;
+    }
+    Ok(ids)
+}
+
+/// Directories waiting to be listed by `walk_files_parallel` workers
+struct WalkQueue {
+    dirs: Vec<PathBuf>,
+    /// Workers listing a directory (and so possibly queueing more)
+    active: usize,
+    /// Set when the consumer has gone away
+    stopped: bool,
+    /// Symlinked directories entered so far (see `follows`)
+    followed: HashSet<PathBuf>,
+}
+
+fn walk_files_parallel(
+    root_path: &Path,
+    walk_path: &Path,
+    policy: &ScanPolicy,
+    jobs: usize,
+    mut f: impl FnMut(&Path, fs::Metadata) -> Result<()>,
+) -> Result<()> {
+    if !is_walked(root_path, policy, walk_path) {
+        return Ok(());
+    }
+    let queue = Mutex::new(WalkQueue {
+        dirs: vec![walk_path.to_path_buf()],
+        active: 0,
+        stopped: false,
+        followed: HashSet::new(),
+    });
+    let queued = Condvar::new();
+    let (stat_sender, stat_receiver) = mpsc::sync_channel::<(PathBuf, bool)>(WALK_CHANNEL_SIZE);
+    let stat_receiver = Mutex::new(stat_receiver);
+    let (sender, receiver) = mpsc::sync_channel::<(PathBuf, fs::Metadata)>(WALK_CHANNEL_SIZE);
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let stat_sender = stat_sender.clone();
+            let (queue, queued) = (&queue, &queued);
+            scope.spawn(move || walk_worker(root_path, policy, queue, queued, stat_sender));
+        }
+        drop(stat_sender);
+        for _ in 0..jobs {
+            let sender = sender.clone();
+            let (queue, queued, stat_receiver) = (&queue, &queued, &stat_receiver);
+            scope.spawn(move || stat_worker(policy, stat_receiver, queue, queued, sender));
+        }
+        drop(sender);
+
+        // Dropping the receiver on error makes the workers' sends fail, so they stop
+        for (path, metadata) in receiver {
+            f(&path, metadata)?;
+        }
+        Ok(())
+    })
+}
+
+/// Files found by walk workers but not yet stat'ed, and stat'ed files not yet
+/// processed; bounds memory when listing outpaces stat'ing or the database
+const WALK_CHANNEL_SIZE: usize = 4096;
+
+fn walk_worker(
+    root_path: &Path,
+    policy: &ScanPolicy,
+    queue: &Mutex<WalkQueue>,
+    queued: &Condvar,
+    sender: mpsc::SyncSender<(PathBuf, bool)>,
+) {
+    loop {
+        let dir = {
+            let mut state = queue.lock().unwrap();
+            loop {
+                if state.stopped {
+                    return;
+                }
+                if let Some(dir) = state.dirs.pop() {
+                    state.active += 1;
+                    break dir;
+                }
+                if state.active == 0 {
+                    // Nothing queued and nobody left to queue more: the walk is done
+                    queued.notify_all();
+                    return;
+                }
+                state = queued.wait(state).unwrap();
+            }
+        };
+
+        let listed = list_dir(root_path, policy, &dir, queue, queued, &sender);
+
+        let mut state = queue.lock().unwrap();
+        state.active -= 1;
+        if !listed {
+            state.stopped = true;
+        }
+        if state.stopped || (state.active == 0 && state.dirs.is_empty()) {
+            queued.notify_all();
+        }
+    }
+}
+
+/// Queue a directory's subdirectories and send its files to be stat'ed, with
+/// whether they are symlinks to record. Returns false once the receiver is gone.
+fn list_dir(
+    root_path: &Path,
+    policy: &ScanPolicy,
+    dir: &Path,
+    queue: &Mutex<WalkQueue>,
+    queued: &Condvar,
+    sender: &mpsc::SyncSender<(PathBuf, bool)>,
+) -> bool {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            policy.warn(dir, format!("Failed to read {}: {}", dir.display(), e));
+            return true;
+        }
+    };
+    for entry in entries {
+        let entry = match entry {
+            Ok(e) => e,
+            Err(e) => {
+                policy.warn(dir, format!("Failed to read {}: {}", dir.display(), e));
+                continue;
+            }
+        };
+        let path = entry.path();
+        let Ok(mut file_type) = entry.file_type() else {
+            continue;
+        };
+        if !is_walked(root_path, policy, &path) {
+            continue;
+        }
+        // Same treatment of symlinks as `walk`
+        let mut record = false;
+        if file_type.is_symlink() {
+            match policy.symlinks {
+                Symlinks::Skip => continue,
+                Symlinks::Record => record = true,
+                Symlinks::Follow => {
+                    if !follows(root_path, policy, &path, &mut queue.lock().unwrap().followed) {
+                        continue;
+                    }
+                    match fs::metadata(&path) {
+                        Ok(m) => file_type = m.file_type(),
+                        Err(_) => continue,
+                    }
+                }
+            }
+        }
+        if file_type.is_dir() {
+            queue.lock().unwrap().dirs.push(path);
+            queued.notify_one();
+        } else if (file_type.is_file() || record) && sender.send((path, record)).is_err() {
+            return false;
+        }
+    }
+    true
+}
+
+/// Stat files listed by walk workers and send them on with their metadata, so
+/// one large directory is stat'ed by every worker rather than the one that
+/// listed it
+fn stat_worker(
+    policy: &ScanPolicy,
+    paths: &Mutex<mpsc::Receiver<(PathBuf, bool)>>,
+    queue: &Mutex<WalkQueue>,
+    queued: &Condvar,
+    sender: mpsc::SyncSender<(PathBuf, fs::Metadata)>,
+) {
+    loop {
+        let next = paths.lock().unwrap().recv();
+        // Every walk worker has finished
+        let Ok((path, record)) = next else {
+            return;
+        };
+        let metadata = if record { fs::symlink_metadata(&path) } else { fs::metadata(&path) };
+        let metadata = match metadata {
+            Ok(m) => m,
+            Err(e) => {
+                policy.warn(&path, format!("Failed to stat {}: {}", path.display(), e));
+                continue;
+            }
+        };
+        if sender.send((path, metadata)).is_err() {
+            // The consumer has gone away: stop the walk, and take what is
+            // still being listed so no walk worker stays blocked sending
+            queue.lock().unwrap().stopped = true;
+            queued.notify_all();
+            for _ in paths.lock().unwrap().iter() {}
+            return;
+        }
+    }
+}
+
+/// Scan a remote root (or subtree) from a single `rclone lsjson` listing. Remote
+/// files have no device/inode, so moves show up as missing + new; backend
+/// SHA-256 hashes, where available, link sources to objects directly.
+fn scan_remote_root(
+    conn: &Connection,
+    rclone: &Rclone,
+    root_id: i64,
+    root_path: &str,
+    scan_prefix: Option<&str>,
+    options: &ScanOptions,
+    now: i64,
+) -> Result<ScanStats> {
+    let mut stats = ScanStats::default();
+    let mut seen_source_ids: HashSet<i64> = HashSet::new();
+    let mut policy = ScanPolicy::load(conn, root_id)?;
+    policy.ignore_also(&options.exclude);
+    policy.skip_hidden |= options.skip_hidden;
+    policy.min_size = options.min_size;
+    policy.max_size = options.max_size;
+
+    let list_path = match scan_prefix {
+        Some(prefix) => remote::join(root_path, prefix),
+        None => root_path.to_string(),
+    };
+    let files = rclone.list(&list_path)?;
+
+    let mut batch = CommitBatch::begin(conn, options.commit_every)?;
+    for file in files {
+        let rel_path = match scan_prefix {
+            Some(prefix) => format!("{}/{}", prefix, file.path),
+            None => file.path.clone(),
+        };
+        if policy.ignores(&rel_path) {
+            continue;
+        }
+        if policy.hides(&rel_path) {
+            stats.hidden_skipped += 1;
+            continue;
+        }
+        if policy.outside_size_limits(file.size.max(0) as u64) {
+            stats.size_skipped += 1;
+            continue;
+        }
+
+        stats.scanned += 1;
+
+        let (size, mtime, move_detection) = (file.size, file.mtime, policy.move_detection);
+        let result =
+            process_file(conn, root_id, None, &rel_path, None, None, None, size, mtime, None, move_detection, now)?;
+        if let Some(ref sha256) = file.sha256 {
+            remote::link_object(conn, result.source_id, sha256)?;
+        }
+
+        seen_source_ids.insert(result.source_id);
+        stats.record(&result, || remote::join(root_path, &rel_path));
+        batch.add(1)?;
+    }
+
+    let missing = mark_missing(conn, root_id, scan_prefix, &seen_source_ids, None, now)?;
+    stats.missing = missing.len() as u64;
+    stats.examples.missing = missing
+        .into_iter()
+        .take(EXAMPLES)
+        .map(|rel_path| remote::join(root_path, &rel_path))
+        .collect();
+    if let Some(age) = options.prune {
+        let (pruned, kept) = prune_missing(conn, root_id, scan_prefix, now - age)?;
+        stats.pruned = pruned.len() as u64;
+        stats.prune_kept = kept;
+        stats.examples.pruned = pruned.into_iter().map(|rel_path| remote::join(root_path, &rel_path)).collect();
+    }
+    conn.execute("UPDATE roots SET scanned_at = ? WHERE id = ?", params![now, root_id])?;
+    batch.commit()?;
 
     Ok(stats)
 }
@@ -231,55 +1391,88 @@ enum FileAction {
     Unchanged,
 }
 
+/// (id, device, inode, size, mtime, basis_rev) of a source already at the scanned path
+type ExistingSource = (i64, Option<i64>, Option<i64>, i64, i64, i64);
+
+/// (id, root_id, root path, rel_path, basis_rev) of a source that moved to the scanned path
+type MovedFrom = (i64, i64, String, String, i64);
+
 struct ProcessResult {
     source_id: i64,
     action: FileAction,
 }
 
+/// Index one file as a scan of its directory would (e.g. after restoring it
+/// from the trash), without marking anything else missing
+pub(crate) fn index_file(conn: &Connection, root_id: i64, root_path: &Path, rel_path: &str) -> Result<()> {
+    let policy = ScanPolicy::load(conn, root_id)?;
+    let full_path = root_path.join(rel_path);
+    let metadata = fs::metadata(&full_path).with_context(|| format!("Failed to stat {}", full_path.display()))?;
+    let file_id = platform::file_id(&full_path, &metadata);
+    let (device, inode) = match file_id {
+        Some(file_id) if policy.inode_tracking => (Some(file_id.device), Some(file_id.inode)),
+        _ => (None, None),
+    };
+    let nlink = file_id.map(|file_id| file_id.links);
+    let (size, mtime, btime) = (metadata.len() as i64, platform::mtime(&metadata), platform::btime(&metadata));
+    let move_detection = policy.move_detection;
+    let now = current_timestamp();
+    let root_path = Some(root_path);
+    process_file(conn, root_id, root_path, rel_path, device, inode, nlink, size, mtime, btime, move_detection, now)?;
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
 fn process_file(
     conn: &Connection,
     root_id: i64,
+    root_path: Option<&Path>,
     rel_path: &str,
-    device: i64,
-    inode: i64,
+    device: Option<i64>,
+    inode: Option<i64>,
+    nlink: Option<i64>,
     size: i64,
     mtime: i64,
+    btime: Option<i64>,
+    move_detection: MoveDetection,
     now: i64,
 ) -> Result<ProcessResult> {
     // First, check if we have an existing source at this path
-    let existing_by_path: Option<(i64, Option<i64>, Option<i64>, i64, i64, i64)> = conn
-        .query_row(
+    let existing_by_path: Option<ExistingSource> = conn
+        .prepare_cached(
             "SELECT id, device, inode, size, mtime, basis_rev FROM sources
              WHERE root_id = ? AND rel_path = ?",
-            params![root_id, rel_path],
-            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)),
-        )
+        )?
+        .query_row(params![root_id, rel_path], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?))
+        })
         .optional()?;
 
     if let Some((id, old_device, old_inode, old_size, old_mtime, old_basis_rev)) = existing_by_path {
-        // Source exists at this path
-        let basis_changed = size != old_size
-            || mtime != old_mtime
-            || Some(device) != old_device
-            || Some(inode) != old_inode;
+        // Source exists at this path. Device/inode only count when recorded on
+        // both sides, so turning inode tracking off or on doesn't invalidate
+        // every source's facts.
+        let inode_changed = device.is_some() && old_device.is_some() && (device != old_device || inode != old_inode);
+        let basis_changed = size != old_size || mtime != old_mtime || inode_changed;
 
         if basis_changed {
             let new_basis_rev = old_basis_rev + 1;
-            conn.execute(
-                "UPDATE sources SET device = ?, inode = ?, size = ?, mtime = ?,
+            conn.prepare_cached(
+                "UPDATE sources SET device = ?, inode = ?, nlink = ?, size = ?, mtime = ?, btime = ?,
                  basis_rev = ?, last_seen_at = ?, present = 1 WHERE id = ?",
-                params![device, inode, size, mtime, new_basis_rev, now, id],
-            )?;
+            )?
+            .execute(params![device, inode, nlink, size, mtime, btime, new_basis_rev, now, id])?;
             return Ok(ProcessResult {
                 source_id: id,
                 action: FileAction::Updated,
             });
         } else {
-            // Just update last_seen_at
-            conn.execute(
-                "UPDATE sources SET last_seen_at = ?, present = 1 WHERE id = ?",
-                params![now, id],
-            )?;
+            // Just update last_seen_at (and drop device/inode if no longer tracked)
+            conn.prepare_cached(
+                "UPDATE sources SET device = ?, inode = ?, nlink = ?, btime = ?, last_seen_at = ?, present = 1
+                 WHERE id = ?",
+            )?
+            .execute(params![device, inode, nlink, btime, now, id])?;
             return Ok(ProcessResult {
                 source_id: id,
                 action: FileAction::Unchanged,
@@ -287,17 +1480,54 @@ fn process_file(
         }
     }
 
-    // Check if we have an existing source with this device+inode (moved file)
-    let existing_by_inode: Option<(i64, i64, String, i64)> = conn
-        .query_row(
-            "SELECT id, root_id, rel_path, basis_rev FROM sources
-             WHERE device = ? AND inode = ?",
-            params![device, inode],
-            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
-        )
-        .optional()?;
+    // A file renamed only in case (IMG_001.JPG -> img_001.jpg) on a
+    // case-insensitive filesystem. Checked before the inode match: the old
+    // spelling still opens the file, so it would pass for another hard link.
+    let case_renamed = match root_path {
+        Some(root_path) => case_renamed_from(conn, root_id, root_path, rel_path, device, inode, size, mtime)?,
+        None => None,
+    };
 
-    if let Some((id, old_root_id, _old_rel_path, old_basis_rev)) = existing_by_inode {
+    // Check if we have an existing source with this device+inode (moved file).
+    // Inode numbers are reused once a file is deleted, so the match must also
+    // pass the root's move-detection policy. A file with several hard links
+    // is indexed at each of its paths; only a source whose path no longer
+    // leads to this file can have moved here, otherwise this is another link.
+    let existing_by_inode: Option<(i64, i64, String, String, i64, i64)> = match (device, inode) {
+        _ if case_renamed.is_some() => None,
+        (Some(device), Some(inode)) => {
+            let mut stmt = conn.prepare_cached(
+                "SELECT s.id, s.root_id, r.path, s.rel_path, s.basis_rev, s.size FROM sources s
+                 JOIN roots r ON s.root_id = r.id
+                 WHERE s.device = ? AND s.inode = ?
+                 ORDER BY s.present, s.id",
+            )?;
+            let candidates = stmt
+                .query_map(params![device, inode], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?))
+                })?
+                .collect::<Result<Vec<(i64, i64, String, String, i64, i64)>, _>>()?;
+            let linked = nlink.is_some_and(|nlink| nlink > 1);
+            candidates.into_iter().find(|(_, _, old_root_path, old_rel_path, _, _)| {
+                !linked || !is_link_to(&Path::new(old_root_path).join(old_rel_path), device, inode)
+            })
+        }
+        _ => None,
+    };
+    let moved_from = match existing_by_inode {
+        Some((id, old_root_id, old_root_path, old_rel_path, old_basis_rev, old_size)) => {
+            if move_detection.accepts(old_size == size, old_root_id == root_id) {
+                Some((id, old_root_id, old_root_path, old_rel_path, old_basis_rev))
+            } else {
+                // A different file had this inode; it belongs to the new file now
+                conn.execute("UPDATE sources SET device = NULL, inode = NULL WHERE id = ?", [id])?;
+                None
+            }
+        }
+        None => case_renamed,
+    };
+
+    if let Some((id, old_root_id, old_root_path, old_rel_path, old_basis_rev)) = moved_from {
         // File was moved
         // Note: We might need to handle cross-root moves differently, but for now
         // we'll just update to the new location
@@ -309,10 +1539,12 @@ fn process_file(
         };
 
         conn.execute(
-            "UPDATE sources SET root_id = ?, rel_path = ?, size = ?, mtime = ?,
+            "UPDATE sources SET root_id = ?, rel_path = ?, nlink = ?, size = ?, mtime = ?, btime = ?,
              basis_rev = ?, last_seen_at = ?, present = 1 WHERE id = ?",
-            params![root_id, rel_path, size, mtime, new_basis_rev, now, id],
+            params![root_id, rel_path, nlink, size, mtime, btime, new_basis_rev, now, id],
         )?;
+        let previous_path = Value::String(format!("{}/{}", old_root_path, old_rel_path));
+        import_facts::insert_fact(conn, "source", id, PREVIOUS_PATH_KEY, &previous_path, now, Some(new_basis_rev))?;
         return Ok(ProcessResult {
             source_id: id,
             action: FileAction::Moved,
@@ -320,12 +1552,12 @@ fn process_file(
     }
 
     // New file
-    conn.execute(
-        "INSERT INTO sources (root_id, rel_path, device, inode, size, mtime,
+    conn.prepare_cached(
+        "INSERT INTO sources (root_id, rel_path, device, inode, nlink, size, mtime, btime,
          basis_rev, scanned_at, last_seen_at, present)
-         VALUES (?, ?, ?, ?, ?, ?, 0, ?, ?, 1)",
-        params![root_id, rel_path, device, inode, size, mtime, now, now],
-    )?;
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, 0, ?, ?, 1)",
+    )?
+    .execute(params![root_id, rel_path, device, inode, nlink, size, mtime, btime, now, now])?;
 
     Ok(ProcessResult {
         source_id: conn.last_insert_rowid(),
@@ -333,50 +1565,296 @@ fn process_file(
     })
 }
 
+/// Keep `source.symlink_target` in step with a changed file: set for a
+/// recorded symlink, cleared when an updated source is no longer one (e.g. the
+/// root's policy changed)
+fn record_symlink_target(
+    conn: &Connection,
+    result: &ProcessResult,
+    full_path: &Path,
+    metadata: &fs::Metadata,
+    now: i64,
+) -> Result<()> {
+    if !metadata.is_symlink() {
+        if matches!(result.action, FileAction::Updated) {
+            conn.execute(
+                "DELETE FROM facts WHERE entity_type = 'source' AND entity_id = ? AND key = ?",
+                params![result.source_id, SYMLINK_TARGET_KEY],
+            )?;
+        }
+        return Ok(());
+    }
+    if matches!(result.action, FileAction::Unchanged) {
+        return Ok(());
+    }
+    let target = fs::read_link(full_path).with_context(|| format!("Failed to read link {}", full_path.display()))?;
+    let basis_rev: i64 =
+        conn.query_row("SELECT basis_rev FROM sources WHERE id = ?", [result.source_id], |row| row.get(0))?;
+    let target = Value::String(target.to_string_lossy().to_string());
+    import_facts::insert_fact(conn, "source", result.source_id, SYMLINK_TARGET_KEY, &target, now, Some(basis_rev))
+}
+
+/// Keep the `source.xattr.*` facts in step with the file's extended
+/// attributes. Attributes change without touching mtime, so unchanged files
+/// are compared too; the facts are rewritten when they differ or the source's
+/// basis_rev moved on. A recorded symlink has none. Attributes that can't be
+/// read are reported and the facts left as they are.
+fn record_xattrs(
+    conn: &Connection,
+    policy: &ScanPolicy,
+    result: &ProcessResult,
+    full_path: &Path,
+    metadata: &fs::Metadata,
+    now: i64,
+) -> Result<()> {
+    let attrs = if metadata.is_symlink() {
+        BTreeMap::new()
+    } else {
+        match xattr::read_facts(full_path) {
+            Ok(attrs) => attrs,
+            Err(e) => {
+                policy.warn(full_path, format!("{:#}", e));
+                return Ok(());
+            }
+        }
+    };
+    let basis_rev: i64 =
+        conn.query_row("SELECT basis_rev FROM sources WHERE id = ?", [result.source_id], |row| row.get(0))?;
+
+    let pattern = format!("{}%", XATTR_FACT_PREFIX);
+    type Stored = (Option<String>, Option<f64>, Option<i64>, Option<String>);
+    let stored: BTreeMap<String, (Stored, Option<i64>)> = conn
+        .prepare_cached(
+            "SELECT key, value_text, value_num, value_time, value_json, observed_basis_rev FROM facts
+             WHERE entity_type = 'source' AND entity_id = ? AND key LIKE ?",
+        )?
+        .query_map(params![result.source_id, pattern], |row| {
+            Ok((row.get(0)?, ((row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?), row.get(5)?)))
+        })?
+        .collect::<Result<_, _>>()?;
+    let up_to_date = stored.len() == attrs.len()
+        && attrs.iter().all(|(key, value)| {
+            stored.get(key).is_some_and(|(stored_value, rev)| {
+                *rev == Some(basis_rev) && *stored_value == import_facts::classify_value(value)
+            })
+        });
+    if up_to_date {
+        return Ok(());
+    }
+
+    conn.execute(
+        "DELETE FROM facts WHERE entity_type = 'source' AND entity_id = ? AND key LIKE ?",
+        params![result.source_id, pattern],
+    )?;
+    for (key, value) in &attrs {
+        import_facts::insert_fact(conn, "source", result.source_id, key, value, now, Some(basis_rev))?;
+    }
+    Ok(())
+}
+
+/// Read the file type of a new or changed file, or of one without a
+/// `source.magic` fact yet, into that fact. An unreadable file is reported
+/// and left as it was.
+fn record_magic(
+    conn: &Connection,
+    policy: &ScanPolicy,
+    result: &ProcessResult,
+    full_path: &Path,
+    now: i64,
+) -> Result<()> {
+    let needs_sniff = match result.action {
+        FileAction::New | FileAction::Updated => true,
+        FileAction::Moved | FileAction::Unchanged => !conn
+            .prepare_cached("SELECT 1 FROM facts WHERE entity_type = 'source' AND entity_id = ? AND key = ?")?
+            .exists(params![result.source_id, MAGIC_FACT_KEY])?,
+    };
+    if !needs_sniff {
+        return Ok(());
+    }
+    let kind = match magic::sniff(full_path) {
+        Ok(kind) => kind,
+        Err(e) => {
+            policy.warn(full_path, format!("{:#}", e));
+            return Ok(());
+        }
+    };
+    let basis_rev: i64 =
+        conn.query_row("SELECT basis_rev FROM sources WHERE id = ?", [result.source_id], |row| row.get(0))?;
+    let value = Value::String(kind.to_string());
+    import_facts::insert_fact(conn, "source", result.source_id, MAGIC_FACT_KEY, &value, now, Some(basis_rev))
+}
+
+/// Hash a scanned file and link its source to the object, when the file is
+/// new or changed or its source has no object yet. A file that can't be read
+/// is reported and left unhashed.
+fn hash_source(conn: &Connection, policy: &ScanPolicy, result: &ProcessResult, full_path: &Path) -> Result<bool> {
+    let needs_hash = match result.action {
+        FileAction::New | FileAction::Updated => true,
+        FileAction::Moved | FileAction::Unchanged => conn
+            .query_row("SELECT object_id IS NULL FROM sources WHERE id = ?", [result.source_id], |row| row.get(0))?,
+    };
+    if !needs_hash {
+        return Ok(false);
+    }
+    let hashes = match apply::hash_file(full_path) {
+        Ok(hashes) => hashes,
+        Err(e) => {
+            policy.warn(full_path, format!("{:#}", e));
+            return Ok(false);
+        }
+    };
+    import_facts::link_hash(conn, result.source_id, &hashes)?;
+    Ok(true)
+}
+
+/// The source at another spelling of `rel_path` in the same root, when that
+/// spelling now opens the scanned file (the filesystem ignores case) and the
+/// source was this file: same device/inode, or, where inodes weren't recorded
+/// or don't survive a rename (exFAT), same size and mtime
+#[allow(clippy::too_many_arguments)]
+fn case_renamed_from(
+    conn: &Connection,
+    root_id: i64,
+    root_path: &Path,
+    rel_path: &str,
+    device: Option<i64>,
+    inode: Option<i64>,
+    size: i64,
+    mtime: i64,
+) -> Result<Option<MovedFrom>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT id, rel_path, device, inode, size, mtime, basis_rev FROM sources
+         WHERE root_id = ? AND rel_path = ? COLLATE NOCASE AND rel_path != ?",
+    )?;
+    let candidates = stmt
+        .query_map(params![root_id, rel_path, rel_path], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?))
+        })?
+        .collect::<Result<Vec<(i64, String, Option<i64>, Option<i64>, i64, i64, i64)>, _>>()?;
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+    let full_path = root_path.join(rel_path);
+    let Some(file_id) = fs::metadata(&full_path).ok().and_then(|metadata| platform::file_id(&full_path, &metadata))
+    else {
+        return Ok(None);
+    };
+    let (file_device, file_inode) = file_id.device_inode();
+    for (id, old_rel_path, old_device, old_inode, old_size, old_mtime, old_basis_rev) in candidates {
+        let same_file = match (device, old_device) {
+            (Some(_), Some(_)) if (device, inode) == (old_device, old_inode) => true,
+            _ => size == old_size && mtime == old_mtime,
+        };
+        let old_path = root_path.join(&old_rel_path);
+        // Both spellings listed means two names, e.g. hard links on a
+        // case-sensitive filesystem
+        if same_file && is_link_to(&old_path, file_device, file_inode) && !is_listed(&old_path) {
+            let root_path = root_path.to_str().context("Root path is not valid UTF-8")?.to_string();
+            return Ok(Some((id, root_id, root_path, old_rel_path, old_basis_rev)));
+        }
+    }
+    Ok(None)
+}
+
+/// Whether the path's directory has an entry with exactly its name
+fn is_listed(path: &Path) -> bool {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return false;
+    };
+    fs::read_dir(dir).is_ok_and(|entries| entries.flatten().any(|entry| entry.file_name() == name))
+}
+
+/// Whether `path` is (still) a name of the file with this device/inode
+fn is_link_to(path: &Path, device: i64, inode: i64) -> bool {
+    fs::metadata(path)
+        .ok()
+        .and_then(|metadata| platform::file_id(path, &metadata))
+        .is_some_and(|file_id| file_id.device_inode() == (device, inode))
+}
+
+/// Mark present sources under the scanned path that the scan didn't see as
+/// missing; returns their relative paths
 fn mark_missing(
     conn: &Connection,
     root_id: i64,
     scan_prefix: Option<&str>,
     seen_ids: &HashSet<i64>,
+    seen_since: Option<i64>,
     now: i64,
-) -> Result<u64> {
-    // Get source IDs for this root that are currently present
+) -> Result<Vec<String>> {
+    // Get sources for this root that are currently present
     // If scanning a subtree, only consider files under that prefix
-    let all_ids: Vec<i64> = match scan_prefix {
+    let present: Vec<(i64, String, i64)> = match scan_prefix {
         Some(prefix) => {
-            let prefix_pattern = format!("{}%", prefix);
             conn.prepare(
-                "SELECT id FROM sources WHERE root_id = ? AND present = 1 AND rel_path LIKE ?"
+                "SELECT id, rel_path, last_seen_at FROM sources WHERE root_id = ?2 AND present = 1
+                 AND (rel_path = ?1 OR substr(rel_path, 1, length(?1) + 1) = ?1 || '/')"
             )?
-            .query_map(params![root_id, prefix_pattern], |row| row.get(0))?
+            .query_map(params![prefix, root_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
             .collect::<Result<Vec<_>, _>>()?
         }
         None => {
             conn.prepare(
-                "SELECT id FROM sources WHERE root_id = ? AND present = 1"
+                "SELECT id, rel_path, last_seen_at FROM sources WHERE root_id = ? AND present = 1"
             )?
-            .query_map([root_id], |row| row.get(0))?
+            .query_map([root_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
             .collect::<Result<Vec<_>, _>>()?
         }
     };
 
-    let mut missing_count = 0u64;
-    for id in all_ids {
-        if !seen_ids.contains(&id) {
+    let mut missing = Vec::new();
+    for (id, rel_path, last_seen_at) in present {
+        // Seen by the interrupted scan this one resumed
+        let seen_before = seen_since.is_some_and(|since| last_seen_at >= since);
+        if !seen_ids.contains(&id) && !seen_before {
             conn.execute(
                 "UPDATE sources SET present = 0, last_seen_at = ? WHERE id = ?",
                 params![now, id],
             )?;
-            missing_count += 1;
+            missing.push(rel_path);
         }
     }
 
-    Ok(missing_count)
+    Ok(missing)
 }
 
-fn current_timestamp() -> i64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_secs() as i64
+/// Delete sources under the scanned path that have been missing since
+/// `cutoff` or earlier, with their source facts, verifications and volume
+/// assignments. Pinned sources, sources whose file is in the trash and the
+/// sources of apply provenance are kept. Returns the pruned relative paths
+/// and the number kept.
+fn prune_missing(conn: &Connection, root_id: i64, scan_prefix: Option<&str>, cutoff: i64) -> Result<(Vec<String>, u64)> {
+    // Exact prefix comparison: LIKE would treat % and _ in paths as wildcards
+    // and let "photos" match "photos2/..."
+    let mut stmt = conn.prepare(
+        "SELECT s.id, s.rel_path,
+                EXISTS (SELECT 1 FROM facts f WHERE f.entity_type = 'source' AND f.entity_id = s.id AND f.key = ?2)
+                OR EXISTS (SELECT 1 FROM trash t WHERE t.source_id = s.id)
+                OR EXISTS (SELECT 1 FROM apply_provenance p WHERE p.source_id = s.id OR p.dest_source_id = s.id)
+         FROM sources s
+         WHERE s.root_id = ?3 AND s.present = 0 AND s.last_seen_at <= ?4
+           AND (?1 IS NULL OR s.rel_path = ?1 OR substr(s.rel_path, 1, length(?1) + 1) = ?1 || '/')
+         ORDER BY s.rel_path",
+    )?;
+    let candidates = stmt
+        .query_map(params![scan_prefix, pin::POLICY_PINNED_KEY, root_id, cutoff], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, bool>(2)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut pruned = Vec::new();
+    let mut kept = 0;
+    for (id, rel_path, keep) in candidates {
+        if keep {
+            kept += 1;
+            continue;
+        }
+        conn.execute("DELETE FROM facts WHERE entity_type = 'source' AND entity_id = ?", [id])?;
+        conn.execute("DELETE FROM verifications WHERE source_id = ?", [id])?;
+        conn.execute("DELETE FROM volume_assignments WHERE source_id = ?", [id])?;
+        conn.execute("DELETE FROM hash_failures WHERE source_id = ?", [id])?;
+        conn.execute("DELETE FROM sources WHERE id = ?", [id])?;
+        pruned.push(rel_path);
+    }
+    Ok((pruned, kept))
 }
@@ -0,0 +1,257 @@
+use anyhow::{bail, Context, Result};
+use rusqlite::params;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+
+use crate::db::{Connection, Db};
+use crate::summary;
+
+/// The single-page UI; all data is fetched from the /api endpoints
+const INDEX_HTML: &str = include_str!("serve/index.html");
+
+/// Files the UI may request as thumbnails (anything a browser can render)
+const IMAGE_TYPES: &[(&str, &str)] = &[
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("png", "image/png"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("bmp", "image/bmp"),
+    ("avif", "image/avif"),
+];
+
+const DEFAULT_LIMIT: i64 = 50;
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn json(value: Value) -> Self {
+        Response {
+            status: "200 OK",
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    fn not_found() -> Self {
+        Response {
+            status: "404 Not Found",
+            content_type: "text/plain",
+            body: b"Not found".to_vec(),
+        }
+    }
+}
+
+/// Serve the web UI on `addr` until interrupted. Requests are handled one at a
+/// time on the calling thread, which is plenty for a local review session.
+pub fn run(db: &Db, addr: &str) -> Result<()> {
+    let listener = TcpListener::bind(addr).with_context(|| format!("Failed to bind {}", addr))?;
+    println!("Serving canon UI at http://{}/ (Ctrl-C to stop)", listener.local_addr()?);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("Warning: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = handle_connection(db.conn(), stream) {
+            eprintln!("Warning: {:#}", e);
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_connection(conn: &Connection, mut stream: TcpStream) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Drain headers; none of them matter here
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header == "\r\n" || header == "\n" {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let target = parts.next().unwrap_or("/");
+
+    let response = if method != "GET" {
+        Response {
+            status: "405 Method Not Allowed",
+            content_type: "text/plain",
+            body: b"Only GET is supported".to_vec(),
+        }
+    } else {
+        let (path, query) = match target.split_once('?') {
+            Some((p, q)) => (p, parse_query(q)),
+            None => (target, HashMap::new()),
+        };
+        route(conn, path, &query).unwrap_or_else(|e| Response {
+            status: "500 Internal Server Error",
+            content_type: "text/plain",
+            body: format!("{:#}", e).into_bytes(),
+        })
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    )?;
+    stream.write_all(&response.body)?;
+    Ok(())
+}
+
+fn route(conn: &Connection, path: &str, query: &HashMap<String, String>) -> Result<Response> {
+    let limit = query
+        .get("limit")
+        .and_then(|l| l.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_LIMIT);
+
+    let response = match path {
+        "/" | "/index.html" => Response {
+            status: "200 OK",
+            content_type: "text/html; charset=utf-8",
+            body: INDEX_HTML.as_bytes().to_vec(),
+        },
+        "/api/coverage" => Response::json(serde_json::to_value(summary::root_coverage(conn)?)?),
+        "/api/unarchived" => Response::json(serde_json::to_value(summary::unarchived_dirs(conn, limit)?)?),
+        "/api/duplicates" => Response::json(serde_json::to_value(summary::duplicate_sets(conn, limit)?)?),
+        "/api/facts" => match query.get("key") {
+            Some(key) => Response::json(fact_values(conn, key, limit)?),
+            None => Response::json(fact_keys(conn)?),
+        },
+        _ => match path.strip_prefix("/file/").and_then(|id| id.parse::<i64>().ok()) {
+            Some(source_id) => serve_image(conn, source_id)?,
+            None => Response::not_found(),
+        },
+    };
+    Ok(response)
+}
+
+// ============================================================================
+// API Endpoints
+// ============================================================================
+
+fn fact_keys(conn: &Connection) -> Result<Value> {
+    let keys = conn
+        .prepare(
+            "SELECT key, COUNT(*) FROM facts
+             WHERE key != 'policy.exclude'
+             GROUP BY key ORDER BY COUNT(*) DESC, key",
+        )?
+        .query_map([], |row| {
+            Ok(json!({ "key": row.get::<_, String>(0)?, "count": row.get::<_, i64>(1)? }))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Value::Array(keys))
+}
+
+fn fact_values(conn: &Connection, key: &str, limit: i64) -> Result<Value> {
+    let values = conn
+        .prepare(
+            "SELECT COALESCE(value_text, CAST(value_num AS TEXT),
+                             datetime(value_time, 'unixepoch'), value_json) AS v,
+                    COUNT(*)
+             FROM facts WHERE key = ?
+             GROUP BY v ORDER BY COUNT(*) DESC, v
+             LIMIT ?",
+        )?
+        .query_map(params![key, limit], |row| {
+            Ok(json!({ "value": row.get::<_, Option<String>>(0)?, "count": row.get::<_, i64>(1)? }))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Value::Array(values))
+}
+
+/// Stream an indexed image file for thumbnails. Only files known to the index
+/// (by source id) with a browser-renderable extension are served.
+fn serve_image(conn: &Connection, source_id: i64) -> Result<Response> {
+    let row: Option<(String, String)> = conn
+        .query_row(
+            "SELECT r.path, s.rel_path FROM sources s
+             JOIN roots r ON s.root_id = r.id
+             WHERE s.id = ? AND s.present = 1",
+            [source_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .ok();
+    let Some((root, rel)) = row else {
+        return Ok(Response::not_found());
+    };
+
+    let path = Path::new(&root).join(&rel);
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+    let Some((_, content_type)) = IMAGE_TYPES.iter().find(|(e, _)| *e == ext) else {
+        return Ok(Response::not_found());
+    };
+
+    let body = match fs::read(&path) {
+        Ok(b) => b,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Response::not_found()),
+        Err(e) => bail!("Failed to read {}: {}", path.display(), e),
+    };
+    Ok(Response {
+        status: "200 OK",
+        content_type,
+        body,
+    })
+}
+
+// ============================================================================
+// Helper Functions
+// ============================================================================
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter_map(|pair| {
+            let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+            Some((percent_decode(k)?, percent_decode(v)?))
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok()?;
+                out.push(u8::from_str_radix(hex, 16).ok()?);
+                i += 3;
+            }
+            b'+' => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(out).ok()
+}
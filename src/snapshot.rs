@@ -0,0 +1,270 @@
+use anyhow::{bail, Result};
+use rusqlite::{params, OptionalExtension};
+
+use crate::db::{Connection, Db};
+use crate::util::{current_timestamp, format_number, format_timestamp};
+
+/// Per-source state columns captured in a snapshot, computed from the live tables.
+/// `archived` is 1 when the source's object is present in any archive root.
+const SOURCE_STATE_SELECT: &str = "
+    SELECT s.id, s.basis_rev, s.object_id, s.present,
+        EXISTS (SELECT 1 FROM archived_objects ao WHERE ao.object_id = s.object_id)
+    FROM sources s";
+
+pub struct DiffOptions {
+    pub list: bool,
+}
+
+struct SnapshotInfo {
+    id: i64,
+    name: String,
+    created_at: i64,
+}
+
+// ============================================================================
+// Create Command
+// ============================================================================
+
+/// Record a snapshot of the current index state under `name`
+pub fn create(db: &mut Db, name: &str) -> Result<()> {
+    let conn = db.conn_mut();
+
+    if find_snapshot(conn, name)?.is_some() {
+        bail!("Snapshot '{}' already exists", name);
+    }
+
+    let tx = conn.transaction()?;
+    tx.execute(
+        "INSERT INTO snapshots (name, created_at, total_sources, present_sources,
+         hashed_sources, archived_sources, objects, facts)
+         VALUES (?, ?, 0, 0, 0, 0, 0, 0)",
+        params![name, current_timestamp()],
+    )?;
+    let snapshot_id = tx.last_insert_rowid();
+
+    tx.execute(
+        &format!(
+            "INSERT INTO snapshot_sources (snapshot_id, source_id, basis_rev, object_id, present, archived)
+             SELECT ?, * FROM ({})",
+            SOURCE_STATE_SELECT
+        ),
+        [snapshot_id],
+    )?;
+
+    tx.execute(
+        "UPDATE snapshots SET
+             total_sources = (SELECT COUNT(*) FROM snapshot_sources WHERE snapshot_id = ?1),
+             present_sources = (SELECT COUNT(*) FROM snapshot_sources WHERE snapshot_id = ?1 AND present = 1),
+             hashed_sources = (SELECT COUNT(*) FROM snapshot_sources
+                               WHERE snapshot_id = ?1 AND present = 1 AND object_id IS NOT NULL),
+             archived_sources = (SELECT COUNT(*) FROM snapshot_sources ss
+                                 JOIN sources s ON s.id = ss.source_id
+                                 JOIN roots r ON r.id = s.root_id
+                                 WHERE ss.snapshot_id = ?1 AND ss.present = 1 AND ss.archived = 1
+                                   AND r.role = 'source'),
+             objects = (SELECT COUNT(*) FROM objects),
+             facts = (SELECT COUNT(*) FROM facts)
+         WHERE id = ?1",
+        [snapshot_id],
+    )?;
+    tx.commit()?;
+
+    let (present, hashed, archived): (i64, i64, i64) = conn.query_row(
+        "SELECT present_sources, hashed_sources, archived_sources FROM snapshots WHERE id = ?",
+        [snapshot_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+
+    println!(
+        "Created snapshot '{}': {} sources present, {} hashed, {} archived",
+        name,
+        format_number(present),
+        format_number(hashed),
+        format_number(archived)
+    );
+    Ok(())
+}
+
+// ============================================================================
+// List Command
+// ============================================================================
+
+/// Print recorded snapshots
+pub fn list(db: &Db) -> Result<()> {
+    let conn = db.conn();
+
+    let rows: Vec<(String, i64, i64, i64, i64, i64)> = conn
+        .prepare(
+            "SELECT name, created_at, present_sources, hashed_sources, archived_sources, objects
+             FROM snapshots ORDER BY created_at, id",
+        )?
+        .query_map([], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if rows.is_empty() {
+        println!("No snapshots. Create one with: canon snapshot create <name>");
+        return Ok(());
+    }
+
+    println!(
+        "{:<30} {:<17} {:>10} {:>10} {:>10} {:>10}",
+        "Snapshot", "Created", "Sources", "Hashed", "Archived", "Objects"
+    );
+    println!("{}", "─".repeat(92));
+    for (name, created_at, present, hashed, archived, objects) in &rows {
+        println!(
+            "{:<30} {:<17} {:>10} {:>10} {:>10} {:>10}",
+            name,
+            format_timestamp(*created_at),
+            format_number(*present),
+            format_number(*hashed),
+            format_number(*archived),
+            format_number(*objects)
+        );
+    }
+
+    Ok(())
+}
+
+// ============================================================================
+// Diff Command
+// ============================================================================
+
+/// Diff categories: (label, SQL condition over old `o` and new `n` state)
+/// The query joins new state to old state, so `o.source_id IS NULL` means the
+/// source did not exist when the older snapshot was taken.
+const DIFF_CATEGORIES: &[(&str, &str)] = &[
+    ("Added", "n.present = 1 AND (o.source_id IS NULL OR o.present = 0)"),
+    ("Removed", "o.present = 1 AND n.present = 0"),
+    ("Modified", "o.present = 1 AND n.present = 1 AND o.basis_rev != n.basis_rev"),
+    ("Newly hashed", "n.present = 1 AND n.object_id IS NOT NULL AND (o.source_id IS NULL OR o.object_id IS NULL)"),
+    ("Newly archived", "n.present = 1 AND n.archived = 1 AND (o.source_id IS NULL OR o.archived = 0) AND r.role = 'source'"),
+    ("No longer archived", "o.archived = 1 AND n.archived = 0 AND r.role = 'source'"),
+];
+
+/// Print what changed between snapshot `from` and `to` (or the current state)
+pub fn diff(db: &mut Db, from: &str, to: Option<&str>, options: &DiffOptions) -> Result<()> {
+    let conn = db.conn_mut();
+
+    let old = find_snapshot(conn, from)?
+        .ok_or_else(|| anyhow::anyhow!("No snapshot named '{}'", from))?;
+
+    // Materialize both sides as temp tables so every category query is a simple join
+    conn.execute_batch(
+        "DROP TABLE IF EXISTS temp_snapshot_old;
+         DROP TABLE IF EXISTS temp_snapshot_new;
+         CREATE TEMP TABLE temp_snapshot_old (
+             source_id INTEGER PRIMARY KEY, basis_rev INTEGER, object_id INTEGER,
+             present INTEGER, archived INTEGER);
+         CREATE TEMP TABLE temp_snapshot_new (
+             source_id INTEGER PRIMARY KEY, basis_rev INTEGER, object_id INTEGER,
+             present INTEGER, archived INTEGER);",
+    )?;
+    conn.execute(
+        "INSERT INTO temp_snapshot_old
+         SELECT source_id, basis_rev, object_id, present, archived
+         FROM snapshot_sources WHERE snapshot_id = ?",
+        [old.id],
+    )?;
+
+    let to_label = match to {
+        Some(name) => {
+            let new = find_snapshot(conn, name)?
+                .ok_or_else(|| anyhow::anyhow!("No snapshot named '{}'", name))?;
+            conn.execute(
+                "INSERT INTO temp_snapshot_new
+                 SELECT source_id, basis_rev, object_id, present, archived
+                 FROM snapshot_sources WHERE snapshot_id = ?",
+                [new.id],
+            )?;
+            format!("{} ({})", new.name, format_timestamp(new.created_at))
+        }
+        None => {
+            conn.execute(
+                &format!("INSERT INTO temp_snapshot_new {}", SOURCE_STATE_SELECT),
+                [],
+            )?;
+            "current".to_string()
+        }
+    };
+
+    // Sources deleted from the table since the old snapshot still count as removed
+    conn.execute(
+        "INSERT OR IGNORE INTO temp_snapshot_new (source_id, basis_rev, object_id, present, archived)
+         SELECT source_id, basis_rev, NULL, 0, 0 FROM temp_snapshot_old",
+        [],
+    )?;
+
+    println!(
+        "Snapshot diff: {} ({}) -> {}\n",
+        old.name,
+        format_timestamp(old.created_at),
+        to_label
+    );
+
+    for (label, condition) in DIFF_CATEGORIES {
+        let count: i64 = conn.query_row(
+            &format!(
+                "SELECT COUNT(*) FROM temp_snapshot_new n
+                 LEFT JOIN temp_snapshot_old o ON o.source_id = n.source_id
+                 LEFT JOIN sources s ON s.id = n.source_id
+                 LEFT JOIN roots r ON r.id = s.root_id
+                 WHERE {}",
+                condition
+            ),
+            [],
+            |row| row.get(0),
+        )?;
+
+        println!("  {:<20} {:>10}", format!("{}:", label), format_number(count));
+
+        if options.list && count > 0 {
+            let paths: Vec<String> = conn
+                .prepare(&format!(
+                    "SELECT COALESCE(r.path || '/' || s.rel_path, '(deleted source ' || n.source_id || ')')
+                     FROM temp_snapshot_new n
+                     LEFT JOIN temp_snapshot_old o ON o.source_id = n.source_id
+                     LEFT JOIN sources s ON s.id = n.source_id
+                     LEFT JOIN roots r ON r.id = s.root_id
+                     WHERE {}
+                     ORDER BY 1",
+                    condition
+                ))?
+                .query_map([], |row| row.get(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            for path in paths {
+                println!("      {}", path);
+            }
+        }
+    }
+
+    conn.execute_batch(
+        "DROP TABLE IF EXISTS temp_snapshot_old;
+         DROP TABLE IF EXISTS temp_snapshot_new;",
+    )?;
+
+    Ok(())
+}
+
+// ============================================================================
+// Helper Functions
+// ============================================================================
+
+fn find_snapshot(conn: &Connection, name: &str) -> Result<Option<SnapshotInfo>> {
+    let info = conn
+        .query_row(
+            "SELECT id, name, created_at FROM snapshots WHERE name = ?",
+            [name],
+            |row| {
+                Ok(SnapshotInfo {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    created_at: row.get(2)?,
+                })
+            },
+        )
+        .optional()?;
+    Ok(info)
+}
@@ -0,0 +1,101 @@
+use clap::ValueEnum;
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Output order for `ls` and `cluster generate`
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum SortKey {
+    /// Index order (the order sources were first scanned)
+    Id,
+    /// Full path
+    Path,
+}
+
+/// How two paths compare
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum PathOrder {
+    /// Byte order: "IMG_10" before "IMG_9", "Zoo" before "apple"
+    #[default]
+    Bytes,
+    /// Component by component, numbers by value and letters ignoring case:
+    /// "IMG_9" before "IMG_10", "apple" before "Zoo", "2024/" before "2024-extra/"
+    Natural,
+}
+
+impl PathOrder {
+    pub fn natural(natural: bool) -> Self {
+        if natural {
+            PathOrder::Natural
+        } else {
+            PathOrder::Bytes
+        }
+    }
+
+    pub fn compare(self, a: &str, b: &str) -> Ordering {
+        match self {
+            PathOrder::Bytes => a.cmp(b),
+            // Paths that only differ in case or leading zeros still get a fixed order
+            PathOrder::Natural => natural_path_cmp(a, b).then_with(|| a.cmp(b)),
+        }
+    }
+}
+
+/// Compare paths component by component, so a directory's files stay together
+fn natural_path_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.split('/');
+    let mut b = b.split('/');
+    loop {
+        match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => match natural_cmp(x, y) {
+                Ordering::Equal => {}
+                ordering => return ordering,
+            },
+        }
+    }
+}
+
+/// Compare runs of digits by value and everything else case-insensitively
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let ordering = compare_numbers(&digits(&mut a), &digits(&mut b));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn digits(chars: &mut Peekable<Chars>) -> String {
+    let mut run = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        run.push(c);
+    }
+    run
+}
+
+/// Compare digit runs by value, without parsing (they can be arbitrarily long)
+fn compare_numbers(a: &str, b: &str) -> Ordering {
+    let a = a.trim_start_matches('0');
+    let b = b.trim_start_matches('0');
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
@@ -0,0 +1,123 @@
+use anyhow::Result;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::db::{Connection, Db};
+use crate::summary::{self, RootCoverage};
+use crate::util::{format_bytes, format_number};
+
+/// Print a one-screen overview of the index: roots, hashing/archive progress,
+/// exclusions, missing files, stale facts and database size
+pub fn run(db: &Db) -> Result<()> {
+    let conn = db.conn();
+
+    let roots = summary::root_coverage(conn)?;
+    let objects: i64 = conn.query_row("SELECT COUNT(*) FROM objects", [], |row| row.get(0))?;
+    let facts: i64 = conn.query_row("SELECT COUNT(*) FROM facts", [], |row| row.get(0))?;
+    let stale_facts: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM facts f
+         JOIN sources s ON f.entity_type = 'source' AND f.entity_id = s.id
+         WHERE f.observed_basis_rev IS NOT NULL
+           AND f.observed_basis_rev != s.basis_rev",
+        [],
+        |row| row.get(0),
+    )?;
+
+    match db_file(conn)? {
+        Some(path) => {
+            let size = file_size(&path);
+            let wal = file_size(&PathBuf::from(format!("{}-wal", path.display())));
+            if wal > 0 {
+                println!("Database: {} ({}, WAL {})", path.display(), format_bytes(size), format_bytes(wal));
+            } else {
+                println!("Database: {} ({})", path.display(), format_bytes(size));
+            }
+        }
+        None => println!("Database: (in memory)"),
+    }
+    println!();
+
+    if roots.is_empty() {
+        println!("No roots yet. Add one with: canon scan --add <path>");
+        return Ok(());
+    }
+
+    println!("Roots:");
+    for root in &roots {
+        if root.available {
+            println!("  [{}] {} ({})", root.id, root.path, root.role);
+        } else {
+            println!("  [{}] {} ({}, offline)", root.id, root.path, root.role);
+        }
+        let mut line = format!("      {} sources", format_number(root.included()));
+        line.push_str(&format!(", {:.1}% hashed", pct(root.hashed, root.included())));
+        if root.role == "source" {
+            line.push_str(&format!(", {:.1}% of hashed archived", pct(root.archived, root.hashed)));
+        }
+        if root.excluded > 0 {
+            line.push_str(&format!(", {} excluded", format_number(root.excluded)));
+        }
+        if root.missing > 0 {
+            line.push_str(&format!(", {} missing", format_number(root.missing)));
+        }
+        println!("{}", line);
+    }
+    println!();
+
+    let sources: Vec<&RootCoverage> = roots.iter().filter(|r| r.role == "source").collect();
+    let included: i64 = sources.iter().map(|r| r.included()).sum();
+    let hashed: i64 = sources.iter().map(|r| r.hashed).sum();
+    let archived: i64 = sources.iter().map(|r| r.archived).sum();
+    let excluded: i64 = roots.iter().map(|r| r.excluded).sum();
+    let missing: i64 = roots.iter().map(|r| r.missing).sum();
+
+    println!("Source roots:");
+    println!("  Sources:         {:>10}", format_number(included));
+    println!("  Hashed:          {:>10} ({:.1}%)", format_number(hashed), pct(hashed, included));
+    println!(
+        "  Archived:        {:>10} ({:.1}% of hashed)",
+        format_number(archived),
+        pct(archived, hashed)
+    );
+    println!("  Unarchived:      {:>10}", format_number(hashed - archived));
+    println!("  Unhashed:        {:>10}", format_number(included - hashed));
+    println!();
+
+    println!("Index:");
+    println!("  Objects:         {:>10}", format_number(objects));
+    println!("  Facts:           {:>10}", format_number(facts));
+    println!("  Excluded:        {:>10}", format_number(excluded));
+    println!("  Missing files:   {:>10}", format_number(missing));
+    if stale_facts > 0 {
+        println!(
+            "  Stale facts:     {:>10} (run `canon facts prune --stale`)",
+            format_number(stale_facts)
+        );
+    } else {
+        println!("  Stale facts:     {:>10}", 0);
+    }
+
+    Ok(())
+}
+
+/// Path of the main database file, if it is file-backed
+fn db_file(conn: &Connection) -> Result<Option<PathBuf>> {
+    let file: String = conn.query_row(
+        "SELECT file FROM pragma_database_list WHERE name = 'main'",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(if file.is_empty() { None } else { Some(PathBuf::from(file)) })
+}
+
+fn file_size(path: &PathBuf) -> u64 {
+    fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+fn pct(part: i64, whole: i64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        (part as f64 / whole as f64) * 100.0
+    }
+}
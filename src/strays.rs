@@ -0,0 +1,154 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::db::Db;
+use crate::output;
+use crate::platform;
+use crate::reconcile::local_archive_root;
+use crate::roots::ScanPolicy;
+use crate::scan;
+
+/// Why nothing in the index accounts for a file
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Reason {
+    /// Not indexed (or marked missing): nothing is known about it
+    Unindexed,
+    /// Size or mtime changed since it was indexed, so its hash no longer applies
+    Modified,
+    /// Indexed without a hash and not placed by apply
+    Unhashed,
+    /// Hashed, but no apply placed it and its content is known nowhere else
+    UnknownContent,
+}
+
+impl Reason {
+    const ALL: [Reason; 4] = [Reason::Unindexed, Reason::Modified, Reason::Unhashed, Reason::UnknownContent];
+
+    fn title(self) -> &'static str {
+        match self {
+            Reason::Unindexed => "Not indexed",
+            Reason::Modified => "Changed since indexed",
+            Reason::Unhashed => "Indexed without a hash, not placed by apply",
+            Reason::UnknownContent => "Content known nowhere else, not placed by apply",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Stray {
+    path: String,
+    reason: Reason,
+}
+
+/// Result of `canon strays`
+#[derive(Serialize)]
+struct StraysReport {
+    root: String,
+    /// Files on disk checked
+    checked: u64,
+    /// Files placed by apply or whose content is indexed elsewhere
+    accounted: u64,
+    strays: Vec<Stray>,
+}
+
+/// What the index knows about one of the root's sources
+struct Indexed {
+    size: i64,
+    mtime: i64,
+    present: bool,
+    hashed: bool,
+    /// Placed by an apply run (with the content it placed), or its object is
+    /// known outside this root
+    accounted: bool,
+}
+
+/// List files physically present in an archive root that the index can't
+/// account for: neither placed there by `canon apply` nor holding content
+/// the index knows from another root or an apply run. What remains came from
+/// somewhere canon never saw, and is what to review before cleaning up a
+/// contaminated archive.
+pub fn run(db: &Db, archive_spec: &str) -> Result<()> {
+    let conn = db.conn();
+    let (root_id, root_path) = local_archive_root(conn, archive_spec)?;
+
+    let mut indexed: HashMap<String, Indexed> = HashMap::new();
+    let mut stmt = conn.prepare(
+        "SELECT s.rel_path, s.size, s.mtime, s.present, s.object_id IS NOT NULL,
+                -- Applied, unless the content changed since (older runs have no provenance)
+                (s.apply_run_id IS NOT NULL AND NOT EXISTS
+                    (SELECT 1 FROM apply_provenance p WHERE p.dest_source_id = s.id AND p.object_id IS NOT s.object_id))
+                OR EXISTS (SELECT 1 FROM apply_provenance p WHERE p.dest_source_id = s.id AND p.object_id IS s.object_id)
+                OR EXISTS (SELECT 1 FROM sources o WHERE o.object_id = s.object_id AND o.root_id != s.root_id)
+                OR EXISTS (SELECT 1 FROM apply_provenance p WHERE p.object_id = s.object_id)
+         FROM sources s
+         WHERE s.root_id = ?",
+    )?;
+    let rows = stmt.query_map([root_id], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            Indexed {
+                size: row.get(1)?,
+                mtime: row.get(2)?,
+                present: row.get(3)?,
+                hashed: row.get(4)?,
+                accounted: row.get(5)?,
+            },
+        ))
+    })?;
+    for row in rows {
+        let (rel_path, source) = row?;
+        indexed.insert(rel_path, source);
+    }
+
+    let mut report = StraysReport { root: root_path.clone(), checked: 0, accounted: 0, strays: Vec::new() };
+    let root = Path::new(&root_path);
+    let policy = ScanPolicy::load(conn, root_id)?;
+    scan::walk_files(root, root, &policy, 1, |path, metadata| {
+        let rel_path = platform::rel_path_str(path.strip_prefix(root).context("Failed to strip root prefix")?)?;
+        let rel_path = &*rel_path;
+        report.checked += 1;
+
+        let reason = match indexed.get(rel_path) {
+            Some(source) if !source.present => Reason::Unindexed,
+            None => Reason::Unindexed,
+            Some(source) if metadata.len() as i64 != source.size || platform::mtime(&metadata) != source.mtime => {
+                Reason::Modified
+            }
+            Some(source) if source.accounted => {
+                report.accounted += 1;
+                return Ok(());
+            }
+            Some(source) if !source.hashed => Reason::Unhashed,
+            Some(_) => Reason::UnknownContent,
+        };
+        report.strays.push(Stray { path: rel_path.to_string(), reason });
+        Ok(())
+    })?;
+    report.strays.sort_by(|a, b| a.path.cmp(&b.path));
+
+    output::emit(&report, print_report)
+}
+
+fn print_report(report: &StraysReport) {
+    println!("Checked {}", report.root);
+    for reason in Reason::ALL {
+        let paths: Vec<&str> =
+            report.strays.iter().filter(|s| s.reason == reason).map(|s| s.path.as_str()).collect();
+        if paths.is_empty() {
+            continue;
+        }
+        println!("\n{} ({}):", reason.title(), paths.len());
+        for path in paths {
+            println!("  {}", path);
+        }
+    }
+    println!(
+        "\n{} files: {} accounted for, {} of unknown provenance",
+        report.checked,
+        report.accounted,
+        report.strays.len()
+    );
+}
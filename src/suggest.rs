@@ -0,0 +1,305 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::apply::expand_pattern;
+use crate::cluster::{self, fetch_source, Manifest, ManifestMeta, ManifestOutput, ManifestSource};
+use crate::db::{self, Connection, Db};
+use crate::output;
+use crate::query::SourceQuery;
+use crate::util::current_timestamp;
+
+/// Layouts recognized in archives without a declared layout, most specific first.
+/// Paths are relative to the area (top-level directory) they are found in.
+const CANDIDATE_LAYOUTS: &[&str] = &[
+    "{year}/{month}/{day}/{filename}",
+    "{year}/{date}/{filename}",
+    "{year}/{month}/{filename}",
+    "{year}/{filename}",
+    "{content_exif_model}/{year}/{month}/{filename}",
+    "{content_exif_model}/{year}/{filename}",
+    "{content_exif_model}/{filename}",
+    "{filename}",
+];
+
+/// Archive files examined per area when inferring its layout
+const LAYOUT_SAMPLE: usize = 200;
+
+pub struct SuggestOptions {
+    /// Manifest to write; numbered (`manifest-1.toml`, ...) when sources go to several destinations
+    pub output: PathBuf,
+}
+
+/// Part of an archive that suggestions can target: the whole root when it
+/// declares a layout, otherwise each top-level directory (or the root's own files)
+struct Area {
+    archive_root_id: i64,
+    root_path: String,
+    base_dir: String,
+    /// Declared or inferred; None when no known layout fits the files
+    layout: Option<String>,
+    files: u64,
+    /// Present files by lowercase extension
+    exts: HashMap<String, u64>,
+    /// Present files by camera model (content.exif.model)
+    cameras: HashMap<String, u64>,
+    /// Archive sources to infer the layout from (path relative to the area)
+    sample: Vec<(i64, String)>,
+}
+
+impl Area {
+    fn path(&self) -> String {
+        if self.base_dir.is_empty() {
+            self.root_path.clone()
+        } else {
+            format!("{}/{}", self.root_path, self.base_dir)
+        }
+    }
+
+    /// How much the area's existing files look like this source. A shared
+    /// camera model counts double a shared extension.
+    fn affinity(&self, ext: Option<&str>, camera: Option<&str>) -> f64 {
+        let share = |count: Option<&u64>| count.copied().unwrap_or(0) as f64 / self.files as f64;
+        let ext_share = ext.map(|e| share(self.exts.get(e))).unwrap_or(0.0);
+        let camera_share = camera.map(|c| share(self.cameras.get(c))).unwrap_or(0.0);
+        ext_share + 2.0 * camera_share
+    }
+}
+
+#[derive(Default, Serialize)]
+struct SuggestReport {
+    manifests: Vec<SuggestedManifest>,
+    /// Sources whose content is already in an archive
+    archived: u64,
+    /// No archive area holds similar files, or the area's layout needs missing facts
+    unmatched: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct SuggestedManifest {
+    path: String,
+    destination: String,
+    pattern: String,
+    sources: usize,
+}
+
+/// Propose a destination (archive area and layout) for each unarchived source
+/// matching the scope and filters, and write the plan as manifests ready to
+/// edit and `canon apply`. Areas are ranked by how many of their files share
+/// the source's extension and camera model.
+pub fn run(db: &Db, scope_path: Option<&Path>, filter_strs: &[String], options: &SuggestOptions) -> Result<()> {
+    let conn = db.conn();
+
+    let mut areas = load_areas(conn)?;
+    for area in &mut areas {
+        if area.layout.is_none() {
+            area.layout = infer_layout(conn, &area.sample)?;
+        }
+    }
+    areas.retain(|area| area.layout.is_some());
+
+    let source_ids = SourceQuery::new()
+        .scope(scope_path)?
+        .filters(conn, filter_strs)?
+        .ids(conn)?;
+
+    let mut report = SuggestReport::default();
+    // Area index -> sources, in area order for stable manifest numbering
+    let mut planned: BTreeMap<usize, Vec<ManifestSource>> = BTreeMap::new();
+
+    for source_id in source_ids {
+        let Some(source) = fetch_source(conn, source_id)? else {
+            continue;
+        };
+        if let Some(object_id) = source.object_id {
+            if db::is_archived(conn, object_id)? {
+                report.archived += 1;
+                continue;
+            }
+        }
+
+        let src_path = Path::new(&source.path);
+        let ext = src_path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        let camera = source.facts.get("content.exif.model").and_then(|v| v.as_str());
+
+        // Best area whose layout can be expanded for this source
+        let best = areas
+            .iter()
+            .enumerate()
+            .map(|(i, area)| (i, area.affinity(ext.as_deref(), camera)))
+            .filter(|&(i, score)| {
+                let layout = areas[i].layout.as_deref().expect("areas without a layout were dropped");
+                score > 0.0 && expand_pattern(layout, &source, src_path).is_ok()
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1).then(areas[a.0].files.cmp(&areas[b.0].files)));
+
+        match best {
+            Some((i, _)) => planned.entry(i).or_default().push(source),
+            None => report.unmatched.push(source.path),
+        }
+    }
+
+    let numbered = planned.len() > 1;
+    for (n, (area_index, sources)) in planned.into_iter().enumerate() {
+        let area = &areas[area_index];
+        let pattern = area.layout.clone().expect("areas without a layout were dropped");
+        let manifest_path = if numbered {
+            numbered_manifest_path(&options.output, n + 1)
+        } else {
+            options.output.clone()
+        };
+
+        report.manifests.push(SuggestedManifest {
+            path: manifest_path.display().to_string(),
+            destination: area.path(),
+            pattern: pattern.clone(),
+            sources: sources.len(),
+        });
+        let manifest = Manifest {
+            meta: ManifestMeta {
+                query: filter_strs.to_vec(),
+                generated_at: current_timestamp(),
+                volume: None,
+            },
+            output: ManifestOutput {
+                pattern,
+                archive_root_id: area.archive_root_id,
+                base_dir: area.base_dir.clone(),
+            },
+            sources,
+        };
+        cluster::write_manifest(&manifest, &manifest_path)?;
+    }
+
+    output::emit(&report, print_report)
+}
+
+fn print_report(report: &SuggestReport) {
+    for path in &report.unmatched {
+        eprintln!("No suggestion: {}", path);
+    }
+    for manifest in &report.manifests {
+        println!(
+            "Suggested {} sources -> {} ({}): {}",
+            manifest.sources, manifest.destination, manifest.pattern, manifest.path
+        );
+    }
+    if report.manifests.is_empty() {
+        println!("No sources to suggest destinations for");
+    }
+    println!(
+        "{} already archived, {} without suggestion (no archive holds similar files, or facts the layout needs are missing)",
+        report.archived,
+        report.unmatched.len()
+    );
+    if !report.manifests.is_empty() {
+        println!("Review the manifests, then run canon apply");
+    }
+}
+
+/// Group present archive files into areas and count their extensions and cameras
+fn load_areas(conn: &Connection) -> Result<Vec<Area>> {
+    let mut stmt = conn.prepare(
+        "SELECT r.id, r.path, r.layout, s.id, s.rel_path,
+                COALESCE(
+                    (SELECT value_text FROM facts WHERE entity_type = 'object' AND entity_id = s.object_id
+                        AND key = 'content.exif.model'),
+                    (SELECT value_text FROM facts WHERE entity_type = 'source' AND entity_id = s.id
+                        AND key = 'content.exif.model'))
+         FROM sources s
+         JOIN roots r ON s.root_id = r.id
+         WHERE r.role = 'archive' AND s.present = 1
+         ORDER BY r.id, s.id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, Option<String>>(2)?,
+            row.get::<_, i64>(3)?,
+            row.get::<_, String>(4)?,
+            row.get::<_, Option<String>>(5)?,
+        ))
+    })?;
+
+    let mut areas: Vec<Area> = Vec::new();
+    let mut index: HashMap<(i64, String), usize> = HashMap::new();
+    for row in rows {
+        let (root_id, root_path, layout, source_id, rel_path, camera) = row?;
+
+        // A declared layout covers the whole root
+        let (base_dir, area_rel) = match (&layout, rel_path.split_once('/')) {
+            (None, Some((top, rest))) => (top.to_string(), rest.to_string()),
+            _ => (String::new(), rel_path.clone()),
+        };
+        let i = *index.entry((root_id, base_dir.clone())).or_insert_with(|| {
+            areas.push(Area {
+                archive_root_id: root_id,
+                root_path,
+                base_dir,
+                layout,
+                files: 0,
+                exts: HashMap::new(),
+                cameras: HashMap::new(),
+                sample: Vec::new(),
+            });
+            areas.len() - 1
+        });
+
+        let area = &mut areas[i];
+        area.files += 1;
+        if let Some(ext) = Path::new(&rel_path).extension().and_then(|e| e.to_str()) {
+            *area.exts.entry(ext.to_lowercase()).or_default() += 1;
+        }
+        if let Some(camera) = camera {
+            *area.cameras.entry(camera).or_default() += 1;
+        }
+        if area.sample.len() < LAYOUT_SAMPLE {
+            area.sample.push((source_id, area_rel));
+        }
+    }
+    Ok(areas)
+}
+
+/// The first candidate layout that reproduces the path of most sampled files
+fn infer_layout(conn: &Connection, sample: &[(i64, String)]) -> Result<Option<String>> {
+    let mut sources = Vec::with_capacity(sample.len());
+    for (source_id, area_rel) in sample {
+        if let Some(source) = fetch_source(conn, *source_id)? {
+            sources.push((source, area_rel));
+        }
+    }
+    if sources.is_empty() {
+        return Ok(None);
+    }
+
+    for layout in CANDIDATE_LAYOUTS {
+        let matching = sources
+            .iter()
+            .filter(|(source, area_rel)| {
+                expand_pattern(layout, source, Path::new(&source.path)).is_ok_and(|p| &p == *area_rel)
+            })
+            .count();
+        if matching * 2 > sources.len() {
+            return Ok(Some(layout.to_string()));
+        }
+    }
+    Ok(None)
+}
+
+/// "manifest.toml" -> "manifest-2.toml"
+fn numbered_manifest_path(output_path: &Path, n: usize) -> PathBuf {
+    let stem = output_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "manifest".to_string());
+    let name = match output_path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, n, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, n),
+    };
+    output_path.with_file_name(name)
+}
@@ -0,0 +1,296 @@
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::db::Connection;
+use crate::exclude;
+
+/// SQL condition: the source's content is present in some archive root
+const ARCHIVED_CONDITION: &str =
+    "EXISTS (SELECT 1 FROM archived_objects ao WHERE ao.object_id = s.object_id)";
+
+/// SQL expression: capture time of source `s` (EXIF, then video creation time,
+/// on the object or the source), falling back to the file mtime
+const CAPTURE_TIME_EXPR: &str = "COALESCE(
+    (SELECT value_time FROM facts WHERE entity_type = 'object' AND entity_id = s.object_id
+        AND key = 'content.exif.datetime_original'),
+    (SELECT value_time FROM facts WHERE entity_type = 'source' AND entity_id = s.id
+        AND key = 'content.exif.datetime_original'),
+    (SELECT value_time FROM facts WHERE entity_type = 'object' AND entity_id = s.object_id
+        AND key = 'content.video.creation_time'),
+    (SELECT value_time FROM facts WHERE entity_type = 'source' AND entity_id = s.id
+        AND key = 'content.video.creation_time'),
+    s.mtime)";
+
+/// SQL condition on fact `m`: another present source's quickhash for its
+/// current version, or any object's
+pub const QUICKHASH_MATCHES_ANY: &str = "(m.entity_type = 'object' OR EXISTS (
+    SELECT 1 FROM sources ms WHERE ms.id = m.entity_id AND ms.present = 1 AND ms.basis_rev = m.observed_basis_rev))";
+
+/// SQL condition on fact `m`: the quickhash of content in some archive root
+pub const QUICKHASH_MATCHES_ARCHIVED: &str =
+    "(m.entity_type = 'object' AND EXISTS (SELECT 1 FROM archived_objects ao WHERE ao.object_id = m.entity_id))";
+
+/// SQL condition: source `s` has a quickhash for its current version that a
+/// fact `m` meeting `matched` shares, so a full hash may well find it is
+/// that content
+pub fn quickhash_match_condition(matched: &str) -> String {
+    format!(
+        "EXISTS (SELECT 1 FROM facts q JOIN facts m ON m.key = 'content.quickhash' AND m.value_text = q.value_text AND m.id != q.id
+         WHERE q.entity_type = 'source' AND q.entity_id = s.id AND q.key = 'content.quickhash'
+           AND q.observed_basis_rev = s.basis_rev AND {})",
+        matched
+    )
+}
+
+/// Per-root counts. `hashed` and `archived` only count included (present, non-excluded) sources.
+#[derive(Serialize)]
+pub struct RootCoverage {
+    pub id: i64,
+    pub path: String,
+    pub role: String,
+    /// Root directory reachable right now (mounted)
+    pub available: bool,
+    /// Present sources, including excluded ones
+    pub sources: i64,
+    pub bytes: i64,
+    pub missing: i64,
+    pub excluded: i64,
+    pub hashed: i64,
+    pub archived: i64,
+}
+
+impl RootCoverage {
+    /// Present sources that are not excluded
+    pub fn included(&self) -> i64 {
+        self.sources - self.excluded
+    }
+}
+
+#[derive(Serialize)]
+pub struct UnarchivedDir {
+    pub path: String,
+    pub files: i64,
+    pub bytes: i64,
+    pub unhashed: i64,
+}
+
+#[derive(Serialize)]
+pub struct DuplicateSource {
+    pub id: i64,
+    pub path: String,
+    pub role: String,
+}
+
+#[derive(Serialize)]
+pub struct DuplicateSet {
+    pub hash: String,
+    pub copies: i64,
+    pub size: i64,
+    pub sources: Vec<DuplicateSource>,
+}
+
+#[derive(Serialize)]
+pub struct DuplicateTotals {
+    /// Objects with more than one present copy (hard links count once)
+    pub sets: i64,
+    /// Present copies beyond the first of each object
+    pub extra_copies: i64,
+    pub reclaimable_bytes: i64,
+    /// Unhashed present sources whose quickhash matches other content: likely
+    /// duplicates a full hash (`canon hash --quick-matches`) would confirm
+    pub quickhash_matches: i64,
+}
+
+#[derive(Serialize)]
+pub struct YearCoverage {
+    /// None when the capture time is unknown or unparseable
+    pub year: Option<i32>,
+    pub sources: i64,
+    pub bytes: i64,
+    pub hashed: i64,
+    pub archived: i64,
+}
+
+pub fn root_coverage(conn: &Connection) -> Result<Vec<RootCoverage>> {
+    let included = exclude::exclude_clause(false);
+    let mut stmt = conn.prepare(&format!(
+        "SELECT r.id, r.path, r.role, r.available,
+                COALESCE(SUM(s.present = 1), 0),
+                COALESCE(SUM(CASE WHEN s.present = 1 THEN s.size ELSE 0 END), 0),
+                COALESCE(SUM(s.present = 0), 0),
+                COALESCE(SUM(s.present = 1 AND NOT ({included})), 0),
+                COALESCE(SUM(s.present = 1 AND {included} AND s.object_id IS NOT NULL), 0),
+                COALESCE(SUM(s.present = 1 AND {included} AND {archived}), 0)
+         FROM roots r
+         LEFT JOIN sources s ON s.root_id = r.id
+         GROUP BY r.id
+         ORDER BY r.role DESC, r.path",
+        archived = ARCHIVED_CONDITION
+    ))?;
+
+    let roots = stmt
+        .query_map([], |row| {
+            Ok(RootCoverage {
+                id: row.get(0)?,
+                path: row.get(1)?,
+                role: row.get(2)?,
+                available: row.get(3)?,
+                sources: row.get(4)?,
+                bytes: row.get(5)?,
+                missing: row.get(6)?,
+                excluded: row.get(7)?,
+                hashed: row.get(8)?,
+                archived: row.get(9)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(roots)
+}
+
+/// Directories in source roots with the most unarchived bytes (unhashed files count as unarchived)
+pub fn unarchived_dirs(conn: &Connection, limit: i64) -> Result<Vec<UnarchivedDir>> {
+    // rtrim(p, replace(p, '/', '')) strips the last path component, leaving "dir/"
+    let mut stmt = conn.prepare(&format!(
+        "SELECT r.path, rtrim(s.rel_path, replace(s.rel_path, '/', '')) AS dir,
+                COUNT(*), SUM(s.size), SUM(CASE WHEN s.object_id IS NULL THEN 1 ELSE 0 END)
+         FROM sources s
+         JOIN roots r ON s.root_id = r.id
+         WHERE s.present = 1 AND r.role = 'source' AND {} AND NOT ({})
+         GROUP BY r.path, dir
+         ORDER BY SUM(s.size) DESC
+         LIMIT ?",
+        exclude::exclude_clause(false),
+        ARCHIVED_CONDITION
+    ))?;
+
+    let dirs = stmt
+        .query_map([limit], |row| {
+            let root: String = row.get(0)?;
+            let dir: String = row.get(1)?;
+            let dir = dir.trim_end_matches('/');
+            Ok(UnarchivedDir {
+                path: if dir.is_empty() { root } else { format!("{}/{}", root, dir) },
+                files: row.get(2)?,
+                bytes: row.get(3)?,
+                unhashed: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(dirs)
+}
+
+/// Counted once per file on disk: hard links to one file (same device and
+/// inode) are not copies that take space
+const PHYSICAL_COPIES: &str = "COUNT(DISTINCT COALESCE(s.device || ':' || s.inode, '#' || s.id))";
+
+/// Objects with more than one present copy on disk, largest reclaimable size first
+pub fn duplicate_sets(conn: &Connection, limit: i64) -> Result<Vec<DuplicateSet>> {
+    let groups: Vec<(i64, String, i64, i64)> = conn
+        .prepare(&format!(
+            "SELECT o.id, o.hash_value, {copies}, MAX(s.size)
+             FROM sources s
+             JOIN objects o ON s.object_id = o.id
+             WHERE s.present = 1
+             GROUP BY o.id
+             HAVING {copies} > 1
+             ORDER BY MAX(s.size) * ({copies} - 1) DESC
+             LIMIT ?",
+            copies = PHYSICAL_COPIES
+        ))?
+        .query_map([limit], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT s.id, r.path, s.rel_path, r.role
+         FROM sources s
+         JOIN roots r ON s.root_id = r.id
+         WHERE s.object_id = ? AND s.present = 1
+         ORDER BY r.role DESC, r.path, s.rel_path",
+    )?;
+
+    let mut sets = Vec::new();
+    for (object_id, hash, copies, size) in groups {
+        let sources = stmt
+            .query_map([object_id], |row| {
+                let root: String = row.get(1)?;
+                let rel: String = row.get(2)?;
+                Ok(DuplicateSource {
+                    id: row.get(0)?,
+                    path: if rel.is_empty() { root } else { format!("{}/{}", root, rel) },
+                    role: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        sets.push(DuplicateSet { hash, copies, size, sources });
+    }
+    Ok(sets)
+}
+
+pub fn duplicate_totals(conn: &Connection) -> Result<DuplicateTotals> {
+    let totals = conn.query_row(
+        &format!(
+            "SELECT COUNT(*), COALESCE(SUM(copies - 1), 0), COALESCE(SUM(size * (copies - 1)), 0)
+             FROM (
+                 SELECT {copies} AS copies, MAX(s.size) AS size
+                 FROM sources s
+                 WHERE s.present = 1 AND s.object_id IS NOT NULL
+                 GROUP BY s.object_id
+                 HAVING {copies} > 1
+             )",
+            copies = PHYSICAL_COPIES
+        ),
+        [],
+        |row| {
+            Ok(DuplicateTotals {
+                sets: row.get(0)?,
+                extra_copies: row.get(1)?,
+                reclaimable_bytes: row.get(2)?,
+                quickhash_matches: 0,
+            })
+        },
+    )?;
+    let quickhash_matches = conn.query_row(
+        &format!(
+            "SELECT COUNT(*) FROM sources s WHERE s.present = 1 AND s.object_id IS NULL AND {}",
+            quickhash_match_condition(QUICKHASH_MATCHES_ANY)
+        ),
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(DuplicateTotals { quickhash_matches, ..totals })
+}
+
+/// Included sources in source roots grouped by capture year (EXIF date or video
+/// creation time, else file mtime)
+pub fn year_breakdown(conn: &Connection) -> Result<Vec<YearCoverage>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT CAST(strftime('%Y', t, 'unixepoch') AS INTEGER) AS year,
+                COUNT(*), SUM(size), SUM(hashed), SUM(archived)
+         FROM (
+             SELECT {} AS t, s.size AS size,
+                    s.object_id IS NOT NULL AS hashed,
+                    {} AS archived
+             FROM sources s
+             JOIN roots r ON s.root_id = r.id
+             WHERE s.present = 1 AND r.role = 'source' AND {}
+         )
+         GROUP BY year
+         ORDER BY year IS NULL, year",
+        CAPTURE_TIME_EXPR,
+        ARCHIVED_CONDITION,
+        exclude::exclude_clause(false)
+    ))?;
+
+    let years = stmt
+        .query_map([], |row| {
+            Ok(YearCoverage {
+                year: row.get(0)?,
+                sources: row.get(1)?,
+                bytes: row.get(2)?,
+                hashed: row.get(3)?,
+                archived: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(years)
+}
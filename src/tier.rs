@@ -0,0 +1,314 @@
+use anyhow::{bail, Context, Result};
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cluster::{self, capture_time, fetch_source, Manifest, ManifestMeta, ManifestOutput, ManifestSource};
+use crate::db::{resolve_archive_path, Connection, Db};
+use crate::output;
+use crate::query::SourceQuery;
+use crate::trash::parse_age;
+use crate::util::{current_timestamp, format_bytes};
+
+/// A tiering policy: tiers are tried in order and each source goes to the
+/// first one it matches, e.g.
+///
+/// ```toml
+/// [[tier]]
+/// name = "hot"
+/// dest = "/mnt/fast/media"
+/// newer_than = "90d"
+///
+/// [[tier]]
+/// name = "cold"
+/// dest = "/mnt/offline/media"
+/// older_than = "2y"
+/// kind = ["video", "raw"]
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Policy {
+    #[serde(default)]
+    tier: Vec<TierRule>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TierRule {
+    name: String,
+    /// Path inside an archive root (relative paths are relative to the policy file)
+    dest: PathBuf,
+    /// Age (capture time, else mtime) at least this, e.g. "2y", "180d"
+    older_than: Option<String>,
+    /// Age less than this
+    newer_than: Option<String>,
+    /// content.kind values (photo, video, raw, ...); any matches
+    #[serde(default)]
+    kind: Vec<String>,
+    min_size: Option<String>,
+    max_size: Option<String>,
+    /// Filter expressions, all of which must match
+    #[serde(rename = "where", default)]
+    filters: Vec<String>,
+    /// Output pattern (defaults to the archive root's layout, else {filename})
+    pattern: Option<String>,
+}
+
+/// A policy tier resolved against the index
+struct Tier {
+    name: String,
+    destination: String,
+    archive_root_id: i64,
+    base_dir: String,
+    pattern: String,
+    filters: Vec<String>,
+    older_than: Option<i64>,
+    newer_than: Option<i64>,
+    kinds: Vec<String>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    /// Sources matching the tier's filters (None when it has none)
+    matching: Option<HashSet<i64>>,
+}
+
+impl Tier {
+    fn matches(&self, source: &ManifestSource, now: i64) -> bool {
+        let age = now - capture_time(source).or(source.mtime).unwrap_or(now);
+        if self.older_than.is_some_and(|min| age < min) || self.newer_than.is_some_and(|max| age >= max) {
+            return false;
+        }
+        let size = source.size as u64;
+        if self.min_size.is_some_and(|min| size < min) || self.max_size.is_some_and(|max| size > max) {
+            return false;
+        }
+        if !self.kinds.is_empty() {
+            let kind = source.facts.get("content.kind").and_then(|v| v.as_str());
+            if !kind.is_some_and(|k| self.kinds.iter().any(|want| want == k)) {
+                return false;
+            }
+        }
+        self.matching.as_ref().is_none_or(|ids| ids.contains(&source.id))
+    }
+}
+
+pub struct TierOptions {
+    /// Manifest name; each tier's manifest is `<stem>-<tier>.toml`
+    pub output: PathBuf,
+}
+
+#[derive(Default, Serialize)]
+struct TierReport {
+    tiers: Vec<TierSummary>,
+    /// Sources matching no tier (they stay where they are)
+    unassigned: u64,
+}
+
+#[derive(Serialize)]
+struct TierSummary {
+    name: String,
+    destination: String,
+    /// Written when the tier has sources to transfer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    manifest: Option<String>,
+    sources: usize,
+    bytes: u64,
+    /// Matching sources whose content is already in the tier's archive root
+    in_place: u64,
+}
+
+/// Assign each source matching the scope and filters to the first policy tier
+/// it matches, and write one manifest per tier with the sources whose content
+/// isn't in that tier's archive root yet. Sources in archive roots are
+/// considered too, so media can move between tiers.
+pub fn suggest(
+    db: &Db,
+    policy_path: &Path,
+    scope_path: Option<&Path>,
+    filter_strs: &[String],
+    options: &TierOptions,
+) -> Result<()> {
+    let conn = db.conn();
+    let tiers = load_policy(conn, policy_path, scope_path)?;
+
+    let source_ids = SourceQuery::new()
+        .scope(scope_path)?
+        .filters(conn, filter_strs)?
+        .include_archived(true)
+        .ids(conn)?;
+
+    let now = current_timestamp();
+    let mut report = TierReport::default();
+    let mut planned: Vec<Vec<ManifestSource>> = tiers.iter().map(|_| Vec::new()).collect();
+    let mut in_place = vec![0u64; tiers.len()];
+    // Content already planned per tier: one copy of each object is enough
+    let mut planned_objects: Vec<HashSet<i64>> = tiers.iter().map(|_| HashSet::new()).collect();
+
+    for source_id in source_ids {
+        let Some(source) = fetch_source(conn, source_id)? else {
+            continue;
+        };
+        let Some(i) = tiers.iter().position(|tier| tier.matches(&source, now)) else {
+            report.unassigned += 1;
+            continue;
+        };
+        if is_in_place(conn, &source, tiers[i].archive_root_id)? {
+            in_place[i] += 1;
+            continue;
+        }
+        if let Some(object_id) = source.object_id {
+            if !planned_objects[i].insert(object_id) {
+                continue;
+            }
+        }
+        planned[i].push(source);
+    }
+
+    let generated_at = current_timestamp();
+    for ((tier, sources), in_place) in tiers.into_iter().zip(planned).zip(in_place) {
+        let bytes = sources.iter().map(|s| s.size as u64).sum();
+        let manifest_path = if sources.is_empty() {
+            None
+        } else {
+            let path = tier_manifest_path(&options.output, &tier.name);
+            let mut query = filter_strs.to_vec();
+            query.extend(tier.filters.iter().cloned());
+            let manifest = Manifest {
+                meta: ManifestMeta { query, generated_at, volume: None },
+                output: ManifestOutput {
+                    pattern: tier.pattern.clone(),
+                    archive_root_id: tier.archive_root_id,
+                    base_dir: tier.base_dir.clone(),
+                },
+                sources,
+            };
+            cluster::write_manifest(&manifest, &path)?;
+            Some((path, manifest.sources.len()))
+        };
+
+        report.tiers.push(TierSummary {
+            name: tier.name,
+            destination: tier.destination,
+            manifest: manifest_path.as_ref().map(|(path, _)| path.display().to_string()),
+            sources: manifest_path.map(|(_, count)| count).unwrap_or(0),
+            bytes,
+            in_place,
+        });
+    }
+
+    output::emit(&report, print_report)
+}
+
+fn print_report(report: &TierReport) {
+    for tier in &report.tiers {
+        match &tier.manifest {
+            Some(path) => println!(
+                "Tier {} -> {}: {} sources ({}) to transfer, {} already there: {}",
+                tier.name,
+                tier.destination,
+                tier.sources,
+                format_bytes(tier.bytes),
+                tier.in_place,
+                path
+            ),
+            None => println!(
+                "Tier {} -> {}: nothing to transfer, {} already there",
+                tier.name, tier.destination, tier.in_place
+            ),
+        }
+    }
+    println!("{} sources match no tier and stay where they are", report.unassigned);
+    if report.tiers.iter().any(|tier| tier.manifest.is_some()) {
+        println!("Review the manifests, then run canon apply (--move to free the faster tier)");
+    }
+}
+
+/// Parse the policy and resolve each tier's destination and filters
+fn load_policy(conn: &Connection, policy_path: &Path, scope_path: Option<&Path>) -> Result<Vec<Tier>> {
+    let content = fs::read_to_string(policy_path)
+        .with_context(|| format!("Failed to read tier policy: {}", policy_path.display()))?;
+    let policy: Policy = toml::from_str(&content)
+        .with_context(|| format!("Failed to parse tier policy: {}", policy_path.display()))?;
+    if policy.tier.is_empty() {
+        bail!("Tier policy {} has no [[tier]] entries", policy_path.display());
+    }
+    let base = policy_path.parent().unwrap_or(Path::new(""));
+
+    let mut names = HashSet::new();
+    let mut tiers = Vec::with_capacity(policy.tier.len());
+    for rule in policy.tier {
+        if !names.insert(rule.name.clone()) {
+            bail!("Tier '{}' is defined more than once", rule.name);
+        }
+        if rule.name.is_empty() || rule.name.contains(['/', '\\']) {
+            bail!("Invalid tier name '{}' (used in the manifest file name)", rule.name);
+        }
+        let context = || format!("Invalid tier '{}'", rule.name);
+
+        let (archive_root_id, root_path, base_dir) =
+            resolve_archive_path(conn, &base.join(&rule.dest)).with_context(context)?;
+        let destination = if base_dir.is_empty() { root_path } else { format!("{}/{}", root_path, base_dir) };
+        let pattern = match rule.pattern {
+            Some(pattern) => pattern,
+            None => cluster::output_pattern(conn, archive_root_id, &base_dir)?,
+        };
+        let matching = if rule.filters.is_empty() {
+            None
+        } else {
+            let query = SourceQuery::new()
+                .scope(scope_path)?
+                .filters(conn, &rule.filters)
+                .with_context(context)?
+                .include_archived(true);
+            Some(query.ids(conn)?.into_iter().collect())
+        };
+
+        tiers.push(Tier {
+            older_than: rule.older_than.as_deref().map(parse_age).transpose().with_context(context)?,
+            newer_than: rule.newer_than.as_deref().map(parse_age).transpose().with_context(context)?,
+            min_size: rule.min_size.as_deref().map(cluster::parse_size).transpose().with_context(context)?,
+            max_size: rule.max_size.as_deref().map(cluster::parse_size).transpose().with_context(context)?,
+            name: rule.name,
+            destination,
+            archive_root_id,
+            base_dir,
+            pattern,
+            filters: rule.filters,
+            kinds: rule.kind,
+            matching,
+        });
+    }
+    Ok(tiers)
+}
+
+/// Whether the source's content already has a present copy in `archive_root_id`
+fn is_in_place(conn: &Connection, source: &ManifestSource, archive_root_id: i64) -> Result<bool> {
+    if source.root_id == archive_root_id {
+        return Ok(true);
+    }
+    let Some(object_id) = source.object_id else {
+        return Ok(false);
+    };
+    Ok(conn
+        .query_row(
+            "SELECT 1 FROM sources WHERE root_id = ? AND object_id = ? AND present = 1 LIMIT 1",
+            [archive_root_id, object_id],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some())
+}
+
+/// "tier.toml" -> "tier-cold.toml"
+fn tier_manifest_path(output_path: &Path, tier: &str) -> PathBuf {
+    let stem = output_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "tier".to_string());
+    let name = match output_path.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, tier, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, tier),
+    };
+    output_path.with_file_name(name)
+}
@@ -0,0 +1,269 @@
+use anyhow::{bail, Context, Result};
+use rusqlite::params;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+use crate::db::{Connection, Db};
+use crate::error::{coded, ErrorCode};
+use crate::output;
+use crate::remote;
+use crate::scan;
+use crate::util::{current_timestamp, format_bytes, format_timestamp};
+
+/// Directory at the top of each root that holds its trashed files, as
+/// `.canon-trash/<trashed_at>-<source id>/<file name>`. Scan never walks it.
+pub const TRASH_DIR: &str = ".canon-trash";
+
+/// Move a source's file into its root's trash instead of deleting it, and mark
+/// the source missing. The file stays on the same filesystem, so this is a
+/// rename; `canon trash restore` puts it back, `canon trash purge` deletes it.
+pub fn trash_source(conn: &Connection, source_id: i64, reason: &str) -> Result<()> {
+    let (root_id, root_path, rel_path, object_id, size): (i64, String, String, Option<i64>, i64) = conn.query_row(
+        "SELECT s.root_id, r.path, s.rel_path, s.object_id, s.size FROM sources s
+         JOIN roots r ON s.root_id = r.id WHERE s.id = ?",
+        [source_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+    )?;
+    if remote::is_remote(&root_path) {
+        bail!("Remote roots have no trash: {}", remote::join(&root_path, &rel_path));
+    }
+
+    let now = current_timestamp();
+    let file_name = Path::new(&rel_path).file_name().context("Source path has no file name")?;
+    let item_dir = format!("{}/{}-{}", TRASH_DIR, now, source_id);
+    let trash_rel_path = format!("{}/{}", item_dir, file_name.to_string_lossy());
+
+    let root = Path::new(&root_path);
+    fs::create_dir_all(root.join(&item_dir))
+        .with_context(|| format!("Failed to create trash directory in {}", root_path))?;
+    fs::rename(root.join(&rel_path), root.join(&trash_rel_path))
+        .with_context(|| format!("Failed to move {}/{} to the trash", root_path, rel_path))?;
+
+    conn.execute(
+        "INSERT INTO trash (source_id, root_id, rel_path, trash_rel_path, object_id, size, reason, trashed_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        params![source_id, root_id, rel_path, trash_rel_path, object_id, size, reason, now],
+    )?;
+    conn.execute(
+        "UPDATE sources SET present = 0, last_seen_at = ? WHERE id = ?",
+        params![now, source_id],
+    )?;
+    Ok(())
+}
+
+// ============================================================================
+// canon trash list
+// ============================================================================
+
+#[derive(Serialize)]
+struct TrashItem {
+    id: i64,
+    /// Where the file was (root path + relative path)
+    original_path: String,
+    /// Where it is now
+    trash_path: String,
+    hash: Option<String>,
+    size: i64,
+    reason: String,
+    trashed_at: i64,
+}
+
+/// Result of `canon trash list`
+#[derive(Serialize)]
+struct TrashList {
+    items: Vec<TrashItem>,
+    total_size: i64,
+}
+
+pub fn list(db: &Db) -> Result<()> {
+    let items = load_items(db.conn(), None)?;
+    let total_size = items.iter().map(|item| item.size).sum();
+    let report = TrashList { items, total_size };
+
+    output::emit(&report, |report| {
+        if report.items.is_empty() {
+            println!("Trash is empty");
+            return;
+        }
+        println!("{:>5}  {:<16}  {:>9}  {:<14}  PATH", "ID", "TRASHED", "SIZE", "REASON");
+        for item in &report.items {
+            println!(
+                "{:>5}  {:<16}  {:>9}  {:<14}  {}",
+                item.id,
+                format_timestamp(item.trashed_at),
+                format_bytes(item.size as u64),
+                item.reason,
+                item.original_path
+            );
+            if let Some(ref hash) = item.hash {
+                println!("{:>5}  {}", "", hash);
+            }
+        }
+        println!("{} items, {}", report.items.len(), format_bytes(report.total_size as u64));
+    })
+}
+
+// ============================================================================
+// canon trash restore
+// ============================================================================
+
+/// Result of `canon trash restore`
+#[derive(Serialize)]
+struct RestoreReport {
+    restored: Vec<String>,
+    /// Items left in the trash because something now occupies the original path
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    blocked: Vec<String>,
+}
+
+/// Move trashed files back to their original paths and index them again
+pub fn restore(db: &Db, ids: &[i64]) -> Result<()> {
+    let conn = db.conn();
+    let mut items = Vec::with_capacity(ids.len());
+    for &id in ids {
+        let item = load_items(conn, Some(id))?
+            .pop()
+            .ok_or_else(|| coded(ErrorCode::Usage, format!("No trash item with id {}", id)))?;
+        items.push(item);
+    }
+
+    let mut report = RestoreReport { restored: Vec::new(), blocked: Vec::new() };
+    for item in items {
+        let original = Path::new(&item.original_path);
+        if original.exists() {
+            eprintln!("Warning: {} already exists, leaving trash item {} in place", item.original_path, item.id);
+            report.blocked.push(item.original_path);
+            continue;
+        }
+        if let Some(parent) = original.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        fs::rename(&item.trash_path, original)
+            .with_context(|| format!("Failed to restore {} to {}", item.trash_path, item.original_path))?;
+        remove_item_dir(Path::new(&item.trash_path));
+
+        let (root_id, root_path, rel_path): (i64, String, String) = conn.query_row(
+            "SELECT t.root_id, r.path, t.rel_path FROM trash t JOIN roots r ON t.root_id = r.id WHERE t.id = ?",
+            [item.id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        scan::index_file(conn, root_id, Path::new(&root_path), &rel_path)?;
+        conn.execute("DELETE FROM trash WHERE id = ?", [item.id])?;
+        report.restored.push(item.original_path);
+    }
+
+    output::emit(&report, |report| {
+        for path in &report.restored {
+            println!("Restored: {}", path);
+        }
+        println!("Restored {} items", report.restored.len());
+    })
+}
+
+// ============================================================================
+// canon trash purge
+// ============================================================================
+
+/// Result of `canon trash purge`
+#[derive(Serialize)]
+struct PurgeReport {
+    dry_run: bool,
+    count: usize,
+    size: i64,
+    /// Purged paths (listed for dry runs only)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    paths: Vec<String>,
+}
+
+/// Permanently delete trashed files older than `older_than` (seconds)
+pub fn purge(db: &Db, older_than: i64, dry_run: bool) -> Result<()> {
+    let conn = db.conn();
+    let cutoff = current_timestamp() - older_than;
+    let items: Vec<TrashItem> = load_items(conn, None)?.into_iter().filter(|item| item.trashed_at <= cutoff).collect();
+    let size = items.iter().map(|item| item.size).sum();
+
+    if dry_run {
+        let paths = items.iter().map(|item| item.original_path.clone()).collect();
+        let report = PurgeReport { dry_run, count: items.len(), size, paths };
+        return output::emit(&report, |report| {
+            println!("Would purge {} items ({}):", report.count, format_bytes(report.size as u64));
+            for path in &report.paths {
+                println!("  {}", path);
+            }
+        });
+    }
+
+    for item in &items {
+        let trash_path = Path::new(&item.trash_path);
+        match fs::remove_file(trash_path) {
+            Ok(()) => {}
+            // Already gone (deleted by hand): just forget the item
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("Failed to delete {}", item.trash_path)),
+        }
+        remove_item_dir(trash_path);
+        conn.execute("DELETE FROM trash WHERE id = ?", [item.id])?;
+    }
+
+    let report = PurgeReport { dry_run, count: items.len(), size, paths: Vec::new() };
+    output::emit(&report, |report| {
+        println!("Purged {} items, freed {}", report.count, format_bytes(report.size as u64))
+    })
+}
+
+/// Parse an age like "30d", "12h", "2w" or "1y" into seconds
+pub fn parse_age(s: &str) -> Result<i64> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: i64 = number.parse().with_context(|| format!("Invalid age '{}'", s))?;
+    let multiplier = match unit.trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "" | "d" => 86400,
+        "w" => 7 * 86400,
+        "y" => 365 * 86400,
+        _ => bail!("Invalid age unit in '{}'. Use s, m, h, d, w or y", s),
+    };
+    Ok(number * multiplier)
+}
+
+// ============================================================================
+// Helper Functions
+// ============================================================================
+
+/// Trash items, oldest first (or just the one with `id`)
+fn load_items(conn: &Connection, id: Option<i64>) -> Result<Vec<TrashItem>> {
+    let mut stmt = conn.prepare(
+        "SELECT t.id, r.path, t.rel_path, t.trash_rel_path, o.hash_value, t.size, t.reason, t.trashed_at
+         FROM trash t
+         JOIN roots r ON t.root_id = r.id
+         LEFT JOIN objects o ON t.object_id = o.id
+         WHERE ?1 IS NULL OR t.id = ?1
+         ORDER BY t.trashed_at, t.id",
+    )?;
+    let items = stmt
+        .query_map([id], |row| {
+            let root_path: String = row.get(1)?;
+            Ok(TrashItem {
+                id: row.get(0)?,
+                original_path: format!("{}/{}", root_path, row.get::<_, String>(2)?),
+                trash_path: format!("{}/{}", root_path, row.get::<_, String>(3)?),
+                hash: row.get(4)?,
+                size: row.get(5)?,
+                reason: row.get(6)?,
+                trashed_at: row.get(7)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(items)
+}
+
+/// Remove a trash item's (now empty) directory
+fn remove_item_dir(trash_path: &Path) {
+    if let Some(dir) = trash_path.parent() {
+        fs::remove_dir(dir).ok();
+    }
+}
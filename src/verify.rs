@@ -0,0 +1,375 @@
+use anyhow::{Context, Result};
+use rusqlite::params;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::apply;
+use crate::db::{parse_root_spec, refresh_one_root_availability, resolve_root_path, Connection, Db};
+use crate::error::{coded, ErrorCode};
+use crate::import_facts;
+use crate::output;
+use crate::platform;
+use crate::remote::{self, Rclone};
+use crate::roots;
+use crate::util::{current_timestamp, format_bytes};
+
+pub struct VerifyOptions {
+    /// Hash downloaded content instead of trusting backend hashes (remote roots)
+    pub download: bool,
+    /// Verify this archive root (a root spec) instead of a path
+    pub archive: Option<String>,
+    /// Local roots: files verified longest ago (or never) first
+    pub oldest_first: bool,
+    /// Local roots: stop before reading more than this many bytes
+    pub max_bytes: Option<u64>,
+}
+
+#[derive(Default, Serialize)]
+struct VerifyStats {
+    files: u64,
+    ok: u64,
+    mismatched: u64,
+    newly_hashed: u64,
+    no_hash: u64,
+    missing: u64,
+    not_indexed: u64,
+    mismatches: Vec<Mismatch>,
+}
+
+#[derive(Serialize)]
+struct Mismatch {
+    path: String,
+    indexed: String,
+    remote: String,
+}
+
+/// Result of verifying files of a local root
+#[derive(Default, Serialize)]
+struct LocalVerifyStats {
+    files: u64,
+    bytes: u64,
+    ok: u64,
+    mismatched: u64,
+    missing: u64,
+    /// Size or mtime differ from the index: modified, not decayed
+    changed: u64,
+    failed: u64,
+    /// Present sources in scope without a hash, which can't be verified
+    no_hash: u64,
+    /// Files left for a later run by --max-bytes
+    over_budget: u64,
+    mismatches: Vec<LocalMismatch>,
+}
+
+#[derive(Serialize)]
+struct LocalMismatch {
+    path: String,
+    indexed: String,
+    actual: String,
+}
+
+/// Re-check indexed hashes of a root or subtree: local files are read and
+/// hashed again, remote ones checked against `rclone hashsum`. `path` may be
+/// None when `options.archive` names the root.
+pub fn run(db: &Db, path: Option<&Path>, options: &VerifyOptions) -> Result<()> {
+    let conn = db.conn();
+
+    let (root_id, root_path, rel_prefix) = match (path, &options.archive) {
+        (Some(path), _) => {
+            let (root_id, root_path, _role, rel_prefix) = resolve_root_path(conn, path)?
+                .with_context(|| format!("Path '{}' is not inside any existing root", path.display()))?;
+            (root_id, root_path, rel_prefix)
+        }
+        (None, Some(spec)) => {
+            let root_id = parse_root_spec(conn, spec, Some("archive"))?;
+            let root_path: String = conn.query_row("SELECT path FROM roots WHERE id = ?", [root_id], |row| row.get(0))?;
+            (root_id, root_path, String::new())
+        }
+        (None, None) => return Err(coded(ErrorCode::Usage, "Give a path or --archive ROOT to verify")),
+    };
+
+    if remote::is_remote(&root_path) {
+        if options.oldest_first || options.max_bytes.is_some() {
+            return Err(coded(ErrorCode::Usage, "--oldest-first and --max-bytes apply to local roots only"));
+        }
+        verify_remote(conn, root_id, &root_path, &rel_prefix, options)
+    } else {
+        if options.download {
+            return Err(coded(ErrorCode::Usage, "--download applies to remote roots only"));
+        }
+        verify_local(conn, root_id, &root_path, &rel_prefix, options)
+    }
+}
+
+/// Compare indexed hashes of a remote root (or subtree) against `rclone hashsum`.
+/// Sources without an object yet are linked to the reported hash. Each check of
+/// an indexed hash (ok, mismatch or missing) is recorded in `verifications`.
+fn verify_remote(
+    conn: &Connection,
+    root_id: i64,
+    root_path: &str,
+    rel_prefix: &str,
+    options: &VerifyOptions,
+) -> Result<()> {
+    let rclone = Rclone::new()?;
+    let target = remote::join(root_path, rel_prefix);
+    let hashes = rclone.hashsum(&target, options.download)?;
+
+    let indexed: Vec<(i64, String, Option<i64>, Option<String>)> = conn
+        .prepare(
+            "SELECT s.id, s.rel_path, s.object_id, o.hash_value
+             FROM sources s
+             LEFT JOIN objects o ON s.object_id = o.id
+             WHERE s.root_id = ?1 AND s.present = 1
+               AND (?2 = '' OR substr(s.rel_path, 1, length(?2) + 1) = ?2 || '/')
+             ORDER BY s.rel_path",
+        )?
+        .query_map(params![root_id, rel_prefix], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut stats = VerifyStats {
+        files: indexed.len() as u64,
+        ..Default::default()
+    };
+    let mut seen: HashSet<&str> = HashSet::new();
+
+    let now = current_timestamp();
+    let tx = conn.unchecked_transaction()?;
+    let mut record = tx.prepare(
+        "INSERT INTO verifications (source_id, object_id, verified_at, result) VALUES (?, ?, ?, ?)",
+    )?;
+    for (source_id, rel_path, object_id, indexed_hash) in &indexed {
+        // hashsum paths are relative to the listed directory
+        let key = if rel_prefix.is_empty() {
+            rel_path.as_str()
+        } else {
+            rel_path
+                .strip_prefix(&format!("{}/", rel_prefix))
+                .unwrap_or(rel_path)
+        };
+        let full_path = remote::join(root_path, rel_path);
+
+        match hashes.get(key) {
+            None => {
+                eprintln!("Warning: missing on remote: {}", full_path);
+                record.execute(params![source_id, object_id, now, "missing"])?;
+                stats.missing += 1;
+            }
+            Some(None) => stats.no_hash += 1,
+            Some(Some(remote_hash)) => match indexed_hash {
+                Some(expected) if expected == remote_hash => {
+                    record.execute(params![source_id, object_id, now, "ok"])?;
+                    stats.ok += 1;
+                }
+                Some(expected) => {
+                    record.execute(params![source_id, object_id, now, "mismatch"])?;
+                    if !output::is_json() {
+                        println!(
+                            "MISMATCH: {} (indexed {}, remote {})",
+                            full_path, expected, remote_hash
+                        );
+                    }
+                    stats.mismatches.push(Mismatch {
+                        path: full_path,
+                        indexed: expected.clone(),
+                        remote: remote_hash.clone(),
+                    });
+                    stats.mismatched += 1;
+                }
+                None => {
+                    remote::link_object(&tx, *source_id, remote_hash)?;
+                    stats.newly_hashed += 1;
+                }
+            },
+        }
+        seen.insert(key);
+    }
+    drop(record);
+    tx.commit()?;
+
+    stats.not_indexed = hashes.keys().filter(|k| !seen.contains(k.as_str())).count() as u64;
+
+    output::emit(&stats, |stats| {
+        println!(
+            "Verified {} files: {} ok, {} mismatched, {} newly hashed, {} without hash, {} missing, {} not indexed",
+            stats.files,
+            stats.ok,
+            stats.mismatched,
+            stats.newly_hashed,
+            stats.no_hash,
+            stats.missing,
+            stats.not_indexed
+        );
+    })?;
+    if stats.no_hash > 0 && !options.download {
+        eprintln!("Note: the backend has no SHA-256 for some files; use --download to hash their content");
+    }
+    if stats.not_indexed > 0 {
+        eprintln!("Note: run `canon scan {}` to index files not yet in the database", target);
+    }
+    if stats.mismatched > 0 {
+        return Err(coded(
+            ErrorCode::PartialFailure,
+            format!("{} files do not match their indexed hash", stats.mismatched),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Re-read and hash the hashed files of a local root (or subtree) and compare
+/// with their indexed SHA-256. Files whose size or mtime changed are reported
+/// as changed rather than checked, so mismatches point at decayed content.
+/// Each check is recorded in `verifications`, and an ok one as the object's
+/// `content.verified_at`.
+fn verify_local(
+    conn: &Connection,
+    root_id: i64,
+    root_path: &str,
+    rel_prefix: &str,
+    options: &VerifyOptions,
+) -> Result<()> {
+    let available = refresh_one_root_availability(conn, root_id)?;
+    if !available {
+        let reason = if roots::setting(conn, root_id, "offline")?.as_deref() == Some("on") {
+            "is marked offline (canon root online to clear)"
+        } else {
+            "is unavailable (not mounted?)"
+        };
+        return Err(coded(ErrorCode::RootUnavailable, format!("Root '{}' {}", root_path, reason)));
+    }
+
+    let order = if options.oldest_first {
+        "last_verified IS NOT NULL, last_verified, s.rel_path"
+    } else {
+        "s.rel_path"
+    };
+    let files: Vec<(i64, String, i64, i64, i64, String)> = conn
+        .prepare(&format!(
+            "SELECT s.id, s.rel_path, s.size, s.mtime, s.object_id, o.hash_value,
+                    (SELECT MAX(v.verified_at) FROM verifications v WHERE v.source_id = s.id) AS last_verified
+             FROM sources s
+             JOIN objects o ON s.object_id = o.id
+             WHERE s.root_id = ?1 AND s.present = 1 AND o.hash_type = 'sha256'
+               AND (?2 = '' OR substr(s.rel_path, 1, length(?2) + 1) = ?2 || '/')
+             ORDER BY {}",
+            order
+        ))?
+        .query_map(params![root_id, rel_prefix], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let no_hash = conn.query_row(
+        "SELECT COUNT(*) FROM sources
+         WHERE root_id = ?1 AND present = 1 AND object_id IS NULL AND (?2 = '' OR substr(rel_path, 1, length(?2) + 1) = ?2 || '/')",
+        params![root_id, rel_prefix],
+        |row| row.get(0),
+    )?;
+    let mut stats = LocalVerifyStats { no_hash, ..Default::default() };
+
+    let mut record =
+        conn.prepare("INSERT INTO verifications (source_id, object_id, verified_at, result) VALUES (?, ?, ?, ?)")?;
+    for (i, (source_id, rel_path, size, mtime, object_id, indexed)) in files.iter().enumerate() {
+        if let Some(max_bytes) = options.max_bytes {
+            if stats.bytes + *size as u64 > max_bytes {
+                stats.over_budget = (files.len() - i) as u64;
+                break;
+            }
+        }
+        let full_path = Path::new(root_path).join(rel_path);
+        let now = current_timestamp();
+        stats.files += 1;
+
+        let unchanged = || -> io::Result<bool> {
+            let metadata = fs::metadata(&full_path)?;
+            Ok(metadata.len() as i64 == *size && platform::mtime(&metadata) == *mtime)
+        };
+        match unchanged() {
+            Ok(true) => {}
+            Ok(false) => {
+                stats.changed += 1;
+                continue;
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                eprintln!("Warning: missing: {}", full_path.display());
+                record.execute(params![source_id, object_id, now, "missing"])?;
+                stats.missing += 1;
+                continue;
+            }
+            Err(e) => {
+                eprintln!("Warning: Failed to stat {}: {}", full_path.display(), e);
+                stats.failed += 1;
+                continue;
+            }
+        }
+        let actual = match apply::hash_file(&full_path) {
+            Ok(hashes) => hashes.sha256,
+            Err(e) => {
+                eprintln!("Warning: {:#}", e);
+                stats.failed += 1;
+                continue;
+            }
+        };
+        if !unchanged().unwrap_or(false) {
+            stats.changed += 1;
+            continue;
+        }
+        stats.bytes += *size as u64;
+
+        if actual == *indexed {
+            record.execute(params![source_id, object_id, now, "ok"])?;
+            let verified_at = chrono::DateTime::from_timestamp(now, 0).expect("valid timestamp").to_rfc3339();
+            import_facts::insert_fact(conn, "object", *object_id, "content.verified_at", &Value::String(verified_at), now, None)?;
+            stats.ok += 1;
+        } else {
+            record.execute(params![source_id, object_id, now, "mismatch"])?;
+            let path = full_path.display().to_string();
+            if !output::is_json() {
+                println!("MISMATCH: {} (indexed {}, now {})", path, indexed, actual);
+            }
+            stats.mismatches.push(LocalMismatch { path, indexed: indexed.clone(), actual });
+            stats.mismatched += 1;
+        }
+    }
+    drop(record);
+
+    output::emit(&stats, |stats| {
+        println!(
+            "Verified {} files ({}): {} ok, {} mismatched, {} missing, {} changed since scan, {} failed",
+            stats.files,
+            format_bytes(stats.bytes),
+            stats.ok,
+            stats.mismatched,
+            stats.missing,
+            stats.changed,
+            stats.failed
+        );
+    })?;
+    if stats.over_budget > 0 {
+        eprintln!(
+            "Note: {} files not verified within --max-bytes; --oldest-first takes them first next time",
+            stats.over_budget
+        );
+    }
+    if stats.changed > 0 {
+        eprintln!("Note: {} files changed since they were scanned; scan them again before verifying", stats.changed);
+    }
+    if stats.no_hash > 0 {
+        eprintln!("Note: {} files have no hash yet and were not verified (canon hash)", stats.no_hash);
+    }
+    if stats.mismatched > 0 {
+        return Err(coded(
+            ErrorCode::PartialFailure,
+            format!("{} files do not match their indexed hash", stats.mismatched),
+        ));
+    }
+
+    Ok(())
+}
@@ -1,13 +1,15 @@
 use anyhow::Result;
+use rusqlite::params;
 use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io::{self, Write};
 use std::path::Path;
 
 use crate::db::{Connection, Db};
-use crate::exclude;
-use crate::filter::{self, Filter};
-
-const BATCH_SIZE: i64 = 1000;
+use crate::error::{coded, ErrorCode};
+use crate::import_facts;
+use crate::query::SourceQuery;
 
 #[derive(Serialize)]
 struct WorklistEntry {
@@ -17,176 +19,223 @@ struct WorklistEntry {
     size: i64,
     mtime: i64,
     basis_rev: i64,
+    /// Requested facts the source has (see `WorklistOptions::with_facts`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    facts: Option<BTreeMap<String, Value>>,
+    #[serde(skip)]
+    object_id: Option<i64>,
 }
 
-struct FetchResult {
-    entries: Vec<WorklistEntry>,
-    max_id_seen: Option<i64>,
+#[derive(Default)]
+pub struct WorklistOptions {
+    /// Include sources from archive roots
+    pub include_archived: bool,
+    /// Include excluded sources
+    pub include_excluded: bool,
+    /// One entry per object (hashed sources); unhashed sources are all listed
+    pub unique_objects: bool,
+    /// Fact keys to copy into each entry's `facts` (`mime` or `content.mime`)
+    pub with_facts: Vec<String>,
 }
 
-pub fn run(db: &Db, scope_path: Option<&Path>, filter_strs: &[String], include_archived: bool, include_excluded: bool) -> Result<()> {
-    // Parse filters upfront
-    let filters: Vec<Filter> = filter_strs
-        .iter()
-        .map(|f| Filter::parse(f))
-        .collect::<Result<Vec<_>>>()?;
+/// Write matching sources to stdout as JSONL worklist entries
+pub fn run(db: &Db, scope_path: Option<&Path>, filter_strs: &[String], options: &WorklistOptions) -> Result<()> {
+    let stdout = io::stdout();
+    write_to(db, scope_path, filter_strs, options, stdout.lock())
+}
 
-    // Resolve scope path to realpath if provided
-    let scope_prefix = if let Some(p) = scope_path {
-        Some(std::fs::canonicalize(p)?.to_string_lossy().to_string())
-    } else {
-        None
-    };
+/// Write matching sources as JSONL worklist entries to any writer
+pub fn write_to<W: Write>(
+    db: &Db,
+    scope_path: Option<&Path>,
+    filter_strs: &[String],
+    options: &WorklistOptions,
+    writer: W,
+) -> Result<()> {
+    if let Some(key) = options.with_facts.iter().find(|key| key.starts_with("source.")) {
+        return Err(coded(
+            ErrorCode::Usage,
+            format!("--with-facts {}: source.* facts are built in; entries already carry path, size and mtime", key),
+        ));
+    }
 
-    // Check excluded count if we're skipping them
     let conn = db.conn();
-    let excluded_count = if !include_excluded {
-        exclude::count_excluded(conn, scope_prefix.as_deref(), include_archived)?
+    let query = SourceQuery::new()
+        .scope(scope_path)?
+        .filters(conn, filter_strs)?
+        .include_archived(options.include_archived)
+        .include_excluded(options.include_excluded);
+
+    // Check excluded count if we're skipping them
+    let excluded_count = if !options.include_excluded {
+        query.count_excluded(conn)?
     } else {
         0
     };
 
-    let stdout = io::stdout();
-    let mut handle = stdout.lock();
-    let mut last_id: i64 = 0;
-
-    loop {
-        let result = fetch_batch(conn, last_id, scope_prefix.as_deref(), &filters, include_archived, include_excluded)?;
-
-        // If we didn't see any source IDs, we're done
-        let max_id = match result.max_id_seen {
-            Some(id) => id,
-            None => break,
-        };
-
-        for entry in &result.entries {
-            let json = serde_json::to_string(entry)?;
-            writeln!(handle, "{}", json)?;
-        }
+    let duplicates = if options.unique_objects {
+        duplicate_copies(conn, &query)?
+    } else {
+        HashSet::new()
+    };
 
-        last_id = max_id;
-    }
+    write_query(conn, &query, &duplicates, &options.with_facts, writer)?;
 
     // Report stats to stderr
-    if include_excluded && excluded_count > 0 {
+    if options.include_excluded && excluded_count > 0 {
         eprintln!("Included {} excluded sources", excluded_count);
-    } else if !include_excluded && excluded_count > 0 {
+    } else if !options.include_excluded && excluded_count > 0 {
         eprintln!("Skipped {} excluded sources", excluded_count);
     }
+    if !duplicates.is_empty() {
+        eprintln!("Skipped {} further copies of the same objects", duplicates.len());
+    }
 
     Ok(())
 }
 
-fn fetch_batch(
-    conn: &Connection,
-    after_id: i64,
-    scope_prefix: Option<&str>,
-    filters: &[Filter],
-    include_archived: bool,
-    include_excluded: bool,
-) -> Result<FetchResult> {
-    // Build the query based on options
-    let role_clause = if include_archived {
-        "1=1" // Include all roles
-    } else {
-        "r.role = 'source'"
-    };
-
-    let exclude_clause = exclude::exclude_clause(include_excluded);
-
-    let source_ids: Vec<i64> = if let Some(prefix) = scope_prefix {
-        // Filter by path prefix
-        conn.prepare(&format!(
-            "SELECT s.id
-             FROM sources s
-             JOIN roots r ON s.root_id = r.id
-             WHERE s.present = 1 AND {} AND {} AND s.id > ?
-               AND (r.path || '/' || s.rel_path) LIKE ? || '/%'
-             ORDER BY s.id
-             LIMIT ?",
-            role_clause, exclude_clause
-        ))?
-        .query_map(rusqlite::params![after_id, prefix, BATCH_SIZE], |row| row.get(0))?
-        .collect::<Result<Vec<_>, _>>()?
-    } else {
-        conn.prepare(&format!(
-            "SELECT s.id
-             FROM sources s
-             JOIN roots r ON s.root_id = r.id
-             WHERE s.present = 1 AND {} AND {} AND s.id > ?
-             ORDER BY s.id
-             LIMIT ?",
-            role_clause, exclude_clause
-        ))?
-        .query_map(rusqlite::params![after_id, BATCH_SIZE], |row| row.get(0))?
-        .collect::<Result<Vec<_>, _>>()?
-    };
+/// Write worklist entries for the given sources (e.g. from
+/// `query::read_source_ids`), in id order. The ids were picked explicitly, so
+/// archived and excluded sources are included.
+pub fn write_sources_to<W: Write>(db: &Db, source_ids: &[i64], writer: W) -> Result<()> {
+    let query = SourceQuery::new()
+        .selection(Some(source_ids))
+        .include_archived(true)
+        .include_excluded(true);
+    write_query(db.conn(), &query, &HashSet::new(), &[], writer)
+}
 
-    if source_ids.is_empty() {
-        return Ok(FetchResult {
-            entries: Vec::new(),
-            max_id_seen: None,
-        });
-    }
+/// Write worklist entries for sources a scan just added or changed, in id
+/// order. Archived sources are included (the scan picked the root), excluded
+/// ones are not.
+pub fn write_changed_to<W: Write>(db: &Db, source_ids: &[i64], writer: W) -> Result<()> {
+    let query = SourceQuery::new().selection(Some(source_ids)).include_archived(true);
+    write_query(db.conn(), &query, &HashSet::new(), &[], writer)
+}
 
-    // Track the max ID we fetched (for pagination), before filtering
-    let max_id_seen = source_ids.last().copied();
+/// Matching sources that are not the chosen copy of their object: a copy in a
+/// source root wins over one in an archive, then the lowest source id
+fn duplicate_copies(conn: &Connection, query: &SourceQuery) -> Result<HashSet<i64>> {
+    let mut chosen: HashMap<i64, (bool, i64)> = HashMap::new();
+    let mut duplicates = HashSet::new();
+    query.for_each_batch(
+        conn,
+        "s.id, s.object_id, r.role",
+        |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Option<i64>>(1)?, row.get::<_, String>(2)? == "archive")),
+        |batch| {
+            for (id, object_id, in_archive) in batch {
+                let Some(object_id) = object_id else {
+                    continue;
+                };
+                match chosen.get(&object_id) {
+                    // Ids arrive in ascending order, so only a source-root copy replaces an archive one
+                    Some(&(chosen_in_archive, chosen_id)) if chosen_in_archive && !in_archive => {
+                        duplicates.insert(chosen_id);
+                        chosen.insert(object_id, (in_archive, id));
+                    }
+                    Some(_) => {
+                        duplicates.insert(id);
+                    }
+                    None => {
+                        chosen.insert(object_id, (in_archive, id));
+                    }
+                }
+            }
+            Ok(())
+        },
+    )?;
+    Ok(duplicates)
+}
 
-    // Apply filters
-    let filtered_ids = if filters.is_empty() {
-        source_ids
-    } else {
-        filter::apply_filters(conn, &source_ids, filters)?
-    };
+fn write_query<W: Write>(
+    conn: &Connection,
+    query: &SourceQuery,
+    skip: &HashSet<i64>,
+    with_facts: &[String],
+    writer: W,
+) -> Result<()> {
+    let mut handle = io::BufWriter::new(writer);
+
+    query.for_each_batch(
+        conn,
+        "s.id, r.path, s.rel_path, s.root_id, s.size, s.mtime, s.basis_rev, s.object_id",
+        |row| {
+            let root_path: String = row.get(1)?;
+            let rel_path: String = row.get(2)?;
+            let path = if rel_path.is_empty() {
+                root_path
+            } else {
+                format!("{}/{}", root_path, rel_path)
+            };
+            Ok(WorklistEntry {
+                source_id: row.get(0)?,
+                path,
+                root_id: row.get(3)?,
+                size: row.get(4)?,
+                mtime: row.get(5)?,
+                basis_rev: row.get(6)?,
+                facts: None,
+                object_id: row.get(7)?,
+            })
+        },
+        |mut entries| {
+            for entry in entries.iter_mut().filter(|entry| !skip.contains(&entry.source_id)) {
+                if !with_facts.is_empty() {
+                    entry.facts = Some(load_facts(conn, entry, with_facts)?);
+                }
+                let json = serde_json::to_string(entry)?;
+                writeln!(handle, "{}", json)?;
+            }
+            Ok(())
+        },
+    )?;
+    handle.flush()?;
+    Ok(())
+}
 
-    // Fetch full entries for filtered IDs
-    let mut entries = Vec::new();
-    for source_id in filtered_ids {
-        if let Some(entry) = fetch_entry(conn, source_id)? {
-            entries.push(entry);
+/// The requested facts of an entry's source and object. Source facts only
+/// count while current (observed at the source's basis_rev), since consumers
+/// such as canonargs may import them again under that basis_rev; object facts
+/// describe the content and take precedence, as in `canon cluster`.
+fn load_facts(conn: &Connection, entry: &WorklistEntry, keys: &[String]) -> Result<BTreeMap<String, Value>> {
+    let mut object_stmt = conn.prepare_cached(
+        "SELECT value_text, value_num, value_time, value_json FROM facts
+         WHERE entity_type = 'object' AND entity_id = ? AND key = ?",
+    )?;
+    let mut source_stmt = conn.prepare_cached(
+        "SELECT value_text, value_num, value_time, value_json FROM facts
+         WHERE entity_type = 'source' AND entity_id = ? AND key = ? AND observed_basis_rev = ?",
+    )?;
+
+    let mut facts = BTreeMap::new();
+    for key in keys {
+        let stored_key = fact_key(key);
+        let mut found = None;
+        if let Some(object_id) = entry.object_id {
+            let mut rows = object_stmt.query(params![object_id, stored_key])?;
+            if let Some(row) = rows.next()? {
+                found = Some(import_facts::stored_value(row)?);
+            }
+        }
+        if found.is_none() {
+            let mut rows = source_stmt.query(params![entry.source_id, stored_key, entry.basis_rev])?;
+            if let Some(row) = rows.next()? {
+                found = Some(import_facts::stored_value(row)?);
+            }
+        }
+        if let Some(value) = found {
+            facts.insert(key.clone(), value);
         }
     }
-
-    Ok(FetchResult {
-        entries,
-        max_id_seen,
-    })
+    Ok(facts)
 }
 
-fn fetch_entry(conn: &Connection, source_id: i64) -> Result<Option<WorklistEntry>> {
-    let row: Option<(i64, String, String, i64, i64, i64, i64)> = conn
-        .query_row(
-            "SELECT s.id, r.path, s.rel_path, s.root_id, s.size, s.mtime, s.basis_rev
-             FROM sources s
-             JOIN roots r ON s.root_id = r.id
-             WHERE s.id = ?",
-            [source_id],
-            |row| Ok((
-                row.get(0)?,
-                row.get(1)?,
-                row.get(2)?,
-                row.get(3)?,
-                row.get(4)?,
-                row.get(5)?,
-                row.get(6)?,
-            )),
-        )
-        .ok();
-
-    Ok(row.map(|(id, root_path, rel_path, root_id, size, mtime, basis_rev)| {
-        let full_path = if rel_path.is_empty() {
-            root_path
-        } else {
-            format!("{}/{}", root_path, rel_path)
-        };
-
-        WorklistEntry {
-            source_id: id,
-            path: full_path,
-            root_id,
-            size,
-            mtime,
-            basis_rev,
-        }
-    }))
+/// Stored key of a requested fact: imported facts live under `content.`,
+/// which may be left off (as with `canon import-facts`)
+fn fact_key(key: &str) -> String {
+    if key.starts_with("content.") {
+        key.to_string()
+    } else {
+        format!("content.{}", key)
+    }
 }
@@ -0,0 +1,263 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Prefix of the facts scan records extended attributes as (`scan --xattrs`)
+pub const XATTR_FACT_PREFIX: &str = "source.xattr.";
+
+/// macOS Finder tags: a binary plist of tag names, each followed by "\n" and
+/// a color number
+const FINDER_TAGS: &str = "com.apple.metadata:_kMDItemUserTags";
+
+/// A file's extended attributes as fact key -> value. Only attributes a user
+/// or tool sets to tag files are read: the `user.` namespace on Linux, and
+/// textual attributes plus Finder tags on macOS. Binary values are skipped.
+/// Filesystems without extended attributes have none.
+pub fn read_facts(path: &Path) -> Result<BTreeMap<String, Value>> {
+    let mut facts = BTreeMap::new();
+    for name in sys::list(path)? {
+        if cfg!(target_os = "linux") && !name.starts_with("user.") {
+            continue;
+        }
+        // Removed between listing and reading
+        let Some(raw) = sys::get(path, &name)? else {
+            continue;
+        };
+        let value = if name == FINDER_TAGS {
+            match finder_tags(&raw) {
+                Some(tags) => Value::from(tags),
+                None => continue,
+            }
+        } else {
+            match text(&raw) {
+                Some(text) => Value::String(text),
+                None => continue,
+            }
+        };
+        facts.insert(fact_key(&name), value);
+    }
+    Ok(facts)
+}
+
+/// "user.xdg.tags" -> "source.xattr.user.xdg.tags"; characters filter keys
+/// can't contain become '_'
+fn fact_key(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '_' || c == '.' { c } else { '_' })
+        .collect();
+    format!("{}{}", XATTR_FACT_PREFIX, name)
+}
+
+/// The value as text, if it is UTF-8 without control characters (a trailing
+/// NUL, as C tools write, is dropped)
+fn text(raw: &[u8]) -> Option<String> {
+    let raw = raw.strip_suffix(&[0]).unwrap_or(raw);
+    let text = std::str::from_utf8(raw).ok()?;
+    if text.chars().any(|c| c.is_control() && c != '\t' && c != '\n') {
+        return None;
+    }
+    Some(text.to_string())
+}
+
+/// Tag names from a Finder tags plist (an array of strings), without their
+/// color numbers
+fn finder_tags(raw: &[u8]) -> Option<Vec<String>> {
+    let plist = BinaryPlist::parse(raw)?;
+    let tags = plist.string_array(plist.top)?;
+    Some(
+        tags.into_iter()
+            .map(|tag| match tag.rsplit_once('\n') {
+                Some((name, color)) if color.chars().all(|c| c.is_ascii_digit()) => name.to_string(),
+                _ => tag,
+            })
+            .collect(),
+    )
+}
+
+/// Just enough of the binary plist format ("bplist00") to read an array of strings
+struct BinaryPlist<'a> {
+    data: &'a [u8],
+    offsets: Vec<usize>,
+    ref_size: usize,
+    top: usize,
+}
+
+impl<'a> BinaryPlist<'a> {
+    fn parse(data: &'a [u8]) -> Option<Self> {
+        if data.len() < 40 || !data.starts_with(b"bplist00") {
+            return None;
+        }
+        let trailer = &data[data.len() - 32..];
+        let offset_size = trailer[6] as usize;
+        let ref_size = trailer[7] as usize;
+        let count = be_uint(&trailer[8..16])?;
+        let top = be_uint(&trailer[16..24])?;
+        let table = be_uint(&trailer[24..32])?;
+        let offsets = (0..count)
+            .map(|i| {
+                let start = table.checked_add(i.checked_mul(offset_size)?)?;
+                be_uint(data.get(start..start.checked_add(offset_size)?)?)
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(BinaryPlist { data, offsets, ref_size, top })
+    }
+
+    fn string_array(&self, object: usize) -> Option<Vec<String>> {
+        let (marker, len, start) = self.object(object)?;
+        if marker != 0xA {
+            return None;
+        }
+        (0..len)
+            .map(|i| {
+                let at = start.checked_add(i.checked_mul(self.ref_size)?)?;
+                let object = be_uint(self.data.get(at..at.checked_add(self.ref_size)?)?)?;
+                self.string(object)
+            })
+            .collect()
+    }
+
+    fn string(&self, object: usize) -> Option<String> {
+        let (marker, len, start) = self.object(object)?;
+        match marker {
+            // ASCII
+            0x5 => Some(String::from_utf8_lossy(self.data.get(start..start.checked_add(len)?)?).into_owned()),
+            // UTF-16BE
+            0x6 => {
+                let bytes = self.data.get(start..start.checked_add(len.checked_mul(2)?)?)?;
+                let units: Vec<u16> = bytes.chunks(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]])).collect();
+                Some(String::from_utf16_lossy(&units))
+            }
+            _ => None,
+        }
+    }
+
+    /// (type, length, offset of the payload) of an object
+    fn object(&self, object: usize) -> Option<(u8, usize, usize)> {
+        let offset = *self.offsets.get(object)?;
+        let marker = *self.data.get(offset)?;
+        let (kind, len) = (marker >> 4, (marker & 0x0F) as usize);
+        if len != 0x0F {
+            return Some((kind, len, offset + 1));
+        }
+        // Longer lengths follow as an integer object of 2^n bytes
+        let int_marker = *self.data.get(offset + 1)?;
+        if int_marker >> 4 != 0x1 {
+            return None;
+        }
+        let size = 1usize.checked_shl((int_marker & 0x0F) as u32)?;
+        let len = be_uint(self.data.get(offset + 2..offset + 2 + size)?)?;
+        Some((kind, len, offset + 2 + size))
+    }
+}
+
+fn be_uint(bytes: &[u8]) -> Option<usize> {
+    if bytes.len() > 8 {
+        return None;
+    }
+    let value = bytes.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64);
+    usize::try_from(value).ok()
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod sys {
+    use anyhow::{Context, Result};
+    use std::ffi::{CStr, CString};
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    /// Names of the attributes set on `path`
+    pub fn list(path: &Path) -> Result<Vec<String>> {
+        let c_path = c_path(path)?;
+        let raw = read_sized(|buf, len| unsafe { listxattr(&c_path, buf, len) })
+            .with_context(|| format!("Failed to list extended attributes of {}", path.display()))?;
+        let Some(raw) = raw else {
+            return Ok(Vec::new());
+        };
+        Ok(raw
+            .split(|&b| b == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect())
+    }
+
+    /// The value of attribute `name`, or None if it is gone
+    pub fn get(path: &Path, name: &str) -> Result<Option<Vec<u8>>> {
+        let c_path = c_path(path)?;
+        let c_name = CString::new(name)?;
+        read_sized(|buf, len| unsafe { getxattr(&c_path, &c_name, buf, len) })
+            .with_context(|| format!("Failed to read extended attribute {} of {}", name, path.display()))
+    }
+
+    fn c_path(path: &Path) -> Result<CString> {
+        Ok(CString::new(path.as_os_str().as_bytes())?)
+    }
+
+    /// Call `read` with no buffer to get the size, then with a buffer of that
+    /// size, retrying if the value grew in between. None when the filesystem
+    /// has no extended attributes or the attribute doesn't exist.
+    fn read_sized(read: impl Fn(*mut libc::c_void, usize) -> isize) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            let size = read(std::ptr::null_mut(), 0);
+            if size < 0 {
+                return absent(io::Error::last_os_error());
+            }
+            let mut buf = vec![0u8; size as usize];
+            let len = read(buf.as_mut_ptr() as *mut libc::c_void, buf.len());
+            if len < 0 {
+                let err = io::Error::last_os_error();
+                if err.raw_os_error() == Some(libc::ERANGE) {
+                    continue;
+                }
+                return absent(err);
+            }
+            buf.truncate(len as usize);
+            return Ok(Some(buf));
+        }
+    }
+
+    fn absent(err: io::Error) -> io::Result<Option<Vec<u8>>> {
+        match err.raw_os_error() {
+            Some(libc::ENOTSUP) | Some(libc::ENODATA) => Ok(None),
+            #[cfg(target_os = "macos")]
+            Some(libc::ENOATTR) => Ok(None),
+            _ => Err(err),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe fn listxattr(path: &CStr, buf: *mut libc::c_void, len: usize) -> isize {
+        libc::listxattr(path.as_ptr(), buf as *mut libc::c_char, len)
+    }
+
+    #[cfg(target_os = "linux")]
+    unsafe fn getxattr(path: &CStr, name: &CStr, buf: *mut libc::c_void, len: usize) -> isize {
+        libc::getxattr(path.as_ptr(), name.as_ptr(), buf, len)
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn listxattr(path: &CStr, buf: *mut libc::c_void, len: usize) -> isize {
+        libc::listxattr(path.as_ptr(), buf as *mut libc::c_char, len, 0)
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn getxattr(path: &CStr, name: &CStr, buf: *mut libc::c_void, len: usize) -> isize {
+        libc::getxattr(path.as_ptr(), name.as_ptr(), buf, len, 0, 0)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+mod sys {
+    use anyhow::Result;
+    use std::path::Path;
+
+    pub fn list(_path: &Path) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    pub fn get(_path: &Path, _name: &str) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+}
@@ -0,0 +1,47 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use canon_core::db::{self, Db};
+use canon_core::query::SourceQuery;
+use canon_core::{query_cache, roots, scan};
+
+/// A scratch directory with a source root and an archive root holding the same file
+fn setup(name: &str) -> (PathBuf, Db) {
+    let dir = std::env::temp_dir().join(format!("canon-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    for root in ["src", "arc"] {
+        fs::create_dir_all(dir.join(root)).unwrap();
+        fs::write(dir.join(root).join("a.txt"), "same content").unwrap();
+    }
+    let db = db::open(&dir.join("canon.db"), false, false).unwrap();
+    let options = scan::ScanOptions { hash: true, ..Default::default() };
+    scan::run(&db, &[dir.join("src")], "source", true, &options).unwrap();
+    scan::run(&db, &[dir.join("arc")], "archive", true, &options).unwrap();
+    (dir, db)
+}
+
+fn matching(db: &Db, filter: &str) -> Vec<i64> {
+    SourceQuery::new().filters(db.conn(), &[filter.to_string()]).unwrap().ids(db.conn()).unwrap()
+}
+
+fn spec(dir: &Path, root: &str) -> String {
+    format!("path:{}", fs::canonicalize(dir.join(root)).unwrap().display())
+}
+
+#[test]
+fn cached_results_follow_root_availability_and_role() {
+    query_cache::set_enabled(true);
+    let (dir, db) = setup("query-cache");
+
+    assert_eq!(matching(&db, "archived.online=true").len(), 1);
+    roots::set_offline(&db, &spec(&dir, "arc"), true).unwrap();
+    assert!(matching(&db, "archived.online=true").is_empty());
+    roots::set_offline(&db, &spec(&dir, "arc"), false).unwrap();
+    assert_eq!(matching(&db, "archived.online=true").len(), 1);
+
+    assert_eq!(matching(&db, "archived=true").len(), 1);
+    roots::set_role(&db, &spec(&dir, "arc"), "source").unwrap();
+    assert!(matching(&db, "archived=true").is_empty());
+
+    fs::remove_dir_all(&dir).unwrap();
+}